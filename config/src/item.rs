@@ -239,6 +239,40 @@ impl ConfigItem<Option<String>> for GCPBucket {
     }
 }
 
+pub(crate) struct SnapshotRowGroupSize {}
+
+impl ConfigItem<usize> for SnapshotRowGroupSize {
+    fn name(&self) -> &'static str {
+        "INFLUXDB_IOX_SNAPSHOT_ROW_GROUP_SIZE"
+    }
+    fn short_description(&self) -> String {
+        "Maximum number of rows in each row group of snapshot Parquet files".into()
+    }
+    fn default(&self) -> Option<String> {
+        Some("65536".into())
+    }
+    fn long_description(&self) -> Option<String> {
+        Some(
+            "Snapshots encode tables to Parquet one row group at a time and \
+             upload each row group before encoding the next, so this bounds \
+             the memory a snapshot needs, regardless of the size of the table."
+                .into(),
+        )
+    }
+    fn parse(&self, val: Option<&str>) -> std::result::Result<usize, String> {
+        let val: &str = val.ok_or_else(|| String::from("Empty value is not valid"))?;
+
+        match val.parse::<usize>() {
+            Ok(0) => Err("Row group size must be greater than zero".into()),
+            Ok(rows) => Ok(rows),
+            Err(e) => Err(format!("Error parsing {} as a usize: {}", val, e)),
+        }
+    }
+    fn unparse(&self, val: &usize) -> String {
+        format!("{}", val)
+    }
+}
+
 /// This value simply passed into the environment and used by the
 /// various loggign / tracing libraries. It has its own structure here
 /// for documentation purposes and so it can be loaded from config file.
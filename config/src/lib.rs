@@ -102,6 +102,9 @@ pub struct Config {
     /// Directory to store local database files
     pub database_directory: PathBuf,
 
+    /// Maximum number of rows in each row group of snapshot Parquet files
+    pub snapshot_row_group_size: usize,
+
     // --- GCP fields ---
     /// GCP object store bucekt
     pub gcp_bucket: Option<String>,
@@ -121,6 +124,7 @@ impl Config {
             http_bind_address: Self::parse_config(&name_values, &HttpBindAddr {})?,
             grpc_bind_address: Self::parse_config(&name_values, &GrpcBindAddr {})?,
             database_directory: Self::parse_config(&name_values, &DBDir {})?,
+            snapshot_row_group_size: Self::parse_config(&name_values, &SnapshotRowGroupSize {})?,
             gcp_bucket: Self::parse_config(&name_values, &GCPBucket {})?,
         })
     }
@@ -136,6 +140,7 @@ impl Config {
         HttpBindAddr {}.display(f, &self.http_bind_address, verbose)?;
         GrpcBindAddr {}.display(f, &self.grpc_bind_address, verbose)?;
         DBDir {}.display(f, &self.database_directory, verbose)?;
+        SnapshotRowGroupSize {}.display(f, &self.snapshot_row_group_size, verbose)?;
         GCPBucket {}.display(f, &self.gcp_bucket, verbose)?;
         Ok(())
     }
@@ -289,6 +294,7 @@ mod test {
             ("INFLUXDB_IOX_DB_DIR".into(), "/foo/bar".into()),
             ("INFLUXDB_IOX_ID".into(), "42".into()),
             ("INFLUXDB_IOX_GCP_BUCKET".into(), "my_bucket".into()),
+            ("INFLUXDB_IOX_SNAPSHOT_ROW_GROUP_SIZE".into(), "1000".into()),
             ("RUST_LOG".into(), "rust_log_level".into()),
             (
                 "OTEL_EXPORTER_JAEGER_AGENT_HOST".into(),
@@ -304,6 +310,7 @@ mod test {
         assert_eq!(config.http_bind_address.to_string(), "127.0.0.1:1010");
         assert_eq!(config.grpc_bind_address.to_string(), "127.0.0.2:2020");
         assert_eq!(config.gcp_bucket, Some("my_bucket".into()));
+        assert_eq!(config.snapshot_row_group_size, 1000);
     }
 
     #[test]
@@ -1,7 +1,9 @@
 //! This module contains helper methods for constructing replicated writes
 //! based on `DatabaseRules`.
 
-use crate::database_rules::DatabaseRules;
+use crate::database_rules::{self, CompiledPartitionTemplate, DatabaseRules};
+use crate::delete::Delete;
+use crate::row_predicate::{self, RowPredicate};
 use crate::TIME_COLUMN_NAME;
 use generated_types::wal as wb;
 use influxdb_line_protocol::{FieldValue, ParsedLine};
@@ -11,6 +13,33 @@ use std::{collections::BTreeMap, fmt};
 use chrono::Utc;
 use crc32fast::Hasher;
 use flatbuffers::FlatBufferBuilder;
+use snafu::{ensure, OptionExt, Snafu};
+
+/// Why the bytes of a `ReplicatedWrite` can't be read
+#[derive(Debug, Snafu)]
+pub enum VerifyError {
+    #[snafu(display("{} at offset {} is out of bounds", what, offset))]
+    OutOfBounds { what: &'static str, offset: usize },
+
+    #[snafu(display("String at offset {} is not valid UTF-8", offset))]
+    InvalidUtf8 { offset: usize },
+
+    #[snafu(display("Unknown column value type {} at offset {}", value_type, offset))]
+    UnknownValueType { value_type: u8, offset: usize },
+
+    #[snafu(display("Invalid bool value {} at offset {}", value, offset))]
+    InvalidBool { value: u8, offset: usize },
+
+    #[snafu(display("Replicated write has no payload"))]
+    MissingPayload {},
+
+    #[snafu(display(
+        "Checksum {} of the replicated write doesn't match its payload's checksum {}",
+        expected,
+        actual
+    ))]
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
 
 pub fn type_description(value: wb::ColumnValue) -> &'static str {
     use wb::ColumnValue::*;
@@ -64,6 +93,82 @@ impl ReplicatedWrite {
         &self.data
     }
 
+    /// Returns the partition keys of the write buffer entries in this
+    /// replicated write that have data. Deletes aren't in a partition.
+    pub fn partition_keys(&self) -> Vec<&str> {
+        self.write_buffer_batch()
+            .and_then(|batch| batch.entries())
+            .map(|entries| {
+                entries
+                    .into_iter()
+                    .filter(|entry| entry.table_batches().is_some())
+                    .map(|entry| entry.partition_key().unwrap_or(""))
+                    .collect()
+            })
+            .unwrap_or_else(Vec::new)
+    }
+
+    /// Returns true if this replicated write contains deletes
+    pub fn has_deletes(&self) -> bool {
+        self.write_buffer_batch()
+            .and_then(|batch| batch.entries())
+            .map_or(false, |entries| {
+                entries.into_iter().any(|entry| entry.delete().is_some())
+            })
+    }
+
+    /// Returns the deletes in this replicated write, or an error if the
+    /// predicate of one of them can't be parsed
+    pub fn deletes(&self) -> Result<Vec<Delete>, row_predicate::Error> {
+        let entries = match self.write_buffer_batch().and_then(|batch| batch.entries()) {
+            Some(entries) => entries,
+            None => return Ok(vec![]),
+        };
+
+        entries
+            .into_iter()
+            .filter_map(|entry| entry.delete())
+            .map(|delete| {
+                let predicate = match delete.predicate() {
+                    Some(predicate) if !predicate.is_empty() => {
+                        Some(predicate.parse::<RowPredicate>()?)
+                    }
+                    _ => None,
+                };
+
+                Ok(Delete {
+                    table_name: delete.table_name().map(ToString::to_string),
+                    start: delete.start_time(),
+                    stop: delete.stop_time(),
+                    predicate,
+                })
+            })
+            .collect()
+    }
+
+    /// Checks that the bytes are a replicated write that can be read: every
+    /// offset points into the bytes, the sequence fields and the write buffer
+    /// entries of the payload are complete and the payload matches the
+    /// checksum. The Flatbuffers accessors don't check the bytes, so writes
+    /// received from other servers have to be verified before they are read.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let verifier = Verifier { buf: &self.data };
+        let write = verifier.root()?;
+        verifier.field(write, 0, 4)?; // writer
+        verifier.field(write, 1, 8)?; // sequence
+        verifier.field(write, 2, 4)?; // checksum
+        let (start, len) = verifier.vector(write, 3, 1)?.context(MissingPayload)?;
+        let payload = &self.data[start..start + len];
+
+        let mut hasher = Hasher::new();
+        hasher.update(payload);
+        let actual = hasher.finalize();
+        let expected = self.to_fb().checksum();
+        ensure!(expected == actual, ChecksumMismatch { expected, actual });
+
+        verify_write_buffer_batch(payload)
+    }
+
     /// Returns the number of write buffer entries in this replicated write
     pub fn entry_count(&self) -> usize {
         if let Some(batch) = self.write_buffer_batch() {
@@ -160,24 +265,154 @@ impl fmt::Display for ReplicatedWrite {
     }
 }
 
+/// Creates a `ReplicatedWrite` with the lines, split into write buffer entries
+/// by the partition template of the rules. Returns an error if the template is
+/// invalid, which `DatabaseRules::validate` reports before any write uses it.
+/// This compiles the template for every call, so use
+/// `compiled_lines_to_replicated_write` with a template compiled once for
+/// many writes.
 pub fn lines_to_replicated_write(
     writer: u32,
     sequence: u64,
     lines: &[ParsedLine<'_>],
     rules: &DatabaseRules,
+) -> database_rules::Result<ReplicatedWrite> {
+    let template = rules.partition_template.compile()?;
+    Ok(compiled_lines_to_replicated_write(
+        writer, sequence, lines, &template,
+    ))
+}
+
+/// Creates a `ReplicatedWrite` with the lines, split into write buffer entries
+/// by the compiled partition template
+pub fn compiled_lines_to_replicated_write(
+    writer: u32,
+    sequence: u64,
+    lines: &[ParsedLine<'_>],
+    template: &CompiledPartitionTemplate,
 ) -> ReplicatedWrite {
     let default_time = Utc::now();
     let entry_bytes = split_lines_into_write_entry_partitions(
-        |line| rules.partition_key(line, &default_time).unwrap(),
+        |line| template.partition_key(line, &default_time),
         lines,
     );
 
+    replicated_write_from_batch_bytes(writer, sequence, &entry_bytes)
+}
+
+/// Creates a `ReplicatedWrite` with a single write buffer entry for the delete.
+/// The entry has no partition key, as the delete applies to all partitions.
+pub fn delete_to_replicated_write(writer: u32, sequence: u64, delete: &Delete) -> ReplicatedWrite {
+    let mut fbb = flatbuffers::FlatBufferBuilder::new_with_capacity(1024);
+    let predicate = delete.predicate.as_ref().map(ToString::to_string);
+    let delete = add_delete(
+        &mut fbb,
+        delete.table_name.as_deref(),
+        predicate.as_deref(),
+        delete.start,
+        delete.stop,
+    );
+
+    let entry = wb::WriteBufferEntry::create(
+        &mut fbb,
+        &wb::WriteBufferEntryArgs {
+            delete: Some(delete),
+            ..Default::default()
+        },
+    );
+    let entry_bytes = finish_write_buffer_batch(fbb, &[entry]);
+
+    replicated_write_from_batch_bytes(writer, sequence, &entry_bytes)
+}
+
+/// Creates a new `ReplicatedWrite` with the same writer and sequence number as
+/// `write` that contains only the write buffer entries whose partition key is
+/// accepted by `partition_filter`, and all deletes. Returns `None` if no entry
+/// was accepted.
+pub fn partitioned_replicated_write(
+    write: &ReplicatedWrite,
+    partition_filter: impl Fn(&str) -> bool,
+) -> Option<ReplicatedWrite> {
+    filtered_replicated_write(write, &PartitionFilter(partition_filter))
+}
+
+/// `WriteFilter` decides which parts of a `ReplicatedWrite` are kept by
+/// `filtered_replicated_write`. By default everything is kept.
+pub trait WriteFilter {
+    /// Returns true if the write buffer entry for the partition key should be
+    /// kept
+    fn keep_partition(&self, _partition_key: &str) -> bool {
+        true
+    }
+
+    /// Returns true if the table should be kept
+    fn keep_table(&self, _table_name: &str) -> bool {
+        true
+    }
+
+    /// Returns true if the row of the table should be kept
+    fn keep_row(&self, _table_name: &str, _row: &wb::Row<'_>) -> bool {
+        true
+    }
+
+    /// Returns true if the delete should be kept. Deletes aren't in a
+    /// partition, so they aren't affected by `keep_partition`.
+    fn keep_delete(&self, _delete: &wb::WriteBufferDelete<'_>) -> bool {
+        true
+    }
+}
+
+struct PartitionFilter<F: Fn(&str) -> bool>(F);
+
+impl<F: Fn(&str) -> bool> WriteFilter for PartitionFilter<F> {
+    fn keep_partition(&self, partition_key: &str) -> bool {
+        (self.0)(partition_key)
+    }
+}
+
+/// Creates a new `ReplicatedWrite` with the same writer and sequence number as
+/// `write` that contains only the partitions, tables, rows and deletes kept by
+/// `filter`. Tables without any kept rows and entries without any kept tables
+/// or delete are left out. Returns `None` if nothing in the write was kept.
+pub fn filtered_replicated_write(
+    write: &ReplicatedWrite,
+    filter: &impl WriteFilter,
+) -> Option<ReplicatedWrite> {
+    let entries = write.write_buffer_batch()?.entries()?;
+
+    let mut fbb = flatbuffers::FlatBufferBuilder::new_with_capacity(1024);
+    let entries = entries
+        .into_iter()
+        .filter_map(|entry| copy_write_entry(&mut fbb, &entry, filter))
+        .collect::<Vec<_>>();
+
+    if entries.is_empty() {
+        return None;
+    }
+
+    let entry_bytes = finish_write_buffer_batch(fbb, &entries);
+    let (writer, sequence) = write.writer_and_sequence();
+
+    Some(replicated_write_from_batch_bytes(
+        writer,
+        sequence,
+        &entry_bytes,
+    ))
+}
+
+/// Wraps the serialized bytes of a `WriteBufferBatch` in a `ReplicatedWrite`,
+/// computing the checksum of the payload.
+fn replicated_write_from_batch_bytes(
+    writer: u32,
+    sequence: u64,
+    entry_bytes: &[u8],
+) -> ReplicatedWrite {
     let mut hasher = Hasher::new();
-    hasher.update(&entry_bytes);
+    hasher.update(entry_bytes);
     let checksum = hasher.finalize();
 
     let mut fbb = flatbuffers::FlatBufferBuilder::new_with_capacity(1024);
-    let payload = fbb.create_vector_direct(&entry_bytes);
+    let payload = fbb.create_vector_direct(entry_bytes);
 
     let write = wb::ReplicatedWrite::create(
         &mut fbb,
@@ -222,7 +457,16 @@ pub fn split_lines_into_write_entry_partitions(
         .map(|(key, lines)| add_write_entry(&mut fbb, Some(&key), &lines))
         .collect::<Vec<_>>();
 
-    let entries_vec = fbb.create_vector(&entries);
+    finish_write_buffer_batch(fbb, &entries)
+}
+
+// creates the WriteBufferBatch from the passed in entries and returns the
+// serialized bytes
+fn finish_write_buffer_batch<'a>(
+    mut fbb: FlatBufferBuilder<'a>,
+    entries: &[flatbuffers::WIPOffset<wb::WriteBufferEntry<'a>>],
+) -> Vec<u8> {
+    let entries_vec = fbb.create_vector(entries);
 
     let batch = wb::WriteBufferBatch::create(
         &mut fbb,
@@ -237,6 +481,156 @@ pub fn split_lines_into_write_entry_partitions(
     data.split_off(idx)
 }
 
+// copies the tables, rows and delete kept by the filter from a write buffer
+// entry into the builder. Returns `None` if neither a table nor the delete was
+// kept.
+fn copy_write_entry<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    entry: &wb::WriteBufferEntry<'_>,
+    filter: &impl WriteFilter,
+) -> Option<flatbuffers::WIPOffset<wb::WriteBufferEntry<'a>>> {
+    let batches = match entry.table_batches() {
+        Some(batches) if filter.keep_partition(entry.partition_key().unwrap_or("")) => batches
+            .into_iter()
+            .filter(|batch| filter.keep_table(batch.name().unwrap_or("")))
+            .filter_map(|batch| copy_table_batch(fbb, &batch, filter))
+            .collect::<Vec<_>>(),
+        _ => vec![],
+    };
+
+    let delete = entry
+        .delete()
+        .filter(|delete| filter.keep_delete(delete))
+        .map(|delete| {
+            add_delete(
+                fbb,
+                delete.table_name(),
+                delete.predicate(),
+                delete.start_time(),
+                delete.stop_time(),
+            )
+        });
+
+    if batches.is_empty() && delete.is_none() {
+        return None;
+    }
+
+    let table_batches = if batches.is_empty() {
+        None
+    } else {
+        Some(fbb.create_vector(&batches))
+    };
+    let partition_key = entry.partition_key().map(|key| fbb.create_string(key));
+
+    Some(wb::WriteBufferEntry::create(
+        fbb,
+        &wb::WriteBufferEntryArgs {
+            partition_key,
+            table_batches,
+            delete,
+        },
+    ))
+}
+
+fn add_delete<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    table_name: Option<&str>,
+    predicate: Option<&str>,
+    start_time: i64,
+    stop_time: i64,
+) -> flatbuffers::WIPOffset<wb::WriteBufferDelete<'a>> {
+    let table_name = table_name.map(|name| fbb.create_string(name));
+    let predicate = predicate.map(|predicate| fbb.create_string(predicate));
+
+    wb::WriteBufferDelete::create(
+        fbb,
+        &wb::WriteBufferDeleteArgs {
+            table_name,
+            predicate,
+            start_time,
+            stop_time,
+        },
+    )
+}
+
+// copies the rows kept by the filter from a table batch into the builder.
+// Returns `None` if no row was kept.
+fn copy_table_batch<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    batch: &wb::TableWriteBatch<'_>,
+    filter: &impl WriteFilter,
+) -> Option<flatbuffers::WIPOffset<wb::TableWriteBatch<'a>>> {
+    let table_name = batch.name().unwrap_or("");
+    let rows = batch
+        .rows()?
+        .into_iter()
+        .filter(|row| filter.keep_row(table_name, row))
+        .map(|row| copy_row(fbb, &row))
+        .collect::<Vec<_>>();
+
+    if rows.is_empty() {
+        return None;
+    }
+
+    Some(add_table_batch_rows(fbb, table_name, &rows))
+}
+
+// copies a row from an existing table batch into the builder
+fn copy_row<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    row: &wb::Row<'_>,
+) -> flatbuffers::WIPOffset<wb::Row<'a>> {
+    let values = row
+        .values()
+        .map(|values| {
+            values
+                .into_iter()
+                .filter_map(|value| copy_value(fbb, &value))
+                .collect()
+        })
+        .unwrap_or_else(Vec::new);
+
+    let values = fbb.create_vector(&values);
+
+    wb::Row::create(
+        fbb,
+        &wb::RowArgs {
+            values: Some(values),
+        },
+    )
+}
+
+// copies a value from an existing row into the builder. Values without a type
+// are skipped.
+fn copy_value<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    value: &wb::Value<'_>,
+) -> Option<flatbuffers::WIPOffset<wb::Value<'a>>> {
+    let column = value.column().unwrap_or("");
+
+    let copied = match value.value_type() {
+        wb::ColumnValue::TagValue => add_tag_value(
+            fbb,
+            column,
+            value.value_as_tag_value()?.value().unwrap_or(""),
+        ),
+        wb::ColumnValue::I64Value => add_i64_value(fbb, column, value.value_as_i64value()?.value()),
+        wb::ColumnValue::U64Value => add_u64_value(fbb, column, value.value_as_u64value()?.value()),
+        wb::ColumnValue::F64Value => add_f64_value(fbb, column, value.value_as_f64value()?.value()),
+        wb::ColumnValue::BoolValue => {
+            add_bool_value(fbb, column, value.value_as_bool_value()?.value())
+        }
+        wb::ColumnValue::StringValue => add_string_value(
+            fbb,
+            column,
+            value.value_as_string_value()?.value().unwrap_or(""),
+        ),
+        wb::ColumnValue::NONE => return None,
+    };
+
+    Some(copied)
+}
+
 fn add_write_entry<'a>(
     fbb: &mut FlatBufferBuilder<'a>,
     partition_key: Option<&str>,
@@ -290,8 +684,16 @@ fn add_table_batch<'a>(
         .map(|line| add_line(fbb, line))
         .collect::<Vec<_>>();
 
+    add_table_batch_rows(fbb, name, &rows)
+}
+
+fn add_table_batch_rows<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    name: &str,
+    rows: &[flatbuffers::WIPOffset<wb::Row<'a>>],
+) -> flatbuffers::WIPOffset<wb::TableWriteBatch<'a>> {
     let table_name = fbb.create_string(name);
-    let rows = fbb.create_vector(&rows);
+    let rows = fbb.create_vector(rows);
 
     wb::TableWriteBatch::create(
         fbb,
@@ -393,6 +795,16 @@ fn add_i64_value<'a>(
     add_value(fbb, column, wb::ColumnValue::I64Value, iv.as_union_value())
 }
 
+fn add_u64_value<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    column: &str,
+    value: u64,
+) -> flatbuffers::WIPOffset<wb::Value<'a>> {
+    let uv = wb::U64Value::create(fbb, &wb::U64ValueArgs { value });
+
+    add_value(fbb, column, wb::ColumnValue::U64Value, uv.as_union_value())
+}
+
 fn add_bool_value<'a>(
     fbb: &mut FlatBufferBuilder<'a>,
     column: &str,
@@ -420,3 +832,303 @@ fn add_value<'a>(
         },
     )
 }
+
+// checks every write buffer entry of the WriteBufferBatch in the buffer
+fn verify_write_buffer_batch(buf: &[u8]) -> Result<(), VerifyError> {
+    use wb::ColumnValue::*;
+    const VALUE_TYPES: [wb::ColumnValue; 7] = [
+        NONE,
+        TagValue,
+        I64Value,
+        U64Value,
+        F64Value,
+        BoolValue,
+        StringValue,
+    ];
+
+    let verifier = Verifier { buf };
+    let batch = verifier.root()?;
+    for entry in verifier.tables(batch, 0)? {
+        verifier.string(entry, 0)?; // partition_key
+        for table_batch in verifier.tables(entry, 1)? {
+            verifier.string(table_batch, 0)?; // name
+            for row in verifier.tables(table_batch, 1)? {
+                for value in verifier.tables(row, 0)? {
+                    verifier.string(value, 0)?; // column
+
+                    let (offset, value_type) = match verifier.field(value, 1, 1)? {
+                        Some(pos) => (pos, buf[pos]),
+                        None => (value.pos, NONE as u8),
+                    };
+                    let value_type = VALUE_TYPES
+                        .iter()
+                        .copied()
+                        .find(|t| *t as u8 == value_type)
+                        .context(UnknownValueType { value_type, offset })?;
+
+                    let table = match verifier.table_field(value, 2)? {
+                        Some(table) => table,
+                        None => continue,
+                    };
+                    match value_type {
+                        NONE => {}
+                        TagValue | StringValue => verifier.string(table, 0)?,
+                        I64Value | U64Value | F64Value => {
+                            verifier.field(table, 0, 8)?;
+                        }
+                        BoolValue => {
+                            if let Some(pos) = verifier.field(table, 0, 1)? {
+                                ensure!(
+                                    buf[pos] <= 1,
+                                    InvalidBool {
+                                        value: buf[pos],
+                                        offset: pos
+                                    }
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(delete) = verifier.table_field(entry, 2)? {
+            verifier.string(delete, 0)?; // table_name
+            verifier.string(delete, 1)?; // predicate
+            verifier.field(delete, 2, 8)?; // start_time
+            verifier.field(delete, 3, 8)?; // stop_time
+        }
+    }
+
+    Ok(())
+}
+
+// Checks that the offsets of the tables, vectors and strings in a Flatbuffers
+// buffer point into it. Fields are identified by their index in the schema.
+struct Verifier<'a> {
+    buf: &'a [u8],
+}
+
+// A table whose vtable and inline fields are in the buffer
+#[derive(Debug, Clone, Copy)]
+struct VerifiedTable {
+    pos: usize,
+    vtable: usize,
+    vtable_len: usize,
+    len: usize,
+}
+
+impl<'a> Verifier<'a> {
+    fn range(
+        &self,
+        what: &'static str,
+        offset: usize,
+        len: usize,
+    ) -> Result<&'a [u8], VerifyError> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.buf.get(offset..end))
+            .context(OutOfBounds { what, offset })
+    }
+
+    fn u16_at(&self, what: &'static str, offset: usize) -> Result<u16, VerifyError> {
+        let bytes = self.range(what, offset, 2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32_at(&self, what: &'static str, offset: usize) -> Result<u32, VerifyError> {
+        let bytes = self.range(what, offset, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn root(&self) -> Result<VerifiedTable, VerifyError> {
+        let offset = self.u32_at("root offset", 0)? as usize;
+        self.table(offset)
+    }
+
+    fn table(&self, pos: usize) -> Result<VerifiedTable, VerifyError> {
+        // the vtable is at the signed offset stored at the start of the table
+        let soffset = i64::from(self.u32_at("table", pos)? as i32);
+        let vtable = pos as i64 - soffset;
+        ensure!(
+            vtable >= 0,
+            OutOfBounds {
+                what: "vtable",
+                offset: pos
+            }
+        );
+        let vtable = vtable as usize;
+
+        let vtable_len = self.u16_at("vtable", vtable)? as usize;
+        let len = self.u16_at("vtable", vtable + 2)? as usize;
+        ensure!(
+            vtable_len >= 4 && len >= 4,
+            OutOfBounds {
+                what: "vtable",
+                offset: vtable
+            }
+        );
+        self.range("vtable", vtable, vtable_len)?;
+        self.range("table", pos, len)?;
+
+        Ok(VerifiedTable {
+            pos,
+            vtable,
+            vtable_len,
+            len,
+        })
+    }
+
+    // returns the position of the field of the table, if it is set
+    fn field(
+        &self,
+        table: VerifiedTable,
+        field: usize,
+        size: usize,
+    ) -> Result<Option<usize>, VerifyError> {
+        let voffset = 4 + 2 * field;
+        if voffset + 2 > table.vtable_len {
+            return Ok(None);
+        }
+
+        let offset = self.u16_at("vtable", table.vtable + voffset)? as usize;
+        if offset == 0 {
+            return Ok(None);
+        }
+        ensure!(
+            offset + size <= table.len,
+            OutOfBounds {
+                what: "field",
+                offset: table.pos + offset
+            }
+        );
+
+        Ok(Some(table.pos + offset))
+    }
+
+    // returns the position the offset in the field of the table points to
+    fn follow(&self, table: VerifiedTable, field: usize) -> Result<Option<usize>, VerifyError> {
+        match self.field(table, field, 4)? {
+            Some(pos) => Ok(Some(pos + self.u32_at("offset", pos)? as usize)),
+            None => Ok(None),
+        }
+    }
+
+    // returns the position of the first element of the vector in the field
+    // of the table and its length
+    fn vector(
+        &self,
+        table: VerifiedTable,
+        field: usize,
+        element_size: usize,
+    ) -> Result<Option<(usize, usize)>, VerifyError> {
+        let pos = match self.follow(table, field)? {
+            Some(pos) => pos,
+            None => return Ok(None),
+        };
+
+        let len = self.u32_at("vector", pos)? as usize;
+        let size = len.checked_mul(element_size).context(OutOfBounds {
+            what: "vector",
+            offset: pos,
+        })?;
+        self.range("vector", pos + 4, size)?;
+
+        Ok(Some((pos + 4, len)))
+    }
+
+    fn string(&self, table: VerifiedTable, field: usize) -> Result<(), VerifyError> {
+        if let Some((start, len)) = self.vector(table, field, 1)? {
+            let bytes = self.range("string", start, len)?;
+            std::str::from_utf8(bytes)
+                .ok()
+                .context(InvalidUtf8 { offset: start })?;
+        }
+
+        Ok(())
+    }
+
+    fn table_field(
+        &self,
+        table: VerifiedTable,
+        field: usize,
+    ) -> Result<Option<VerifiedTable>, VerifyError> {
+        self.follow(table, field)?
+            .map(|pos| self.table(pos))
+            .transpose()
+    }
+
+    fn tables(
+        &self,
+        table: VerifiedTable,
+        field: usize,
+    ) -> Result<Vec<VerifiedTable>, VerifyError> {
+        let (start, len) = match self.vector(table, field, 4)? {
+            Some(vector) => vector,
+            None => return Ok(vec![]),
+        };
+
+        (0..len)
+            .map(|i| {
+                let pos = start + 4 * i;
+                self.table(pos + self.u32_at("offset", pos)? as usize)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use influxdb_line_protocol::parse_lines;
+
+    #[test]
+    fn verifies_replicated_writes() {
+        let lines: Vec<_> = parse_lines("cpu,host=a bar=1,baz=\"x\",on=true 10\nmem free=2i 10")
+            .map(|l| l.unwrap())
+            .collect();
+        let write = lines_to_replicated_write(1, 2, &lines, &DatabaseRules::default()).unwrap();
+        write.verify().unwrap();
+
+        // truncated writes are rejected rather than read out of bounds
+        for len in 0..write.data.len() {
+            let _ = ReplicatedWrite::from(&write.data[..len]).verify();
+        }
+        let truncated = ReplicatedWrite::from(&write.data[..write.data.len() / 2]);
+        assert!(matches!(
+            truncated.verify(),
+            Err(VerifyError::OutOfBounds { .. })
+        ));
+        let garbage = ReplicatedWrite::from(&[0xff_u8; 16][..]);
+        assert!(matches!(
+            garbage.verify(),
+            Err(VerifyError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_payload_that_does_not_match_checksum() {
+        let lines: Vec<_> = parse_lines("cpu bar=1 10").map(|l| l.unwrap()).collect();
+        let write = lines_to_replicated_write(1, 2, &lines, &DatabaseRules::default()).unwrap();
+        let fb = write.to_fb();
+
+        let mut fbb = flatbuffers::FlatBufferBuilder::new_with_capacity(1024);
+        let payload = fbb.create_vector_direct(fb.payload().unwrap());
+        let corrupted = wb::ReplicatedWrite::create(
+            &mut fbb,
+            &wb::ReplicatedWriteArgs {
+                writer: fb.writer(),
+                sequence: fb.sequence(),
+                checksum: fb.checksum() ^ 1,
+                payload: Some(payload),
+            },
+        );
+        fbb.finish(corrupted, None);
+
+        let corrupted = ReplicatedWrite::from(fbb.finished_data());
+        assert!(matches!(
+            corrupted.verify(),
+            Err(VerifyError::ChecksumMismatch { .. })
+        ));
+    }
+}
@@ -1,8 +1,16 @@
-use influxdb_line_protocol::ParsedLine;
+use crate::data::WriteFilter;
+use crate::row_predicate::{self, RowPredicate};
+use generated_types::wal as wb;
+use influxdb_line_protocol::{FieldValue, ParsedLine};
 
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, TimeZone, Utc,
+};
+use crc32fast::Hasher;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use snafu::Snafu;
+use snafu::{ensure, ResultExt, Snafu};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -11,6 +19,32 @@ pub enum Error {
         source_module: &'static str,
         source: Box<dyn std::error::Error + Send + Sync + 'static>,
     },
+
+    #[snafu(display("Invalid table regex '{}' in matcher: {}", regex, source))]
+    InvalidTableRegex { regex: String, source: regex::Error },
+
+    #[snafu(display("Invalid predicate in matcher: {}", source))]
+    InvalidMatcherPredicate { source: row_predicate::Error },
+
+    #[snafu(display("Invalid time format '{}' in partition template", format))]
+    InvalidTimeFormat { format: String },
+
+    #[snafu(display(
+        "Invalid regex '{}' for column {} in partition template: {}",
+        regex,
+        column,
+        source
+    ))]
+    InvalidTemplateRegex {
+        column: String,
+        regex: String,
+        source: regex::Error,
+    },
+
+    #[snafu(display(
+        "Hash bucket part of partition template needs at least one column and one bucket"
+    ))]
+    InvalidHashBucket {},
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -43,7 +77,8 @@ pub struct DatabaseRules {
     /// The minimum number of host groups to replicate a write to before success
     /// is returned. This can be overridden on a per request basis.
     /// Replication will continue to write to the other host groups in the
-    /// background.
+    /// background. Zero, the default, requires every host group in
+    /// `replication`.
     #[serde(default)]
     pub replication_count: u8,
     /// How long the replication queue can get before either rejecting writes or
@@ -96,9 +131,28 @@ pub struct DatabaseRules {
     /// configuration.
     #[serde(default)]
     pub wal_buffer_config: Option<WalBufferConfig>,
+
+    /// If set, chunks of the mutable buffer are closed, persisted and evicted
+    /// from memory according to these rules. Otherwise chunks only move when
+    /// a partition is explicitly snapshotted.
+    #[serde(default)]
+    pub lifecycle_rules: Option<LifecycleRules>,
+
+    /// If set, data older than this many seconds is dropped from the mutable
+    /// buffer, the read buffer and object storage. Partitions and chunks are
+    /// dropped as a whole once the newest of their rows is this old.
+    #[serde(default)]
+    pub retention_seconds: Option<u32>,
 }
 
 impl DatabaseRules {
+    /// Checks the parts of the rules that are compiled when they are used,
+    /// such as the partition template, so invalid rules are rejected when the
+    /// database is created rather than by its writes.
+    pub fn validate(&self) -> Result<()> {
+        self.partition_template.validate()
+    }
+
     pub fn partition_key(
         &self,
         line: &ParsedLine<'_>,
@@ -132,6 +186,41 @@ pub struct WalBufferConfig {
     pub buffer_rollover: WalBufferRollover,
 }
 
+/// LifecycleRules define when the chunks of a database's mutable buffer are
+/// closed, persisted to object storage, moved to the read buffer and evicted
+/// from memory.
+#[derive(Debug, Default, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct LifecycleRules {
+    /// The open chunk of a partition is closed once its estimated size in
+    /// bytes crosses this threshold
+    #[serde(default)]
+    pub mutable_size_threshold: Option<usize>,
+    /// The open chunk of a partition is closed once this many seconds have
+    /// passed since its first write
+    #[serde(default)]
+    pub mutable_linger_seconds: Option<u32>,
+    /// If true, closed chunks are written to object storage as Parquet
+    #[serde(default)]
+    pub persist: bool,
+    /// Once the chunks in the mutable buffer and the read buffer grow past
+    /// this size in bytes, the oldest closed chunks that have been persisted
+    /// are dropped from memory until they are below the limit again. Queries
+    /// read the dropped chunks from object storage.
+    #[serde(default)]
+    pub buffer_size_limit: Option<usize>,
+    /// Writes that would take the data of the mutable buffer and the read
+    /// buffer together over this size in bytes are rejected, until chunks are
+    /// dropped from memory. This should be larger than `buffer_size_limit`,
+    /// so the lifecycle rules free up memory before writes are rejected.
+    #[serde(default)]
+    pub buffer_size_hard: Option<usize>,
+    /// If true, closed chunks are moved from the mutable buffer to the read
+    /// buffer, which holds them in a compressed form. If `persist` is also
+    /// set, chunks are only moved once they have been persisted.
+    #[serde(default)]
+    pub move_to_read_buffer: bool,
+}
+
 /// WalBufferRollover defines the behavior of what should happen if a write
 /// comes in that would cause the buffer to exceed its max size AND the oldest
 /// segment can't be dropped because it has not yet been persisted.
@@ -162,33 +251,233 @@ pub struct PartitionTemplate {
 }
 
 impl PartitionTemplate {
+    /// Computes the partition key of the line. Lines without a timestamp are
+    /// partitioned as if they were written at `default_time`. This compiles
+    /// the template for every call, so use `compile` to compute the keys of
+    /// many lines.
     pub fn partition_key(
         &self,
         line: &ParsedLine<'_>,
         default_time: &DateTime<Utc>,
     ) -> Result<String> {
+        Ok(self.compile()?.partition_key(line, default_time))
+    }
+
+    /// Checks that every part of the template can be used to compute
+    /// partition keys, so that bad templates are rejected before any write
+    /// uses them.
+    pub fn validate(&self) -> Result<()> {
+        self.compile().map(|_| ())
+    }
+
+    /// Compiles the regexes and checks the time formats of the template so it
+    /// can compute partition keys
+    pub fn compile(&self) -> Result<CompiledPartitionTemplate> {
+        let parts = self
+            .parts
+            .iter()
+            .map(|part| {
+                Ok(match part {
+                    TemplatePart::Table => CompiledTemplatePart::Table,
+                    TemplatePart::Column(column) => CompiledTemplatePart::Column(column.clone()),
+                    TemplatePart::TimeFormat(format) => {
+                        validate_time_format(format)?;
+                        CompiledTemplatePart::TimeFormat(format.clone())
+                    }
+                    TemplatePart::RegexCapture(RegexCapture { column, regex }) => {
+                        let compiled = Regex::new(regex).context(InvalidTemplateRegex {
+                            column: column.as_str(),
+                            regex: regex.as_str(),
+                        })?;
+                        CompiledTemplatePart::RegexCapture(column.clone(), compiled)
+                    }
+                    TemplatePart::StrftimeColumn(StrftimeColumn { column, format }) => {
+                        validate_time_format(format)?;
+                        CompiledTemplatePart::StrftimeColumn(column.clone(), format.clone())
+                    }
+                    TemplatePart::HashBucket(HashBucket { columns, buckets }) => {
+                        ensure!(!columns.is_empty() && *buckets > 0, InvalidHashBucket);
+                        CompiledTemplatePart::HashBucket(columns.clone(), *buckets)
+                    }
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(CompiledPartitionTemplate { parts })
+    }
+}
+
+/// `CompiledPartitionTemplate` is a `PartitionTemplate` that is ready to
+/// compute the partition keys of lines. It doesn't borrow the template, so it
+/// can be kept alongside the rules and reused for every write. The default
+/// template puts all lines into the same partition.
+#[derive(Debug, Default, Clone)]
+pub struct CompiledPartitionTemplate {
+    parts: Vec<CompiledTemplatePart>,
+}
+
+#[derive(Debug, Clone)]
+enum CompiledTemplatePart {
+    Table,
+    Column(String),
+    TimeFormat(String),
+    RegexCapture(String, Regex),
+    StrftimeColumn(String, String),
+    HashBucket(Vec<String>, u32),
+}
+
+impl CompiledPartitionTemplate {
+    /// Computes the partition key of the line. Lines without a timestamp are
+    /// partitioned as if they were written at `default_time`.
+    pub fn partition_key(&self, line: &ParsedLine<'_>, default_time: &DateTime<Utc>) -> String {
         let parts: Vec<_> = self
             .parts
             .iter()
             .map(|p| match p {
-                TemplatePart::Table => line.series.measurement.to_string(),
-                TemplatePart::Column(column) => match line.tag_value(&column) {
+                CompiledTemplatePart::Table => line.series.measurement.to_string(),
+                CompiledTemplatePart::Column(column) => match line.tag_value(column) {
                     Some(v) => format!("{}_{}", column, v),
-                    None => match line.field_value(&column) {
+                    None => match line.field_value(column) {
                         Some(v) => format!("{}_{}", column, v),
                         None => "".to_string(),
                     },
                 },
-                TemplatePart::TimeFormat(format) => match line.timestamp {
-                    Some(t) => Utc.timestamp_nanos(t).format(&format).to_string(),
-                    None => default_time.format(&format).to_string(),
+                CompiledTemplatePart::TimeFormat(format) => match line.timestamp {
+                    Some(t) => Utc.timestamp_nanos(t).format(format).to_string(),
+                    None => default_time.format(format).to_string(),
                 },
-                _ => unimplemented!(),
+                CompiledTemplatePart::RegexCapture(column, regex) => {
+                    regex_capture(line, column, regex)
+                }
+                CompiledTemplatePart::StrftimeColumn(column, format) => {
+                    match line.field_value(column) {
+                        Some(FieldValue::I64(t)) => {
+                            Utc.timestamp_nanos(*t).format(format).to_string()
+                        }
+                        _ => "".to_string(),
+                    }
+                }
+                CompiledTemplatePart::HashBucket(columns, buckets) => {
+                    hash_bucket(line, columns, *buckets)
+                }
             })
             .collect();
 
-        Ok(parts.join("-"))
+        parts.join("-")
+    }
+}
+
+// returns the names of the columns and the bucket the hash of their values in
+// the line falls into, with the bucket number zero padded to the width of the
+// largest one. The CRC32 of the canonical encoding of the values is used, as
+// both are the same on every server and in every version, so rows always go
+// to the same bucket. Returns an empty string if the line has none of the
+// columns.
+fn hash_bucket(line: &ParsedLine<'_>, columns: &[String], buckets: u32) -> String {
+    let mut hasher = Hasher::new();
+    let mut found = false;
+    for column in columns {
+        found |= match (line.tag_value(column), line.field_value(column)) {
+            (Some(v), _) => {
+                hash_string(&mut hasher, v.as_str());
+                true
+            }
+            (None, Some(v)) => {
+                hash_field_value(&mut hasher, v);
+                true
+            }
+            (None, None) => {
+                hasher.update(&[HASH_MISSING]);
+                false
+            }
+        };
+    }
+
+    if !found {
+        return "".to_string();
     }
+
+    let width = (buckets - 1).to_string().len();
+    format!(
+        "{}_bucket_{:0width$}",
+        columns.join("_"),
+        hasher.finalize() % buckets,
+        width = width
+    )
+}
+
+// The type bytes the values hashed by `hash_bucket` are prefixed with. Each
+// value is encoded as its type byte followed by its little endian bytes, with
+// strings prefixed by their length, so that values of different columns and
+// types can't run into each other. These must never change, or rows move to
+// other buckets.
+const HASH_MISSING: u8 = 0;
+const HASH_STRING: u8 = 1;
+const HASH_I64: u8 = 2;
+const HASH_F64: u8 = 3;
+const HASH_BOOL: u8 = 4;
+
+fn hash_string(hasher: &mut Hasher, value: &str) {
+    hasher.update(&[HASH_STRING]);
+    hasher.update(&(value.len() as u64).to_le_bytes());
+    hasher.update(value.as_bytes());
+}
+
+fn hash_field_value(hasher: &mut Hasher, value: &FieldValue<'_>) {
+    match value {
+        FieldValue::String(v) => hash_string(hasher, v.as_str()),
+        FieldValue::I64(v) => {
+            hasher.update(&[HASH_I64]);
+            hasher.update(&v.to_le_bytes());
+        }
+        FieldValue::F64(v) => {
+            // -0.0 and 0.0 are the same value
+            let v = if *v == 0.0 { 0.0_f64 } else { *v };
+            hasher.update(&[HASH_F64]);
+            hasher.update(&v.to_bits().to_le_bytes());
+        }
+        FieldValue::Boolean(v) => hasher.update(&[HASH_BOOL, u8::from(*v)]),
+    }
+}
+
+// returns the column name and the groups the regex captured in the value of
+// the tag or string field, joined by `_`. The whole match is used if the regex
+// has no groups. Any `/` is replaced by `_`, as partition keys are used in
+// object store paths. Returns an empty string if the line doesn't have the
+// column or the regex doesn't match.
+fn regex_capture(line: &ParsedLine<'_>, column: &str, regex: &Regex) -> String {
+    let value = match (line.tag_value(column), line.field_value(column)) {
+        (Some(v), _) => v.as_str(),
+        (None, Some(FieldValue::String(v))) => v.as_str(),
+        _ => return "".to_string(),
+    };
+
+    let captures = match regex.captures(value) {
+        Some(captures) => captures,
+        None => return "".to_string(),
+    };
+
+    let groups: Vec<_> = if captures.len() > 1 {
+        captures
+            .iter()
+            .skip(1)
+            .flatten()
+            .map(|m| m.as_str())
+            .collect()
+    } else {
+        captures.iter().flatten().map(|m| m.as_str()).collect()
+    };
+
+    format!("{}_{}", column, groups.join("_")).replace('/', "_")
+}
+
+// returns an error if the strftime format string has invalid specifiers
+fn validate_time_format(format: &str) -> Result<()> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return InvalidTimeFormat { format }.fail();
+    }
+
+    Ok(())
 }
 
 /// `TemplatePart` specifies what part of a row should be used to compute this
@@ -200,27 +489,49 @@ pub enum TemplatePart {
     TimeFormat(String),
     RegexCapture(RegexCapture),
     StrftimeColumn(StrftimeColumn),
+    HashBucket(HashBucket),
 }
 
 /// `RegexCapture` is for pulling parts of a string column into the partition
 /// key.
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
 pub struct RegexCapture {
-    column: String,
-    regex: String,
+    /// The tag or string field the regex is matched against
+    pub column: String,
+    /// The groups this regex captures are used in the partition key, or the
+    /// whole match if it has no groups
+    pub regex: String,
 }
 
 /// `StrftimeColumn` can be used to create a time based partition key off some
 /// column other than the builtin `time` column.
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
 pub struct StrftimeColumn {
-    column: String,
-    format: String,
+    /// An integer field with a timestamp in nanoseconds since the epoch
+    pub column: String,
+    /// The strftime format the timestamp is formatted with
+    pub format: String,
+}
+
+/// `HashBucket` splits rows into a fixed number of partitions by the hash of
+/// the values of some columns, for columns with too many distinct values to
+/// give each its own partition. The part of the key looks like
+/// `host_bucket_07`. As the same values always hash to the same bucket, the
+/// partition keys, and so the hosts consistent hashing routes them to, don't
+/// change between writes.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct HashBucket {
+    /// The tags or fields whose values are hashed
+    pub columns: Vec<String>,
+    /// The number of buckets
+    pub buckets: u32,
 }
 
 /// `PartitionId` is the object storage identifier for a specific partition. It
 /// should be a path that can be used against an object store to locate all the
-/// files and subdirectories for a partition. It takes the form of `/<writer
+/// files and subdirectories for a partition. It takes the form of
+/// `<database path>/<partition key>`, where the database path is the directory
+/// the database's snapshots were written to, for example `/<writer
 /// ID>/<database>/<partition key>/`.
 pub type PartitionId = String;
 pub type WriterId = u32;
@@ -247,11 +558,99 @@ pub struct Subscription {
 pub struct Matcher {
     #[serde(flatten)]
     pub tables: MatchTables,
-    // TODO: make this work with query::Predicate
+    /// A predicate that rows must match, in the form parsed by
+    /// `RowPredicate`, for example `region = 'west' AND usage > 90`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub predicate: Option<String>,
 }
 
+impl Matcher {
+    /// Returns true if this matcher matches every row of every table
+    pub fn matches_all(&self) -> bool {
+        self.tables == MatchTables::All && self.predicate.is_none()
+    }
+
+    /// Compiles the table regex and the predicate of this matcher so it can be
+    /// evaluated against writes.
+    pub fn compile(&self) -> Result<CompiledMatcher> {
+        let tables = match &self.tables {
+            MatchTables::All => CompiledMatchTables::All,
+            MatchTables::Table(name) => CompiledMatchTables::Table(name.clone()),
+            MatchTables::Regex(regex) => {
+                CompiledMatchTables::Regex(Regex::new(regex).context(InvalidTableRegex { regex })?)
+            }
+        };
+
+        let predicate = self
+            .predicate
+            .as_ref()
+            .map(|p| p.parse::<RowPredicate>())
+            .transpose()
+            .context(InvalidMatcherPredicate)?;
+
+        Ok(CompiledMatcher { tables, predicate })
+    }
+}
+
+/// `CompiledMatcher` is a `Matcher` that is ready to be evaluated against the
+/// tables and rows of a write. It is used as a `WriteFilter` to split out the
+/// part of a replicated write that a subscriber is interested in.
+#[derive(Debug, Clone)]
+pub struct CompiledMatcher {
+    tables: CompiledMatchTables,
+    predicate: Option<RowPredicate>,
+}
+
+#[derive(Debug, Clone)]
+enum CompiledMatchTables {
+    All,
+    Table(String),
+    Regex(Regex),
+}
+
+impl CompiledMatcher {
+    /// Returns true if rows of the table can match
+    pub fn matches_table(&self, table_name: &str) -> bool {
+        let table_matches = match &self.tables {
+            CompiledMatchTables::All => true,
+            CompiledMatchTables::Table(name) => name == table_name,
+            CompiledMatchTables::Regex(regex) => regex.is_match(table_name),
+        };
+
+        table_matches
+            && self
+                .predicate
+                .as_ref()
+                .map(|p| p.matches_table(table_name))
+                .unwrap_or(true)
+    }
+
+    /// Returns true if the row of the table matches
+    pub fn matches_row(&self, table_name: &str, row: &wb::Row<'_>) -> bool {
+        self.predicate
+            .as_ref()
+            .map(|p| p.matches_row(table_name, row))
+            .unwrap_or(true)
+    }
+}
+
+impl WriteFilter for CompiledMatcher {
+    fn keep_table(&self, table_name: &str) -> bool {
+        self.matches_table(table_name)
+    }
+
+    fn keep_row(&self, table_name: &str, row: &wb::Row<'_>) -> bool {
+        self.matches_row(table_name, row)
+    }
+
+    // deletes from all tables might remove matching rows
+    fn keep_delete(&self, delete: &wb::WriteBufferDelete<'_>) -> bool {
+        delete
+            .table_name()
+            .map_or(true, |table_name| self.matches_table(table_name))
+    }
+}
+
 /// `MatchTables` looks at the table name of a row to determine if it should
 /// match the rule.
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
@@ -275,7 +674,9 @@ pub struct HostGroup {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data::{filtered_replicated_write, lines_to_replicated_write};
     use influxdb_line_protocol::parse_lines;
+    use std::collections::BTreeSet;
 
     #[allow(dead_code)]
     type TestError = Box<dyn std::error::Error + Send + Sync + 'static>;
@@ -432,6 +833,290 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn partition_template_validation() {
+        let template = PartitionTemplate {
+            parts: vec![
+                TemplatePart::Table,
+                TemplatePart::Column("region".to_string()),
+                TemplatePart::TimeFormat("%Y-%m-%d %H:00:00".to_string()),
+            ],
+        };
+        template.validate().unwrap();
+
+        let template = PartitionTemplate {
+            parts: vec![TemplatePart::TimeFormat("%Y-%Q".to_string())],
+        };
+        assert!(matches!(
+            template.validate(),
+            Err(Error::InvalidTimeFormat { .. })
+        ));
+
+        let template = PartitionTemplate {
+            parts: vec![TemplatePart::RegexCapture(RegexCapture {
+                column: "url".to_string(),
+                regex: "^https?://([^/]+".to_string(),
+            })],
+        };
+        assert!(matches!(
+            template.validate(),
+            Err(Error::InvalidTemplateRegex { .. })
+        ));
+
+        let template = PartitionTemplate {
+            parts: vec![TemplatePart::StrftimeColumn(StrftimeColumn {
+                column: "started".to_string(),
+                format: "%Y-%Q".to_string(),
+            })],
+        };
+        assert!(matches!(
+            template.validate(),
+            Err(Error::InvalidTimeFormat { .. })
+        ));
+
+        // writes with invalid rules fail instead of panicking
+        let rules = DatabaseRules {
+            partition_template: template,
+            ..Default::default()
+        };
+        assert!(matches!(
+            rules.validate(),
+            Err(Error::InvalidTimeFormat { .. })
+        ));
+        let lines = parsed_lines("cpu started=1i 10");
+        assert!(matches!(
+            lines_to_replicated_write(1, 1, &lines, &rules),
+            Err(Error::InvalidTimeFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn partition_key_with_regex_capture() -> Result {
+        let template = PartitionTemplate {
+            parts: vec![
+                TemplatePart::Table,
+                TemplatePart::RegexCapture(RegexCapture {
+                    column: "url".to_string(),
+                    regex: "^https?://([^/.]+)\\.([^/]+)".to_string(),
+                }),
+                TemplatePart::RegexCapture(RegexCapture {
+                    column: "path".to_string(),
+                    regex: "^/[a-z]+".to_string(),
+                }),
+            ],
+        };
+
+        let line =
+            parse_line(r#"requests,url=https://api.example.com path="/users/1",status=200i 10"#);
+        assert_eq!(
+            "requests-url_api_example.com-path__users",
+            template.partition_key(&line, &Utc::now()).unwrap()
+        );
+
+        // missing columns, values that don't match and non string fields
+        // are blank
+        let line = parse_line("requests,url=ftp://example.com path=1i 10");
+        assert_eq!(
+            "requests--",
+            template.partition_key(&line, &Utc::now()).unwrap()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn partition_key_with_hash_bucket() -> Result {
+        let template = PartitionTemplate {
+            parts: vec![
+                TemplatePart::Table,
+                TemplatePart::HashBucket(HashBucket {
+                    columns: vec!["host".to_string()],
+                    buckets: 16,
+                }),
+            ],
+        };
+
+        let key = |lp: &str| {
+            template
+                .partition_key(&parse_line(lp), &Utc::now())
+                .unwrap()
+        };
+
+        // the bucket only depends on the values of the columns
+        let a = key("cpu,host=a usage=1 10");
+        assert!(a.starts_with("cpu-host_bucket_"));
+        assert_eq!(a.len(), "cpu-host_bucket_00".len());
+        assert_eq!(a, key("cpu,host=a,region=west usage=2 20"));
+        assert_eq!(key("cpu usage=1 10"), "cpu-");
+
+        // the buckets must never change, or rows move to other partitions
+        assert_eq!(key("cpu,host=a usage=1 10"), "cpu-host_bucket_05");
+        assert_eq!(key("cpu,host=b usage=1 10"), "cpu-host_bucket_15");
+        assert_eq!(key("cpu,host=server01 usage=1 10"), "cpu-host_bucket_03");
+        assert_eq!(key("cpu host=7i 10"), "cpu-host_bucket_01");
+        assert_eq!(key("cpu host=1.5 10"), "cpu-host_bucket_03");
+        assert_eq!(key("cpu host=true 10"), "cpu-host_bucket_13");
+        // a string field hashes like a tag with the same value
+        assert_eq!(key(r#"cpu host="a" 10"#), "cpu-host_bucket_05");
+
+        let keys: BTreeSet<_> = (0..1000)
+            .map(|i| key(&format!("cpu,host=host{} usage=1 10", i)))
+            .collect();
+        assert_eq!(keys.len(), 16);
+
+        let template = PartitionTemplate {
+            parts: vec![TemplatePart::HashBucket(HashBucket {
+                columns: vec!["host".to_string(), "region".to_string()],
+                buckets: 1,
+            })],
+        };
+        assert_eq!(
+            "host_region_bucket_0",
+            template.partition_key(&parse_line("cpu,host=a usage=1 10"), &Utc::now())?
+        );
+
+        let template = PartitionTemplate {
+            parts: vec![TemplatePart::HashBucket(HashBucket {
+                columns: vec!["host".to_string(), "region".to_string()],
+                buckets: 16,
+            })],
+        };
+        let key = |lp: &str| {
+            template
+                .partition_key(&parse_line(lp), &Utc::now())
+                .unwrap()
+        };
+        assert_eq!(
+            key("cpu,host=a,region=west usage=1 10"),
+            "host_region_bucket_11"
+        );
+        assert_eq!(key("cpu,host=a usage=1 10"), "host_region_bucket_04");
+
+        let template = PartitionTemplate {
+            parts: vec![TemplatePart::HashBucket(HashBucket {
+                columns: vec!["host".to_string()],
+                buckets: 100,
+            })],
+        };
+        assert_eq!(
+            "host_bucket_69",
+            template.partition_key(&parse_line("cpu,host=a usage=1 10"), &Utc::now())?
+        );
+
+        let template = PartitionTemplate {
+            parts: vec![TemplatePart::HashBucket(HashBucket {
+                columns: vec!["host".to_string()],
+                buckets: 0,
+            })],
+        };
+        assert!(matches!(
+            template.validate(),
+            Err(Error::InvalidHashBucket { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn partition_key_with_strftime_column() -> Result {
+        let template = PartitionTemplate {
+            parts: vec![TemplatePart::StrftimeColumn(StrftimeColumn {
+                column: "started".to_string(),
+                format: "%Y-%m-%d".to_string(),
+            })],
+        };
+
+        let line = parse_line("jobs started=1602338097000000000i 10");
+        assert_eq!(
+            "2020-10-10",
+            template.partition_key(&line, &Utc::now()).unwrap()
+        );
+
+        let line = parse_line("jobs started=1.5 10");
+        assert_eq!("", template.partition_key(&line, &Utc::now()).unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn matcher_compile_errors() {
+        let matcher = Matcher {
+            tables: MatchTables::Regex("cpu(".to_string()),
+            predicate: None,
+        };
+        assert!(matches!(
+            matcher.compile(),
+            Err(Error::InvalidTableRegex { .. })
+        ));
+
+        let matcher = Matcher {
+            tables: MatchTables::All,
+            predicate: Some("region = ".to_string()),
+        };
+        assert!(matches!(
+            matcher.compile(),
+            Err(Error::InvalidMatcherPredicate { .. })
+        ));
+    }
+
+    #[test]
+    fn matcher_filters_write() -> Result {
+        let lines = parsed_lines(
+            "cpu,region=west user=23.2 10\n\
+             cpu,region=east user=10.1 10\n\
+             mem,region=west free=1i 10\n\
+             disk,region=west bytes=3i 10",
+        );
+        let write = lines_to_replicated_write(3, 7, &lines, &DatabaseRules::default())?;
+
+        let matcher = Matcher {
+            tables: MatchTables::Table("cpu".to_string()),
+            predicate: None,
+        }
+        .compile()?;
+        let filtered = filtered_replicated_write(&write, &matcher).unwrap();
+        assert_eq!(filtered.writer_and_sequence(), (3, 7));
+        let filtered = filtered.to_string();
+        assert!(filtered.contains("table:cpu"));
+        assert!(!filtered.contains("table:mem"));
+        assert!(!filtered.contains("table:disk"));
+
+        let matcher = Matcher {
+            tables: MatchTables::Regex("^(mem|disk)$".to_string()),
+            predicate: None,
+        }
+        .compile()?;
+        let filtered = filtered_replicated_write(&write, &matcher)
+            .unwrap()
+            .to_string();
+        assert!(!filtered.contains("table:cpu"));
+        assert!(filtered.contains("table:mem"));
+        assert!(filtered.contains("table:disk"));
+
+        let matcher = Matcher {
+            tables: MatchTables::All,
+            predicate: Some("region = 'west' AND _measurement != 'disk'".to_string()),
+        }
+        .compile()?;
+        let filtered = filtered_replicated_write(&write, &matcher)
+            .unwrap()
+            .to_string();
+        assert!(filtered.contains("table:cpu"));
+        assert!(filtered.contains("west"));
+        assert!(!filtered.contains("east"));
+        assert!(filtered.contains("table:mem"));
+        assert!(!filtered.contains("table:disk"));
+
+        let matcher = Matcher {
+            tables: MatchTables::Table("swap".to_string()),
+            predicate: None,
+        }
+        .compile()?;
+        assert!(filtered_replicated_write(&write, &matcher).is_none());
+
+        Ok(())
+    }
+
     fn parsed_lines(lp: &str) -> Vec<ParsedLine<'_>> {
         parse_lines(lp).map(|l| l.unwrap()).collect()
     }
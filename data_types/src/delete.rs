@@ -0,0 +1,128 @@
+//! This module contains the deletes that can be sent to a database. A delete
+//! removes the rows with a time in a range that match a `RowPredicate`, like
+//! the InfluxDB 2 delete API does. It is replicated like a write and applies
+//! to the rows that were written before it.
+
+use crate::row_predicate::{RowPredicate, RowValue};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delete {
+    /// The table to delete rows from. Rows are deleted from all tables if
+    /// this isn't set.
+    pub table_name: Option<String>,
+
+    /// The time of the first row to delete, in nanoseconds since the epoch
+    pub start: i64,
+
+    /// The time of the last row to delete, inclusive
+    pub stop: i64,
+
+    /// The predicate the rows have to match to be deleted. All rows in the
+    /// time range are deleted if this isn't set.
+    pub predicate: Option<RowPredicate>,
+}
+
+impl Delete {
+    /// Returns true if the delete might remove rows from the table
+    pub fn matches_table(&self, table_name: &str) -> bool {
+        self.table_name.as_deref().map_or(true, |t| t == table_name)
+            && self
+                .predicate
+                .as_ref()
+                .map_or(true, |p| p.matches_table(table_name))
+    }
+
+    /// Returns true if the delete removes the row of the table with the
+    /// specified time, where `lookup` returns the value of a column in the
+    /// row.
+    pub fn matches<'a>(
+        &self,
+        table_name: &'a str,
+        time: i64,
+        lookup: impl Fn(&str) -> Option<RowValue<'a>>,
+    ) -> bool {
+        self.table_name.as_deref().map_or(true, |t| t == table_name)
+            && self.start <= time
+            && time <= self.stop
+            && self
+                .predicate
+                .as_ref()
+                .map_or(true, |p| p.matches(table_name, lookup))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{
+        delete_to_replicated_write, filtered_replicated_write, partitioned_replicated_write,
+    };
+    use crate::database_rules::{MatchTables, Matcher};
+
+    #[test]
+    fn matches_rows_in_time_range() {
+        let delete = Delete {
+            table_name: None,
+            start: 10,
+            stop: 20,
+            predicate: Some(r#"_measurement="cpu" AND host="a""#.parse().unwrap()),
+        };
+        let host = |host| {
+            move |column: &str| match column {
+                "host" => Some(RowValue::Tag(host)),
+                _ => None,
+            }
+        };
+
+        assert!(delete.matches("cpu", 10, host("a")));
+        assert!(delete.matches("cpu", 20, host("a")));
+        assert!(!delete.matches("cpu", 9, host("a")));
+        assert!(!delete.matches("cpu", 21, host("a")));
+        assert!(!delete.matches("cpu", 15, host("b")));
+        assert!(!delete.matches("mem", 15, host("a")));
+
+        assert!(delete.matches_table("cpu"));
+        assert!(!delete.matches_table("mem"));
+
+        let delete = Delete {
+            table_name: Some("mem".to_string()),
+            start: i64::MIN,
+            stop: i64::MAX,
+            predicate: None,
+        };
+        assert!(delete.matches("mem", 0, |_| None));
+        assert!(!delete.matches("cpu", 0, |_| None));
+        assert!(!delete.matches_table("cpu"));
+    }
+
+    #[test]
+    fn replicated_write_round_trip() {
+        let delete = Delete {
+            table_name: Some("cpu".to_string()),
+            start: 10,
+            stop: 20,
+            predicate: Some(r#"host="a""#.parse().unwrap()),
+        };
+        let write = delete_to_replicated_write(1, 2, &delete);
+
+        assert_eq!(write.writer_and_sequence(), (1, 2));
+        assert!(write.has_deletes());
+        assert!(write.partition_keys().is_empty());
+        assert_eq!(write.deletes().unwrap(), vec![delete.clone()]);
+
+        // deletes are kept regardless of the partition, but not for tables
+        // a subscriber isn't interested in
+        let filtered = partitioned_replicated_write(&write, |_| false).unwrap();
+        assert_eq!(filtered.deletes().unwrap(), vec![delete]);
+
+        let matcher = Matcher {
+            tables: MatchTables::Table("mem".to_string()),
+            predicate: None,
+        }
+        .compile()
+        .unwrap();
+        assert!(filtered_replicated_write(&write, &matcher).is_none());
+    }
+}
@@ -11,10 +11,30 @@
 
 pub const TIME_COLUMN_NAME: &str = "time";
 
+/// The IOx column type of tag columns in Arrow schema metadata
+pub const TAG_COLUMN_TYPE: &str = "tag";
+
+/// The IOx column type of field columns in Arrow schema metadata
+pub const FIELD_COLUMN_TYPE: &str = "field";
+
+/// The prefix of the Arrow schema metadata keys made by
+/// `column_type_metadata_key`
+pub const COLUMN_TYPE_METADATA_PREFIX: &str = "iox::column_type::";
+
+/// Returns the key of the Arrow schema metadata entry that holds the IOx
+/// column type, `TAG_COLUMN_TYPE` or `FIELD_COLUMN_TYPE`, of a column. Tags
+/// and string fields are both Utf8 in Arrow, so this is how they are told
+/// apart when record batches are turned back into IOx columns.
+pub fn column_type_metadata_key(column_name: &str) -> String {
+    format!("{}{}", COLUMN_TYPE_METADATA_PREFIX, column_name)
+}
+
 pub mod data;
 pub mod database_rules;
+pub mod delete;
 pub mod error;
 pub mod partition_metadata;
+pub mod row_predicate;
 pub mod table_schema;
 
 mod database_name;
@@ -1,7 +1,10 @@
 //! This module contains structs that describe the metadata for a partition
 //! including schema, summary statistics, and file locations in storage.
 
-use std::fmt::{Debug, Display};
+use std::{
+    collections::BTreeMap,
+    fmt::{Debug, Display},
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -14,17 +17,32 @@ pub struct Partition {
     pub key: String,
     /// The tables in this partition
     pub tables: Vec<Table>,
+    /// The largest sequence of the writes in this partition, by writer id.
+    /// Writes from a writer up to its sequence don't have to be replayed into
+    /// the partition.
+    #[serde(default)]
+    pub max_sequences: BTreeMap<u32, u64>,
 }
 
 /// Metadata and statistics information for a table.
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
+    /// Statistics of the timestamps of the table's rows, if it has any
+    #[serde(default)]
+    pub time: Option<Statistics<i64>>,
+}
+
+impl Table {
+    /// Returns the largest timestamp of the table's rows, if it has any
+    pub fn max_time(&self) -> Option<i64> {
+        self.time.as_ref().map(|time| time.max)
+    }
 }
 
 /// Statistics and type information for a column.
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub enum Column {
     I64(Statistics<i64>),
     U64(Statistics<u64>),
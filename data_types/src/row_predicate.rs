@@ -0,0 +1,488 @@
+//! This module contains a simple predicate that can be evaluated against the
+//! rows of a replicated write. It is used to match rows for subscriptions.
+//!
+//! The predicate syntax follows the one used by the InfluxDB 2 delete API: a
+//! list of comparisons between a column and a literal joined by `AND`, for
+//! example `_measurement="cpu" AND host="a" AND usage_user > 90`. The special
+//! column `_measurement` refers to the table name.
+
+use generated_types::wal as wb;
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, OptionExt, Snafu};
+
+/// The name of the column that can be used in predicates to match on the
+/// table name.
+pub const MEASUREMENT_COLUMN_NAME: &str = "_measurement";
+
+#[derive(Debug, Snafu, Clone, PartialEq)]
+pub enum Error {
+    #[snafu(display("Error parsing predicate '{}': {}", predicate, message))]
+    ParsingPredicate { predicate: String, message: String },
+
+    #[snafu(display("Empty predicate"))]
+    EmptyPredicate,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A `RowPredicate` is a conjunction of comparisons. A row matches if all of
+/// the comparisons are true for it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RowPredicate {
+    pub comparisons: Vec<Comparison>,
+}
+
+/// A comparison of the value of a column with a literal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Comparison {
+    pub column: String,
+    pub op: Operator,
+    pub value: Scalar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+/// A literal value in a predicate
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Scalar {
+    String(String),
+    I64(i64),
+    F64(f64),
+    Bool(bool),
+}
+
+/// A borrowed value from a row that a comparison can be evaluated against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RowValue<'a> {
+    Tag(&'a str),
+    String(&'a str),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+}
+
+impl<'a> RowValue<'a> {
+    /// Returns the row value for a Flatbuffers value, or `None` if the value
+    /// has no type.
+    pub fn from_fb(value: &wb::Value<'a>) -> Option<Self> {
+        Some(match value.value_type() {
+            wb::ColumnValue::TagValue => Self::Tag(value.value_as_tag_value()?.value()?),
+            wb::ColumnValue::StringValue => Self::String(value.value_as_string_value()?.value()?),
+            wb::ColumnValue::I64Value => Self::I64(value.value_as_i64value()?.value()),
+            wb::ColumnValue::U64Value => Self::U64(value.value_as_u64value()?.value()),
+            wb::ColumnValue::F64Value => Self::F64(value.value_as_f64value()?.value()),
+            wb::ColumnValue::BoolValue => Self::Bool(value.value_as_bool_value()?.value()),
+            wb::ColumnValue::NONE => return None,
+        })
+    }
+}
+
+impl RowPredicate {
+    /// Returns true if the comparisons against the table name are true. Rows
+    /// in tables that don't match never match the predicate.
+    pub fn matches_table(&self, table_name: &str) -> bool {
+        self.comparisons
+            .iter()
+            .filter(|c| c.column == MEASUREMENT_COLUMN_NAME)
+            .all(|c| c.evaluate(Some(RowValue::String(table_name))))
+    }
+
+    /// Returns true if all comparisons are true for the row
+    pub fn matches_row(&self, table_name: &str, row: &wb::Row<'_>) -> bool {
+        self.matches(table_name, |column| {
+            row.values()?
+                .into_iter()
+                .find(|v| v.column() == Some(column))
+                .and_then(|v| RowValue::from_fb(&v))
+        })
+    }
+
+    /// Returns true if all comparisons are true for a row in the table where
+    /// `lookup` returns the value of a column in the row.
+    pub fn matches<'a>(
+        &self,
+        table_name: &'a str,
+        lookup: impl Fn(&str) -> Option<RowValue<'a>>,
+    ) -> bool {
+        self.comparisons.iter().all(|c| {
+            let value = if c.column == MEASUREMENT_COLUMN_NAME {
+                Some(RowValue::String(table_name))
+            } else {
+                lookup(&c.column)
+            };
+
+            c.evaluate(value)
+        })
+    }
+}
+
+impl Comparison {
+    /// Evaluates the comparison against the value of the column in a row. A
+    /// missing value or a value of a different type is not equal to the
+    /// literal and can't be ordered against it.
+    pub fn evaluate(&self, value: Option<RowValue<'_>>) -> bool {
+        match value.and_then(|v| compare(v, &self.value)) {
+            Some(ordering) => match self.op {
+                Operator::Equal => ordering == Ordering::Equal,
+                Operator::NotEqual => ordering != Ordering::Equal,
+                Operator::Less => ordering == Ordering::Less,
+                Operator::LessEqual => ordering != Ordering::Greater,
+                Operator::Greater => ordering == Ordering::Greater,
+                Operator::GreaterEqual => ordering != Ordering::Less,
+            },
+            None => self.op == Operator::NotEqual,
+        }
+    }
+}
+
+// compares a row value with a literal, returning None if they can't be
+// compared
+fn compare(value: RowValue<'_>, literal: &Scalar) -> Option<Ordering> {
+    match (value, literal) {
+        (RowValue::Tag(v), Scalar::String(l)) | (RowValue::String(v), Scalar::String(l)) => {
+            Some(v.cmp(l.as_str()))
+        }
+        (RowValue::I64(v), Scalar::I64(l)) => Some(v.cmp(l)),
+        (RowValue::U64(v), Scalar::I64(l)) => Some(if *l < 0 {
+            Ordering::Greater
+        } else {
+            v.cmp(&(*l as u64))
+        }),
+        (RowValue::F64(v), Scalar::I64(l)) => v.partial_cmp(&(*l as f64)),
+        (RowValue::I64(v), Scalar::F64(l)) => (v as f64).partial_cmp(l),
+        (RowValue::U64(v), Scalar::F64(l)) => (v as f64).partial_cmp(l),
+        (RowValue::F64(v), Scalar::F64(l)) => v.partial_cmp(l),
+        (RowValue::Bool(v), Scalar::Bool(l)) => Some(v.cmp(l)),
+        _ => None,
+    }
+}
+
+impl FromStr for RowPredicate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            predicate: s,
+            chars: s.char_indices().peekable(),
+        };
+
+        let mut comparisons = vec![parser.comparison()?];
+        while parser.and()? {
+            comparisons.push(parser.comparison()?);
+        }
+
+        Ok(Self { comparisons })
+    }
+}
+
+impl fmt::Display for RowPredicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, c) in self.comparisons.iter().enumerate() {
+            if i > 0 {
+                write!(f, " AND ")?;
+            }
+            write!(f, "{}", c)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_quoted(f, &self.column, '"')?;
+        write!(f, "{}{}", self.op, self.value)
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Self::Equal => "=",
+            Self::NotEqual => "!=",
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterEqual => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(v) => write_quoted(f, v, '\''),
+            Self::I64(v) => write!(f, "{}", v),
+            Self::F64(v) => write!(f, "{:?}", v),
+            Self::Bool(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+// writes the string in quotes the parser reads back, escaping the quote and
+// backslashes with a backslash
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &str, quote: char) -> fmt::Result {
+    write!(f, "{}", quote)?;
+    for c in s.chars() {
+        if c == quote || c == '\\' {
+            write!(f, "\\")?;
+        }
+        write!(f, "{}", c)?;
+    }
+    write!(f, "{}", quote)
+}
+
+struct Parser<'a> {
+    predicate: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn comparison(&mut self) -> Result<Comparison> {
+        self.skip_whitespace();
+        ensure!(self.chars.peek().is_some(), EmptyPredicate);
+
+        let column = match self.chars.peek() {
+            Some((_, '"')) => self.quoted()?,
+            _ => self.word(),
+        };
+        if column.is_empty() {
+            return self.error("expected column name");
+        }
+
+        self.skip_whitespace();
+        let op = self.operator()?;
+        self.skip_whitespace();
+        let value = self.scalar()?;
+
+        Ok(Comparison { column, op, value })
+    }
+
+    // consumes an `AND` between comparisons, returning false at the end of the
+    // predicate
+    fn and(&mut self) -> Result<bool> {
+        self.skip_whitespace();
+        if self.chars.peek().is_none() {
+            return Ok(false);
+        }
+
+        let word = self.word();
+        if !word.eq_ignore_ascii_case("and") {
+            return self.error(format!("expected AND, got '{}'", word));
+        }
+
+        Ok(true)
+    }
+
+    fn operator(&mut self) -> Result<Operator> {
+        let first = self.chars.next().map(|(_, c)| c);
+        let equals_next = matches!(self.chars.peek(), Some((_, '=')));
+
+        let op = match (first, equals_next) {
+            (Some('='), _) => Operator::Equal,
+            (Some('!'), true) => Operator::NotEqual,
+            (Some('<'), true) => Operator::LessEqual,
+            (Some('<'), false) => Operator::Less,
+            (Some('>'), true) => Operator::GreaterEqual,
+            (Some('>'), false) => Operator::Greater,
+            _ => return self.error("expected one of =, !=, <, <=, >, >="),
+        };
+
+        if equals_next && first != Some('=') {
+            self.chars.next();
+        }
+
+        Ok(op)
+    }
+
+    fn scalar(&mut self) -> Result<Scalar> {
+        match self.chars.peek() {
+            Some((_, '"')) | Some((_, '\'')) => Ok(Scalar::String(self.quoted()?)),
+            Some(_) => {
+                let word = self.word();
+                if word == "true" {
+                    Ok(Scalar::Bool(true))
+                } else if word == "false" {
+                    Ok(Scalar::Bool(false))
+                } else if let Ok(v) = word.parse::<i64>() {
+                    Ok(Scalar::I64(v))
+                } else if let Ok(v) = word.parse::<f64>() {
+                    Ok(Scalar::F64(v))
+                } else {
+                    self.error(format!("invalid literal '{}'", word))
+                }
+            }
+            None => self.error("expected value"),
+        }
+    }
+
+    // reads a string quoted with the character at the current position,
+    // allowing the quote to be escaped with a backslash
+    fn quoted(&mut self) -> Result<String> {
+        let (_, quote) = self.chars.next().context(EmptyPredicate)?;
+        let mut value = String::new();
+
+        loop {
+            match self.chars.next() {
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, c)) => value.push(c),
+                    None => break,
+                },
+                Some((_, c)) if c == quote => return Ok(value),
+                Some((_, c)) => value.push(c),
+                None => break,
+            }
+        }
+
+        self.error("unterminated string")
+    }
+
+    // reads characters up to whitespace, an operator or a quote
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some((_, c)) = self.chars.peek() {
+            if c.is_whitespace() || matches!(c, '=' | '!' | '<' | '>' | '"' | '\'') {
+                break;
+            }
+            word.push(*c);
+            self.chars.next();
+        }
+        word
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some((_, c)) if c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn error<T>(&mut self, message: impl Into<String>) -> Result<T> {
+        let position = self
+            .chars
+            .peek()
+            .map(|(i, _)| *i)
+            .unwrap_or_else(|| self.predicate.len());
+
+        ParsingPredicate {
+            predicate: self.predicate,
+            message: format!("{} at position {}", message.into(), position),
+        }
+        .fail()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_predicate() {
+        let predicate: RowPredicate = r#"_measurement="cpu" AND "host name" != 'a\'b' and usage_user>=90.5 AND count < 3 AND up=true"#
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            predicate.comparisons,
+            vec![
+                Comparison {
+                    column: "_measurement".to_string(),
+                    op: Operator::Equal,
+                    value: Scalar::String("cpu".to_string()),
+                },
+                Comparison {
+                    column: "host name".to_string(),
+                    op: Operator::NotEqual,
+                    value: Scalar::String("a'b".to_string()),
+                },
+                Comparison {
+                    column: "usage_user".to_string(),
+                    op: Operator::GreaterEqual,
+                    value: Scalar::F64(90.5),
+                },
+                Comparison {
+                    column: "count".to_string(),
+                    op: Operator::Less,
+                    value: Scalar::I64(3),
+                },
+                Comparison {
+                    column: "up".to_string(),
+                    op: Operator::Equal,
+                    value: Scalar::Bool(true),
+                },
+            ]
+        );
+
+        let round_trip: RowPredicate = predicate.to_string().parse().unwrap();
+        assert_eq!(predicate, round_trip);
+    }
+
+    #[test]
+    fn display_quotes_and_escapes() {
+        let predicate = RowPredicate {
+            comparisons: vec![Comparison {
+                column: "say \"hi\"\\\n".to_string(),
+                op: Operator::Equal,
+                value: Scalar::String("it's ünïcode\n".to_string()),
+            }],
+        };
+
+        let text = predicate.to_string();
+        assert_eq!(text, "\"say \\\"hi\\\"\\\\\n\"='it\\'s ünïcode\n'");
+
+        let round_trip: RowPredicate = text.parse().unwrap();
+        assert_eq!(predicate, round_trip);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "".parse::<RowPredicate>().unwrap_err(),
+            Error::EmptyPredicate
+        );
+        assert!("host".parse::<RowPredicate>().is_err());
+        assert!("host=".parse::<RowPredicate>().is_err());
+        assert!(r#"host="a"#.parse::<RowPredicate>().is_err());
+        assert!("host=a".parse::<RowPredicate>().is_err());
+        assert!(r#"host="a" OR host="b""#.parse::<RowPredicate>().is_err());
+        assert!(r#"host="a" AND"#.parse::<RowPredicate>().is_err());
+    }
+
+    #[test]
+    fn evaluate_comparisons() {
+        let predicate: RowPredicate =
+            r#"_measurement="cpu" AND host!="b" AND usage>10"#.parse().unwrap();
+
+        let row = |host, usage| {
+            move |column: &str| match column {
+                "host" => Some(RowValue::Tag(host)),
+                "usage" => usage,
+                _ => None,
+            }
+        };
+
+        assert!(predicate.matches("cpu", row("a", Some(RowValue::F64(10.5)))));
+        assert!(predicate.matches("cpu", row("a", Some(RowValue::I64(11)))));
+        assert!(predicate.matches("cpu", row("a", Some(RowValue::U64(11)))));
+        assert!(!predicate.matches("cpu", row("a", Some(RowValue::I64(10)))));
+        assert!(!predicate.matches("cpu", row("b", Some(RowValue::I64(11)))));
+        assert!(!predicate.matches("mem", row("a", Some(RowValue::I64(11)))));
+        assert!(!predicate.matches("cpu", row("a", None)));
+        assert!(!predicate.matches("cpu", row("a", Some(RowValue::String("11")))));
+
+        assert!(predicate.matches_table("cpu"));
+        assert!(!predicate.matches_table("mem"));
+    }
+}
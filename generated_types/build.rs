@@ -22,8 +22,8 @@ fn main() -> Result<()> {
 
 /// Schema used with IOx specific gRPC requests
 ///
-/// Creates `influxdata.platform.storage.rs` and
-/// `com.github.influxdata.idpe.storage.read.rs`
+/// Creates `influxdata.platform.storage.rs`,
+/// `com.github.influxdata.idpe.storage.read.rs` and `grpc.health.v1.rs`
 fn generate_grpc_types(root: &Path) -> Result<()> {
     let proto_files = vec![
         root.join("test.proto"),
@@ -32,6 +32,9 @@ fn generate_grpc_types(root: &Path) -> Result<()> {
         root.join("storage_common_idpe.proto"),
         root.join("service.proto"),
         root.join("source.proto"),
+        root.join("replication.proto"),
+        root.join("query.proto"),
+        root.join("health.proto"),
     ];
 
     // Tell cargo to recompile if any of these proto files are changed
@@ -15,6 +15,11 @@ include!(concat!(
 ));
 include!(concat!(env!("OUT_DIR"), "/wal_generated.rs"));
 
+/// The standard gRPC health checking protocol
+pub mod health {
+    include!(concat!(env!("OUT_DIR"), "/grpc.health.v1.rs"));
+}
+
 // Can't implement `Default` because `prost::Message` implements `Default`
 impl TimestampRange {
     pub fn max() -> Self {
@@ -9,7 +9,7 @@ use arrow_deps::{
 };
 use chrono::{DateTime, Utc};
 use generated_types::wal as wb;
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{hash_map::Entry, BTreeSet, HashMap, HashSet};
 
 use data_types::{partition_metadata::Table as TableStats, TIME_COLUMN_NAME};
 use query::{
@@ -79,6 +79,11 @@ pub struct Chunk {
 
     /// map of the dictionary ID for the table name to the table
     pub tables: HashMap<u32, Table>,
+
+    /// The estimated size of `tables` in memory, in bytes, which is updated
+    /// as rows are written, so it doesn't have to be computed for every
+    /// write
+    tables_size: usize,
 }
 
 /// Describes the result of translating a set of strings into
@@ -182,6 +187,7 @@ impl Chunk {
             id,
             dictionary: Dictionary::new(),
             tables: HashMap::new(),
+            tables_size: 0,
             time_of_first_write: None,
             time_of_last_write: None,
             time_closed: None,
@@ -206,20 +212,46 @@ impl Chunk {
 
     fn write_table_batch(&mut self, batch: &wb::TableWriteBatch<'_>) -> Result<()> {
         let table_name = batch.name().context(TableWriteWithoutName)?;
+
+        self.update_table(table_name, |table, dictionary| match batch.rows() {
+            Some(rows) => table.append_rows(dictionary, &rows),
+            None => Ok(()),
+        })
+    }
+
+    /// Appends the rows of an Arrow record batch to the named table, for
+    /// example to load a table that was snapshotted to Parquet. Utf8 columns
+    /// are stored as tags, as Arrow doesn't distinguish them from string
+    /// fields.
+    pub fn write_record_batch(&mut self, table_name: &str, batch: &RecordBatch) -> Result<()> {
+        self.update_table(table_name, |table, dictionary| {
+            table.append_record_batch(dictionary, batch)
+        })
+    }
+
+    // applies `update` to the named table, which is created if it doesn't
+    // exist yet, and accounts for the memory the update allocated
+    fn update_table(
+        &mut self,
+        table_name: &str,
+        update: impl FnOnce(&mut Table, &mut Dictionary) -> Result<(), crate::table::Error>,
+    ) -> Result<()> {
         let table_id = self.dictionary.lookup_value_or_insert(table_name);
 
-        let table = self
-            .tables
-            .entry(table_id)
-            .or_insert_with(|| Table::new(table_id));
+        let (table, size_before) = match self.tables.entry(table_id) {
+            Entry::Occupied(entry) => {
+                let table = entry.into_mut();
+                let size = table.size();
+                (table, size)
+            }
+            Entry::Vacant(entry) => (entry.insert(Table::new(table_id)), 0),
+        };
 
-        if let Some(rows) = batch.rows() {
-            table
-                .append_rows(&mut self.dictionary, &rows)
-                .context(TableWrite { table_name })?;
-        }
+        // rows written before an error are kept, so they are accounted for
+        let result = update(table, &mut self.dictionary);
+        self.tables_size = self.tables_size + table.size() - size_before;
 
-        Ok(())
+        result.context(TableWrite { table_name })
     }
 
     /// Mark the chunk as closed
@@ -338,6 +370,12 @@ impl Chunk {
         self.id
     }
 
+    /// Returns the estimated size of the data in this chunk, including its
+    /// dictionary, in bytes
+    pub fn size(&self) -> usize {
+        self.dictionary.size() + self.tables_size
+    }
+
     /// Convert the table specified in this chunk into some number of
     /// record batches, appended to dst
     pub fn table_to_arrow(
@@ -359,6 +397,7 @@ impl Chunk {
     /// Returns a vec of the summary statistics of the tables in this chunk
     pub fn table_stats(&self) -> Result<Vec<TableStats>> {
         let mut stats = Vec::with_capacity(self.tables.len());
+        let time_column_id = self.dictionary.id(TIME_COLUMN_NAME);
 
         for (id, table) in &self.tables {
             let name = self
@@ -370,10 +409,12 @@ impl Chunk {
                 })?;
 
             let columns = table.stats();
+            let time = time_column_id.and_then(|id| table.time_stats(id));
 
             stats.push(TableStats {
                 name: name.to_string(),
                 columns,
+                time,
             });
         }
 
@@ -432,6 +473,28 @@ impl query::PartitionChunk for Chunk {
     ) -> Result<(), Self::Error> {
         self.table_to_arrow(dst, table_name, columns)
     }
+
+    // a table of the mutable buffer is a single row group
+    fn table_row_groups(&self, table_name: &str) -> Result<usize, Self::Error> {
+        Ok(if self.table(table_name)?.is_some() {
+            1
+        } else {
+            0
+        })
+    }
+
+    fn row_group_to_arrow(
+        &self,
+        dst: &mut Vec<RecordBatch>,
+        table_name: &str,
+        row_group: usize,
+        columns: &[&str],
+    ) -> Result<(), Self::Error> {
+        if row_group == 0 {
+            self.table_to_arrow(dst, table_name, columns)?;
+        }
+        Ok(())
+    }
 }
 
 /// Used to figure out if we know how to deal with this kind of
@@ -473,6 +536,8 @@ impl ExpressionVisitor for SupportVisitor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use data_types::data::split_lines_into_write_entry_partitions;
+    use influxdb_line_protocol::parse_lines;
 
     #[test]
     fn test_make_range_expr() {
@@ -486,4 +551,36 @@ mod tests {
 
         assert_eq!(actual_string, expected_string);
     }
+
+    #[test]
+    fn size_is_kept_up_to_date() {
+        let mut chunk = Chunk::new(0);
+        assert_eq!(chunk.size(), 0);
+
+        write_lp(
+            &mut chunk,
+            "cpu,host=a user=1 10\nmem,host=a state=\"ok\" 10",
+        );
+        let size = chunk.size();
+        assert!(size > 0);
+        assert_eq!(size, computed_size(&chunk));
+
+        write_lp(&mut chunk, "cpu,host=b user=2,system=3 20");
+        assert!(chunk.size() > size);
+        assert_eq!(chunk.size(), computed_size(&chunk));
+    }
+
+    fn computed_size(chunk: &Chunk) -> usize {
+        chunk.dictionary.size() + chunk.tables.values().map(Table::size).sum::<usize>()
+    }
+
+    fn write_lp(chunk: &mut Chunk, lp: &str) {
+        let lines: Vec<_> = parse_lines(lp).map(|l| l.unwrap()).collect();
+        let data = split_lines_into_write_entry_partitions(|_| "key".into(), &lines);
+        let batch = flatbuffers::get_root::<wb::WriteBufferBatch<'_>>(&data);
+
+        for entry in batch.entries().unwrap() {
+            chunk.write_entry(&entry).unwrap();
+        }
+    }
 }
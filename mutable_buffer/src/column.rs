@@ -4,7 +4,12 @@ use snafu::Snafu;
 use crate::dictionary::Dictionary;
 use data_types::{data::type_description, partition_metadata::Statistics};
 
-use arrow_deps::arrow::datatypes::DataType as ArrowDataType;
+use std::mem;
+
+use arrow_deps::arrow::{
+    array::{Array, BooleanArray, Float64Array, Int64Array, StringArray},
+    datatypes::DataType as ArrowDataType,
+};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -103,6 +108,152 @@ impl Column {
         })
     }
 
+    /// Creates a column from the values of an Arrow array, preceded by
+    /// `capacity` None values. Utf8 arrays become tag columns if `is_tag` is
+    /// set, and string field columns otherwise. Returns `None` if all values
+    /// in the array are null, as there is nothing to start the statistics of
+    /// the column with.
+    pub fn from_arrow(
+        dictionary: &mut Dictionary,
+        capacity: usize,
+        array: &dyn Array,
+        is_tag: bool,
+    ) -> Result<Option<Self>> {
+        let first = match (0..array.len()).find(|&i| array.is_valid(i)) {
+            Some(first) => first,
+            None => return Ok(None),
+        };
+
+        let mut column = match array.data_type() {
+            ArrowDataType::Float64 => {
+                let val = as_array::<Float64Array>(array).value(first);
+                Self::F64(vec![None; capacity], Statistics::new(val))
+            }
+            ArrowDataType::Int64 => {
+                let val = as_array::<Int64Array>(array).value(first);
+                Self::I64(vec![None; capacity], Statistics::new(val))
+            }
+            ArrowDataType::Boolean => {
+                let val = as_array::<BooleanArray>(array).value(first);
+                Self::Bool(vec![None; capacity], Statistics::new(val))
+            }
+            ArrowDataType::Utf8 => {
+                let val = as_array::<StringArray>(array).value(first);
+                if is_tag {
+                    Self::Tag(vec![None; capacity], Statistics::new(val.to_string()))
+                } else {
+                    Self::String(vec![None; capacity], Statistics::new(val.to_string()))
+                }
+            }
+            data_type => {
+                return UnknownColumnType {
+                    inserted_value_type: format!("{:?}", data_type),
+                }
+                .fail()
+            }
+        };
+
+        column.append_arrow(dictionary, array, Some(first))?;
+
+        Ok(Some(column))
+    }
+
+    /// Appends all values of an Arrow array to the end of this column
+    pub fn push_arrow(&mut self, dictionary: &mut Dictionary, array: &dyn Array) -> Result<()> {
+        self.append_arrow(dictionary, array, None)
+    }
+
+    // Appends the values of the array, updating the statistics with every
+    // non-null value except the one at `counted`, which they already include
+    fn append_arrow(
+        &mut self,
+        dictionary: &mut Dictionary,
+        array: &dyn Array,
+        counted: Option<usize>,
+    ) -> Result<()> {
+        match (&mut *self, array.data_type()) {
+            (Self::F64(vals, stats), ArrowDataType::Float64) => {
+                let array = as_array::<Float64Array>(array);
+                for i in 0..array.len() {
+                    if array.is_null(i) {
+                        vals.push(None);
+                        continue;
+                    }
+                    let val = array.value(i);
+                    vals.push(Some(val));
+                    if counted != Some(i) {
+                        stats.update(val);
+                    }
+                }
+            }
+            (Self::I64(vals, stats), ArrowDataType::Int64) => {
+                let array = as_array::<Int64Array>(array);
+                for i in 0..array.len() {
+                    if array.is_null(i) {
+                        vals.push(None);
+                        continue;
+                    }
+                    let val = array.value(i);
+                    vals.push(Some(val));
+                    if counted != Some(i) {
+                        stats.update(val);
+                    }
+                }
+            }
+            (Self::Bool(vals, stats), ArrowDataType::Boolean) => {
+                let array = as_array::<BooleanArray>(array);
+                for i in 0..array.len() {
+                    if array.is_null(i) {
+                        vals.push(None);
+                        continue;
+                    }
+                    let val = array.value(i);
+                    vals.push(Some(val));
+                    if counted != Some(i) {
+                        stats.update(val);
+                    }
+                }
+            }
+            (Self::String(vals, stats), ArrowDataType::Utf8) => {
+                let array = as_array::<StringArray>(array);
+                for i in 0..array.len() {
+                    if array.is_null(i) {
+                        vals.push(None);
+                        continue;
+                    }
+                    let val = array.value(i);
+                    vals.push(Some(val.to_string()));
+                    if counted != Some(i) {
+                        Statistics::update_string(stats, val);
+                    }
+                }
+            }
+            (Self::Tag(vals, stats), ArrowDataType::Utf8) => {
+                let array = as_array::<StringArray>(array);
+                for i in 0..array.len() {
+                    if array.is_null(i) {
+                        vals.push(None);
+                        continue;
+                    }
+                    let val = array.value(i);
+                    vals.push(Some(dictionary.lookup_value_or_insert(val)));
+                    if counted != Some(i) {
+                        Statistics::update_string(stats, val);
+                    }
+                }
+            }
+            (column, data_type) => {
+                return TypeMismatch {
+                    existing_column_type: column.type_description(),
+                    inserted_value_type: format!("{:?}", data_type),
+                }
+                .fail()
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn len(&self) -> usize {
         match self {
             Self::F64(v, _) => v.len(),
@@ -117,6 +268,28 @@ impl Column {
         self.len() == 0
     }
 
+    /// Returns the estimated size of the memory the column's values and
+    /// statistics have allocated, in bytes, including the capacity reserved
+    /// for more values. Tag values are counted as their dictionary ids.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::F64(v, _) => mem::size_of::<Option<f64>>() * v.capacity(),
+            Self::I64(v, _) => mem::size_of::<Option<i64>>() * v.capacity(),
+            Self::String(v, stats) => {
+                mem::size_of::<Option<String>>() * v.capacity()
+                    + v.iter().flatten().map(String::capacity).sum::<usize>()
+                    + stats.min.capacity()
+                    + stats.max.capacity()
+            }
+            Self::Bool(v, _) => mem::size_of::<Option<bool>>() * v.capacity(),
+            Self::Tag(v, stats) => {
+                mem::size_of::<Option<u32>>() * v.capacity()
+                    + stats.min.capacity()
+                    + stats.max.capacity()
+            }
+        }
+    }
+
     pub fn type_description(&self) -> &'static str {
         match self {
             Self::F64(_, _) => "f64",
@@ -232,6 +405,17 @@ impl Column {
         }
     }
 
+    /// Adds None values to the end of the column until it has `len` values
+    pub fn push_none_to_len(&mut self, len: usize) {
+        match self {
+            Self::F64(v, _) => v.resize(len.max(v.len()), None),
+            Self::I64(v, _) => v.resize(len.max(v.len()), None),
+            Self::String(v, _) => v.resize(len.max(v.len()), None),
+            Self::Bool(v, _) => v.resize(len.max(v.len()), None),
+            Self::Tag(v, _) => v.resize(len.max(v.len()), None),
+        }
+    }
+
     /// Returns true if any rows are within the range [min_value,
     /// max_value). Inclusive of `start`, exclusive of `end`
     pub fn has_i64_range(&self, start: i64, end: i64) -> Result<bool> {
@@ -277,6 +461,13 @@ impl Column {
     }
 }
 
+fn as_array<T: 'static>(array: &dyn Array) -> &T {
+    array
+        .as_any()
+        .downcast_ref::<T>()
+        .expect("array type should match its data type")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -305,6 +496,56 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_from_arrow() -> Result {
+        let mut dictionary = Dictionary::new();
+
+        let array = Int64Array::from(vec![None, Some(3), Some(1)]);
+        let mut col = Column::from_arrow(&mut dictionary, 2, &array, false)?.unwrap();
+        col.push_arrow(&mut dictionary, &Int64Array::from(vec![Some(5)]))?;
+
+        match &col {
+            Column::I64(vals, stats) => {
+                assert_eq!(vals, &[None, None, None, Some(3), Some(1), Some(5)]);
+                assert_eq!(
+                    stats,
+                    &Statistics {
+                        min: 1,
+                        max: 5,
+                        count: 3
+                    }
+                );
+            }
+            _ => panic!("expected an i64 column, got {}", col.type_description()),
+        }
+
+        let array = StringArray::from(vec![Some("b"), None, Some("a")]);
+        let mut col = Column::from_arrow(&mut dictionary, 0, &array, true)?.unwrap();
+        assert!(col.is_tag());
+        assert_eq!(col.len(), 3);
+
+        let field = Column::from_arrow(&mut dictionary, 1, &array, false)?.unwrap();
+        match &field {
+            Column::String(vals, stats) => {
+                assert_eq!(
+                    vals,
+                    &[None, Some("b".to_string()), None, Some("a".to_string())]
+                );
+                assert_eq!(stats.min, "a");
+                assert_eq!(stats.max, "b");
+            }
+            _ => panic!("expected a string column, got {}", field.type_description()),
+        }
+
+        let floats = Float64Array::from(vec![1.0]);
+        assert!(col.push_arrow(&mut dictionary, &floats).is_err());
+
+        let nulls = Int64Array::from(vec![None, None]);
+        assert!(Column::from_arrow(&mut dictionary, 0, &nulls, false)?.is_none());
+
+        Ok(())
+    }
+
     #[test]
     fn test_has_i64_range_does_not_panic() -> Result {
         // providing the wrong column type should get an internal error, not a panic
@@ -12,17 +12,20 @@ use crate::column::Column;
 use crate::table::Table;
 use crate::{
     chunk::{Chunk, ChunkPredicate},
-    partition::Partition,
+    partition::{ChunkSummary, Partition},
 };
 
 use std::collections::{BTreeSet, HashMap, HashSet};
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
 use arrow_deps::{
     arrow::record_batch::RecordBatch,
     datafusion::{error::DataFusionError, logical_plan::LogicalPlan},
 };
-use data_types::data::ReplicatedWrite;
+use data_types::{data::ReplicatedWrite, partition_metadata::Table as TableStats};
 
 use crate::dictionary::Error as DictionaryError;
 
@@ -126,6 +129,10 @@ pub struct MutableBufferDb {
 
     /// Maps partition keys to partitions which hold the actual data
     partitions: RwLock<HashMap<String, Arc<RwLock<Partition>>>>,
+
+    /// The estimated size of all partitions in bytes, kept up to date as
+    /// their chunks change
+    size: AtomicUsize,
 }
 
 impl MutableBufferDb {
@@ -141,13 +148,18 @@ impl MutableBufferDb {
     async fn write_entries_to_partitions(&self, batch: &wal::WriteBufferBatch<'_>) -> Result<()> {
         if let Some(entries) = batch.entries() {
             for entry in entries {
+                // deletes aren't stored in a partition, they are applied to
+                // the data when it is queried
+                if entry.table_batches().is_none() {
+                    continue;
+                }
+
                 let key = entry
                     .partition_key()
                     .expect("partition key should have been inserted");
 
-                let partition = self.get_partition(key).await;
-                let mut partition = partition.write().await;
-                partition.write_entry(&entry)?
+                self.update_partition(key, |partition| partition.write_entry(&entry))
+                    .await?
             }
         }
 
@@ -164,11 +176,127 @@ impl MutableBufferDb {
         Ok(batches)
     }
 
+    /// Converts the table in each chunk of every partition into record
+    /// batches, which are returned with the key of the partition and the id
+    /// of the chunk they came from
+    pub async fn chunk_table_to_arrow(
+        &self,
+        table_name: &str,
+        columns: &[&str],
+    ) -> Result<Vec<(String, u64, Vec<RecordBatch>)>> {
+        let mut chunk_batches = Vec::new();
+        for partition in self.partition_snapshot().await.into_iter() {
+            let partition = partition.read().await;
+            for chunk in partition.iter() {
+                let mut batches = Vec::new();
+                chunk.table_to_arrow(&mut batches, table_name, columns)?;
+                chunk_batches.push((partition.key().to_string(), chunk.id(), batches));
+            }
+        }
+
+        Ok(chunk_batches)
+    }
+
+    /// Adds a closed chunk with the record batches of each table in `tables`
+    /// to the partition, creating the partition if needed
+    pub async fn load_chunk(
+        &self,
+        partition_key: &str,
+        tables: &[(String, Vec<RecordBatch>)],
+    ) -> Result<Arc<Chunk>> {
+        Ok(self
+            .update_partition(partition_key, |partition| partition.load_chunk(tables))
+            .await?)
+    }
+
+    /// Replaces the closed chunk with the specified id in the partition by a
+    /// chunk with the same id that holds the record batches of each table in
+    /// `tables`, dropping it if they have no data. Returns the new chunk, or
+    /// `None` if there is no such chunk, for example because it was dropped
+    /// in the meantime.
+    pub async fn replace_chunk(
+        &self,
+        partition_key: &str,
+        chunk_id: u64,
+        tables: &[(String, Vec<RecordBatch>)],
+    ) -> Result<Option<Arc<Chunk>>> {
+        // not created if it doesn't exist, unlike by `update_partition`
+        let partition = match self.partitions.read().await.get(partition_key).cloned() {
+            Some(partition) => partition,
+            None => return Ok(None),
+        };
+        let mut partition = partition.write().await;
+
+        let before = partition.size();
+        let chunk = partition.replace_chunk(chunk_id, tables)?;
+        self.size.fetch_add(partition.size(), Ordering::SeqCst);
+        self.size.fetch_sub(before, Ordering::SeqCst);
+
+        Ok(chunk)
+    }
+
     /// Rolls over the active chunk in this partititon
     pub async fn rollover_partition(&self, partition_key: &str) -> Result<Arc<Chunk>> {
-        let partition = self.get_partition(partition_key).await;
-        let mut partition = partition.write().await;
-        Ok(partition.rollover_chunk())
+        Ok(self
+            .update_partition(partition_key, Partition::rollover_chunk)
+            .await)
+    }
+
+    /// Returns a summary of every chunk in the database, including the open
+    /// chunk of each partition
+    pub async fn chunk_summaries(&self) -> Vec<ChunkSummary> {
+        let mut summaries = Vec::new();
+        for partition in self.partition_snapshot().await {
+            let partition = partition.read().await;
+            summaries.extend(partition.chunk_summaries());
+        }
+        summaries
+    }
+
+    /// Returns the closed chunk with the specified id in the partition, if
+    /// any
+    pub async fn closed_chunk(&self, partition_key: &str, chunk_id: u64) -> Option<Arc<Chunk>> {
+        let partition = self.partitions.read().await.get(partition_key).cloned()?;
+        let partition = partition.read().await;
+        partition.closed_chunk(chunk_id)
+    }
+
+    /// Drops the closed chunk with the specified id from the partition,
+    /// freeing its memory once any running queries are done with it
+    pub async fn drop_chunk(&self, partition_key: &str, chunk_id: u64) -> Result<Arc<Chunk>> {
+        Ok(self
+            .update_partition(partition_key, |partition| partition.drop_chunk(chunk_id))
+            .await?)
+    }
+
+    /// Returns the statistics of the tables in every chunk of the partition,
+    /// including its open chunk
+    pub async fn partition_table_stats(&self, partition_key: &str) -> Result<Vec<TableStats>> {
+        let partition = match self.partitions.read().await.get(partition_key).cloned() {
+            Some(partition) => partition,
+            None => return Ok(vec![]),
+        };
+        let partition = partition.read().await;
+
+        let mut stats = Vec::new();
+        for chunk in partition.iter() {
+            stats.extend(chunk.table_stats()?);
+        }
+        Ok(stats)
+    }
+
+    /// Drops the partition with all of its chunks, returning their ids, or
+    /// `None` if there is no such partition
+    pub async fn drop_partition(&self, partition_key: &str) -> Option<Vec<u64>> {
+        let partition = self.partitions.write().await.remove(partition_key)?;
+        let partition = partition.read().await;
+        self.size.fetch_sub(partition.size(), Ordering::SeqCst);
+        Some(partition.iter().map(|chunk| chunk.id()).collect())
+    }
+
+    /// Returns the estimated size of all the data in the database, in bytes
+    pub fn size(&self) -> usize {
+        self.size.load(Ordering::SeqCst)
     }
 }
 
@@ -415,6 +543,25 @@ impl MutableBufferDb {
         }
     }
 
+    // applies `f` to the partition, creating the partition if needed, and
+    // updates the size of the database by how much the partition changed
+    async fn update_partition<T>(
+        &self,
+        partition_key: &str,
+        f: impl FnOnce(&mut Partition) -> T,
+    ) -> T {
+        let partition = self.get_partition(partition_key).await;
+        let mut partition = partition.write().await;
+
+        let before = partition.size();
+        let result = f(&mut partition);
+        // added before the old size is subtracted, so the size can't underflow
+        self.size.fetch_add(partition.size(), Ordering::SeqCst);
+        self.size.fetch_sub(before, Ordering::SeqCst);
+
+        result
+    }
+
     /// get a snapshot of all the current partitions -- useful so that
     /// while doing stuff with one partition we don't prevent creating
     /// new partitions
@@ -1140,6 +1287,75 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn drop_partition_with_stats() -> Result {
+        let db = MutableBufferDb::new("foo");
+
+        let lines: Vec<_> = parse_lines("cpu,region=west user=23.2 10\nmem free=1 20")
+            .map(|l| l.unwrap())
+            .collect();
+        write_lines(&db, &lines).await;
+
+        let partition_keys = db.partition_keys().await?;
+        assert_eq!(partition_keys.len(), 1);
+        let partition_key = &partition_keys[0];
+
+        db.rollover_partition(partition_key).await?;
+        let lines: Vec<_> = parse_lines("cpu,region=west user=1.0 30")
+            .map(|l| l.unwrap())
+            .collect();
+        write_lines(&db, &lines).await;
+
+        let stats = db.partition_table_stats(partition_key).await?;
+        let mut max_times: Vec<_> = stats
+            .iter()
+            .map(|t| (t.name.as_str(), t.max_time()))
+            .collect();
+        max_times.sort();
+        assert_eq!(
+            max_times,
+            vec![("cpu", Some(10)), ("cpu", Some(30)), ("mem", Some(20))]
+        );
+
+        assert_eq!(db.drop_partition(partition_key).await, Some(vec![0, 1]));
+        assert!(db.partition_keys().await?.is_empty());
+        assert!(db.partition_table_stats(partition_key).await?.is_empty());
+        assert_eq!(db.drop_partition(partition_key).await, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn size_tracks_chunks() -> Result {
+        let db = MutableBufferDb::new("foo");
+        assert_eq!(db.size(), 0);
+
+        let lines: Vec<_> = parse_lines("cpu,region=west user=23.2 10")
+            .map(|l| l.unwrap())
+            .collect();
+        write_lines(&db, &lines).await;
+        let partition_key = &db.partition_keys().await?[0];
+        let first = db.size();
+        assert!(first > 0);
+
+        let closed = db.rollover_partition(partition_key).await?;
+        assert_eq!(db.size(), first);
+        assert_eq!(closed.size(), first);
+
+        write_lines(&db, &lines).await;
+        assert!(db.size() > first);
+
+        db.drop_chunk(partition_key, closed.id()).await?;
+        let summaries = db.chunk_summaries().await;
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(db.size(), summaries[0].size);
+
+        db.drop_partition(partition_key).await;
+        assert_eq!(db.size(), 0);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn list_column_names() -> Result {
         let db = MutableBufferDb::new("column_namedb");
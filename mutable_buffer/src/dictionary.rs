@@ -5,6 +5,13 @@ use string_interner::{
     backend::StringBackend, DefaultHashBuilder, DefaultSymbol, StringInterner, Symbol,
 };
 
+use std::mem;
+
+/// The memory an entry of the dictionary takes besides its string: the
+/// offset of the end of the string in the interner's buffer and the symbol in
+/// its hash table
+const ENTRY_OVERHEAD: usize = mem::size_of::<usize>() + mem::size_of::<DefaultSymbol>();
+
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Dictionary lookup error on id {}", id))]
@@ -17,9 +24,12 @@ pub enum Error {
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[derive(Debug)]
-pub struct Dictionary(
-    StringInterner<DefaultSymbol, StringBackend<DefaultSymbol>, DefaultHashBuilder>,
-);
+pub struct Dictionary {
+    interner: StringInterner<DefaultSymbol, StringBackend<DefaultSymbol>, DefaultHashBuilder>,
+    /// The total length of the interned strings and the overhead of their
+    /// entries, in bytes
+    size: usize,
+}
 
 impl Default for Dictionary {
     fn default() -> Self {
@@ -29,13 +39,21 @@ impl Default for Dictionary {
 
 impl Dictionary {
     pub fn new() -> Self {
-        Self(StringInterner::new())
+        Self {
+            interner: StringInterner::new(),
+            size: 0,
+        }
     }
 
     /// Returns the id corresponding to value, adding an entry for the
     /// id if it is not yet present in the dictionary.
     pub fn lookup_value_or_insert(&mut self, value: &str) -> u32 {
-        symbol_to_u32(self.0.get_or_intern(value))
+        let len = self.interner.len();
+        let symbol = self.interner.get_or_intern(value);
+        if self.interner.len() > len {
+            self.size += value.len() + ENTRY_OVERHEAD;
+        }
+        symbol_to_u32(symbol)
     }
 
     /// Returns the ID in self.dictionary that corresponds to `value`, if any.
@@ -49,7 +67,7 @@ impl Dictionary {
     /// if any. No error is returned to avoid an allocation when no value is
     /// present
     pub fn id(&self, value: &str) -> Option<u32> {
-        self.0.get(value).map(symbol_to_u32)
+        self.interner.get(value).map(symbol_to_u32)
     }
 
     /// Returns the str in self.dictionary that corresponds to `id`,
@@ -57,10 +75,16 @@ impl Dictionary {
     pub fn lookup_id(&self, id: u32) -> Result<&str> {
         let symbol =
             Symbol::try_from_usize(id as usize).expect("to be able to convert u32 to symbol");
-        self.0
+        self.interner
             .resolve(symbol)
             .context(DictionaryIdLookupError { id })
     }
+
+    /// Returns the estimated size of the dictionary in memory, in bytes: the
+    /// length of its strings and the overhead of each entry
+    pub fn size(&self) -> usize {
+        self.size
+    }
 }
 
 fn symbol_to_u32(sym: DefaultSymbol) -> u32 {
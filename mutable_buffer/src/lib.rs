@@ -60,7 +60,7 @@ pub mod chunk;
 mod column;
 pub mod database;
 mod dictionary;
-mod partition;
+pub mod partition;
 mod table;
 
 // Allow restore chunks to be used outside of this crate (for
@@ -1,6 +1,7 @@
 //! Holds one or more Chunks.
 
 use arrow_deps::arrow::record_batch::RecordBatch;
+use chrono::{DateTime, Utc};
 use generated_types::wal as wb;
 use std::{collections::BTreeMap, sync::Arc};
 
@@ -41,6 +42,16 @@ pub enum Error {
         chunk_id: u64,
         valid_chunk_ids: Vec<u64>,
     },
+
+    #[snafu(display(
+        "Error loading chunk data of partition with key '{}': {}",
+        partition_key,
+        source
+    ))]
+    LoadingChunkData {
+        partition_key: String,
+        source: ChunkError,
+    },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -121,6 +132,25 @@ impl Partition {
         }
     }
 
+    /// Return a summary of each chunk in this partition, closed chunks first,
+    /// in creation order
+    pub fn chunk_summaries(&self) -> Vec<ChunkSummary> {
+        self.iter()
+            .map(|chunk| ChunkSummary {
+                partition_key: self.key.clone(),
+                id: chunk.id(),
+                size: chunk.size(),
+                time_of_first_write: chunk.time_of_first_write,
+                time_closed: chunk.time_closed,
+            })
+            .collect()
+    }
+
+    /// Return the closed chunk with the specified id, if any
+    pub fn closed_chunk(&self, chunk_id: u64) -> Option<Arc<Chunk>> {
+        self.closed_chunks.get(&chunk_id).cloned()
+    }
+
     /// Close the currently open chunk and create a new open
     /// chunk. The newly closed chunk is adding to the list of closed
     /// chunks if it had data, and is returned.
@@ -143,9 +173,69 @@ impl Partition {
         chunk
     }
 
+    /// Adds a closed chunk with the record batches of each table in
+    /// `tables`, for example data loaded from a snapshot in object storage.
+    /// The chunk is added to the list of closed chunks if it has data, and is
+    /// returned.
+    pub fn load_chunk(&mut self, tables: &[(String, Vec<RecordBatch>)]) -> Result<Arc<Chunk>> {
+        let chunk_id = self.id_generator;
+        self.id_generator += 1;
+        let mut chunk = Chunk::new(chunk_id);
+        self.write_tables(&mut chunk, tables)?;
+
+        chunk.mark_closed();
+        let chunk = Arc::new(chunk);
+        if !chunk.is_empty() {
+            let existing_value = self.closed_chunks.insert(chunk.id(), chunk.clone());
+            assert!(existing_value.is_none());
+        }
+        Ok(chunk)
+    }
+
+    /// Replaces the closed chunk with the specified id by a chunk with the
+    /// same id and times that holds the record batches of each table in
+    /// `tables`, for example the rows of the chunk that are left after a
+    /// delete. The chunk is dropped if `tables` has no data. Returns the new
+    /// chunk, or `None` if there is no closed chunk with the id.
+    pub fn replace_chunk(
+        &mut self,
+        chunk_id: u64,
+        tables: &[(String, Vec<RecordBatch>)],
+    ) -> Result<Option<Arc<Chunk>>> {
+        let replaced = match self.closed_chunks.get(&chunk_id) {
+            Some(replaced) => replaced,
+            None => return Ok(None),
+        };
+        let mut chunk = Chunk::new(chunk_id);
+        chunk.time_of_first_write = replaced.time_of_first_write;
+        chunk.time_of_last_write = replaced.time_of_last_write;
+        chunk.time_closed = replaced.time_closed;
+        self.write_tables(&mut chunk, tables)?;
+
+        let chunk = Arc::new(chunk);
+        if chunk.is_empty() {
+            self.closed_chunks.remove(&chunk_id);
+        } else {
+            self.closed_chunks.insert(chunk_id, Arc::clone(&chunk));
+        }
+        Ok(Some(chunk))
+    }
+
+    fn write_tables(&self, chunk: &mut Chunk, tables: &[(String, Vec<RecordBatch>)]) -> Result<()> {
+        for (table_name, batches) in tables {
+            for batch in batches {
+                chunk
+                    .write_record_batch(table_name, batch)
+                    .with_context(|| LoadingChunkData {
+                        partition_key: &self.key,
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
     /// Drop the specified chunk for the partition, returning a reference to the
     /// chunk
-    #[allow(dead_code)]
     pub fn drop_chunk(&mut self, chunk_id: u64) -> Result<Arc<Chunk>> {
         self.closed_chunks.remove(&chunk_id).ok_or_else(|| {
             let partition_key = self.key.clone();
@@ -165,6 +255,12 @@ impl Partition {
         })
     }
 
+    /// Returns the estimated size of the data in all chunks of this
+    /// partition, in bytes
+    pub fn size(&self) -> usize {
+        self.iter().map(Chunk::size).sum()
+    }
+
     /// Return the partition key shared by all data stored in this
     /// partition
     pub fn key(&self) -> &str {
@@ -183,6 +279,26 @@ pub struct PartitionChunkInfo {
     pub num_closed_chunks: usize,
 }
 
+/// Summary of a chunk, used to decide when it should be closed, persisted
+/// or dropped
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkSummary {
+    /// The key of the partition the chunk is in
+    pub partition_key: String,
+
+    /// The id of the chunk within its partition
+    pub id: u64,
+
+    /// The estimated size of the chunk's data in memory, in bytes
+    pub size: usize,
+
+    /// Time at which the first data was written into the chunk
+    pub time_of_first_write: Option<DateTime<Utc>>,
+
+    /// Time at which the chunk was closed, if it is closed
+    pub time_closed: Option<DateTime<Utc>>,
+}
+
 /// Iterates over chunks in a partition. Always iterates over chunks
 /// in their creation (id) order: Closed chunks first, followed by the
 /// open chunk, if any. This allows data to be read out in the same order it
@@ -228,7 +344,10 @@ impl<'a> Iterator for ChunkIter<'a> {
 mod tests {
     use super::*;
     use chrono::Utc;
-    use data_types::data::split_lines_into_write_entry_partitions;
+    use data_types::{
+        column_type_metadata_key, data::split_lines_into_write_entry_partitions, FIELD_COLUMN_TYPE,
+        TAG_COLUMN_TYPE,
+    };
 
     use arrow_deps::{
         arrow::record_batch::RecordBatch, assert_table_eq, test_util::sort_record_batch,
@@ -621,6 +740,84 @@ mod tests {
         assert!(chunk.time_closed.unwrap() < after_rollover);
     }
 
+    #[tokio::test]
+    async fn test_load_chunk() {
+        let mut source = Partition::new("a_key");
+        load_data(
+            &mut source,
+            &[
+                "h2o,state=MA,city=Boston temp=70.4 100",
+                "h2o,state=MA,city=Boston temp=71.4,humidity=20i,status=\"ok\" 200",
+            ],
+        )
+        .await;
+        load_data(&mut source, &["h2o,state=MA humidity=10i 300"]).await;
+        let batches = dump_table(&source, "h2o");
+
+        let mut partition = Partition::new("a_key");
+        let chunk = partition
+            .load_chunk(&[("h2o".to_string(), batches)])
+            .unwrap();
+
+        assert_eq!(chunk.id(), 1);
+        assert!(chunk.time_closed.is_some());
+        assert_eq!(row_count("h2o", &chunk), 3);
+        assert_eq!(all_ids_with_data(&partition), vec![1]);
+
+        let expected = &[
+            "+--------+----------+-------+--------+------+------+",
+            "| city   | humidity | state | status | temp | time |",
+            "+--------+----------+-------+--------+------+------+",
+            "| Boston | 20       | MA    | ok     | 71.4 | 200  |",
+            "| Boston |          | MA    |        | 70.4 | 100  |",
+            "|        | 10       | MA    |        |      | 300  |",
+            "+--------+----------+-------+--------+------+------+",
+        ];
+        let loaded = dump_table(&partition, "h2o");
+        assert_table_eq!(expected, &loaded);
+
+        // the string field stays a field, the tags stay tags
+        let metadata = loaded[0].schema().metadata().clone();
+        let column_type = |column| metadata[&column_type_metadata_key(column)].as_str();
+        assert_eq!(column_type("city"), TAG_COLUMN_TYPE);
+        assert_eq!(column_type("state"), TAG_COLUMN_TYPE);
+        assert_eq!(column_type("status"), FIELD_COLUMN_TYPE);
+        assert_eq!(column_type("humidity"), FIELD_COLUMN_TYPE);
+
+        // new writes go to the open chunk
+        load_data(&mut partition, &["h2o,state=MA,city=Boston temp=72.4 400"]).await;
+        assert_eq!(dump_table(&partition, "h2o").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_summaries() {
+        let mut partition = Partition::new("a_key");
+        let summaries = partition.chunk_summaries();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].size, 0);
+        assert!(summaries[0].time_of_first_write.is_none());
+
+        load_data(&mut partition, &["h2o,state=MA,city=Boston temp=70.4 100"]).await;
+        let open_size = partition.chunk_summaries()[0].size;
+        assert!(open_size > 0);
+
+        partition.rollover_chunk();
+        load_data(&mut partition, &["h2o,state=MA,city=Boston temp=70.4 200"]).await;
+
+        let summaries = partition.chunk_summaries();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].partition_key, "a_key");
+        assert_eq!(summaries[0].id, 0);
+        assert_eq!(summaries[0].size, open_size);
+        assert!(summaries[0].time_closed.is_some());
+        assert_eq!(summaries[1].id, 1);
+        assert!(summaries[1].time_of_first_write.is_some());
+        assert!(summaries[1].time_closed.is_none());
+
+        assert_eq!(partition.closed_chunk(0).unwrap().id(), 0);
+        assert!(partition.closed_chunk(1).is_none());
+    }
+
     fn row_count(table_name: &str, chunk: &Chunk) -> u32 {
         let stats = chunk.table_stats().unwrap();
         for s in &stats {
@@ -7,7 +7,7 @@ use query::{
 };
 use tracing::debug;
 
-use std::{collections::BTreeSet, collections::HashMap, sync::Arc};
+use std::{collections::BTreeSet, collections::HashMap, mem, sync::Arc};
 
 use crate::{
     chunk::ChunkIdSet,
@@ -16,7 +16,11 @@ use crate::{
     column::Column,
     dictionary::{Dictionary, Error as DictionaryError},
 };
-use data_types::{partition_metadata::Column as ColumnStats, TIME_COLUMN_NAME};
+use data_types::{
+    column_type_metadata_key,
+    partition_metadata::{Column as ColumnStats, Statistics},
+    FIELD_COLUMN_TYPE, TAG_COLUMN_TYPE, TIME_COLUMN_NAME,
+};
 use snafu::{OptionExt, ResultExt, Snafu};
 
 use arrow_deps::{
@@ -206,6 +210,17 @@ impl Table {
         self.columns.first().map_or(0, |v| v.len())
     }
 
+    /// Returns the estimated size of the table in memory, in bytes: its
+    /// columns, their data and the index of their ids
+    pub fn size(&self) -> usize {
+        let columns = mem::size_of::<Column>() * self.columns.capacity()
+            + self.columns.iter().map(Column::size).sum::<usize>();
+        let index =
+            (mem::size_of::<u32>() + mem::size_of::<usize>()) * self.column_id_to_index.capacity();
+
+        mem::size_of::<Self>() + columns + index
+    }
+
     /// Returns a reference to the specified column
     fn column(&self, column_id: u32) -> Result<&Column> {
         Ok(self
@@ -244,6 +259,56 @@ impl Table {
         Ok(())
     }
 
+    /// Appends the rows of an Arrow record batch to this table. Columns of the
+    /// table that aren't in the batch get None values for the new rows.
+    pub fn append_record_batch(
+        &mut self,
+        dictionary: &mut Dictionary,
+        batch: &RecordBatch,
+    ) -> Result<()> {
+        let row_count = self.row_count();
+        let schema = batch.schema();
+
+        for (field, array) in schema.fields().iter().zip(batch.columns()) {
+            let column_name = field.name();
+            let column_id = dictionary.lookup_value_or_insert(column_name);
+            let is_tag = schema
+                .metadata()
+                .get(&column_type_metadata_key(column_name))
+                .map_or(false, |column_type| column_type == TAG_COLUMN_TYPE);
+
+            match self.column_id_to_index.get(&column_id) {
+                Some(idx) => self.columns[*idx]
+                    .push_arrow(dictionary, array.as_ref())
+                    .context(ColumnError {
+                        column: column_name,
+                    })?,
+                None => {
+                    let column = Column::from_arrow(dictionary, row_count, array.as_ref(), is_tag)
+                        .context(ColumnError {
+                            column: column_name,
+                        })?;
+
+                    // columns without any values are only added once a value
+                    // shows up
+                    if let Some(column) = column {
+                        self.column_id_to_index
+                            .insert(column_id, self.columns.len());
+                        self.columns.push(column);
+                    }
+                }
+            }
+        }
+
+        // make sure all the columns are of the same length
+        let row_count = row_count + batch.num_rows();
+        for col in &mut self.columns {
+            col.push_none_to_len(row_count);
+        }
+
+        Ok(())
+    }
+
     /// Creates and adds a datafuson filtering expression, if any out of the
     /// combination of predicate and timestamp. Returns the builder
     fn add_datafusion_predicate(
@@ -864,8 +929,21 @@ impl Table {
     ) -> Result<RecordBatch> {
         let mut fields = Vec::with_capacity(requested_columns_with_index.len());
         let mut columns: Vec<ArrayRef> = Vec::with_capacity(requested_columns_with_index.len());
+        let mut metadata = HashMap::new();
 
         for &(column_name, column_index) in requested_columns_with_index.iter() {
+            if column_name != TIME_COLUMN_NAME {
+                let column_type = if self.columns[column_index].is_tag() {
+                    TAG_COLUMN_TYPE
+                } else {
+                    FIELD_COLUMN_TYPE
+                };
+                metadata.insert(
+                    column_type_metadata_key(column_name),
+                    column_type.to_string(),
+                );
+            }
+
             let arrow_col: ArrayRef = match &self.columns[column_index] {
                 Column::String(vals, _) => {
                     fields.push(ArrowField::new(column_name, ArrowDataType::Utf8, true));
@@ -938,7 +1016,7 @@ impl Table {
             columns.push(arrow_col);
         }
 
-        let schema = ArrowSchema::new(fields);
+        let schema = ArrowSchema::new_with_metadata(fields, metadata);
 
         RecordBatch::try_new(Arc::new(schema), columns).context(ArrowError {})
     }
@@ -1048,6 +1126,16 @@ impl Table {
             })
             .collect()
     }
+
+    /// Returns the statistics of the time column, which has the specified
+    /// id, if the table has it
+    pub fn time_stats(&self, time_column_id: u32) -> Option<Statistics<i64>> {
+        let &index = self.column_id_to_index.get(&time_column_id)?;
+        match &self.columns[index] {
+            Column::I64(_, stats) => Some(stats.clone()),
+            _ => None,
+        }
+    }
 }
 
 /// Reorders tag_columns so that its prefix matches exactly
@@ -2,8 +2,10 @@
 //! store.
 use crate::{
     path::{CloudConverter, ObjectStorePath, DELIMITER},
-    Error, ListResult, NoDataFromS3, ObjectMeta, Result, UnableToDeleteDataFromS3,
-    UnableToGetDataFromS3, UnableToGetPieceOfDataFromS3, UnableToPutDataToS3,
+    Error, ListResult, NoDataFromS3, NoUploadIdFromS3, ObjectMeta, Result,
+    UnableToCompleteMultipartUploadToS3, UnableToCreateMultipartUploadToS3,
+    UnableToDeleteDataFromS3, UnableToGetDataFromS3, UnableToGetPieceOfDataFromS3,
+    UnableToPutDataToS3, UnableToReadPartData, UnableToUploadPartToS3,
 };
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
@@ -15,6 +17,10 @@ use snafu::{futures::TryStreamExt as _, OptionExt, ResultExt};
 use std::convert::TryFrom;
 use std::{fmt, io};
 
+/// S3 rejects multipart uploads with parts, other than the last, smaller than
+/// this
+const MIN_MULTIPART_PART_SIZE: usize = 5 * 1024 * 1024;
+
 /// Configuration for connecting to [Amazon S3](https://aws.amazon.com/s3/).
 pub struct AmazonS3 {
     client: rusoto_s3::S3Client,
@@ -78,6 +84,128 @@ impl AmazonS3 {
         Ok(())
     }
 
+    /// Save the parts yielded by the stream to the specified location using a
+    /// multipart upload. Parts are buffered until they reach the minimum part
+    /// size S3 accepts, so at most one such part is held in memory at a
+    /// time. The upload is aborted if any part fails.
+    pub async fn put_multipart<S>(&self, location: &ObjectStorePath, parts: S) -> Result<()>
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        let key = CloudConverter::convert(&location);
+
+        let create_request = rusoto_s3::CreateMultipartUploadRequest {
+            bucket: self.bucket_name.clone(),
+            key: key.clone(),
+            ..Default::default()
+        };
+        let upload_id = self
+            .client
+            .create_multipart_upload(create_request)
+            .await
+            .context(UnableToCreateMultipartUploadToS3 {
+                bucket: &self.bucket_name,
+                location: &key,
+            })?
+            .upload_id
+            .context(NoUploadIdFromS3 {
+                bucket: &self.bucket_name,
+                location: &key,
+            })?;
+
+        match self.upload_parts(&key, &upload_id, parts).await {
+            Ok(completed_parts) => {
+                let complete_request = rusoto_s3::CompleteMultipartUploadRequest {
+                    bucket: self.bucket_name.clone(),
+                    key: key.clone(),
+                    upload_id,
+                    multipart_upload: Some(rusoto_s3::CompletedMultipartUpload {
+                        parts: Some(completed_parts),
+                    }),
+                    ..Default::default()
+                };
+                self.client
+                    .complete_multipart_upload(complete_request)
+                    .await
+                    .context(UnableToCompleteMultipartUploadToS3 {
+                        bucket: &self.bucket_name,
+                        location: key,
+                    })?;
+                Ok(())
+            }
+            Err(e) => {
+                let abort_request = rusoto_s3::AbortMultipartUploadRequest {
+                    bucket: self.bucket_name.clone(),
+                    key,
+                    upload_id,
+                    ..Default::default()
+                };
+                // the original error is more useful than a failure to abort
+                let _ = self.client.abort_multipart_upload(abort_request).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn upload_parts<S>(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: S,
+    ) -> Result<Vec<rusoto_s3::CompletedPart>>
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        futures::pin_mut!(parts);
+
+        let mut completed_parts = vec![];
+        let mut buffer = Vec::new();
+        let mut done = false;
+
+        while !done {
+            match parts.try_next().await.context(UnableToReadPartData)? {
+                Some(part) => buffer.extend_from_slice(&part),
+                None => done = true,
+            }
+
+            // every part but the last has to be at least the minimum size
+            let ready = buffer.len() >= MIN_MULTIPART_PART_SIZE
+                || (done && (!buffer.is_empty() || completed_parts.is_empty()));
+            if !ready {
+                continue;
+            }
+
+            let part_number = completed_parts.len() as i64 + 1;
+            let body = std::mem::take(&mut buffer);
+            let upload_request = rusoto_s3::UploadPartRequest {
+                bucket: self.bucket_name.clone(),
+                key: key.to_string(),
+                upload_id: upload_id.to_string(),
+                part_number,
+                content_length: Some(body.len() as i64),
+                body: Some(body.into()),
+                ..Default::default()
+            };
+
+            let e_tag = self
+                .client
+                .upload_part(upload_request)
+                .await
+                .context(UnableToUploadPartToS3 {
+                    bucket: &self.bucket_name,
+                    location: key,
+                })?
+                .e_tag;
+
+            completed_parts.push(rusoto_s3::CompletedPart {
+                e_tag,
+                part_number: Some(part_number),
+            });
+        }
+
+        Ok(completed_parts)
+    }
+
     /// Return the bytes that are stored at the specified location.
     pub async fn get(
         &self,
@@ -313,7 +441,9 @@ impl Error {
 mod tests {
     use crate::{
         path::ObjectStorePath,
-        tests::{get_nonexistent_object, list_with_delimiter, put_get_delete_list},
+        tests::{
+            get_nonexistent_object, list_with_delimiter, put_get_delete_list, put_multipart_get,
+        },
         AmazonS3, Error, ObjectStore,
     };
     use bytes::Bytes;
@@ -409,6 +539,7 @@ mod tests {
         check_credentials(put_get_delete_list(&integration).await)?;
 
         check_credentials(list_with_delimiter(&integration).await).unwrap();
+        check_credentials(put_multipart_get(&integration).await)?;
 
         Ok(())
     }
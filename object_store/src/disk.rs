@@ -4,13 +4,17 @@ use crate::{
     path::{FileConverter, ObjectStorePath},
     DataDoesNotMatchLength, Result, UnableToCopyDataToFile, UnableToCreateDir, UnableToCreateFile,
     UnableToDeleteFile, UnableToListDirectory, UnableToOpenFile, UnableToProcessEntry,
-    UnableToPutDataInMemory, UnableToReadBytes,
+    UnableToPutDataInMemory, UnableToReadBytes, UnableToReadPartData, UnableToRenameFile,
 };
 use bytes::Bytes;
 use futures::{Stream, TryStreamExt};
 use snafu::{ensure, futures::TryStreamExt as _, OptionExt, ResultExt};
-use std::{io, path::PathBuf};
-use tokio::fs;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::{fs, io::AsyncWriteExt};
 use tokio_util::codec::{BytesCodec, FramedRead};
 
 /// Local filesystem storage suitable for testing or for opting out of using a
@@ -53,31 +57,52 @@ impl File {
             }
         );
 
+        // written like a multipart upload, so the location never has a
+        // partially written object
+        let content = content.freeze();
+        self.put_multipart(location, futures::stream::once(async move { Ok(content) }))
+            .await
+    }
+
+    /// Save the parts yielded by the stream to the specified location,
+    /// writing each part to the file as it arrives.
+    ///
+    /// The parts are written to a temporary file next to the location, which
+    /// is renamed to the location once all parts are written, so the location
+    /// never has a partially written object.
+    pub async fn put_multipart<S>(&self, location: &ObjectStorePath, parts: S) -> Result<()>
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + Sync + 'static,
+    {
         let path = self.path(location);
+        let temp_path = temp_path(&path);
 
-        let mut file = match fs::File::create(&path).await {
-            Ok(f) => f,
-            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-                let parent = path
-                    .parent()
-                    .context(UnableToCreateFile { path: &path, err })?;
-                fs::create_dir_all(&parent)
-                    .await
-                    .context(UnableToCreateDir { path: parent })?;
+        let written = async {
+            let mut file = create_file(&temp_path).await?;
 
-                match fs::File::create(&path).await {
-                    Ok(f) => f,
-                    Err(err) => return UnableToCreateFile { path, err }.fail(),
-                }
+            futures::pin_mut!(parts);
+            while let Some(part) = parts.try_next().await.context(UnableToReadPartData)? {
+                file.write_all(&part)
+                    .await
+                    .context(UnableToCopyDataToFile)?;
             }
-            Err(err) => return UnableToCreateFile { path, err }.fail(),
-        };
+            file.flush().await.context(UnableToCopyDataToFile)?;
+            file.sync_all().await.context(UnableToCopyDataToFile)?;
 
-        tokio::io::copy(&mut &content[..], &mut file)
-            .await
-            .context(UnableToCopyDataToFile)?;
+            fs::rename(&temp_path, &path)
+                .await
+                .context(UnableToRenameFile {
+                    from: &temp_path,
+                    to: &path,
+                })
+        }
+        .await;
 
-        Ok(())
+        if written.is_err() {
+            // the temporary file may not exist if creating it failed
+            let _ = fs::remove_file(&temp_path).await;
+        }
+        written
     }
 
     /// Return the bytes that are stored at the specified location.
@@ -132,6 +157,40 @@ impl File {
     }
 }
 
+// creates (or truncates) the file at the path, creating any missing parent
+// directories
+async fn create_file(path: &Path) -> Result<fs::File> {
+    match fs::File::create(path).await {
+        Ok(f) => Ok(f),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let parent = path.parent().context(UnableToCreateFile { path, err })?;
+            fs::create_dir_all(&parent)
+                .await
+                .context(UnableToCreateDir { path: parent })?;
+
+            match fs::File::create(path).await {
+                Ok(f) => Ok(f),
+                Err(err) => UnableToCreateFile { path, err }.fail(),
+            }
+        }
+        Err(err) => UnableToCreateFile { path, err }.fail(),
+    }
+}
+
+// returns a path in the same directory as `path` to write its contents to
+// before they are complete, unique within this process
+fn temp_path(path: &Path) -> PathBuf {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    path.with_file_name(file_name)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -141,7 +200,10 @@ mod tests {
 
     use tempfile::TempDir;
 
-    use crate::{tests::put_get_delete_list, Error, ObjectStore};
+    use crate::{
+        tests::{put_get_delete_list, put_multipart_get},
+        Error, ObjectStore,
+    };
     use futures::stream;
 
     #[tokio::test]
@@ -154,6 +216,27 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn multipart_upload() -> Result<()> {
+        let root = TempDir::new()?;
+        let integration = ObjectStore::new_file(File::new(root.path()));
+
+        put_multipart_get(&integration).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn missing_object_is_not_found() -> Result<()> {
+        let root = TempDir::new()?;
+        let integration = ObjectStore::new_file(File::new(root.path()));
+        let location = ObjectStorePath::from_path_buf_unchecked("missing");
+
+        let err = integration.get(&location).await.err().unwrap();
+        assert!(err.is_not_found());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn length_mismatch_is_an_error() -> Result<()> {
         let root = TempDir::new()?;
@@ -202,4 +285,23 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn failed_multipart_upload_leaves_no_file() -> Result<()> {
+        let root = TempDir::new()?;
+        let storage = ObjectStore::new_file(File::new(root.path()));
+        let location = ObjectStorePath::from_path_buf_unchecked("partial");
+
+        let parts = stream::iter(vec![
+            Ok(Bytes::from("hello")),
+            Err(io::Error::new(io::ErrorKind::Other, "upload interrupted")),
+        ]);
+        let res = storage.put_multipart(&location, parts).await;
+        assert!(matches!(res.err().unwrap(), Error::UnableToReadPartData { .. }));
+
+        assert!(storage.get(&location).await.err().unwrap().is_not_found());
+        assert_eq!(std::fs::read_dir(root.path())?.count(), 0);
+
+        Ok(())
+    }
 }
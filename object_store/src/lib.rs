@@ -32,7 +32,7 @@ use path::ObjectStorePath;
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use futures::{Stream, StreamExt, TryStreamExt};
-use snafu::Snafu;
+use snafu::{ResultExt, Snafu};
 use std::{io, path::PathBuf, unimplemented};
 
 /// Universal interface to multiple object store services.
@@ -82,6 +82,40 @@ impl ObjectStore {
         Ok(())
     }
 
+    /// Save the parts yielded by the stream to the specified location, one
+    /// after another, without requiring the total length up front. Backends
+    /// that support multipart uploads (S3 and local files) write each part as
+    /// it arrives; the others buffer the parts and upload them with `put`.
+    pub async fn put_multipart<S>(&self, location: &ObjectStorePath, parts: S) -> Result<()>
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        use ObjectStoreIntegration::*;
+        match &self.0 {
+            AmazonS3(s3) => s3.put_multipart(location, parts).await?,
+            InMemory(in_mem) => in_mem.put_multipart(location, parts).await?,
+            File(file) => file.put_multipart(location, parts).await?,
+            GoogleCloudStorage(_) | MicrosoftAzure(_) => {
+                let content = parts
+                    .map_ok(|b| bytes::BytesMut::from(&b[..]))
+                    .try_concat()
+                    .await
+                    .context(UnableToReadPartData)?
+                    .freeze();
+                let length = content.len();
+
+                self.put(
+                    location,
+                    futures::stream::once(async move { Ok(content) }),
+                    length,
+                )
+                .await?
+            }
+        }
+
+        Ok(())
+    }
+
     /// Return the bytes that are stored at the specified location.
     pub async fn get(
         &self,
@@ -282,6 +316,30 @@ pub enum Error {
         source: rusoto_core::RusotoError<rusoto_s3::ListObjectsV2Error>,
         bucket: String,
     },
+    UnableToCreateMultipartUploadToS3 {
+        source: rusoto_core::RusotoError<rusoto_s3::CreateMultipartUploadError>,
+        bucket: String,
+        location: String,
+    },
+    UnableToUploadPartToS3 {
+        source: rusoto_core::RusotoError<rusoto_s3::UploadPartError>,
+        bucket: String,
+        location: String,
+    },
+    UnableToCompleteMultipartUploadToS3 {
+        source: rusoto_core::RusotoError<rusoto_s3::CompleteMultipartUploadError>,
+        bucket: String,
+        location: String,
+    },
+    NoUploadIdFromS3 {
+        bucket: String,
+        location: String,
+    },
+
+    #[snafu(display("Unable to read part data: {}", source))]
+    UnableToReadPartData {
+        source: io::Error,
+    },
 
     UnableToPutDataInMemory {
         source: std::io::Error,
@@ -342,6 +400,39 @@ pub enum Error {
     UnableToCopyDataToFile {
         source: io::Error,
     },
+    #[snafu(display("Unable to rename file {} to {}: {}", from.display(), to.display(), source))]
+    UnableToRenameFile {
+        source: io::Error,
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+impl Error {
+    /// Returns true if the error is about an object that doesn't exist,
+    /// whichever object store it came from
+    pub fn is_not_found(&self) -> bool {
+        use rusoto_core::RusotoError;
+
+        match self {
+            Self::NoDataInMemory => true,
+            Self::UnableToOpenFile { source, .. } => source.kind() == io::ErrorKind::NotFound,
+            Self::UnableToGetDataFromS3 { source, .. } => match source {
+                RusotoError::Service(rusoto_s3::GetObjectError::NoSuchKey(_)) => true,
+                RusotoError::Unknown(response) => response.status.as_u16() == 404,
+                _ => false,
+            },
+            Self::UnableToGetDataFromGcs2 {
+                source: cloud_storage::Error::Google(response),
+                ..
+            } => response.error.code == 404,
+            Self::UnableToGetDataFromAzure {
+                source: azure_sdk_core::errors::AzureError::UnexpectedHTTPResult(result),
+                ..
+            } => result.status_code().as_u16() == 404,
+            _ => false,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -420,6 +511,31 @@ mod tests {
         Ok(())
     }
 
+    pub(crate) async fn put_multipart_get(storage: &ObjectStore) -> Result<()> {
+        let location = ObjectStorePath::from_cloud_unchecked("multipart/test_file");
+        let parts = vec![
+            Ok(Bytes::from("first ")),
+            Ok(Bytes::from("second ")),
+            Ok(Bytes::from("third")),
+        ];
+
+        storage
+            .put_multipart(&location, stream::iter(parts))
+            .await?;
+
+        let read_data = storage
+            .get(&location)
+            .await?
+            .map_ok(|b| bytes::BytesMut::from(&b[..]))
+            .try_concat()
+            .await?;
+        assert_eq!(&*read_data, b"first second third");
+
+        storage.delete(&location).await?;
+
+        Ok(())
+    }
+
     pub(crate) async fn list_with_delimiter(storage: &ObjectStore) -> Result<()> {
         delete_fixtures(storage).await;
 
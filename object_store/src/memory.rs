@@ -60,6 +60,22 @@ impl InMemory {
         Ok(())
     }
 
+    /// Save the parts yielded by the stream to the specified location.
+    pub async fn put_multipart<S>(&self, location: &ObjectStorePath, parts: S) -> Result<()>
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        let content = parts
+            .map_ok(|b| bytes::BytesMut::from(&b[..]))
+            .try_concat()
+            .await
+            .context(UnableToPutDataInMemory)?
+            .freeze();
+
+        self.storage.write().await.insert(location.clone(), content);
+        Ok(())
+    }
+
     /// Return the bytes that are stored at the specified location.
     pub async fn get(
         &self,
@@ -157,7 +173,7 @@ mod tests {
     type Result<T, E = TestError> = std::result::Result<T, E>;
 
     use crate::{
-        tests::{list_with_delimiter, put_get_delete_list},
+        tests::{list_with_delimiter, put_get_delete_list, put_multipart_get},
         Error, ObjectStore,
     };
     use futures::stream;
@@ -174,6 +190,25 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn multipart_upload() -> Result<()> {
+        let integration = ObjectStore::new_in_memory(InMemory::new());
+
+        put_multipart_get(&integration).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn missing_object_is_not_found() -> Result<()> {
+        let integration = ObjectStore::new_in_memory(InMemory::new());
+        let location = ObjectStorePath::from_cloud_unchecked("missing");
+
+        let err = integration.get(&location).await.err().unwrap();
+        assert!(err.is_not_found());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn length_mismatch_is_an_error() -> Result<()> {
         let integration = ObjectStore::new_in_memory(InMemory::new());
@@ -3,8 +3,14 @@ use std::sync::Arc;
 use snafu::{ResultExt, Snafu};
 
 use crate::{exec::Executor, Database};
-use arrow_deps::datafusion::{
-    datasource::MemTable, error::DataFusionError, physical_plan::ExecutionPlan,
+use arrow_deps::{
+    arrow::{
+        array::{ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray, UInt64Array},
+        datatypes::{DataType, Field, Schema},
+        error::ArrowError,
+        record_batch::RecordBatch,
+    },
+    datafusion::{datasource::MemTable, error::DataFusionError, physical_plan::ExecutionPlan},
 };
 
 #[derive(Debug, Snafu)]
@@ -35,6 +41,9 @@ pub enum Error {
         table: String,
         source: Box<dyn std::error::Error + Send + Sync>,
     },
+
+    #[snafu(display("Error combining the record batches of table {}: {}", table, source))]
+    CombiningBatches { table: String, source: ArrowError },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -73,17 +82,105 @@ impl SQLQueryPlanner {
                     source: Box::new(e),
                 }
             })?;
-            let schema = data[0].schema().clone();
-            let provider = Box::new(
-                MemTable::try_new(schema, vec![data])
-                    .context(InternalMemTableCreation { table: &table })?,
-            );
+            let provider = mem_table(&table, data)?;
 
             ctx.inner_mut().register_table(&table, provider);
         }
 
         ctx.prepare_sql(query).await.context(Preparing)
     }
+
+    /// Plan a SQL query against the record batches of each table in
+    /// `tables`, such as the rows a coordinator collected from the servers
+    /// of a query group. The batches of a table can have different columns,
+    /// as they come from different servers; the columns a batch doesn't have
+    /// are null for its rows.
+    pub async fn query_batches(
+        &self,
+        query: &str,
+        tables: Vec<(String, Vec<RecordBatch>)>,
+        executor: &Executor,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let mut ctx = executor.new_context();
+
+        for (table, batches) in tables {
+            let data = combine_batches(&batches).context(CombiningBatches { table: &table })?;
+            let provider = mem_table(&table, data)?;
+
+            ctx.inner_mut().register_table(&table, provider);
+        }
+
+        ctx.prepare_sql(query).await.context(Preparing)
+    }
+}
+
+// creates a table provider for the record batches, which have to have the
+// same schema. A table without batches has no columns and no rows.
+fn mem_table(table: &str, data: Vec<RecordBatch>) -> Result<Box<MemTable>> {
+    let schema = match data.first() {
+        Some(batch) => batch.schema().clone(),
+        None => Arc::new(Schema::empty()),
+    };
+
+    let provider =
+        MemTable::try_new(schema, vec![data]).context(InternalMemTableCreation { table })?;
+    Ok(Box::new(provider))
+}
+
+// returns the batches with the union of their columns, in the order they
+// first appear. The columns a batch doesn't have are filled in with nulls.
+fn combine_batches(batches: &[RecordBatch]) -> Result<Vec<RecordBatch>, ArrowError> {
+    let mut fields: Vec<Field> = vec![];
+    for batch in batches {
+        for field in batch.schema().fields() {
+            match fields.iter().find(|f| f.name() == field.name()) {
+                Some(existing) if existing.data_type() != field.data_type() => {
+                    return Err(ArrowError::SchemaError(format!(
+                        "column {} is both {:?} and {:?}",
+                        field.name(),
+                        existing.data_type(),
+                        field.data_type()
+                    )))
+                }
+                Some(_) => {}
+                None => fields.push(Field::new(field.name(), field.data_type().clone(), true)),
+            }
+        }
+    }
+    let schema = Arc::new(Schema::new(fields));
+
+    batches
+        .iter()
+        .map(|batch| {
+            let columns = schema
+                .fields()
+                .iter()
+                .map(|field| match batch.schema().index_of(field.name()) {
+                    Ok(index) => Ok(Arc::clone(batch.column(index))),
+                    Err(_) => null_array(field.data_type(), batch.num_rows()),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            RecordBatch::try_new(Arc::clone(&schema), columns)
+        })
+        .collect()
+}
+
+fn null_array(data_type: &DataType, len: usize) -> Result<ArrayRef, ArrowError> {
+    let array: ArrayRef = match data_type {
+        DataType::Utf8 => Arc::new(StringArray::from(vec![None::<&str>; len])),
+        DataType::Int64 => Arc::new(Int64Array::from(vec![None; len])),
+        DataType::UInt64 => Arc::new(UInt64Array::from(vec![None; len])),
+        DataType::Float64 => Arc::new(Float64Array::from(vec![None; len])),
+        DataType::Boolean => Arc::new(BooleanArray::from(vec![None; len])),
+        _ => {
+            return Err(ArrowError::SchemaError(format!(
+                "can't fill in nulls for a column of type {:?}",
+                data_type
+            )))
+        }
+    };
+    Ok(array)
 }
 
 use sqlparser::{
@@ -94,7 +191,7 @@ use sqlparser::{
 
 /// return a list of table names that appear in the query
 /// TODO find some way to avoid using sql parser direcly here
-fn table_names(query: &str) -> Result<Vec<String>> {
+pub fn table_names(query: &str) -> Result<Vec<String>> {
     let mut tables = vec![];
 
     let dialect = GenericDialect {};
@@ -122,3 +219,40 @@ fn table_names(query: &str) -> Result<Vec<String>> {
     }
     Ok(tables)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_deps::arrow::util::pretty::pretty_format_batches;
+
+    #[test]
+    fn combine_batches_fills_in_missing_columns() {
+        let host = Field::new("host", DataType::Utf8, false);
+        let val = Field::new("val", DataType::Float64, false);
+        let first = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![host.clone()])),
+            vec![Arc::new(StringArray::from(vec!["a"]))],
+        )
+        .unwrap();
+        let second = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![val, host])),
+            vec![
+                Arc::new(Float64Array::from(vec![1.5])),
+                Arc::new(StringArray::from(vec!["b"])),
+            ],
+        )
+        .unwrap();
+
+        let combined = combine_batches(&[first, second]).unwrap();
+        let expected = vec![
+            "+------+-----+",
+            "| host | val |",
+            "+------+-----+",
+            "| a    |     |",
+            "| b    | 1.5 |",
+            "+------+-----+",
+        ];
+        let results = pretty_format_batches(&combined).unwrap();
+        assert_eq!(results.trim().lines().collect::<Vec<_>>(), expected);
+    }
+}
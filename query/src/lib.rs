@@ -128,6 +128,21 @@ pub trait PartitionChunk: Debug + Send + Sync {
         table_name: &str,
         columns: &[&str],
     ) -> Result<(), Self::Error>;
+
+    /// returns the number of row groups the table is stored in, each of which
+    /// `row_group_to_arrow` can convert on its own. 0 if there is no such
+    /// table.
+    fn table_row_groups(&self, table_name: &str) -> Result<usize, Self::Error>;
+
+    /// converts a single row group of the table to an Arrow RecordBatch and
+    /// writes to dst
+    fn row_group_to_arrow(
+        &self,
+        dst: &mut Vec<RecordBatch>,
+        table_name: &str,
+        row_group: usize,
+        columns: &[&str],
+    ) -> Result<(), Self::Error>;
 }
 
 #[async_trait]
@@ -455,6 +455,20 @@ impl PartitionChunk for TestChunk {
     ) -> Result<(), Self::Error> {
         unimplemented!()
     }
+
+    fn table_row_groups(&self, _table_name: &str) -> Result<usize, Self::Error> {
+        unimplemented!()
+    }
+
+    fn row_group_to_arrow(
+        &self,
+        _dst: &mut Vec<RecordBatch>,
+        _table_name: &str,
+        _row_group: usize,
+        _columns: &[&str],
+    ) -> Result<(), Self::Error> {
+        unimplemented!()
+    }
 }
 
 #[derive(Debug)]
@@ -542,7 +556,8 @@ impl TestLPWriter {
             ..Default::default()
         };
 
-        let write = lines_to_replicated_write(self.writer_id, self.sequence_number, &lines, &rules);
+        let write = lines_to_replicated_write(self.writer_id, self.sequence_number, &lines, &rules)
+            .expect("partition template is valid");
         self.sequence_number += 1;
         database
             .store_replicated_write(&write)
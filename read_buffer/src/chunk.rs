@@ -1,8 +1,13 @@
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use arrow_deps::arrow::record_batch::RecordBatch;
+use data_types::partition_metadata::Table as TableStats;
 
 use crate::column::AggregateType;
 use crate::row_group::{ColumnName, Predicate};
 use crate::table::{ReadFilterResults, ReadGroupResults, Table};
+use crate::Result;
 
 type TableName = String;
 
@@ -31,6 +36,99 @@ impl Chunk {
         p
     }
 
+    /// The unique identifier for this chunk.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The total size in bytes of the chunk.
+    pub fn size(&self) -> u64 {
+        self.meta.size
+    }
+
+    /// The total number of rows across all tables in the chunk.
+    pub fn rows(&self) -> u64 {
+        self.meta.rows
+    }
+
+    /// Add a table to the chunk. Panics if the chunk already has a table with
+    /// the same name.
+    pub fn add_table(&mut self, table: Table) {
+        assert!(
+            !self.tables.contains_key(table.name()),
+            "table {} already in chunk",
+            table.name()
+        );
+
+        self.meta.add_table(&table);
+        self.tables.insert(table.name().to_owned(), table);
+    }
+
+    /// Returns the table with the specified name, if any.
+    pub fn table(&self, table_name: &str) -> Option<&Table> {
+        self.tables.get(table_name)
+    }
+
+    /// Returns summary statistics for each table in the chunk.
+    pub fn table_stats(&self) -> Vec<TableStats> {
+        self.tables.values().map(Table::stats).collect()
+    }
+
+    /// Materialises the specified columns of the table, or all of its columns
+    /// if `columns` is empty, as record batches appended to `dst`. Nothing is
+    /// appended if the chunk has no such table.
+    pub fn table_to_arrow(
+        &self,
+        dst: &mut Vec<RecordBatch>,
+        table_name: &str,
+        columns: &[ColumnName<'_>],
+    ) -> Result<()> {
+        if let Some(table) = self.tables.get(table_name) {
+            dst.extend(table.to_arrow(columns)?);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of row groups of the table, or 0 if the chunk has no
+    /// such table.
+    pub fn table_row_groups(&self, table_name: &str) -> usize {
+        self.tables.get(table_name).map_or(0, Table::len)
+    }
+
+    /// Materialises the specified columns of the row group at `position` of
+    /// the table, or all of its columns if `columns` is empty, as a record
+    /// batch appended to `dst`. Nothing is appended if the chunk has no such
+    /// table or row group.
+    pub fn row_group_to_arrow(
+        &self,
+        dst: &mut Vec<RecordBatch>,
+        table_name: &str,
+        position: usize,
+        columns: &[ColumnName<'_>],
+    ) -> Result<()> {
+        if let Some(table) = self.tables.get(table_name) {
+            dst.extend(table.segment_to_arrow(position, columns)?);
+        }
+        Ok(())
+    }
+
+    /// Materialises the rows of the table satisfying the predicates of the
+    /// specified columns, or of all its columns if `columns` is empty, as
+    /// record batches appended to `dst`. Nothing is appended if the chunk has
+    /// no such table.
+    pub fn read_filter_to_arrow(
+        &self,
+        dst: &mut Vec<RecordBatch>,
+        table_name: &str,
+        columns: &[ColumnName<'_>],
+        predicates: &[Predicate<'_>],
+    ) -> Result<()> {
+        if let Some(table) = self.tables.get(table_name) {
+            dst.extend(table.read_filter_to_arrow(columns, predicates)?);
+        }
+        Ok(())
+    }
+
     /// Returns data for the specified column selections on the specified table
     /// name.
     ///
@@ -80,43 +178,55 @@ impl Chunk {
         // TODO(edd): do we want to add the ability to apply a predicate to the
         // table names? For example, a regex where you only want table names
         // beginning with /cpu.+/ or something?
-        todo!()
+        self.tables
+            .values()
+            .filter(|table| table.satisfies_predicates(predicates))
+            .map(|table| table.name().to_owned())
+            .collect()
     }
 
-    /// Returns the distinct set of tag keys (column names) matching the
-    /// provided optional predicates and time range.
+    /// Returns the distinct set of tag keys (column names) of the table with a
+    /// non-null value in any row matching the provided predicates, excluding
+    /// those in `found_keys`.
     pub fn tag_keys(
         &self,
-        table_name: String,
+        table_name: &str,
         predicates: &[Predicate<'_>],
         found_keys: &BTreeSet<ColumnName<'_>>,
     ) -> BTreeSet<ColumnName<'_>> {
-        // Lookup table by name and dispatch execution if the table's time range
-        // overlaps the requested time range *and* there exists columns in the
-        // table's schema that are *not* already found.
-        todo!();
+        match self.tables.get(table_name) {
+            Some(table) => table.tag_keys(predicates, found_keys),
+            None => BTreeSet::new(),
+        }
     }
 
     /// Returns the distinct set of tag values (column values) for each provided
-    /// tag key, where each returned value lives in a row matching the provided
-    /// optional predicates and time range.
+    /// tag key of the table, where each returned value lives in a row matching
+    /// the provided predicates.
     ///
     /// As a special case, if `tag_keys` is empty then all distinct values for
-    /// all columns (tag keys) are returned for the chunk.
+    /// all columns (tag keys) are returned for the table.
     pub fn tag_values(
         &self,
-        table_name: String,
+        table_name: &str,
         predicates: &[Predicate<'_>],
         tag_keys: &[ColumnName<'_>],
-        found_tag_values: &BTreeMap<ColumnName<'_>, BTreeSet<&String>>,
     ) -> BTreeMap<ColumnName<'_>, BTreeSet<&String>> {
-        // Lookup table by name and dispatch execution to the table for that
-        // if the chunk's time range overlaps the requested time range.
-        //
-        // This method also has the ability to short-circuit execution against
-        // columns if those columns only contain values that have already been
-        // found.
-        todo!();
+        match self.tables.get(table_name) {
+            Some(table) => table.tag_values(predicates, tag_keys),
+            None => BTreeMap::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chunk")
+            .field("id", &self.id)
+            .field("tables", &self.tables.keys().collect::<Vec<_>>())
+            .field("size", &self.meta.size)
+            .field("rows", &self.meta.rows)
+            .finish()
     }
 }
 
@@ -144,8 +254,14 @@ impl MetaData {
     }
 
     pub fn add_table(&mut self, table: &Table) {
-        // Update size, rows, time_range
-        todo!()
+        self.size += table.size();
+        self.rows += table.rows();
+        self.time_range = match (self.time_range, table.time_range()) {
+            (Some((min, max)), Some((table_min, table_max))) => {
+                Some((min.min(table_min), max.max(table_max)))
+            }
+            (range, None) | (None, range) => range,
+        };
     }
 
     // invalidate should be called when a table is removed. All meta data must
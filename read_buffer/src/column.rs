@@ -1,3 +1,4 @@
+pub mod boolean;
 pub mod cmp;
 pub mod dictionary;
 pub mod fixed;
@@ -43,8 +44,10 @@ pub enum Column {
     // type probably needs some thought.
     Unsigned(MetaData<u64>, IntegerEncoding), // TODO - 64-bit unsigned integers
 
+    // A column of boolean values.
+    Bool(MetaData<bool>, BooleanEncoding),
+
     // These are TODO
-    Bool,                                         // TODO - booleans
     ByteArray(MetaData<Vec<u8>>, StringEncoding), // TODO - arbitrary bytes
 }
 
@@ -58,7 +61,7 @@ impl Column {
             Column::Float(meta, _) => meta.rows,
             Column::Integer(meta, _) => meta.rows,
             Column::Unsigned(meta, _) => meta.rows,
-            Column::Bool => todo!(),
+            Column::Bool(meta, _) => meta.rows,
             Column::ByteArray(meta, _) => meta.rows,
         }
     }
@@ -98,7 +101,10 @@ impl Column {
                 )),
                 None => None,
             },
-            Column::Bool => todo!(),
+            Column::Bool(meta, _) => match meta.range {
+                Some(range) => Some((OwnedValue::Boolean(range.0), OwnedValue::Boolean(range.1))),
+                None => None,
+            },
             Column::ByteArray(_, _) => todo!(),
         }
     }
@@ -109,7 +115,7 @@ impl Column {
             Column::Float(meta, _) => &meta.properties,
             Column::Integer(meta, _) => &meta.properties,
             Column::Unsigned(meta, _) => &meta.properties,
-            Column::Bool => todo!(),
+            Column::Bool(meta, _) => &meta.properties,
             Column::ByteArray(meta, _) => &meta.properties,
         }
     }
@@ -142,7 +148,7 @@ impl Column {
             Column::Float(_, data) => data.value(row_id),
             Column::Integer(_, data) => data.value(row_id),
             Column::Unsigned(_, data) => data.value(row_id),
-            Column::Bool => todo!(),
+            Column::Bool(_, data) => data.value(row_id),
             Column::ByteArray(_, _) => todo!(),
         }
     }
@@ -163,7 +169,7 @@ impl Column {
             Column::Float(_, data) => data.values(row_ids),
             Column::Integer(_, data) => data.values(row_ids),
             Column::Unsigned(_, data) => data.values(row_ids),
-            Column::Bool => todo!(),
+            Column::Bool(_, data) => data.values(row_ids),
             Column::ByteArray(_, _) => todo!(),
         }
     }
@@ -175,7 +181,7 @@ impl Column {
             Column::Float(_, data) => data.all_values(),
             Column::Integer(_, data) => data.all_values(),
             Column::Unsigned(_, data) => data.all_values(),
-            Column::Bool => todo!(),
+            Column::Bool(_, data) => data.all_values(),
             Column::ByteArray(_, _) => todo!(),
         }
     }
@@ -282,7 +288,7 @@ impl Column {
             Column::Float(_, data) => data.row_ids_filter(op, value.scalar(), dst),
             Column::Integer(_, data) => data.row_ids_filter(op, value.scalar(), dst),
             Column::Unsigned(_, data) => data.row_ids_filter(op, value.scalar(), dst),
-            Column::Bool => todo!(),
+            Column::Bool(_, data) => data.row_ids_filter(op, value.boolean(), dst),
             Column::ByteArray(_, data) => todo!(),
         };
 
@@ -341,7 +347,15 @@ impl Column {
             Column::Unsigned(_, data) => {
                 data.row_ids_filter_range((&low.0, low.1.scalar()), (&high.0, high.1.scalar()), dst)
             }
-            Column::Bool => todo!(),
+            Column::Bool(_, data) => {
+                // there are only two values, so the range is the rows that
+                // satisfy both predicates
+                let mut row_ids = data.row_ids_filter(&low.0, low.1.boolean(), dst);
+                let high_row_ids =
+                    data.row_ids_filter(&high.0, high.1.boolean(), RowIDs::new_bitmap());
+                row_ids.intersect(&high_row_ids);
+                row_ids
+            }
             Column::ByteArray(_, data) => todo!(),
         };
 
@@ -424,7 +438,7 @@ impl Column {
                 .scalar()
                 .try_as_u64()
                 .map_or_else(|| false, |v| meta.might_contain_value(v)),
-            Column::Bool => todo!(),
+            Column::Bool(meta, _) => meta.might_contain_value(value.boolean()),
             Column::ByteArray(meta, _) => todo!(),
         }
     }
@@ -481,7 +495,13 @@ impl Column {
                     .try_as_u64()
                     .map_or_else(|| false, |v| meta.might_match_all_values(op, v))
             }
-            Column::Bool => todo!(),
+            Column::Bool(meta, data) => {
+                if data.contains_null() {
+                    return false;
+                }
+
+                meta.might_match_all_values(op, value.boolean())
+            }
             Column::ByteArray(meta, _) => todo!(),
         }
     }
@@ -505,7 +525,7 @@ impl Column {
             Column::Float(meta, data) => meta.match_no_values(op, value.scalar().as_f64()),
             Column::Integer(meta, data) => meta.match_no_values(op, value.scalar().as_i64()),
             Column::Unsigned(meta, data) => meta.match_no_values(op, value.scalar().as_u64()),
-            Column::Bool => todo!(),
+            Column::Bool(meta, _) => meta.match_no_values(op, value.boolean()),
             Column::ByteArray(meta, _) => todo!(),
         }
     }
@@ -523,7 +543,7 @@ impl Column {
             Column::Float(_, data) => data.min(row_ids),
             Column::Integer(_, data) => data.min(row_ids),
             Column::Unsigned(_, data) => data.min(row_ids),
-            Column::Bool => todo!(),
+            Column::Bool(_, data) => data.min(row_ids),
             Column::ByteArray(_, _) => todo!(),
         }
     }
@@ -537,7 +557,7 @@ impl Column {
             Column::Float(_, data) => data.max(row_ids),
             Column::Integer(_, data) => data.max(row_ids),
             Column::Unsigned(_, data) => data.max(row_ids),
-            Column::Bool => todo!(),
+            Column::Bool(_, data) => data.max(row_ids),
             Column::ByteArray(_, _) => todo!(),
         }
     }
@@ -567,7 +587,7 @@ impl Column {
             Column::Float(_, data) => data.count(row_ids),
             Column::Integer(_, data) => data.count(row_ids),
             Column::Unsigned(_, data) => data.count(row_ids),
-            Column::Bool => todo!(),
+            Column::Bool(_, data) => data.count(row_ids),
             Column::ByteArray(_, _) => todo!(),
         }
     }
@@ -579,7 +599,7 @@ impl Column {
     /// Determines if the column has a non-null value at any of the provided
     /// rows.
     pub fn has_non_null_value(&self, row_ids: &[u32]) -> bool {
-        todo!()
+        self.count(row_ids) > 0
     }
 
     /// Determines if the column contains other values than those provided in
@@ -1256,7 +1276,9 @@ impl IntegerEncoding {
                 c.row_ids_filter_range((low.1.as_u8(), low.0), (high.1.as_u8(), high.0), dst)
             }
 
-            Self::I64I64N(c) => todo!(),
+            Self::I64I64N(c) => {
+                c.row_ids_filter_range((low.1.as_i64(), *low.0), (high.1.as_i64(), *high.0), dst)
+            }
         }
     }
 
@@ -1339,14 +1361,18 @@ impl IntegerEncoding {
 }
 
 pub enum FloatEncoding {
-    Fixed64(fixed::Fixed<f64>), // TODO(edd): encodings for nullable columns
+    Fixed64(fixed::Fixed<f64>),
+
+    // Nullable encodings
+    Fixed64N(fixed_null::FixedNull<arrow::datatypes::Float64Type>),
 }
 
 impl FloatEncoding {
     /// Determines if the column contains a NULL value.
     pub fn contains_null(&self) -> bool {
-        // TODO(edd): when adding the nullable columns then ask the nullable
-        // encoding if it has any null values.
+        if let Self::Fixed64N(c) = &self {
+            return c.contains_null();
+        }
         false
     }
 
@@ -1354,6 +1380,10 @@ impl FloatEncoding {
     pub fn value(&self, row_id: u32) -> Value<'_> {
         match &self {
             Self::Fixed64(c) => Value::Scalar(Scalar::F64(c.value(row_id))),
+            Self::Fixed64N(c) => match c.value(row_id) {
+                Some(v) => Value::Scalar(Scalar::F64(v)),
+                None => Value::Null,
+            },
         }
     }
 
@@ -1363,6 +1393,7 @@ impl FloatEncoding {
     pub fn values(&self, row_ids: &[u32]) -> Values<'_> {
         match &self {
             Self::Fixed64(c) => Values::F64(c.values::<f64>(row_ids, vec![])),
+            Self::Fixed64N(c) => Values::F64N(c.values(row_ids, vec![])),
         }
     }
 
@@ -1372,6 +1403,7 @@ impl FloatEncoding {
     pub fn all_values(&self) -> Values<'_> {
         match &self {
             Self::Fixed64(c) => Values::F64(c.all_values::<f64>(vec![])),
+            Self::Fixed64N(c) => Values::F64N(c.all_values(vec![])),
         }
     }
 
@@ -1383,6 +1415,7 @@ impl FloatEncoding {
     pub fn row_ids_filter(&self, op: &cmp::Operator, value: &Scalar, dst: RowIDs) -> RowIDs {
         match &self {
             FloatEncoding::Fixed64(c) => c.row_ids_filter(value.as_f64(), op, dst),
+            FloatEncoding::Fixed64N(c) => c.row_ids_filter(value.as_f64(), op, dst),
         }
     }
 
@@ -1401,30 +1434,115 @@ impl FloatEncoding {
             FloatEncoding::Fixed64(c) => {
                 c.row_ids_filter_range((low.1.as_f64(), &low.0), (high.1.as_f64(), &high.0), dst)
             }
+            FloatEncoding::Fixed64N(c) => {
+                c.row_ids_filter_range((low.1.as_f64(), *low.0), (high.1.as_f64(), *high.0), dst)
+            }
         }
     }
 
     pub fn min(&self, row_ids: &[u32]) -> Value<'_> {
         match &self {
             FloatEncoding::Fixed64(c) => Value::Scalar(Scalar::F64(c.min(row_ids))),
+            FloatEncoding::Fixed64N(c) => match c.min(row_ids) {
+                Some(v) => Value::Scalar(Scalar::F64(v)),
+                None => Value::Null,
+            },
         }
     }
 
     pub fn max(&self, row_ids: &[u32]) -> Value<'_> {
         match &self {
             FloatEncoding::Fixed64(c) => Value::Scalar(Scalar::F64(c.max(row_ids))),
+            FloatEncoding::Fixed64N(c) => match c.max(row_ids) {
+                Some(v) => Value::Scalar(Scalar::F64(v)),
+                None => Value::Null,
+            },
         }
     }
 
     pub fn sum(&self, row_ids: &[u32]) -> Scalar {
         match &self {
             FloatEncoding::Fixed64(c) => Scalar::F64(c.sum(row_ids)),
+            FloatEncoding::Fixed64N(c) => match c.sum(row_ids) {
+                Some(v) => Scalar::F64(v),
+                None => Scalar::Null,
+            },
         }
     }
 
     pub fn count(&self, row_ids: &[u32]) -> u32 {
         match &self {
             FloatEncoding::Fixed64(c) => c.count(row_ids),
+            FloatEncoding::Fixed64N(c) => c.count(row_ids),
+        }
+    }
+}
+
+pub enum BooleanEncoding {
+    // Nullable encodings
+    BooleanNull(boolean::Bool),
+}
+
+impl BooleanEncoding {
+    /// Determines if the column contains a NULL value.
+    pub fn contains_null(&self) -> bool {
+        match &self {
+            Self::BooleanNull(c) => c.contains_null(),
+        }
+    }
+
+    /// Returns the logical value found at the provided row id.
+    pub fn value(&self, row_id: u32) -> Value<'_> {
+        match &self {
+            Self::BooleanNull(c) => match c.value(row_id) {
+                Some(v) => Value::Boolean(v),
+                None => Value::Null,
+            },
+        }
+    }
+
+    /// Returns the logical values found at the provided row ids.
+    pub fn values(&self, row_ids: &[u32]) -> Values<'_> {
+        match &self {
+            Self::BooleanNull(c) => Values::Bool(c.values(row_ids, vec![])),
+        }
+    }
+
+    /// Returns all logical values in the column.
+    pub fn all_values(&self) -> Values<'_> {
+        match &self {
+            Self::BooleanNull(c) => Values::Bool(c.all_values(vec![])),
+        }
+    }
+
+    /// Returns the row ids that satisfy the provided predicate.
+    pub fn row_ids_filter(&self, op: &cmp::Operator, value: bool, dst: RowIDs) -> RowIDs {
+        match &self {
+            Self::BooleanNull(c) => c.row_ids_filter(value, op, dst),
+        }
+    }
+
+    pub fn min(&self, row_ids: &[u32]) -> Value<'_> {
+        match &self {
+            Self::BooleanNull(c) => match c.min(row_ids) {
+                Some(v) => Value::Boolean(v),
+                None => Value::Null,
+            },
+        }
+    }
+
+    pub fn max(&self, row_ids: &[u32]) -> Value<'_> {
+        match &self {
+            Self::BooleanNull(c) => match c.max(row_ids) {
+                Some(v) => Value::Boolean(v),
+                None => Value::Null,
+            },
+        }
+    }
+
+    pub fn count(&self, row_ids: &[u32]) -> u32 {
+        match &self {
+            Self::BooleanNull(c) => c.count(row_ids),
         }
     }
 }
@@ -1972,6 +2090,72 @@ impl From<&[f64]> for Column {
     }
 }
 
+/// Converts an Arrow `Float64Array` into a column. Arrays without NULL values
+/// use the non-nullable fixed-width encoding.
+impl From<arrow::array::Float64Array> for Column {
+    fn from(arr: arrow::array::Float64Array) -> Self {
+        if arr.null_count() == 0 {
+            return Self::from(arr.value_slice(0, arr.len()));
+        }
+
+        // determine min and max values.
+        let mut range: Option<(f64, f64)> = None;
+        for i in 0..arr.len() {
+            if arr.is_null(i) {
+                continue;
+            }
+
+            let v = arr.value(i);
+            range = match range {
+                Some((min, max)) => Some((min.min(v), max.max(v))),
+                None => Some((v, v)),
+            };
+        }
+
+        let data = fixed_null::FixedNull::<arrow::datatypes::Float64Type>::from(arr);
+        let meta = MetaData {
+            size: data.size(),
+            rows: data.num_rows(),
+            range,
+            ..MetaData::default()
+        };
+        Column::Float(meta, FloatEncoding::Fixed64N(data))
+    }
+}
+
+impl From<arrow::array::BooleanArray> for Column {
+    fn from(arr: arrow::array::BooleanArray) -> Self {
+        // determine min and max values.
+        let mut range: Option<(bool, bool)> = None;
+        for i in 0..arr.len() {
+            if arr.is_null(i) {
+                continue;
+            }
+
+            let v = arr.value(i);
+            range = match range {
+                Some((min, max)) => Some((min & v, max | v)),
+                None => Some((v, v)),
+            };
+        }
+
+        let data = boolean::Bool::from(arr);
+        let meta = MetaData {
+            size: data.size(),
+            rows: data.num_rows(),
+            range,
+            ..MetaData::default()
+        };
+        Column::Bool(meta, BooleanEncoding::BooleanNull(data))
+    }
+}
+
+impl From<&[Option<bool>]> for Column {
+    fn from(arr: &[Option<bool>]) -> Self {
+        Self::from(arrow::array::BooleanArray::from(arr.to_vec()))
+    }
+}
+
 /// These variants describe supported aggregates that can applied to columnar
 /// data.
 #[derive(Copy, Clone)]
@@ -2325,6 +2509,7 @@ impl PartialEq<Value<'_>> for OwnedValue {
         match (&self, other) {
             (OwnedValue::String(a), Value::String(b)) => a == b,
             (OwnedValue::Scalar(a), Value::Scalar(b)) => a == b,
+            (OwnedValue::Boolean(a), Value::Boolean(b)) => a == b,
             _ => false,
         }
     }
@@ -2335,6 +2520,7 @@ impl PartialOrd<Value<'_>> for OwnedValue {
         match (&self, other) {
             (OwnedValue::String(a), Value::String(b)) => Some(a.as_str().cmp(b)),
             (OwnedValue::Scalar(a), Value::Scalar(b)) => a.partial_cmp(b),
+            (OwnedValue::Boolean(a), Value::Boolean(b)) => a.partial_cmp(b),
             _ => None,
         }
     }
@@ -2377,6 +2563,13 @@ impl Value<'_> {
         }
         panic!("cannot unwrap Value to String");
     }
+
+    pub fn boolean(&self) -> bool {
+        if let Self::Boolean(b) = self {
+            return *b;
+        }
+        panic!("cannot unwrap Value to Boolean");
+    }
 }
 
 impl std::fmt::Display for Value<'_> {
@@ -2402,6 +2595,12 @@ impl<'a> From<&'a str> for Value<'a> {
     }
 }
 
+impl From<bool> for Value<'_> {
+    fn from(v: bool) -> Self {
+        Self::Boolean(v)
+    }
+}
+
 // Implementations of From trait for various concrete types.
 macro_rules! scalar_from_impls {
     ($(($variant:ident, $type:ident),)*) => {
@@ -2494,6 +2693,25 @@ impl<'a> Values<'a> {
     }
 }
 
+/// Converts materialised values into the Arrow array of the same logical type.
+impl From<Values<'_>> for arrow::array::ArrayRef {
+    fn from(values: Values<'_>) -> Self {
+        use std::sync::Arc;
+
+        match values {
+            Values::String(values) => Arc::new(arrow::array::StringArray::from(values)),
+            Values::I64(values) => Arc::new(arrow::array::Int64Array::from(values)),
+            Values::U64(values) => Arc::new(arrow::array::UInt64Array::from(values)),
+            Values::F64(values) => Arc::new(arrow::array::Float64Array::from(values)),
+            Values::I64N(values) => Arc::new(arrow::array::Int64Array::from(values)),
+            Values::U64N(values) => Arc::new(arrow::array::UInt64Array::from(values)),
+            Values::F64N(values) => Arc::new(arrow::array::Float64Array::from(values)),
+            Values::Bool(values) => Arc::new(arrow::array::BooleanArray::from(values)),
+            Values::ByteArray(values) => Arc::new(arrow::array::BinaryArray::from(values)),
+        }
+    }
+}
+
 pub struct ValuesIterator<'a> {
     v: &'a Values<'a>,
     next_i: usize,
@@ -3494,6 +3712,37 @@ mod test {
         assert!(matches!(row_ids, RowIDsOption::All(_)));
     }
 
+    #[test]
+    fn row_ids_filter_bool() {
+        let input = &[Some(true), None, Some(false), Some(true)];
+
+        let col = Column::from(&input[..]);
+        let mut row_ids = col.row_ids_filter(
+            &cmp::Operator::Equal,
+            &Value::from(true),
+            RowIDs::new_bitmap(),
+        );
+        assert_eq!(row_ids.unwrap().to_vec(), vec![0, 3]);
+
+        row_ids = col.row_ids_filter(
+            &cmp::Operator::NotEqual,
+            &Value::from(true),
+            RowIDs::new_bitmap(),
+        );
+        assert_eq!(row_ids.unwrap().to_vec(), vec![2]);
+
+        assert_eq!(col.count(&[0, 1, 2, 3]), 3);
+        assert_eq!(col.values(&[0, 1]), Values::Bool(vec![Some(true), None]));
+
+        let col = Column::from(&[Some(true), Some(true)][..]);
+        row_ids = col.row_ids_filter(
+            &cmp::Operator::Equal,
+            &Value::from(false),
+            RowIDs::new_bitmap(),
+        );
+        assert!(matches!(row_ids, RowIDsOption::None(_)));
+    }
+
     #[test]
     fn row_ids_filter_float() {
         let input = &[100.2, 200.0, 300.1, 2.22, -200.2, 22.2, 30.2];
@@ -3597,6 +3846,26 @@ mod test {
         assert_eq!(row_ids.unwrap().to_vec(), vec![0, 1, 2, 3, 4, 5, 6]);
     }
 
+    #[test]
+    fn row_ids_range_bool() {
+        let input = &[Some(true), None, Some(false), Some(true)];
+
+        let col = Column::from(&input[..]);
+        let row_ids = col.row_ids_filter_range(
+            &(cmp::Operator::GTE, Value::Boolean(true)),
+            &(cmp::Operator::Equal, Value::Boolean(true)),
+            RowIDs::new_bitmap(),
+        );
+        assert_eq!(row_ids.unwrap().to_vec(), vec![0, 3]);
+
+        let row_ids = col.row_ids_filter_range(
+            &(cmp::Operator::GT, Value::Boolean(false)),
+            &(cmp::Operator::NotEqual, Value::Boolean(true)),
+            RowIDs::new_bitmap(),
+        );
+        assert!(matches!(row_ids, RowIDsOption::None(_)));
+    }
+
     #[test]
     fn might_contain_value() {
         let input = &[100i64, 200, 300, 2, 200, 22, 30, -1228282828282];
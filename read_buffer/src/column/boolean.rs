@@ -0,0 +1,211 @@
+//! An encoding for nullable boolean values backed by an Arrow array.
+//!
+//! Arrow already packs boolean values into a bitmap, so this encoding doesn't
+//! try to compress the values any further.
+use arrow_deps::arrow::array::{Array, BooleanArray};
+
+use crate::column::{cmp, RowIDs};
+
+#[derive(Debug)]
+pub struct Bool {
+    // backing data
+    arr: BooleanArray,
+}
+
+impl std::fmt::Display for Bool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[Bool] rows: {:?}, nulls: {:?}, size: {}",
+            self.arr.len(),
+            self.arr.null_count(),
+            self.size()
+        )
+    }
+}
+
+impl Bool {
+    pub fn num_rows(&self) -> u32 {
+        self.arr.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arr.is_empty()
+    }
+
+    pub fn contains_null(&self) -> bool {
+        self.arr.null_count() > 0
+    }
+
+    /// Returns the total size in bytes of the encoded data.
+    pub fn size(&self) -> u64 {
+        0
+    }
+
+    //
+    //
+    // ---- Methods for getting decoded (materialised) values.
+    //
+    //
+
+    /// Return the logical (decoded) value at the provided row ID. A NULL value
+    /// is represented by None.
+    pub fn value(&self, row_id: u32) -> Option<bool> {
+        if self.arr.is_null(row_id as usize) {
+            return None;
+        }
+        Some(self.arr.value(row_id as usize))
+    }
+
+    /// Returns the logical (decoded) values for the provided row IDs.
+    ///
+    /// NULL values are represented by None.
+    pub fn values(&self, row_ids: &[u32], mut dst: Vec<Option<bool>>) -> Vec<Option<bool>> {
+        dst.clear();
+        dst.reserve(row_ids.len());
+
+        for &row_id in row_ids {
+            dst.push(self.value(row_id));
+        }
+        dst
+    }
+
+    /// Returns the logical (decoded) values for all the rows in the column.
+    ///
+    /// NULL values are represented by None.
+    pub fn all_values(&self, mut dst: Vec<Option<bool>>) -> Vec<Option<bool>> {
+        dst.clear();
+        dst.reserve(self.arr.len());
+
+        for i in 0..self.num_rows() {
+            dst.push(self.value(i));
+        }
+        dst
+    }
+
+    //
+    //
+    // ---- Methods for aggregation.
+    //
+    //
+
+    /// Returns the count of the non-null values for the provided row IDs.
+    pub fn count(&self, row_ids: &[u32]) -> u32 {
+        if self.arr.null_count() == 0 {
+            return row_ids.len() as u32;
+        }
+
+        row_ids
+            .iter()
+            .filter(|&&i| !self.arr.is_null(i as usize))
+            .count() as u32
+    }
+
+    /// Returns the minimum non-null value from the provided row IDs, where
+    /// `false` is smaller than `true`.
+    pub fn min(&self, row_ids: &[u32]) -> Option<bool> {
+        row_ids.iter().filter_map(|&i| self.value(i)).min()
+    }
+
+    /// Returns the maximum non-null value from the provided row IDs, where
+    /// `false` is smaller than `true`.
+    pub fn max(&self, row_ids: &[u32]) -> Option<bool> {
+        row_ids.iter().filter_map(|&i| self.value(i)).max()
+    }
+
+    //
+    //
+    // ---- Methods for filtering via operators.
+    //
+    //
+
+    /// Returns the set of row ids that satisfy a binary operator on a logical
+    /// value, with `false` ordered before `true`.
+    ///
+    /// NULL values never satisfy the operator.
+    pub fn row_ids_filter(&self, value: bool, op: &cmp::Operator, mut dst: RowIDs) -> RowIDs {
+        dst.clear();
+
+        let mut found = false;
+        let mut count = 0;
+        for i in 0..self.num_rows() {
+            let matches = match self.value(i) {
+                Some(v) => match op {
+                    cmp::Operator::Equal => v == value,
+                    cmp::Operator::NotEqual => v != value,
+                    cmp::Operator::GT => v & !value,
+                    cmp::Operator::GTE => v >= value,
+                    cmp::Operator::LT => !v & value,
+                    cmp::Operator::LTE => v <= value,
+                },
+                None => false,
+            };
+
+            if !matches && found {
+                dst.add_range(i - count, i);
+                found = false;
+                count = 0;
+                continue;
+            } else if !matches {
+                continue;
+            }
+
+            found = true;
+            count += 1;
+        }
+
+        // add any remaining range.
+        if found {
+            dst.add_range(self.num_rows() - count, self.num_rows());
+        }
+        dst
+    }
+}
+
+impl From<BooleanArray> for Bool {
+    fn from(arr: BooleanArray) -> Self {
+        Self { arr }
+    }
+}
+
+impl From<&[Option<bool>]> for Bool {
+    fn from(v: &[Option<bool>]) -> Self {
+        Self {
+            arr: BooleanArray::from(v.to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::cmp::Operator;
+    use super::*;
+
+    #[test]
+    fn row_ids_filter() {
+        let v = Bool::from(&[Some(true), Some(true), None, Some(false), Some(true)][..]);
+
+        let dst = v.row_ids_filter(true, &Operator::Equal, RowIDs::new_vector());
+        assert_eq!(dst.unwrap_vector(), &vec![0, 1, 4]);
+
+        let dst = v.row_ids_filter(true, &Operator::NotEqual, RowIDs::new_vector());
+        assert_eq!(dst.unwrap_vector(), &vec![3]);
+
+        let dst = v.row_ids_filter(false, &Operator::GT, RowIDs::new_vector());
+        assert_eq!(dst.unwrap_vector(), &vec![0, 1, 4]);
+
+        let dst = v.row_ids_filter(true, &Operator::LTE, RowIDs::new_vector());
+        assert_eq!(dst.unwrap_vector(), &vec![0, 1, 3, 4]);
+    }
+
+    #[test]
+    fn aggregates() {
+        let v = Bool::from(&[None, Some(true), Some(false), None][..]);
+
+        assert_eq!(v.count(&[0, 1, 2, 3]), 2);
+        assert_eq!(v.min(&[0, 1, 2, 3]), Some(false));
+        assert_eq!(v.max(&[0, 1, 2, 3]), Some(true));
+        assert_eq!(v.max(&[0, 3]), None);
+        assert_eq!(v.values(&[1, 3], vec![]), vec![Some(true), None]);
+    }
+}
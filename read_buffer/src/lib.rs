@@ -2,19 +2,51 @@
 #![allow(dead_code)]
 #![allow(clippy::too_many_arguments)]
 #![allow(unused_variables)]
-pub(crate) mod chunk;
+pub mod chunk;
 pub mod column;
 pub mod row_group;
-pub(crate) mod table;
+pub mod table;
 
-use std::{collections::BTreeMap, fmt};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
 
-use arrow_deps::arrow::record_batch::RecordBatch;
+use arrow_deps::arrow::{datatypes::DataType, record_batch::RecordBatch};
+use snafu::Snafu;
 
 use chunk::Chunk;
 use column::AggregateType;
 use row_group::{ColumnName, Predicate};
 
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display(
+        "Column {} has type {:?}, which the read buffer doesn't support",
+        column_name,
+        data_type
+    ))]
+    UnsupportedColumnType {
+        column_name: String,
+        data_type: DataType,
+    },
+
+    #[snafu(display("Row group has no time column"))]
+    MissingTimeColumn,
+
+    #[snafu(display("Column {} not found in table {}", column_name, table_name))]
+    ColumnNotFound {
+        table_name: String,
+        column_name: String,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
 /// Generate a predicate for the time range [from, to).
 pub fn time_range_predicate<'a>(from: i64, to: i64) -> Vec<row_group::Predicate<'a>> {
     vec![
@@ -40,21 +72,27 @@ pub fn time_range_predicate<'a>(from: i64, to: i64) -> Vec<row_group::Predicate<
 // measurement name.
 #[derive(Default)]
 pub struct Database {
-    // The collection of chunks in the database. Each chunk is uniquely
-    // identified by a chunk key.
-    chunks: BTreeMap<String, Chunk>,
+    // The collection of chunks in the database, by the key of the partition
+    // they belong to. Each chunk is uniquely identified by its id within the
+    // partition.
+    partitions: RwLock<BTreeMap<String, BTreeMap<u32, Arc<Chunk>>>>,
 
-    // The current total size of the database.
-    size: u64,
+    // The total size in bytes of all chunks, kept up to date as chunks are
+    // added and removed.
+    size: AtomicU64,
 }
 
 impl fmt::Debug for Database {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let chunk_ids: Vec<_> = self.chunks.iter().map(|(id, _chunk)| id).collect();
+        let partitions = self.partitions.read().expect("lock poisoned");
+        let chunk_ids: BTreeMap<_, Vec<_>> = partitions
+            .iter()
+            .map(|(key, chunks)| (key, chunks.keys().collect()))
+            .collect();
 
         f.debug_struct("Database")
             .field("chunks", &chunk_ids)
-            .field("size", &self.size)
+            .field("size", &self.size())
             .finish()
     }
 }
@@ -64,16 +102,89 @@ impl Database {
         Self::default()
     }
 
-    pub fn add_chunk(&mut self, chunk: Chunk) {
-        todo!()
+    /// Adds the chunk to the partition, replacing any chunk with the same id.
+    pub fn add_chunk(&self, partition_key: &str, chunk: Chunk) -> Arc<Chunk> {
+        let chunk = Arc::new(chunk);
+        let mut partitions = self.partitions.write().expect("lock poisoned");
+        self.size.fetch_add(chunk.size(), Ordering::SeqCst);
+        let replaced = partitions
+            .entry(partition_key.to_string())
+            .or_default()
+            .insert(chunk.id(), Arc::clone(&chunk));
+        if let Some(replaced) = replaced {
+            self.size.fetch_sub(replaced.size(), Ordering::SeqCst);
+        }
+        chunk
     }
 
-    pub fn remove_chunk(&mut self, chunk: Chunk) {
-        todo!()
+    /// Replaces the chunk of the partition that has the same id as `chunk`,
+    /// returning the new chunk, or `None` if there is no such chunk, for
+    /// example because it was removed in the meantime.
+    pub fn replace_chunk(&self, partition_key: &str, chunk: Chunk) -> Option<Arc<Chunk>> {
+        let mut partitions = self.partitions.write().expect("lock poisoned");
+        let replaced = partitions.get_mut(partition_key)?.get_mut(&chunk.id())?;
+        let chunk = Arc::new(chunk);
+        self.size.fetch_add(chunk.size(), Ordering::SeqCst);
+        self.size.fetch_sub(replaced.size(), Ordering::SeqCst);
+        *replaced = Arc::clone(&chunk);
+        Some(chunk)
+    }
+
+    /// Removes the chunk from the partition, returning it if it existed. The
+    /// chunk's memory is freed once any running queries are done with it.
+    pub fn remove_chunk(&self, partition_key: &str, chunk_id: u32) -> Option<Arc<Chunk>> {
+        let mut partitions = self.partitions.write().expect("lock poisoned");
+        let chunks = partitions.get_mut(partition_key)?;
+        let chunk = chunks.remove(&chunk_id);
+        if chunks.is_empty() {
+            partitions.remove(partition_key);
+        }
+        if let Some(chunk) = &chunk {
+            self.size.fetch_sub(chunk.size(), Ordering::SeqCst);
+        }
+        chunk
+    }
+
+    /// Returns the chunk of the partition with the specified id, if any.
+    pub fn chunk(&self, partition_key: &str, chunk_id: u32) -> Option<Arc<Chunk>> {
+        let partitions = self.partitions.read().expect("lock poisoned");
+        partitions.get(partition_key)?.get(&chunk_id).cloned()
+    }
+
+    /// Returns the chunks of the partition, ordered by id.
+    pub fn chunks(&self, partition_key: &str) -> Vec<Arc<Chunk>> {
+        let partitions = self.partitions.read().expect("lock poisoned");
+        partitions
+            .get(partition_key)
+            .map(|chunks| chunks.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the keys of the partitions that have chunks, in order.
+    pub fn partition_keys(&self) -> Vec<String> {
+        let partitions = self.partitions.read().expect("lock poisoned");
+        partitions.keys().cloned().collect()
     }
 
+    /// The total size in bytes of all chunks in the database.
     pub fn size(&self) -> u64 {
-        self.size
+        self.size.load(Ordering::SeqCst)
+    }
+
+    /// Materialises the specified columns of the table, or all of its columns
+    /// if `columns` is empty, from every chunk that has the table.
+    pub fn table_to_arrow(
+        &self,
+        table_name: &str,
+        columns: &[ColumnName<'_>],
+    ) -> Result<Vec<RecordBatch>> {
+        let partitions = self.partitions.read().expect("lock poisoned");
+        let mut batches = vec![];
+        for chunk in partitions.values().flat_map(|chunks| chunks.values()) {
+            chunk.table_to_arrow(&mut batches, table_name, columns)?;
+        }
+
+        Ok(batches)
     }
 
     /// Executes selections against matching chunks, returning a single
@@ -1,12 +1,24 @@
-use std::{borrow::Cow, collections::BTreeMap};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    sync::Arc,
+};
 
+use arrow_deps::arrow::{
+    array::{self, Array, ArrayRef},
+    datatypes::{DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+use data_types::{column_type_metadata_key, FIELD_COLUMN_TYPE, TAG_COLUMN_TYPE};
 use hashbrown::{hash_map, HashMap};
 use itertools::Itertools;
 
 use crate::column::{
     cmp::Operator, AggregateResult, AggregateType, Column, EncodedValues, OwnedValue, RowIDs,
-    RowIDsOption, Scalar, Value, Values, ValuesIterator,
+    RowIDsOption, Scalar, Value, ValueSet, Values, ValuesIterator,
 };
+use crate::{Error, MissingTimeColumn, Result, UnsupportedColumnType};
 
 /// The name used for a timestamp column.
 pub const TIME_COLUMN_NAME: &str = data_types::TIME_COLUMN_NAME;
@@ -134,6 +146,18 @@ impl RowGroup {
         self.meta.time_range
     }
 
+    /// The number of non-null values in the named column, or zero if the
+    /// `RowGroup` has no such column.
+    pub fn column_count(&self, name: ColumnName<'_>) -> u32 {
+        match self.all_columns_by_name.get(name) {
+            Some(&index) => {
+                let row_ids = (0..self.rows()).collect::<Vec<_>>();
+                self.columns[index].count(&row_ids)
+            }
+            None => 0,
+        }
+    }
+
     /// Efficiently determine if the provided predicate might be satisfied by
     /// the provided column.
     pub fn column_could_satisfy_predicate(
@@ -162,6 +186,161 @@ impl RowGroup {
         ReadFilterResult(self.materialise_rows(columns, row_ids))
     }
 
+    /// Materialises the rows satisfying the predicates of the provided
+    /// columns as a record batch, or returns `None` if no rows satisfy them.
+    /// The IOx column type of tags and fields is kept in the schema metadata.
+    ///
+    /// It is the caller's responsibility to ensure the columns exist in the
+    /// `RowGroup`.
+    pub fn read_filter_to_arrow(
+        &self,
+        columns: &[ColumnName<'_>],
+        predicates: &[Predicate<'_>],
+    ) -> Option<RecordBatch> {
+        match self.row_ids_from_predicates(predicates) {
+            RowIDsOption::None(_) => None,
+            row_ids => Some(self.materialise_to_arrow(columns, row_ids)),
+        }
+    }
+
+    /// Materialises every row of the provided columns as a record batch. All
+    /// fields in the schema are nullable and the IOx column type of tags and
+    /// fields is kept in the schema metadata.
+    ///
+    /// It is the caller's responsibility to ensure the columns exist in the
+    /// `RowGroup`.
+    pub fn to_arrow(&self, columns: &[ColumnName<'_>]) -> RecordBatch {
+        self.materialise_to_arrow(columns, RowIDsOption::All(RowIDs::new_vector()))
+    }
+
+    fn materialise_to_arrow(
+        &self,
+        columns: &[ColumnName<'_>],
+        row_ids: RowIDsOption,
+    ) -> RecordBatch {
+        let row_ids = match row_ids {
+            RowIDsOption::None(_) => Some(vec![]),
+            RowIDsOption::Some(row_ids) => Some(row_ids.to_vec()),
+            RowIDsOption::All(_) => None,
+        };
+
+        let mut fields = Vec::with_capacity(columns.len());
+        let mut arrays = Vec::with_capacity(columns.len());
+        let mut metadata = std::collections::HashMap::new();
+        for &name in columns {
+            let column = self.column_by_name(name);
+            let array = ArrayRef::from(match &row_ids {
+                Some(row_ids) => column.values(row_ids),
+                None => column.all_values(),
+            });
+
+            let column_type = if self.tag_columns_by_name.contains_key(name) {
+                Some(TAG_COLUMN_TYPE)
+            } else if self.field_columns_by_name.contains_key(name) {
+                Some(FIELD_COLUMN_TYPE)
+            } else {
+                None
+            };
+            if let Some(column_type) = column_type {
+                metadata.insert(column_type_metadata_key(name), column_type.to_string());
+            }
+
+            fields.push(Field::new(name, array.data_type().clone(), true));
+            arrays.push(array);
+        }
+
+        RecordBatch::try_new(
+            Arc::new(Schema::new_with_metadata(fields, metadata)),
+            arrays,
+        )
+        .expect("columns of a row group have the same number of rows")
+    }
+
+    /// Determines if any row satisfies the predicates.
+    ///
+    /// It is the caller's responsibility to ensure the columns of the
+    /// predicates exist in the `RowGroup`.
+    pub fn satisfies_predicates(&self, predicates: &[Predicate<'_>]) -> bool {
+        self.rows() > 0
+            && !matches!(
+                self.row_ids_from_predicates(predicates),
+                RowIDsOption::None(_)
+            )
+    }
+
+    /// Adds the names of the tag columns that have a non-null value in any
+    /// row satisfying the predicates to `dst`, skipping those already in
+    /// `found_keys` or `dst`.
+    ///
+    /// It is the caller's responsibility to ensure the columns of the
+    /// predicates exist in the `RowGroup`.
+    pub fn tag_keys<'a>(
+        &'a self,
+        predicates: &[Predicate<'_>],
+        found_keys: &BTreeSet<ColumnName<'_>>,
+        dst: &mut BTreeSet<ColumnName<'a>>,
+    ) {
+        let candidates = self
+            .tag_columns_by_name
+            .iter()
+            .filter(|(name, _)| !found_keys.contains(name.as_str()) && !dst.contains(name.as_str()))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return;
+        }
+
+        let row_ids = match self.matching_row_ids(predicates) {
+            Some(row_ids) => row_ids,
+            None => return,
+        };
+        for (name, &index) in candidates {
+            if self.columns[index].has_non_null_value(&row_ids) {
+                dst.insert(name.as_str());
+            }
+        }
+    }
+
+    /// Adds the distinct non-null values of the tag columns in the rows
+    /// satisfying the predicates to `dst`. Only the values of the tag columns
+    /// in `tag_keys` are added, or of all tag columns if it is empty.
+    ///
+    /// It is the caller's responsibility to ensure the columns of the
+    /// predicates exist in the `RowGroup`.
+    pub fn tag_values<'a>(
+        &'a self,
+        predicates: &[Predicate<'_>],
+        tag_keys: &[ColumnName<'_>],
+        dst: &mut BTreeMap<ColumnName<'a>, BTreeSet<&'a String>>,
+    ) {
+        let row_ids = match self.matching_row_ids(predicates) {
+            Some(row_ids) => row_ids,
+            None => return,
+        };
+
+        for (name, &index) in &self.tag_columns_by_name {
+            if !tag_keys.is_empty() && !tag_keys.iter().any(|key| *key == name.as_str()) {
+                continue;
+            }
+
+            if let ValueSet::String(values) = self.columns[index].distinct_values(&row_ids) {
+                let mut values = values.into_iter().flatten().peekable();
+                if values.peek().is_some() {
+                    dst.entry(name.as_str()).or_default().extend(values);
+                }
+            }
+        }
+    }
+
+    // Returns the ids of the rows satisfying the predicates, or `None` if no
+    // rows satisfy them.
+    fn matching_row_ids(&self, predicates: &[Predicate<'_>]) -> Option<Vec<u32>> {
+        match self.row_ids_from_predicates(predicates) {
+            RowIDsOption::None(_) => None,
+            RowIDsOption::Some(row_ids) => Some(row_ids.to_vec()),
+            RowIDsOption::All(_) => Some((0..self.rows()).collect()),
+        }
+    }
+
     fn materialise_rows(
         &self,
         names: &[ColumnName<'_>],
@@ -888,6 +1067,71 @@ impl ColumnType {
     }
 }
 
+/// Converts a record batch into a `RowGroup`, choosing an encoding for each
+/// column. The column named `TIME_COLUMN_NAME` becomes the time column and
+/// numeric and boolean columns become fields. String columns become fields if
+/// the schema metadata gives them the IOx field column type, and tags
+/// otherwise.
+impl TryFrom<RecordBatch> for RowGroup {
+    type Error = Error;
+
+    fn try_from(rb: RecordBatch) -> Result<Self, Self::Error> {
+        let schema = rb.schema();
+        let metadata = schema.metadata();
+        let mut columns = BTreeMap::new();
+
+        for (field, array) in schema.fields().iter().zip(rb.columns()) {
+            let column_name = field.name();
+            let column = match array.data_type() {
+                DataType::Int64 if column_name == TIME_COLUMN_NAME => {
+                    ColumnType::Time(integer_column(array))
+                }
+                DataType::Int64 => ColumnType::Field(integer_column(array)),
+                DataType::Float64 => {
+                    ColumnType::Field(Column::from(array::Float64Array::from(array.data())))
+                }
+                DataType::Boolean => {
+                    ColumnType::Field(Column::from(array::BooleanArray::from(array.data())))
+                }
+                DataType::Utf8 => {
+                    let column = Column::from(array::StringArray::from(array.data()));
+                    match metadata.get(&column_type_metadata_key(column_name)) {
+                        Some(column_type) if column_type == FIELD_COLUMN_TYPE => {
+                            ColumnType::Field(column)
+                        }
+                        _ => ColumnType::Tag(column),
+                    }
+                }
+                data_type => {
+                    return UnsupportedColumnType {
+                        column_name,
+                        data_type: data_type.clone(),
+                    }
+                    .fail()
+                }
+            };
+            columns.insert(column_name.to_string(), column);
+        }
+
+        if !matches!(columns.get(TIME_COLUMN_NAME), Some(ColumnType::Time(_))) {
+            return MissingTimeColumn.fail();
+        }
+
+        Ok(Self::new(rb.num_rows() as u32, columns))
+    }
+}
+
+// Integer arrays without NULL values can use one of the more compact
+// non-nullable encodings.
+fn integer_column(array: &ArrayRef) -> Column {
+    let arr = array::Int64Array::from(array.data());
+    if arr.null_count() == 0 {
+        Column::from(arr.value_slice(0, arr.len()))
+    } else {
+        Column::from(arr)
+    }
+}
+
 #[derive(Default, Debug)]
 struct MetaData {
     // The total size of the table in bytes.
@@ -1727,4 +1971,191 @@ west,host-d,11,9
 "
         );
     }
+
+    #[test]
+    fn record_batch_conversion() {
+        let schema = Schema::new(vec![
+            Field::new("active", DataType::Float64, true),
+            Field::new("region", DataType::Utf8, true),
+            Field::new("requests", DataType::Int64, true),
+            Field::new(TIME_COLUMN_NAME, DataType::Int64, false),
+        ]);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(array::Float64Array::from(vec![Some(1.5), None, Some(3.0)])),
+            Arc::new(array::StringArray::from(vec![
+                Some("west"),
+                Some("east"),
+                None,
+            ])),
+            Arc::new(array::Int64Array::from(vec![None, Some(20), Some(10)])),
+            Arc::new(array::Int64Array::from(vec![300, 100, 200])),
+        ];
+        let rb = RecordBatch::try_new(Arc::new(schema), columns).unwrap();
+
+        let row_group = RowGroup::try_from(rb).unwrap();
+        assert_eq!(row_group.rows(), 3);
+        assert_eq!(row_group.time_range(), (100, 300));
+        assert_eq!(row_group.column_count("active"), 2);
+        assert_eq!(row_group.column_count("region"), 2);
+        assert_eq!(row_group.column_count("missing"), 0);
+
+        let results = row_group.read_filter(
+            &["region", "requests"],
+            &[("active", (Operator::GT, Value::Scalar(Scalar::F64(2.0))))],
+        );
+        assert_eq!(
+            format!("{:?}", &results),
+            "region,requests
+NULL,10
+"
+        );
+
+        let rb = row_group.to_arrow(&["requests", TIME_COLUMN_NAME]);
+        assert_eq!(rb.num_rows(), 3);
+        assert_eq!(rb.schema().field(0).data_type(), &DataType::Int64);
+        let requests = rb
+            .column(0)
+            .as_any()
+            .downcast_ref::<array::Int64Array>()
+            .unwrap();
+        assert!(requests.is_null(0));
+        assert_eq!(requests.value(1), 20);
+
+        // only tags, fields and a timestamp can be held by a row group
+        let schema = Schema::new(vec![Field::new("ratio", DataType::Float32, true)]);
+        let columns: Vec<ArrayRef> = vec![Arc::new(array::Float32Array::from(vec![0.5]))];
+        let rb = RecordBatch::try_new(Arc::new(schema), columns).unwrap();
+        assert!(matches!(
+            RowGroup::try_from(rb),
+            Err(Error::UnsupportedColumnType { .. })
+        ));
+
+        let schema = Schema::new(vec![Field::new("requests", DataType::Int64, true)]);
+        let columns: Vec<ArrayRef> = vec![Arc::new(array::Int64Array::from(vec![1]))];
+        let rb = RecordBatch::try_new(Arc::new(schema), columns).unwrap();
+        assert!(matches!(
+            RowGroup::try_from(rb),
+            Err(Error::MissingTimeColumn)
+        ));
+    }
+
+    #[test]
+    fn record_batch_conversion_column_types() {
+        let mut metadata = std::collections::HashMap::new();
+        metadata.insert(
+            column_type_metadata_key("host"),
+            TAG_COLUMN_TYPE.to_string(),
+        );
+        metadata.insert(
+            column_type_metadata_key("state"),
+            FIELD_COLUMN_TYPE.to_string(),
+        );
+        let schema = Schema::new_with_metadata(
+            vec![
+                Field::new("active", DataType::Boolean, true),
+                Field::new("host", DataType::Utf8, true),
+                Field::new("state", DataType::Utf8, true),
+                Field::new(TIME_COLUMN_NAME, DataType::Int64, false),
+            ],
+            metadata,
+        );
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(array::BooleanArray::from(vec![
+                Some(true),
+                None,
+                Some(false),
+            ])),
+            Arc::new(array::StringArray::from(vec!["a", "b", "a"])),
+            Arc::new(array::StringArray::from(vec!["up", "down", "up"])),
+            Arc::new(array::Int64Array::from(vec![1, 2, 3])),
+        ];
+        let rb = RecordBatch::try_new(Arc::new(schema), columns).unwrap();
+
+        let row_group = RowGroup::try_from(rb).unwrap();
+        assert!(row_group.tag_columns_by_name.contains_key("host"));
+        assert!(row_group.field_columns_by_name.contains_key("state"));
+        assert!(row_group.field_columns_by_name.contains_key("active"));
+        assert_eq!(row_group.column_count("active"), 2);
+
+        let results = row_group.read_filter(
+            &["active", "state"],
+            &[("active", (Operator::Equal, Value::Boolean(true)))],
+        );
+        assert_eq!(
+            format!("{:?}", &results),
+            "active,state
+true,up
+"
+        );
+
+        let rb = row_group.to_arrow(&["active", "host", "state", TIME_COLUMN_NAME]);
+        assert_eq!(rb.schema().field(0).data_type(), &DataType::Boolean);
+        let column_type = |column| {
+            rb.schema()
+                .metadata()
+                .get(&column_type_metadata_key(column))
+                .cloned()
+        };
+        assert_eq!(column_type("active").unwrap(), FIELD_COLUMN_TYPE);
+        assert_eq!(column_type("host").unwrap(), TAG_COLUMN_TYPE);
+        assert_eq!(column_type("state").unwrap(), FIELD_COLUMN_TYPE);
+        assert_eq!(column_type(TIME_COLUMN_NAME), None);
+
+        let rb = row_group
+            .read_filter_to_arrow(
+                &["host"],
+                &[("state", (Operator::Equal, Value::String("up")))],
+            )
+            .unwrap();
+        assert_eq!(rb.num_rows(), 2);
+        assert!(row_group
+            .read_filter_to_arrow(
+                &["host"],
+                &[("host", (Operator::Equal, Value::String("c")))]
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn tag_keys_and_values() {
+        let mut columns = BTreeMap::new();
+        let tc = ColumnType::Time(Column::from(&[1_i64, 2, 3, 4][..]));
+        columns.insert("time".to_string(), tc);
+        let rc = ColumnType::Tag(Column::from(&["west", "west", "east", "north"][..]));
+        columns.insert("region".to_string(), rc);
+        let hc = ColumnType::Tag(Column::from(&[Some("a"), None, None, Some("b")][..]));
+        columns.insert("host".to_string(), hc);
+        let row_group = RowGroup::new(4, columns);
+
+        let west = vec![("region", (Operator::Equal, Value::String("west")))];
+        let east = vec![("region", (Operator::Equal, Value::String("east")))];
+        assert!(row_group.satisfies_predicates(&west));
+        assert!(!row_group
+            .satisfies_predicates(&[("region", (Operator::Equal, Value::String("south")))]));
+
+        let mut keys = BTreeSet::new();
+        row_group.tag_keys(&east, &BTreeSet::new(), &mut keys);
+        assert_eq!(keys.into_iter().collect::<Vec<_>>(), vec!["region"]);
+
+        let mut keys = BTreeSet::new();
+        row_group.tag_keys(&west, &BTreeSet::new(), &mut keys);
+        assert_eq!(keys.into_iter().collect::<Vec<_>>(), vec!["host", "region"]);
+
+        let mut keys = BTreeSet::new();
+        let found = vec!["region"].into_iter().collect();
+        row_group.tag_keys(&[], &found, &mut keys);
+        assert_eq!(keys.into_iter().collect::<Vec<_>>(), vec!["host"]);
+
+        let mut values = BTreeMap::new();
+        row_group.tag_values(&west, &[], &mut values);
+        let values = values
+            .into_iter()
+            .map(|(k, v)| (k, v.into_iter().map(String::as_str).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![("host", vec!["a"]), ("region", vec!["west"])]);
+
+        let mut values = BTreeMap::new();
+        row_group.tag_values(&east, &["host"], &mut values);
+        assert!(values.is_empty());
+    }
 }
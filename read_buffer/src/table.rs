@@ -3,12 +3,15 @@ use std::fmt::Display;
 use std::slice::Iter;
 
 use arrow_deps::arrow::record_batch::RecordBatch;
+use data_types::partition_metadata::{Column as ColumnStats, Statistics, Table as TableStats};
+use snafu::ensure;
 
-use crate::row_group::{ColumnName, GroupKey, Predicate, RowGroup};
+use crate::row_group::{ColumnName, GroupKey, Predicate, RowGroup, TIME_COLUMN_NAME};
 use crate::{
     column::{AggregateResult, AggregateType, OwnedValue, Scalar, Value},
     row_group::{ReadFilterResult, ReadGroupResult},
 };
+use crate::{ColumnNotFound, Result};
 
 /// A Table represents data for a single measurement.
 ///
@@ -48,6 +51,7 @@ impl Table {
 
     /// Add a new segment to this table.
     pub fn add_segment(&mut self, segment: RowGroup) {
+        self.meta.add_segment(&segment);
         self.segments.push(segment);
     }
 
@@ -78,22 +82,142 @@ impl Table {
 
     /// The total size of the table in bytes.
     pub fn size(&self) -> u64 {
-        todo!()
+        self.meta.size
     }
 
     /// The number of rows in this table.
     pub fn rows(&self) -> u64 {
-        todo!()
+        self.meta.rows
     }
 
     /// The time range of all segments within this table.
     pub fn time_range(&self) -> Option<(i64, i64)> {
-        todo!()
+        self.meta.time_range
     }
 
     /// The ranges on each column in the table (across all segments).
     pub fn column_ranges(&self) -> BTreeMap<String, (OwnedValue, OwnedValue)> {
-        todo!()
+        self.meta.column_ranges.clone()
+    }
+
+    /// Summary statistics for each column in the table (across all segments).
+    pub fn stats(&self) -> TableStats {
+        let columns = self
+            .meta
+            .column_ranges
+            .iter()
+            .filter_map(|(name, (min, max))| column_stats(min, max, self.column_count(name)))
+            .collect();
+        let time = self.meta.time_range.map(|(min, max)| Statistics {
+            min,
+            max,
+            count: self.column_count(TIME_COLUMN_NAME),
+        });
+
+        TableStats {
+            name: self.name.clone(),
+            columns,
+            time,
+        }
+    }
+
+    // the number of non-null values of the column across all segments
+    fn column_count(&self, name: &str) -> u32 {
+        self.segments
+            .iter()
+            .map(|segment| segment.column_count(name))
+            .sum()
+    }
+
+    /// Determines if the table has all the columns of the predicates and at
+    /// least one segment that could satisfy them.
+    pub fn could_satisfy_predicates(&self, predicates: &[Predicate<'_>]) -> bool {
+        self.has_predicate_columns(predicates) && !self.filter_segments(predicates).is_empty()
+    }
+
+    /// Determines if the table has all the columns of the predicates and at
+    /// least one row satisfying them. Unlike `could_satisfy_predicates` this
+    /// evaluates the predicates against the segments that could satisfy them.
+    pub fn satisfies_predicates(&self, predicates: &[Predicate<'_>]) -> bool {
+        self.has_predicate_columns(predicates)
+            && self
+                .filter_segments(predicates)
+                .iter()
+                .any(|segment| segment.satisfies_predicates(predicates))
+    }
+
+    /// Materialises the specified columns, or all columns in name order if
+    /// `columns` is empty, as one record batch per segment.
+    pub fn to_arrow(&self, columns: &[ColumnName<'_>]) -> Result<Vec<RecordBatch>> {
+        let columns = self.check_columns(columns)?;
+
+        Ok(self
+            .segments
+            .iter()
+            .map(|segment| segment.to_arrow(&columns))
+            .collect())
+    }
+
+    /// Materialises the specified columns of the segment at `position`, or
+    /// all columns in name order if `columns` is empty, as a record batch.
+    /// Returns `None` if the table has no such segment.
+    pub fn segment_to_arrow(
+        &self,
+        position: usize,
+        columns: &[ColumnName<'_>],
+    ) -> Result<Option<RecordBatch>> {
+        let columns = self.check_columns(columns)?;
+
+        Ok(self
+            .segments
+            .get(position)
+            .map(|segment| segment.to_arrow(&columns)))
+    }
+
+    /// Materialises the rows satisfying the predicates of the specified
+    /// columns, or all columns in name order if `columns` is empty, as one
+    /// record batch per segment with matching rows. No batches are returned
+    /// if the table doesn't have all the columns of the predicates.
+    pub fn read_filter_to_arrow(
+        &self,
+        columns: &[ColumnName<'_>],
+        predicates: &[Predicate<'_>],
+    ) -> Result<Vec<RecordBatch>> {
+        let columns = self.check_columns(columns)?;
+        if !self.has_predicate_columns(predicates) {
+            return Ok(vec![]);
+        }
+
+        Ok(self
+            .filter_segments(predicates)
+            .into_iter()
+            .filter_map(|segment| segment.read_filter_to_arrow(&columns, predicates))
+            .collect())
+    }
+
+    // Returns the provided column names, or all column names if there are
+    // none, after checking that the table has all of them.
+    fn check_columns<'a>(&'a self, columns: &[ColumnName<'a>]) -> Result<Vec<ColumnName<'a>>> {
+        if columns.is_empty() {
+            return Ok(self.meta.column_ranges.keys().map(String::as_str).collect());
+        }
+
+        for &column_name in columns {
+            ensure!(
+                self.meta.column_ranges.contains_key(column_name),
+                ColumnNotFound {
+                    table_name: &self.name,
+                    column_name,
+                }
+            );
+        }
+        Ok(columns.to_vec())
+    }
+
+    // Determines if the table has all the columns of the predicates.
+    fn has_predicate_columns(&self, predicates: &[Predicate<'_>]) -> bool {
+        let columns: Vec<_> = predicates.iter().map(|(name, _)| *name).collect();
+        self.has_all_columns(&columns)
     }
 
     // Determines if schema contains all the provided column names.
@@ -397,44 +521,45 @@ impl Table {
     // ---- Schema API queries
     //
 
-    /// Returns the distinct set of tag keys (column names) matching the
-    /// provided optional predicates and time range.
-    pub fn tag_keys<'a>(
+    /// Returns the distinct set of tag keys (column names) with a non-null
+    /// value in any row matching the provided predicates, excluding those in
+    /// `found_keys`.
+    pub fn tag_keys(
         &self,
-        time_range: (i64, i64),
-        predicates: &[(&str, &str)],
-        found_columns: &BTreeSet<String>,
-    ) -> BTreeSet<ColumnName<'a>> {
-        // Firstly, this should short-circuit early if all of the table's columns
-        // are present in `found_columns`.
-        //
-        // Otherwise, identify segments where time range and predicates match could
-        // match using segment meta data and then execute against those segments
-        // and merge results.
-        todo!();
+        predicates: &[Predicate<'_>],
+        found_keys: &BTreeSet<ColumnName<'_>>,
+    ) -> BTreeSet<ColumnName<'_>> {
+        let mut keys = BTreeSet::new();
+        if !self.has_predicate_columns(predicates) {
+            return keys;
+        }
+
+        for segment in self.filter_segments(predicates) {
+            segment.tag_keys(predicates, found_keys, &mut keys);
+        }
+        keys
     }
 
     /// Returns the distinct set of tag values (column values) for each provided
     /// tag key, where each returned value lives in a row matching the provided
-    /// optional predicates and time range.
+    /// predicates.
     ///
     /// As a special case, if `tag_keys` is empty then all distinct values for
-    /// all columns (tag keys) are returned for the chunk.
-    pub fn tag_values<'a>(
+    /// all columns (tag keys) are returned for the table.
+    pub fn tag_values(
         &self,
-        time_range: (i64, i64),
-        predicates: &[(&str, &str)],
-        tag_keys: &[String],
-        found_tag_values: &BTreeMap<String, BTreeSet<&String>>,
-    ) -> BTreeMap<ColumnName<'a>, BTreeSet<&String>> {
-        // identify segments where time range, predicates and tag keys match
-        // could match using segment meta data, and then execute against those
-        // segments and merge results.
-        //
-        // For each segment push the tag values that have already been found for
-        // the tag key down in an attempt to reduce execution against columns
-        // that only have values that have already been found.
-        todo!();
+        predicates: &[Predicate<'_>],
+        tag_keys: &[ColumnName<'_>],
+    ) -> BTreeMap<ColumnName<'_>, BTreeSet<&String>> {
+        let mut values = BTreeMap::new();
+        if !self.has_predicate_columns(predicates) {
+            return values;
+        }
+
+        for segment in self.filter_segments(predicates) {
+            segment.tag_values(predicates, tag_keys, &mut values);
+        }
+        values
     }
 }
 
@@ -487,6 +612,12 @@ impl MetaData {
         self.size += segment.size();
         self.rows += u64::from(segment.rows());
 
+        let (segment_min, segment_max) = segment.time_range();
+        self.time_range = match self.time_range {
+            Some((min, max)) => Some((min.min(segment_min), max.max(segment_max))),
+            None => Some((segment_min, segment_max)),
+        };
+
         assert_eq!(self.column_ranges.len(), segment.column_ranges().len());
         for (segment_column_name, (segment_column_range_min, segment_column_range_max)) in
             segment.column_ranges()
@@ -513,6 +644,47 @@ impl MetaData {
     }
 }
 
+// Converts the range and non-null count of a column into its summary
+// statistics. Byte array columns have no statistics.
+fn column_stats(min: &OwnedValue, max: &OwnedValue, count: u32) -> Option<ColumnStats> {
+    let stats = match (min, max) {
+        (OwnedValue::String(min), OwnedValue::String(max)) => ColumnStats::String(Statistics {
+            min: min.clone(),
+            max: max.clone(),
+            count,
+        }),
+        (OwnedValue::Boolean(min), OwnedValue::Boolean(max)) => ColumnStats::Bool(Statistics {
+            min: *min,
+            max: *max,
+            count,
+        }),
+        (OwnedValue::Scalar(Scalar::I64(min)), OwnedValue::Scalar(Scalar::I64(max))) => {
+            ColumnStats::I64(Statistics {
+                min: *min,
+                max: *max,
+                count,
+            })
+        }
+        (OwnedValue::Scalar(Scalar::U64(min)), OwnedValue::Scalar(Scalar::U64(max))) => {
+            ColumnStats::U64(Statistics {
+                min: *min,
+                max: *max,
+                count,
+            })
+        }
+        (OwnedValue::Scalar(Scalar::F64(min)), OwnedValue::Scalar(Scalar::F64(max))) => {
+            ColumnStats::F64(Statistics {
+                min: *min,
+                max: *max,
+                count,
+            })
+        }
+        _ => return None,
+    };
+
+    Some(stats)
+}
+
 /// Encapsulates results from tables with a structure that makes them easier
 /// to work with and display.
 pub struct ReadFilterResults<'input, 'segment> {
@@ -591,7 +763,7 @@ impl std::fmt::Display for ReadGroupResults<'_, '_> {
 mod test {
     use super::*;
     use crate::column::{cmp::Operator, Column};
-    use crate::row_group::{ColumnType, TIME_COLUMN_NAME};
+    use crate::row_group::ColumnType;
 
     fn build_predicates(
         from: i64,
@@ -685,4 +857,115 @@ mod test {
 ",
         );
     }
+
+    #[test]
+    fn to_arrow_and_stats() {
+        let mut columns = BTreeMap::new();
+        let tc = ColumnType::Time(Column::from(&[1_i64, 2, 3][..]));
+        columns.insert("time".to_string(), tc);
+        let rc = ColumnType::Tag(Column::from(&["west", "east", "west"][..]));
+        columns.insert("region".to_string(), rc);
+        let mut table = Table::new("cpu".to_owned(), RowGroup::new(3, columns));
+
+        let mut columns = BTreeMap::new();
+        let tc = ColumnType::Time(Column::from(&[10_i64, 20][..]));
+        columns.insert("time".to_string(), tc);
+        let rc = ColumnType::Tag(Column::from(&[Some("south"), None][..]));
+        columns.insert("region".to_string(), rc);
+        table.add_segment(RowGroup::new(2, columns));
+
+        assert_eq!(table.rows(), 5);
+        assert_eq!(table.time_range(), Some((1, 20)));
+
+        let batches = table.to_arrow(&[]).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].num_columns(), 2);
+        assert_eq!(batches[0].schema().field(0).name(), "region");
+        assert_eq!(batches[1].num_rows(), 2);
+
+        assert!(matches!(
+            table.to_arrow(&["region", "temp"]),
+            Err(crate::Error::ColumnNotFound { .. })
+        ));
+
+        let stats = table.stats();
+        assert_eq!(stats.name, "cpu");
+        assert_eq!(
+            stats.columns,
+            vec![
+                ColumnStats::String(Statistics {
+                    min: "east".to_string(),
+                    max: "west".to_string(),
+                    count: 4,
+                }),
+                ColumnStats::I64(Statistics {
+                    min: 1,
+                    max: 20,
+                    count: 5,
+                }),
+            ]
+        );
+        assert_eq!(stats.max_time(), Some(20));
+
+        assert!(table.could_satisfy_predicates(&build_predicates(15, 30, vec![])));
+        assert!(!table.could_satisfy_predicates(&build_predicates(30, 40, vec![])));
+        assert!(!table.could_satisfy_predicates(&[(
+            "temp",
+            (Operator::Equal, Value::Scalar(Scalar::I64(1)))
+        )]));
+    }
+
+    #[test]
+    fn read_filter_to_arrow_and_tags() {
+        let mut columns = BTreeMap::new();
+        let tc = ColumnType::Time(Column::from(&[1_i64, 2, 3][..]));
+        columns.insert("time".to_string(), tc);
+        let rc = ColumnType::Tag(Column::from(&["west", "east", "west"][..]));
+        columns.insert("region".to_string(), rc);
+        let mut table = Table::new("cpu".to_owned(), RowGroup::new(3, columns));
+
+        let mut columns = BTreeMap::new();
+        let tc = ColumnType::Time(Column::from(&[10_i64, 20][..]));
+        columns.insert("time".to_string(), tc);
+        let rc = ColumnType::Tag(Column::from(&["south", "east"][..]));
+        columns.insert("region".to_string(), rc);
+        table.add_segment(RowGroup::new(2, columns));
+
+        let east = vec![("region", (Operator::Equal, Value::String("east")))];
+        let batches = table.read_filter_to_arrow(&["time"], &east).unwrap();
+        assert_eq!(
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![1, 1]
+        );
+
+        // only the second segment has rows in the time range
+        let batches = table
+            .read_filter_to_arrow(&[], &build_predicates(5, 15, vec![]))
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_rows(), 1);
+
+        // predicates on missing columns match nothing
+        let temp = vec![("temp", (Operator::Equal, Value::Scalar(Scalar::I64(1))))];
+        assert!(table.read_filter_to_arrow(&[], &temp).unwrap().is_empty());
+        assert!(!table.satisfies_predicates(&temp));
+
+        // the metadata of both segments could match, but no row does
+        let north = vec![("region", (Operator::Equal, Value::String("north")))];
+        assert!(!table.satisfies_predicates(&north));
+        assert!(table.satisfies_predicates(&east));
+
+        assert_eq!(
+            table.tag_keys(&east, &BTreeSet::new()),
+            vec!["region"].into_iter().collect()
+        );
+        assert!(table.tag_keys(&north, &BTreeSet::new()).is_empty());
+
+        let values = table.tag_values(&build_predicates(2, 15, vec![]), &[]);
+        let values = values["region"]
+            .iter()
+            .map(|v| v.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec!["east", "south", "west"]);
+    }
 }
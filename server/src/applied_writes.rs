@@ -0,0 +1,301 @@
+//! This module keeps track of the replicated writes a database has applied,
+//! by the id of the server that wrote them and their sequence number, so a
+//! write that is retried or delivered more than once is only applied once.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+};
+
+/// The sequence writers number their first write with
+pub const FIRST_SEQUENCE: u64 = 1;
+
+/// The number of out of order sequences remembered for each writer by default
+pub const DEFAULT_WINDOW: usize = 10_000;
+
+/// `AppliedWrites` tracks for each writer the sequence up to which all of its
+/// writes were applied, and the sequences above it that were applied out of
+/// order. Writes from a writer can arrive out of order, as failed
+/// replications are retried in the background, so a sequence above the
+/// highest one applied isn't necessarily a duplicate. Once the missing
+/// sequences arrive, the watermark moves up past the sequences above it.
+///
+/// Some sequences never arrive, as writers number their writes across all
+/// databases and partitions. Once a writer has more than `window` sequences
+/// above its watermark, the lowest of them is forgotten. The watermark doesn't
+/// move past the sequences that are missing, as they might still arrive, for
+/// example when a replication is retried. A missing sequence below a
+/// forgotten one is stale: whether it was applied isn't known anymore, so it
+/// is applied again rather than lost.
+///
+/// A write is only recorded once it has been applied, and a write that is
+/// being applied is tracked as pending, so a duplicate that arrives in the
+/// meantime isn't applied as well.
+#[derive(Debug)]
+pub struct AppliedWrites {
+    window: usize,
+    writers: Mutex<BTreeMap<u32, WriterSequences>>,
+}
+
+#[derive(Debug, Default)]
+struct WriterSequences {
+    /// Every sequence up to and including this one was applied
+    watermark: Option<u64>,
+    /// The sequences above the watermark that were applied, as far as they
+    /// are remembered
+    above: BTreeSet<u64>,
+    /// The highest sequence that was forgotten to keep `above` within the
+    /// window
+    forgotten: Option<u64>,
+    /// The sequences that are being applied
+    pending: BTreeSet<u64>,
+}
+
+impl WriterSequences {
+    fn contains(&self, sequence: u64) -> bool {
+        self.watermark.map_or(false, |w| sequence <= w) || self.above.contains(&sequence)
+    }
+
+    fn is_stale(&self, sequence: u64) -> bool {
+        !self.contains(sequence) && self.forgotten.map_or(false, |f| sequence <= f)
+    }
+
+    fn record(&mut self, sequence: u64, window: usize) -> bool {
+        if self.contains(sequence) {
+            return false;
+        }
+        self.above.insert(sequence);
+
+        loop {
+            let next = self.watermark.map_or(FIRST_SEQUENCE, |w| w + 1);
+            match self.above.iter().next() {
+                Some(&lowest) if lowest == next => {
+                    self.above.remove(&lowest);
+                    self.watermark = Some(lowest);
+                }
+                _ => break,
+            }
+        }
+
+        while self.above.len() > window {
+            let lowest = *self.above.iter().next().expect("above is not empty");
+            self.above.remove(&lowest);
+            self.forgotten = Some(self.forgotten.map_or(lowest, |f| f.max(lowest)));
+        }
+
+        true
+    }
+}
+
+/// Why `AppliedWrites::begin` didn't return a pending write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplicate {
+    /// The write was applied already
+    Applied,
+    /// The write is being applied
+    Pending,
+}
+
+impl Default for AppliedWrites {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl AppliedWrites {
+    /// Creates a new `AppliedWrites` that remembers `window` out of order
+    /// sequences per writer
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            writers: Mutex::default(),
+        }
+    }
+
+    /// Returns true if the write with the sequence from the writer was
+    /// applied
+    pub fn contains(&self, writer: u32, sequence: u64) -> bool {
+        self.writers
+            .lock()
+            .expect("mutex poisoned")
+            .get(&writer)
+            .map_or(false, |w| w.contains(sequence))
+    }
+
+    /// Records the write with the sequence from the writer as applied, for
+    /// example when it is replayed from the WAL. Returns false if it already
+    /// was.
+    pub fn record(&self, writer: u32, sequence: u64) -> bool {
+        let mut writers = self.writers.lock().expect("mutex poisoned");
+        writers
+            .entry(writer)
+            .or_default()
+            .record(sequence, self.window)
+    }
+
+    /// Marks the write with the sequence from the writer as being applied.
+    /// Returns why it shouldn't be applied if it was applied already or is
+    /// being applied. The write is recorded as applied by
+    /// `PendingWrite::applied`, if the pending write is dropped before that
+    /// it gets applied when it is retried.
+    pub fn begin(&self, writer: u32, sequence: u64) -> Result<PendingWrite<'_>, Duplicate> {
+        let mut writers = self.writers.lock().expect("mutex poisoned");
+        let sequences = writers.entry(writer).or_default();
+        if sequences.contains(sequence) {
+            return Err(Duplicate::Applied);
+        }
+        if !sequences.pending.insert(sequence) {
+            return Err(Duplicate::Pending);
+        }
+
+        Ok(PendingWrite {
+            applied_writes: self,
+            writer,
+            sequence,
+            stale: sequences.is_stale(sequence),
+        })
+    }
+}
+
+/// A write that is being applied, returned by `AppliedWrites::begin`
+#[derive(Debug)]
+pub struct PendingWrite<'a> {
+    applied_writes: &'a AppliedWrites,
+    writer: u32,
+    sequence: u64,
+    stale: bool,
+}
+
+impl PendingWrite<'_> {
+    /// Returns true if the sequence of the write is so far below the highest
+    /// sequences of its writer that whether it was applied before isn't
+    /// known anymore
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    /// Records the write as applied
+    pub fn applied(self) {
+        self.applied_writes.record(self.writer, self.sequence);
+        // no longer pending once self is dropped
+    }
+}
+
+impl Drop for PendingWrite<'_> {
+    fn drop(&mut self) {
+        let mut writers = self.applied_writes.writers.lock().expect("mutex poisoned");
+        if let Some(sequences) = writers.get_mut(&self.writer) {
+            sequences.pending.remove(&self.sequence);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_each_write_once() {
+        let applied = AppliedWrites::default();
+        assert!(!applied.contains(1, 5));
+
+        assert!(applied.record(1, 5));
+        assert!(!applied.record(1, 5));
+        assert!(applied.contains(1, 5));
+
+        // sequences are tracked per writer and can arrive out of order
+        assert!(applied.record(2, 5));
+        assert!(applied.record(1, 3));
+        assert!(!applied.contains(1, 4));
+        assert!(applied.record(1, 4));
+        assert!(!applied.record(1, 4));
+    }
+
+    #[test]
+    fn watermark_moves_up_over_contiguous_sequences() {
+        let applied = AppliedWrites::default();
+        assert!(applied.record(1, 2));
+        assert!(applied.record(1, 4));
+
+        let writers = applied.writers.lock().unwrap();
+        assert_eq!(writers[&1].watermark, None);
+        drop(writers);
+
+        assert!(applied.record(1, FIRST_SEQUENCE));
+        let writers = applied.writers.lock().unwrap();
+        assert_eq!(writers[&1].watermark, Some(2));
+        assert_eq!(writers[&1].above.iter().collect::<Vec<_>>(), vec![&4]);
+        drop(writers);
+
+        assert!(applied.record(1, 3));
+        let writers = applied.writers.lock().unwrap();
+        assert_eq!(writers[&1].watermark, Some(4));
+        assert!(writers[&1].above.is_empty());
+    }
+
+    #[test]
+    fn forgets_sequences_outside_window() {
+        let applied = AppliedWrites::new(2);
+        assert!(applied.record(1, 3));
+        assert!(applied.record(1, 6));
+        assert!(applied.record(1, 5));
+
+        // 3 dropped out of the window, the sequences up to it that aren't
+        // known to be applied are stale, but not considered applied
+        assert!(!applied.contains(1, 3));
+        assert!(!applied.contains(1, 2));
+        assert!(applied.begin(1, 2).unwrap().is_stale());
+        assert!(!applied.begin(1, 4).unwrap().is_stale());
+        assert!(applied.contains(1, 5));
+        assert!(!applied.record(1, 6));
+
+        let writers = applied.writers.lock().unwrap();
+        assert_eq!(writers[&1].watermark, None);
+        assert_eq!(writers[&1].above.len(), 2);
+    }
+
+    #[test]
+    fn pending_writes_are_recorded_once_applied() {
+        let applied = AppliedWrites::default();
+
+        let pending = applied.begin(1, 1).unwrap();
+        assert_eq!(applied.begin(1, 1).unwrap_err(), Duplicate::Pending);
+        assert!(!applied.contains(1, 1));
+
+        // a write that fails is applied when it is retried
+        drop(pending);
+        let pending = applied.begin(1, 1).unwrap();
+
+        pending.applied();
+        assert!(applied.contains(1, 1));
+        assert_eq!(applied.begin(1, 1).unwrap_err(), Duplicate::Applied);
+    }
+
+    #[test]
+    fn retries_after_the_window_moved_are_applied() {
+        let applied = AppliedWrites::new(2);
+
+        // the write fails and is queued to be retried, while later writes of
+        // the writer are applied, with gaps for the writes to other databases
+        drop(applied.begin(1, 1).unwrap());
+        for sequence in (2..20).step_by(2) {
+            applied.begin(1, sequence).unwrap().applied();
+        }
+
+        let retry = applied.begin(1, 1).unwrap();
+        assert!(retry.is_stale());
+        retry.applied();
+        assert!(applied.contains(1, 1));
+        assert_eq!(applied.begin(1, 1).unwrap_err(), Duplicate::Applied);
+
+        // a write that is being applied isn't applied again meanwhile, and
+        // is applied when it is retried after failing
+        let pending = applied.begin(1, 3).unwrap();
+        for sequence in (20..40).step_by(2) {
+            applied.begin(1, sequence).unwrap().applied();
+        }
+        assert_eq!(applied.begin(1, 3).unwrap_err(), Duplicate::Pending);
+        drop(pending);
+        assert!(applied.begin(1, 3).unwrap().is_stale());
+    }
+}
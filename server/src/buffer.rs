@@ -2,13 +2,15 @@
 
 use data_types::{
     data::ReplicatedWrite,
-    database_rules::{WalBufferRollover, WriterId},
+    database_rules::{WalBufferConfig, WalBufferRollover, WriterId},
 };
+use generated_types::wal as wb;
+use object_store::path::ObjectStorePath;
 
 use std::{collections::BTreeMap, convert::TryFrom, mem, sync::Arc};
 
 use chrono::{DateTime, Utc};
-use snafu::Snafu;
+use snafu::{OptionExt, Snafu};
 use tokio::sync::Mutex;
 
 use tracing::warn;
@@ -36,11 +38,29 @@ pub enum Error {
         current_sequence: u64,
         incoming_sequence: u64,
     },
+
+    #[snafu(display("Persisted segment {} is missing its {}", segment_id, field))]
+    IncompleteSegmentFile { segment_id: u64, field: String },
 }
 
 #[allow(dead_code)]
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// The size the buffer is limited to if the config doesn't specify one
+pub const DEFAULT_BUFFER_SIZE: u64 = 100 * 1024 * 1024;
+
+/// The size at which segments are closed if the config doesn't specify one
+pub const DEFAULT_SEGMENT_SIZE: u64 = 10 * 1024 * 1024;
+
+/// The number of segments in a `Buffer`, including the open one, and how far
+/// persisting the closed ones lags behind
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BufferStats {
+    pub segments: usize,
+    pub unpersisted_segments: usize,
+    pub unpersisted_bytes: u64,
+}
+
 /// An in-memory buffer of a write ahead log. It is split up into segments,
 /// which can be persisted to object storage.
 #[derive(Debug)]
@@ -66,6 +86,28 @@ impl Buffer {
         }
     }
 
+    /// Creates a new buffer from the configuration in the database rules. The
+    /// first segment gets `first_segment_id`, so that a rebuilt database
+    /// doesn't reuse the ids of segments that were already persisted.
+    pub fn new_from_config(config: &WalBufferConfig, first_segment_id: u64) -> Self {
+        let mut buffer = Self::new(
+            config.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE),
+            config.segment_size.unwrap_or(DEFAULT_SEGMENT_SIZE),
+            config.buffer_rollover.clone(),
+        );
+        buffer.open_segment = Segment::new(first_segment_id);
+        buffer
+    }
+
+    /// Applies the sizes and rollover behavior of a changed configuration.
+    /// The segments already in the buffer are kept; if it is now over its max
+    /// size, segments get dropped on the next append.
+    pub fn set_config(&mut self, config: &WalBufferConfig) {
+        self.max_size = config.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE);
+        self.segment_size = config.segment_size.unwrap_or(DEFAULT_SEGMENT_SIZE);
+        self.rollover_behavior = config.buffer_rollover.clone();
+    }
+
     /// Appends a replicated write onto the buffer, returning the segment if it
     /// has been closed out. If the max size of the buffer would be exceeded
     /// by accepting the write, the oldest (first) of the closed segments
@@ -204,6 +246,48 @@ impl Buffer {
         writes
     }
 
+    /// Returns the writes in the buffer that come after the passed in writer
+    /// sequences, in the order they were appended. For the writers in
+    /// `last_seen` only writes with a higher sequence number are returned.
+    /// For all other writers every write in the buffer is returned.
+    pub fn writes_after(&self, last_seen: &[WriterSequence]) -> Vec<Arc<ReplicatedWrite>> {
+        let last_seen: BTreeMap<_, _> = last_seen.iter().map(|s| (s.id, s.sequence)).collect();
+
+        self.closed_segments
+            .iter()
+            .flat_map(|s| s.writes.iter())
+            .chain(self.open_segment.writes.iter())
+            .filter(|w| {
+                let (writer, sequence) = w.writer_and_sequence();
+                last_seen.get(&writer).map_or(true, |seen| sequence > *seen)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the number of segments in the buffer and the number and size
+    /// of the closed segments that haven't been persisted yet
+    pub async fn stats(&self) -> BufferStats {
+        let unpersisted = self.unpersisted_segments().await;
+        BufferStats {
+            segments: self.closed_segments.len() + 1,
+            unpersisted_segments: unpersisted.len(),
+            unpersisted_bytes: unpersisted.iter().map(|s| s.size).sum(),
+        }
+    }
+
+    /// Returns the closed segments that haven't been persisted yet, oldest
+    /// first
+    pub async fn unpersisted_segments(&self) -> Vec<Arc<Segment>> {
+        let mut segments = vec![];
+        for segment in &self.closed_segments {
+            if segment.persisted_at().await.is_none() {
+                segments.push(Arc::clone(segment));
+            }
+        }
+        segments
+    }
+
     // Removes the oldest segment present in the buffer, returning its id
     #[allow(dead_code)]
     fn remove_oldest_segment(&mut self) -> u64 {
@@ -287,8 +371,109 @@ impl Segment {
         Ok(())
     }
 
+    /// Returns the id of this segment. Ids increase with each segment that
+    /// gets closed.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the writes in this segment in the order they were appended.
+    pub fn writes(&self) -> &[Arc<ReplicatedWrite>] {
+        &self.writes
+    }
+
+    /// Serializes the writes in this segment and the summaries of their
+    /// writers as a flatbuffers `Segment`, which is the payload of a persisted
+    /// segment file. `writer_id` is the id of the server persisting it.
+    pub fn to_file_bytes(&self, writer_id: WriterId) -> Vec<u8> {
+        let size = usize::try_from(self.size).expect("segment size must fit in memory");
+        let mut fbb = flatbuffers::FlatBufferBuilder::new_with_capacity(size + 1024);
+
+        let mut writes = Vec::with_capacity(self.writes.len());
+        for write in &self.writes {
+            let fb = write.to_fb();
+            let payload = fb.payload().map(|p| fbb.create_vector_direct(p));
+            writes.push(wb::ReplicatedWrite::create(
+                &mut fbb,
+                &wb::ReplicatedWriteArgs {
+                    writer: fb.writer(),
+                    sequence: fb.sequence(),
+                    checksum: fb.checksum(),
+                    payload,
+                },
+            ));
+        }
+        let writes = fbb.create_vector(&writes);
+
+        let writers: Vec<_> = self
+            .writers
+            .iter()
+            .map(|(writer_id, summary)| {
+                wb::WriterSummary::create(
+                    &mut fbb,
+                    &wb::WriterSummaryArgs {
+                        writer_id: *writer_id,
+                        start_sequence: summary.start_sequence,
+                        end_sequence: summary.end_sequence,
+                        missing_sequences: summary.missing_sequence,
+                    },
+                )
+            })
+            .collect();
+        let writers = fbb.create_vector(&writers);
+
+        let segment = wb::Segment::create(
+            &mut fbb,
+            &wb::SegmentArgs {
+                number: self.id,
+                writer_id,
+                writes: Some(writes),
+                writers: Some(writers),
+            },
+        );
+        fbb.finish(segment, None);
+
+        let (mut data, idx) = fbb.collapse();
+        data.split_off(idx)
+    }
+
+    /// Deserializes a segment that was persisted with `to_file_bytes`. Each
+    /// write is copied out into its own `ReplicatedWrite`.
+    pub fn from_file_bytes(data: &[u8]) -> Result<Self> {
+        let fb = flatbuffers::get_root::<wb::Segment<'_>>(data);
+        let mut segment = Self::new(fb.number());
+
+        let writes = fb.writes().context(IncompleteSegmentFile {
+            segment_id: segment.id,
+            field: "writes",
+        })?;
+        for write in writes {
+            let payload = write.payload().context(IncompleteSegmentFile {
+                segment_id: segment.id,
+                field: "write payload",
+            })?;
+
+            let mut fbb = flatbuffers::FlatBufferBuilder::new_with_capacity(payload.len() + 1024);
+            let payload = fbb.create_vector_direct(payload);
+            let copy = wb::ReplicatedWrite::create(
+                &mut fbb,
+                &wb::ReplicatedWriteArgs {
+                    writer: write.writer(),
+                    sequence: write.sequence(),
+                    checksum: write.checksum(),
+                    payload: Some(payload),
+                },
+            );
+            fbb.finish(copy, None);
+
+            let (data, idx) = fbb.collapse();
+            segment.append(ReplicatedWrite::from(&data[idx..]))?;
+        }
+
+        Ok(segment)
+    }
+
     /// sets the time this segment was persisted at
-    #[allow(dead_code)]
     pub async fn set_persisted_at(&self, time: DateTime<Utc>) {
         let mut persisted = self.persisted.lock().await;
         *persisted = Some(time);
@@ -301,6 +486,26 @@ impl Segment {
     }
 }
 
+/// Returns the location of the persisted segment with the passed in id for a
+/// database stored under `database_path`. Ids are zero padded so that listing
+/// the segments returns them in order.
+pub fn object_store_path_for_segment(
+    database_path: &ObjectStorePath,
+    segment_id: u64,
+) -> ObjectStorePath {
+    let mut path = object_store_path_for_segments(database_path);
+    path.push(format!("{:020}.segment", segment_id));
+    path
+}
+
+/// Returns the location under which all persisted segments of a database
+/// stored under `database_path` are written.
+pub fn object_store_path_for_segments(database_path: &ObjectStorePath) -> ObjectStorePath {
+    let mut path = database_path.clone();
+    path.push("wal");
+    path
+}
+
 /// The summary information for a writer that has data in a segment
 #[derive(Debug, Eq, PartialEq)]
 pub struct WriterSummary {
@@ -578,6 +783,39 @@ mod tests {
         assert!(writes[0].equal_to_writer_and_sequence(2, 2));
     }
 
+    #[tokio::test]
+    async fn writes_after() {
+        let max = 1 << 63;
+        let write = lp_to_replicated_write(1, 1, "cpu val=1 10");
+        let segment = (write.data.len() + 1) as u64;
+        let mut buf = Buffer::new(max, segment, WalBufferRollover::ReturnError);
+
+        buf.append(write).await.unwrap();
+        for (writer, sequence) in &[(2, 1), (1, 2), (1, 3), (2, 2)] {
+            let write = lp_to_replicated_write(*writer, *sequence, "cpu val=1 10");
+            buf.append(write).await.unwrap();
+        }
+
+        let writer_sequences = |writes: Vec<Arc<ReplicatedWrite>>| -> Vec<(u32, u64)> {
+            writes.iter().map(|w| w.writer_and_sequence()).collect()
+        };
+
+        let writes = buf.writes_after(&[]);
+        assert_eq!(
+            writer_sequences(writes),
+            vec![(1, 1), (2, 1), (1, 2), (1, 3), (2, 2)]
+        );
+
+        let writes = buf.writes_after(&[WriterSequence { id: 1, sequence: 2 }]);
+        assert_eq!(writer_sequences(writes), vec![(2, 1), (1, 3), (2, 2)]);
+
+        let writes = buf.writes_after(&[
+            WriterSequence { id: 1, sequence: 3 },
+            WriterSequence { id: 2, sequence: 1 },
+        ]);
+        assert_eq!(writer_sequences(writes), vec![(2, 2)]);
+    }
+
     #[tokio::test]
     async fn returns_error_if_sequence_decreases() {
         let max = 1 << 63;
@@ -625,9 +863,87 @@ mod tests {
         );
     }
 
+    #[test]
+    fn segment_serializes_to_file_bytes() {
+        let mut segment = Segment::new(3);
+        let write = lp_to_replicated_write(1, 1, "cpu val=1 10");
+        segment.append(write).unwrap();
+        let write = lp_to_replicated_write(2, 3, "cpu val=2 20");
+        segment.append(write).unwrap();
+
+        let data = segment.to_file_bytes(7);
+        let fb = flatbuffers::get_root::<wb::Segment<'_>>(&data);
+
+        assert_eq!(3, fb.number());
+        assert_eq!(7, fb.writer_id());
+
+        let writes = fb.writes().unwrap();
+        assert_eq!(2, writes.len());
+        for (write, original) in writes.into_iter().zip(&segment.writes) {
+            let original = original.to_fb();
+            assert_eq!(original.writer(), write.writer());
+            assert_eq!(original.sequence(), write.sequence());
+            assert_eq!(original.checksum(), write.checksum());
+            assert_eq!(original.payload(), write.payload());
+        }
+
+        let writers = fb.writers().unwrap();
+        assert_eq!(2, writers.len());
+        assert_eq!(2, writers.get(1).writer_id());
+        assert_eq!(3, writers.get(1).start_sequence());
+        assert_eq!(3, writers.get(1).end_sequence());
+    }
+
+    #[test]
+    fn segment_round_trips_through_file_bytes() {
+        let mut segment = Segment::new(3);
+        let write = lp_to_replicated_write(1, 1, "cpu val=1 10");
+        segment.append(write).unwrap();
+        let write = lp_to_replicated_write(2, 3, "cpu val=2 20");
+        segment.append(write).unwrap();
+
+        let data = segment.to_file_bytes(7);
+        let loaded = Segment::from_file_bytes(&data).unwrap();
+
+        assert_eq!(3, loaded.id());
+        assert_eq!(segment.writers, loaded.writers);
+        assert_eq!(2, loaded.writes().len());
+        for (write, original) in loaded.writes().iter().zip(segment.writes()) {
+            assert_eq!(original.to_string(), write.to_string());
+            assert_eq!(original.to_fb().payload(), write.to_fb().payload());
+        }
+    }
+
+    #[tokio::test]
+    async fn unpersisted_segments() {
+        let mut buf = Buffer::new(1 << 16, 1, WalBufferRollover::ReturnError);
+
+        let write = lp_to_replicated_write(1, 1, "cpu val=1 10");
+        let first = buf.append(write).await.unwrap().unwrap();
+        let write = lp_to_replicated_write(1, 2, "cpu val=1 10");
+        buf.append(write).await.unwrap().unwrap();
+
+        let ids: Vec<_> = buf
+            .unpersisted_segments()
+            .await
+            .iter()
+            .map(|s| s.id())
+            .collect();
+        assert_eq!(vec![1, 2], ids);
+
+        first.set_persisted_at(Utc::now()).await;
+        let ids: Vec<_> = buf
+            .unpersisted_segments()
+            .await
+            .iter()
+            .map(|s| s.id())
+            .collect();
+        assert_eq!(vec![2], ids);
+    }
+
     fn lp_to_replicated_write(writer_id: u32, sequence_number: u64, lp: &str) -> ReplicatedWrite {
         let lines: Vec<_> = parse_lines(lp).map(|l| l.unwrap()).collect();
         let rules = DatabaseRules::default();
-        lines_to_replicated_write(writer_id, sequence_number, &lines, &rules)
+        lines_to_replicated_write(writer_id, sequence_number, &lines, &rules).unwrap()
     }
 }
@@ -0,0 +1,699 @@
+//! This module contains the catalog of the chunks a database has persisted to
+//! object storage: where the Parquet files of each chunk are, the statistics
+//! and columns of its tables and the range of sequences of each writer whose
+//! writes it holds, and the deletes that apply to it. Servers read the catalog
+//! to decide which chunks a query has to look at and to recover a database,
+//! rather than listing the object store.
+//!
+//! Every update writes the whole catalog as a new version to
+//! `<database path>/catalog/<version>.json` instead of overwriting a file, so
+//! a reader, which reads the latest version, sees the catalog either before
+//! or after an update, never a mix of the two. Object stores, including the
+//! file system one, only make an object visible once it is fully written.
+
+use arrow_deps::arrow::{
+    array::{Array, Int64Array},
+    compute::kernels::aggregate::{max, min},
+    datatypes::DataType,
+};
+use data_types::{
+    column_type_metadata_key, delete::Delete, partition_metadata::Table, FIELD_COLUMN_TYPE,
+    TIME_COLUMN_NAME,
+};
+use object_store::{path::ObjectStorePath, ObjectStore};
+use query::{predicate::Predicate, PartitionChunk};
+
+use crate::retention;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bytes::Bytes;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Error reading catalog from object store: {}", source))]
+    ReadingCatalog { source: object_store::Error },
+
+    #[snafu(display("Error writing catalog to object store: {}", source))]
+    WritingCatalog { source: object_store::Error },
+
+    #[snafu(display("Error parsing catalog {}: {}", path, source))]
+    ParsingCatalog {
+        path: String,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("Error serializing catalog: {}", source))]
+    SerializingCatalog { source: serde_json::Error },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The directory, under the path of a database, the catalog versions are
+/// stored in
+const CATALOG_DIRECTORY: &str = "catalog";
+
+/// The number of versions before the latest that are kept, so readers that
+/// listed the catalog just before an update can still read what they found
+const PREVIOUS_VERSIONS_TO_KEEP: u64 = 1;
+
+/// The chunks a database has persisted to object storage
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Catalog {
+    /// Incremented by every update, starting from 1 for the first chunk
+    pub version: u64,
+    pub chunks: Vec<CatalogChunk>,
+}
+
+impl Catalog {
+    /// Returns a new version of the catalog that includes the chunk
+    pub fn with_chunk(&self, chunk: CatalogChunk) -> Self {
+        let mut chunks = self.chunks.clone();
+        chunks.push(chunk);
+
+        Self {
+            version: self.version + 1,
+            chunks,
+        }
+    }
+
+    /// Returns a new version of the catalog where the delete applies to every
+    /// chunk that could hold rows it matches. Chunks the delete already
+    /// applies to are left as they are.
+    pub fn with_delete(&self, delete: &Delete) -> Self {
+        let mut chunks = self.chunks.clone();
+        for chunk in &mut chunks {
+            let tables_match = chunk
+                .tables
+                .iter()
+                .any(|table| delete.matches_table(&table.name));
+            let time_matches = chunk.time_range.map_or(true, |range| {
+                range.min <= delete.stop && delete.start <= range.max
+            });
+
+            if tables_match && time_matches && !chunk.deletes.contains(delete) {
+                chunk.deletes.push(delete.clone());
+            }
+        }
+
+        Self {
+            version: self.version + 1,
+            chunks,
+        }
+    }
+
+    /// Returns a new version of the catalog without the chunks whose rows are
+    /// all older than `cutoff`, in nanoseconds since the epoch, and the chunks
+    /// that were left out
+    pub fn without_expired(&self, cutoff: i64) -> (Self, Vec<CatalogChunk>) {
+        let (expired, chunks) = self
+            .chunks
+            .iter()
+            .cloned()
+            .partition(|chunk| chunk.is_expired(cutoff));
+
+        let catalog = Self {
+            version: self.version + 1,
+            chunks,
+        };
+        (catalog, expired)
+    }
+
+    /// Returns the chunks that could hold rows matching the table names,
+    /// time range and partition key of the predicate
+    pub fn chunks_matching<'a>(
+        &'a self,
+        predicate: &'a Predicate,
+    ) -> impl Iterator<Item = &'a CatalogChunk> + 'a {
+        self.chunks.iter().filter(move |chunk| {
+            let partition_matches = predicate
+                .partition_key
+                .as_ref()
+                .map_or(true, |key| key == &chunk.partition_key);
+            let tables_match = predicate.table_names.as_ref().map_or(true, |names| {
+                chunk.tables.iter().any(|table| names.contains(&table.name))
+            });
+            let time_matches = match (&predicate.range, &chunk.time_range) {
+                (Some(range), Some(time_range)) => {
+                    time_range.min < range.end && range.start <= time_range.max
+                }
+                _ => true,
+            };
+
+            partition_matches && tables_match && time_matches
+        })
+    }
+
+    /// Returns true if a chunk of the partition holds the write with the
+    /// sequence from the writer, so it doesn't have to be replayed from the
+    /// WAL
+    pub fn covers(&self, partition_key: &str, writer: u32, sequence: u64) -> bool {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.partition_key == partition_key)
+            .flat_map(|chunk| &chunk.sequences)
+            .any(|s| s.writer == writer && s.min <= sequence && sequence <= s.max)
+    }
+}
+
+/// A chunk that was persisted to object storage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogChunk {
+    pub partition_key: String,
+    /// The id the chunk had in the mutable buffer when it was persisted
+    pub chunk_id: u64,
+    /// The path of the chunk's snapshot, relative to the path of the database
+    pub path: String,
+    /// The smallest and largest timestamp in the chunk, if it has any rows
+    pub time_range: Option<TimeRange>,
+    pub tables: Vec<Table>,
+    /// The range of sequences of the writes from each writer in the chunk
+    pub sequences: Vec<WriterSequences>,
+    /// The deletes that apply to the rows of the chunk's snapshot
+    #[serde(default)]
+    pub deletes: Vec<Delete>,
+    /// The columns of each table, so metadata queries don't have to read the
+    /// chunk. `None` for chunks persisted before the catalog recorded them.
+    #[serde(default)]
+    pub columns: Option<Vec<TableColumns>>,
+}
+
+impl CatalogChunk {
+    pub fn new(
+        partition_key: impl Into<String>,
+        chunk_id: u64,
+        path: impl Into<String>,
+        time_range: Option<TimeRange>,
+        tables: Vec<Table>,
+        sequences: Vec<WriterSequences>,
+    ) -> Self {
+        Self {
+            partition_key: partition_key.into(),
+            chunk_id,
+            path: path.into(),
+            time_range,
+            tables,
+            sequences,
+            deletes: vec![],
+            columns: None,
+        }
+    }
+
+    /// Returns the columns of the table of the chunk, if the catalog
+    /// recorded them
+    pub fn table_columns(&self, table_name: &str) -> Option<&TableColumns> {
+        self.columns
+            .as_ref()?
+            .iter()
+            .find(|columns| columns.table_name == table_name)
+    }
+
+    /// Returns true if the chunk has rows and all of them are older than
+    /// `cutoff`. Chunks persisted before table statistics included the time
+    /// range of the rows fall back to `time_range`.
+    pub fn is_expired(&self, cutoff: i64) -> bool {
+        if self.tables.iter().any(|table| table.time.is_some()) {
+            retention::is_expired(&self.tables, cutoff)
+        } else {
+            self.time_range.map_or(false, |range| range.max < cutoff)
+        }
+    }
+
+    /// Returns the path of the chunk's snapshot for a database stored under
+    /// `database_path`
+    pub fn location(&self, database_path: &ObjectStorePath) -> ObjectStorePath {
+        let mut location = database_path.clone();
+        for part in self.path.split('/').filter(|part| !part.is_empty()) {
+            location.push(part);
+        }
+        location
+    }
+}
+
+/// An inclusive range of timestamps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl TimeRange {
+    /// Returns the smallest and largest timestamp in the tables of the chunk,
+    /// if it has any rows
+    pub fn of_chunk<T: PartitionChunk>(
+        chunk: &T,
+        tables: &[Table],
+    ) -> Result<Option<Self>, T::Error> {
+        let mut range: Option<Self> = None;
+
+        for table in tables {
+            let mut batches = vec![];
+            chunk.table_to_arrow(&mut batches, &table.name, &[TIME_COLUMN_NAME])?;
+
+            for batch in &batches {
+                let times = match batch.column(0).as_any().downcast_ref::<Int64Array>() {
+                    Some(times) => times,
+                    None => continue,
+                };
+
+                if let (Some(batch_min), Some(batch_max)) = (min(times), max(times)) {
+                    range = Some(match range {
+                        Some(r) => Self {
+                            min: r.min.min(batch_min),
+                            max: r.max.max(batch_max),
+                        },
+                        None => Self {
+                            min: batch_min,
+                            max: batch_max,
+                        },
+                    });
+                }
+            }
+        }
+
+        Ok(range)
+    }
+}
+
+/// The tag and field columns of a table of a chunk
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableColumns {
+    pub table_name: String,
+    pub tags: Vec<String>,
+    pub fields: Vec<FieldColumn>,
+}
+
+/// A field column of a table, with the largest timestamp of the rows that
+/// have a value for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldColumn {
+    pub name: String,
+    pub field_type: FieldType,
+    pub last_timestamp: i64,
+}
+
+/// The type of the values of a field column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldType {
+    I64,
+    U64,
+    F64,
+    Bool,
+    String,
+}
+
+impl FieldType {
+    fn of(data_type: &DataType) -> Option<Self> {
+        match data_type {
+            DataType::Int64 => Some(Self::I64),
+            DataType::UInt64 => Some(Self::U64),
+            DataType::Float64 => Some(Self::F64),
+            DataType::Boolean => Some(Self::Bool),
+            DataType::Utf8 => Some(Self::String),
+            _ => None,
+        }
+    }
+
+    /// Returns the Arrow data type of the values
+    pub fn data_type(self) -> DataType {
+        match self {
+            Self::I64 => DataType::Int64,
+            Self::U64 => DataType::UInt64,
+            Self::F64 => DataType::Float64,
+            Self::Bool => DataType::Boolean,
+            Self::String => DataType::Utf8,
+        }
+    }
+}
+
+impl TableColumns {
+    /// Returns the columns of the tables of the chunk. String columns are
+    /// tags unless their type is recorded as field, as in the read buffer.
+    pub fn of_chunk<T: PartitionChunk>(chunk: &T, tables: &[Table]) -> Result<Vec<Self>, T::Error> {
+        let mut columns = Vec::with_capacity(tables.len());
+
+        for table in tables {
+            let mut batches = vec![];
+            chunk.table_to_arrow(&mut batches, &table.name, &[])?;
+
+            let mut tags = BTreeSet::new();
+            let mut fields: BTreeMap<String, FieldColumn> = BTreeMap::new();
+            for batch in &batches {
+                let schema = batch.schema();
+                let times = schema
+                    .index_of(TIME_COLUMN_NAME)
+                    .ok()
+                    .and_then(|index| batch.column(index).as_any().downcast_ref::<Int64Array>());
+
+                for (field, array) in schema.fields().iter().zip(batch.columns()) {
+                    let name = field.name();
+                    if name == TIME_COLUMN_NAME {
+                        continue;
+                    }
+
+                    let column_type = schema.metadata().get(&column_type_metadata_key(name));
+                    if field.data_type() == &DataType::Utf8
+                        && column_type.map(String::as_str) != Some(FIELD_COLUMN_TYPE)
+                    {
+                        tags.insert(name.clone());
+                        continue;
+                    }
+
+                    let (field_type, times) = match (FieldType::of(field.data_type()), times) {
+                        (Some(field_type), Some(times)) => (field_type, times),
+                        _ => continue,
+                    };
+                    let last_timestamp = (0..batch.num_rows())
+                        .filter(|&row| !array.is_null(row) && !times.is_null(row))
+                        .map(|row| times.value(row))
+                        .max();
+                    if let Some(last_timestamp) = last_timestamp {
+                        let column = fields.entry(name.clone()).or_insert(FieldColumn {
+                            name: name.clone(),
+                            field_type,
+                            last_timestamp,
+                        });
+                        column.last_timestamp = column.last_timestamp.max(last_timestamp);
+                    }
+                }
+            }
+
+            columns.push(Self {
+                table_name: table.name.clone(),
+                tags: tags.into_iter().collect(),
+                fields: fields.into_iter().map(|(_, column)| column).collect(),
+            });
+        }
+
+        Ok(columns)
+    }
+}
+
+/// The smallest and largest sequence of the writes of a writer, inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriterSequences {
+    pub writer: u32,
+    pub min: u64,
+    pub max: u64,
+}
+
+impl WriterSequences {
+    /// Returns the largest sequence of each writer
+    pub fn max_by_writer(sequences: &[Self]) -> BTreeMap<u32, u64> {
+        sequences.iter().map(|s| (s.writer, s.max)).collect()
+    }
+}
+
+/// The sequences of the writes from each writer that went into a chunk,
+/// keyed by writer
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SequenceRanges(BTreeMap<u32, (u64, u64)>);
+
+impl SequenceRanges {
+    /// Records a write with the sequence from the writer
+    pub fn record(&mut self, writer: u32, sequence: u64) {
+        self.0
+            .entry(writer)
+            .and_modify(|(min, max)| {
+                *min = (*min).min(sequence);
+                *max = (*max).max(sequence);
+            })
+            .or_insert((sequence, sequence));
+    }
+
+    pub fn to_writer_sequences(&self) -> Vec<WriterSequences> {
+        self.0
+            .iter()
+            .map(|(&writer, &(min, max))| WriterSequences { writer, min, max })
+            .collect()
+    }
+}
+
+/// Returns the path of the directory the catalog of a database stored under
+/// `database_path` is kept in
+pub fn catalog_path(database_path: &ObjectStorePath) -> ObjectStorePath {
+    let mut path = database_path.clone();
+    path.push(CATALOG_DIRECTORY);
+    path
+}
+
+fn version_path(database_path: &ObjectStorePath, version: u64) -> ObjectStorePath {
+    let mut path = catalog_path(database_path);
+    // zero padded, so the versions sort in order
+    path.push(&format!("{:020}.json", version));
+    path
+}
+
+// returns the versions of the catalog in the object store, in order
+async fn list_versions(
+    store: &ObjectStore,
+    database_path: &ObjectStorePath,
+) -> Result<Vec<(u64, ObjectStorePath)>> {
+    let prefix = catalog_path(database_path);
+    let paths: Vec<_> = store
+        .list(Some(&prefix))
+        .await
+        .context(ReadingCatalog)?
+        .try_concat()
+        .await
+        .context(ReadingCatalog)?;
+
+    let mut versions: Vec<_> = paths
+        .into_iter()
+        .filter_map(|path| {
+            let name = store.convert_path(&path);
+            let version = name
+                .rsplit('/')
+                .next()?
+                .strip_suffix(".json")?
+                .parse()
+                .ok()?;
+            Some((version, path))
+        })
+        .collect();
+    versions.sort_by_key(|(version, _)| *version);
+
+    Ok(versions)
+}
+
+/// Reads the latest version of the catalog of the database stored under
+/// `database_path`. A database that hasn't persisted any chunks has an empty
+/// catalog.
+pub async fn read_catalog(store: &ObjectStore, database_path: &ObjectStorePath) -> Result<Catalog> {
+    let (_, path) = match list_versions(store, database_path).await?.pop() {
+        Some(latest) => latest,
+        None => return Ok(Catalog::default()),
+    };
+
+    let data = store
+        .get(&path)
+        .await
+        .context(ReadingCatalog)?
+        .map_ok(|b| bytes::BytesMut::from(&b[..]))
+        .try_concat()
+        .await
+        .context(ReadingCatalog)?;
+
+    serde_json::from_slice(&data).context(ParsingCatalog {
+        path: store.convert_path(&path),
+    })
+}
+
+/// Writes the catalog as its version and removes the versions that are no
+/// longer needed. Updates of a database's catalog must not run concurrently.
+pub async fn write_catalog(
+    store: &ObjectStore,
+    database_path: &ObjectStorePath,
+    catalog: &Catalog,
+) -> Result<()> {
+    let data = Bytes::from(serde_json::to_vec(catalog).context(SerializingCatalog)?);
+    let len = data.len();
+    store
+        .put(
+            &version_path(database_path, catalog.version),
+            futures::stream::once(async move { Ok(data) }),
+            len,
+        )
+        .await
+        .context(WritingCatalog)?;
+
+    let oldest_to_keep = catalog.version.saturating_sub(PREVIOUS_VERSIONS_TO_KEEP);
+    for (version, path) in list_versions(store, database_path).await? {
+        if version < oldest_to_keep {
+            store.delete(&path).await.context(WritingCatalog)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes all versions of the catalog of the database stored under
+/// `database_path`
+pub async fn delete_catalog(store: &ObjectStore, database_path: &ObjectStorePath) -> Result<()> {
+    for (_, path) in list_versions(store, database_path).await? {
+        store.delete(&path).await.context(WritingCatalog)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_types::partition_metadata::Statistics;
+    use object_store::memory::InMemory;
+    use query::predicate::TimestampRange;
+
+    fn table(name: &str) -> Table {
+        Table {
+            name: name.to_string(),
+            columns: vec![],
+            time: None,
+        }
+    }
+
+    fn chunk(
+        partition_key: &str,
+        table_name: &str,
+        times: (i64, i64),
+        sequences: (u64, u64),
+    ) -> CatalogChunk {
+        CatalogChunk::new(
+            partition_key,
+            0,
+            format!("chunks/{}", partition_key),
+            Some(TimeRange {
+                min: times.0,
+                max: times.1,
+            }),
+            vec![table(table_name)],
+            vec![WriterSequences {
+                writer: 1,
+                min: sequences.0,
+                max: sequences.1,
+            }],
+        )
+    }
+
+    #[tokio::test]
+    async fn catalog_versions_round_trip() {
+        let store = ObjectStore::new_in_memory(InMemory::new());
+        let mut database_path = ObjectStorePath::default();
+        database_path.push_all(&["1", "mydb"]);
+
+        assert_eq!(
+            read_catalog(&store, &database_path).await.unwrap(),
+            Catalog::default()
+        );
+
+        let mut catalog = Catalog::default();
+        for key in &["a", "b", "c"] {
+            catalog = catalog.with_chunk(chunk(key, "cpu", (1, 2), (1, 2)));
+            write_catalog(&store, &database_path, &catalog)
+                .await
+                .unwrap();
+        }
+        assert_eq!(catalog.version, 3);
+
+        let read = read_catalog(&store, &database_path).await.unwrap();
+        assert_eq!(read, catalog);
+
+        // only the latest version and the one before it are kept
+        let versions: Vec<_> = list_versions(&store, &database_path)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(version, _)| version)
+            .collect();
+        assert_eq!(versions, vec![2, 3]);
+
+        delete_catalog(&store, &database_path).await.unwrap();
+        assert_eq!(
+            read_catalog(&store, &database_path).await.unwrap(),
+            Catalog::default()
+        );
+    }
+
+    #[test]
+    fn prunes_chunks_and_covers_sequences() {
+        let catalog = Catalog::default()
+            .with_chunk(chunk("a", "cpu", (10, 20), (1, 5)))
+            .with_chunk(chunk("b", "mem", (30, 40), (6, 9)));
+
+        let keys = |predicate: &Predicate| -> Vec<String> {
+            catalog
+                .chunks_matching(predicate)
+                .map(|c| c.partition_key.clone())
+                .collect()
+        };
+
+        assert_eq!(keys(&Predicate::default()), vec!["a", "b"]);
+
+        let predicate = Predicate {
+            range: Some(TimestampRange::new(20, 30)),
+            ..Default::default()
+        };
+        assert_eq!(keys(&predicate), vec!["a"]);
+
+        let predicate = Predicate {
+            table_names: Some(vec!["mem".to_string()].into_iter().collect()),
+            ..Default::default()
+        };
+        assert_eq!(keys(&predicate), vec!["b"]);
+
+        assert!(catalog.covers("a", 1, 5));
+        assert!(!catalog.covers("a", 1, 6));
+        assert!(!catalog.covers("a", 2, 5));
+        assert!(catalog.covers("b", 1, 6));
+    }
+
+    #[test]
+    fn applies_deletes_to_matching_chunks() {
+        let catalog = Catalog::default()
+            .with_chunk(chunk("a", "cpu", (10, 20), (1, 5)))
+            .with_chunk(chunk("b", "mem", (30, 40), (6, 9)))
+            .with_chunk(chunk("c", "cpu", (30, 40), (10, 12)));
+
+        let delete = Delete {
+            table_name: Some("cpu".to_string()),
+            start: 0,
+            stop: 25,
+            predicate: None,
+        };
+        let updated = catalog.with_delete(&delete).with_delete(&delete);
+
+        assert_eq!(updated.version, catalog.version + 2);
+        let deletes: Vec<_> = updated.chunks.iter().map(|c| c.deletes.len()).collect();
+        assert_eq!(deletes, vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn leaves_out_expired_chunks() {
+        let mut with_stats = chunk("c", "cpu", (10, 20), (10, 12));
+        with_stats.tables[0].time = Some(Statistics {
+            min: 10,
+            max: 50,
+            count: 2,
+        });
+        let catalog = Catalog::default()
+            .with_chunk(chunk("a", "cpu", (10, 20), (1, 5)))
+            .with_chunk(chunk("b", "mem", (30, 40), (6, 9)))
+            .with_chunk(with_stats);
+
+        // the time statistics of the tables take precedence over the range
+        let (updated, expired) = catalog.without_expired(41);
+        assert_eq!(updated.version, catalog.version + 1);
+        let keys = |chunks: &[CatalogChunk]| -> Vec<String> {
+            chunks.iter().map(|c| c.partition_key.clone()).collect()
+        };
+        assert_eq!(keys(&updated.chunks), vec!["c"]);
+        assert_eq!(keys(&expired), vec!["a", "b"]);
+
+        let (updated, expired) = catalog.without_expired(10);
+        assert_eq!(updated.chunks, catalog.chunks);
+        assert!(expired.is_empty());
+    }
+}
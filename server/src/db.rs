@@ -1,20 +1,58 @@
 //! This module contains the main IOx Database object which has the
 //! instances of the immutable buffer, read buffer, and object store
 
-use std::sync::{
-    atomic::{AtomicU64, Ordering},
-    Arc,
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
+use arrow_deps::arrow::record_batch::RecordBatch;
 use async_trait::async_trait;
-use data_types::{data::ReplicatedWrite, database_rules::DatabaseRules};
-use mutable_buffer::MutableBufferDb;
-use query::{Database, PartitionChunk};
-use read_buffer::Database as ReadBufferDb;
+use chrono::{DateTime, Utc};
+use data_types::{
+    data::ReplicatedWrite,
+    database_rules::{CompiledMatcher, CompiledPartitionTemplate, DatabaseRules},
+    delete::Delete,
+    partition_metadata::Table as TableStats,
+};
+use mutable_buffer::{partition::ChunkSummary, MutableBufferDb};
+use object_store::{path::ObjectStorePath, ObjectStore};
+use query::{
+    exec::{
+        fieldlist::{Field, FieldList},
+        stringset::StringSet,
+        FieldListPlan, SeriesSetPlans, StringSetPlan,
+    },
+    group_by::GroupByAndAggregate,
+    predicate::{Predicate, PredicateBuilder},
+    Database, PartitionChunk,
+};
+use read_buffer::{
+    chunk::Chunk as ReadBufferChunk, row_group::RowGroup, table::Table as ReadBufferTable,
+    Database as ReadBufferDb,
+};
 use serde::{Deserialize, Serialize};
 use snafu::{OptionExt, ResultExt, Snafu};
+use tokio::sync::{broadcast, Mutex, RwLock};
+
+use crate::{
+    applied_writes::AppliedWrites,
+    buffer::{self, Buffer, BufferStats, Segment, WriterSequence},
+    catalog::{Catalog, CatalogChunk, SequenceRanges, TableColumns, WriterSequences},
+    delete::{apply_deletes, project},
+    read_buffer_query,
+    replication_queue::ReplicationQueue,
+    retention::{self, Expired, ExpiredCounts},
+    snapshot::{self, LoadedSnapshot},
+};
 
-use crate::buffer::Buffer;
+/// The number of writes appended to the WAL buffer that a subscriber can fall
+/// behind on before it misses writes
+const WRITE_NOTIFICATION_CAPACITY: usize = 1024;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -29,11 +67,22 @@ pub enum Error {
     #[snafu(display("Cannot read to this database: no mutable buffer configured"))]
     DatabaseNotReadable {},
 
+    #[snafu(display(
+        "Cannot read this database through the storage API: its data is stored on query group {}, query it with SQL instead",
+        host_group_id
+    ))]
+    StoredOnQueryGroup { host_group_id: String },
+
     #[snafu(display("Error rolling partition: {}", source))]
     RollingPartition {
         source: mutable_buffer::database::Error,
     },
 
+    #[snafu(display("Error dropping chunk: {}", source))]
+    DroppingChunk {
+        source: mutable_buffer::database::Error,
+    },
+
     #[snafu(display("Error querying mutable buffer: {}", source))]
     MutableBufferRead {
         source: mutable_buffer::database::Error,
@@ -43,6 +92,107 @@ pub enum Error {
     MutableBufferWrite {
         source: mutable_buffer::database::Error,
     },
+
+    #[snafu(display("Error appending to WAL buffer: {}", source))]
+    WalBufferAppend { source: buffer::Error },
+
+    #[snafu(display("Cannot subscribe to this database: no WAL buffer configured"))]
+    NoWalBuffer {},
+
+    #[snafu(display("Invalid partition template: {}", source))]
+    InvalidPartitionTemplate {
+        source: data_types::database_rules::Error,
+    },
+
+    #[snafu(display("Invalid matcher for subscription {}: {}", subscription, source))]
+    InvalidSubscriptionMatcher {
+        subscription: String,
+        source: data_types::database_rules::Error,
+    },
+
+    #[snafu(display("Error loading read only partition {}: {}", partition_key, source))]
+    LoadingReadOnlyPartition {
+        partition_key: String,
+        source: mutable_buffer::database::Error,
+    },
+
+    #[snafu(display("Read Buffer Chunk Error: {}", source))]
+    ReadBufferChunk { source: read_buffer::Error },
+
+    #[snafu(display("Error querying read buffer: {}", source))]
+    ReadBufferRead { source: read_buffer_query::Error },
+
+    #[snafu(display("Error loading persisted chunk {} for querying: {}", path, source))]
+    LoadingEvictedChunk {
+        path: String,
+        source: mutable_buffer::database::Error,
+    },
+
+    #[snafu(display("Error querying persisted chunks: {}", source))]
+    EvictedChunkRead {
+        source: mutable_buffer::database::Error,
+    },
+
+    #[snafu(display("No closed chunk {} in partition {}", chunk_id, partition_key))]
+    ClosedChunkNotFound {
+        partition_key: String,
+        chunk_id: u64,
+    },
+
+    #[snafu(display(
+        "Cannot load chunk {} of partition {} into the read buffer: {}",
+        chunk_id,
+        partition_key,
+        source
+    ))]
+    LoadingReadBufferChunk {
+        partition_key: String,
+        chunk_id: u64,
+        source: read_buffer::Error,
+    },
+
+    #[snafu(display(
+        "Cannot load chunk {} of partition {} into the read buffer: it has no data",
+        chunk_id,
+        partition_key
+    ))]
+    EmptyChunk {
+        partition_key: String,
+        chunk_id: u64,
+    },
+
+    #[snafu(display("Chunk id {} is too large for the read buffer", chunk_id))]
+    ReadBufferChunkId { chunk_id: u64 },
+
+    #[snafu(display("Invalid delete: {}", source))]
+    InvalidDelete {
+        source: data_types::row_predicate::Error,
+    },
+
+    #[snafu(display("Error applying deletes to table {}: {}", table_name, source))]
+    ApplyingDeletes {
+        table_name: String,
+        source: arrow_deps::arrow::error::ArrowError,
+    },
+
+    #[snafu(display(
+        "Cannot drop chunk {} of partition {} from memory: it isn't in the catalog",
+        chunk_id,
+        partition_key
+    ))]
+    ChunkNotPersisted {
+        partition_key: String,
+        chunk_id: u64,
+    },
+
+    #[snafu(display("Cannot read chunks dropped from memory: no object store configured"))]
+    NoObjectStore {},
+
+    #[snafu(display("Error reading persisted chunk {}: {}", path, source))]
+    ReadingPersistedChunk {
+        path: String,
+        source: snapshot::Error,
+    },
 }
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -56,7 +206,9 @@ pub struct Db {
     #[serde(skip)]
     /// The (optional) mutable buffer stores incoming writes. If a
     /// database does not have a mutable buffer it can not accept
-    /// writes (it is a read replica)
+    /// writes (it is a read replica). A database that doesn't store writes
+    /// locally still has a mutable buffer to hold its
+    /// `read_only_partitions`.
     pub mutable_buffer: Option<Arc<MutableBufferDb>>,
 
     #[serde(skip)]
@@ -65,11 +217,318 @@ pub struct Db {
     pub read_buffer: Arc<ReadBufferDb>,
 
     #[serde(skip)]
-    wal_buffer: Option<Buffer>,
+    wal_buffer: Option<Arc<Mutex<Buffer>>>,
+
+    #[serde(skip, default = "new_write_notifier")]
+    /// Sends the writes appended to the WAL buffer to its subscribers
+    write_notifier: broadcast::Sender<Arc<ReplicatedWrite>>,
+
+    #[serde(skip)]
+    sequence: Arc<AtomicU64>,
+
+    #[serde(skip)]
+    /// The compiled `rules.partition_template`, which computes the partition
+    /// keys of the lines written to the database
+    partition_template: CompiledPartitionTemplate,
+
+    #[serde(skip)]
+    /// The compiled matchers of `rules.subscriptions`, in the same order
+    subscription_matchers: Vec<CompiledMatcher>,
+
+    #[serde(skip)]
+    /// Writes that still have to be replicated to some of the host groups in
+    /// `rules.replication`
+    pub replication_queue: Arc<ReplicationQueue>,
 
     #[serde(skip)]
-    sequence: AtomicU64,
+    /// The writers and sequences of the replicated writes that were applied,
+    /// so duplicates of them are acknowledged without being applied again
+    pub applied_writes: Arc<AppliedWrites>,
+
+    #[serde(skip)]
+    /// The memory reserved for the writes that are being stored, which counts
+    /// towards the memory limit until the buffers account for their data
+    reserved_memory: Arc<std::sync::Mutex<usize>>,
+
+    #[serde(skip)]
+    /// The partition key and id of the closed chunks in memory that are in
+    /// the catalog, and so can be dropped from memory, with the path of
+    /// their snapshot in the catalog. The chunks of the catalog that aren't
+    /// in memory are queried from object storage.
+    persisted_chunks: Arc<Mutex<BTreeMap<(String, u64), String>>>,
+
+    #[serde(skip)]
+    /// The partition key and id of the chunks of the `read_only_partitions`,
+    /// which the lifecycle rules leave alone
+    read_only_chunks: Arc<Mutex<BTreeSet<(String, u64)>>>,
+
+    #[serde(skip)]
+    /// When the chunks of the read buffer were closed in the mutable buffer
+    read_buffer_closed_at: Arc<Mutex<BTreeMap<(String, u64), DateTime<Utc>>>>,
+
+    #[serde(skip)]
+    /// The object store and path of the database, the chunks of the catalog
+    /// that aren't in memory are read from
+    object_store: Option<(Arc<ObjectStore>, ObjectStorePath)>,
+
+    #[serde(skip)]
+    /// Writes hold this for reading while they store their data and record
+    /// their sequence, rollovers hold it for writing, so the sequence of a
+    /// write is always recorded for the chunk its data went into
+    rollover_lock: Arc<RwLock<()>>,
+
+    #[serde(skip)]
+    /// The sequences of the writes in the chunks that haven't been persisted
+    write_sequences: Arc<Mutex<WriteSequences>>,
+
+    #[serde(skip)]
+    /// The deletes that apply to the closed chunks of the mutable buffer and
+    /// the chunks of the read buffer. The rows they match are removed from
+    /// the chunks already, they are kept for the catalog.
+    deletes: Arc<Mutex<ChunkDeletes>>,
+
+    #[serde(skip)]
+    /// The latest version of the catalog of the chunks this database
+    /// persisted. Updates hold the lock until the new version is written.
+    pub catalog: Arc<Mutex<Catalog>>,
+
+    #[serde(skip)]
+    /// The number of partitions and chunks dropped because they were older
+    /// than the retention period
+    pub expired_counts: Arc<ExpiredCounts>,
+}
+
+/// The sequences of the writes in the open chunk of each partition and in the
+/// closed chunks that haven't been persisted yet
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WriteSequences {
+    open: BTreeMap<String, SequenceRanges>,
+    closed: BTreeMap<(String, u64), SequenceRanges>,
+}
+
+impl WriteSequences {
+    /// Records the sequence of a write to the open chunk of the partition
+    pub fn record(&mut self, partition_key: &str, writer: u32, sequence: u64) {
+        self.open
+            .entry(partition_key.to_string())
+            .or_default()
+            .record(writer, sequence);
+    }
+
+    /// Moves the sequences recorded for the open chunk of the partition to
+    /// the chunk with the specified id, after it was closed
+    pub fn close_chunk(&mut self, partition_key: &str, chunk_id: u64) {
+        if let Some(ranges) = self.open.remove(partition_key) {
+            self.closed
+                .insert((partition_key.to_string(), chunk_id), ranges);
+        }
+    }
+}
+
+/// The deletes that apply to each chunk, by partition key and chunk id
+pub type ChunkDeletes = BTreeMap<(String, u64), Vec<Arc<Delete>>>;
+
+/// Closes the open chunk of every partition of the mutable buffer that could
+/// have rows the delete matches and adds the delete to the deletes of the
+/// closed chunks that could have such rows, so it applies to the data written
+/// so far but not to the data written afterwards.
+pub async fn delete_from_mutable_buffer(
+    mutable_buffer: &MutableBufferDb,
+    delete: &Arc<Delete>,
+    sequences: &mut WriteSequences,
+    deletes: &mut ChunkDeletes,
+) -> Result<()> {
+    for chunk in mutable_buffer.chunk_summaries().await {
+        let chunk_id = match chunk.time_closed {
+            Some(_) => {
+                let closed = mutable_buffer
+                    .closed_chunk(&chunk.partition_key, chunk.id)
+                    .await;
+                let stats = match closed {
+                    Some(closed) => closed.table_stats().context(MutableBufferChunk)?,
+                    None => continue,
+                };
+                if !delete_touches(delete, &stats) {
+                    continue;
+                }
+                chunk.id
+            }
+            None if chunk.size == 0 => continue,
+            None => {
+                // the open chunk can't be looked at on its own, the tables
+                // of the partition are a superset of its tables
+                let stats = mutable_buffer
+                    .partition_table_stats(&chunk.partition_key)
+                    .await
+                    .context(MutableBufferRead)?;
+                if !delete_touches(delete, &stats) {
+                    continue;
+                }
+
+                let closed = mutable_buffer
+                    .rollover_partition(&chunk.partition_key)
+                    .await
+                    .context(RollingPartition)?;
+                sequences.close_chunk(&chunk.partition_key, closed.id());
+                closed.id()
+            }
+        };
+
+        deletes
+            .entry((chunk.partition_key, chunk_id))
+            .or_default()
+            .push(Arc::clone(delete));
+    }
+
+    Ok(())
+}
+
+// returns true if a table with the statistics could have rows the delete
+// matches
+fn delete_touches(delete: &Delete, stats: &[TableStats]) -> bool {
+    stats.iter().any(|table| {
+        delete.matches_table(&table.name)
+            && table.time.as_ref().map_or(true, |time| {
+                time.min <= delete.stop && delete.start <= time.max
+            })
+    })
+}
+
+// splits the chunks of the catalog into the ones metadata queries with the
+// predicate can be answered for from the catalog, returned as the columns of
+// their tables the predicate selects, and the ones that have to be read
+fn evicted_columns(
+    chunks: Vec<CatalogChunk>,
+    predicate: &Predicate,
+) -> (Vec<TableColumns>, Vec<CatalogChunk>) {
+    let mut columns = vec![];
+    let mut to_read = vec![];
+    for chunk in chunks {
+        match catalog_columns(&chunk, predicate) {
+            Some(chunk_columns) => columns.extend(chunk_columns),
+            None => to_read.push(chunk),
+        }
+    }
+
+    (columns, to_read)
 }
+
+// returns the columns of the tables of the chunk the predicate selects, if
+// the catalog has them and they are exact for the predicate. That takes a
+// predicate without expressions and selected tables without deletes whose
+// rows are either all or none in the time range of the predicate.
+fn catalog_columns(chunk: &CatalogChunk, predicate: &Predicate) -> Option<Vec<TableColumns>> {
+    if predicate.has_exprs() || predicate.field_columns.is_some() {
+        return None;
+    }
+
+    let mut columns = vec![];
+    for table in &chunk.tables {
+        let selected = predicate
+            .table_names
+            .as_ref()
+            .map_or(true, |names| names.contains(&table.name));
+        if !selected {
+            continue;
+        }
+
+        if let Some(range) = &predicate.range {
+            let time = table.time.as_ref()?;
+            if time.max < range.start || range.end <= time.min {
+                continue;
+            }
+            if time.min < range.start || range.end <= time.max {
+                return None;
+            }
+        }
+        if chunk
+            .deletes
+            .iter()
+            .any(|delete| delete.matches_table(&table.name))
+        {
+            return None;
+        }
+
+        columns.push(chunk.table_columns(&table.name)?.clone());
+    }
+
+    Some(columns)
+}
+
+// reads the tables of the chunk without the rows the deletes match, leaving
+// out the tables that have no rows left
+fn chunk_tables(
+    chunk: &DBChunk,
+    deletes: &[Arc<Delete>],
+) -> Result<Vec<(String, Vec<RecordBatch>)>> {
+    let mut tables = vec![];
+    for stats in chunk.table_stats()? {
+        let mut batches = vec![];
+        chunk.table_to_arrow(&mut batches, &stats.name, &[])?;
+
+        let mut left = Vec::with_capacity(batches.len());
+        for batch in batches {
+            let batch = apply_deletes(&stats.name, &batch, deletes).context(ApplyingDeletes {
+                table_name: &stats.name,
+            })?;
+            if batch.num_rows() > 0 {
+                left.push(batch);
+            }
+        }
+        if !left.is_empty() {
+            tables.push((stats.name, left));
+        }
+    }
+
+    Ok(tables)
+}
+
+// builds a read buffer chunk with the tables, or returns `None` if there are
+// none
+fn read_buffer_chunk(
+    partition_key: &str,
+    chunk_id: u32,
+    tables: Vec<(String, Vec<RecordBatch>)>,
+) -> Result<Option<ReadBufferChunk>> {
+    let mut rb_chunk: Option<ReadBufferChunk> = None;
+    for (table_name, batches) in tables {
+        let mut table: Option<ReadBufferTable> = None;
+        for batch in batches {
+            let row_group = RowGroup::try_from(batch).context(LoadingReadBufferChunk {
+                partition_key,
+                chunk_id: u64::from(chunk_id),
+            })?;
+            match &mut table {
+                Some(table) => table.add_segment(row_group),
+                None => table = Some(ReadBufferTable::new(table_name.clone(), row_group)),
+            }
+        }
+
+        if let Some(table) = table {
+            match &mut rb_chunk {
+                Some(rb_chunk) => rb_chunk.add_table(table),
+                None => rb_chunk = Some(ReadBufferChunk::new(chunk_id, table)),
+            }
+        }
+    }
+
+    Ok(rb_chunk)
+}
+
+/// Memory reserved for a write by `Db::reserve_memory`
+#[derive(Debug)]
+pub struct MemoryReservation<'a> {
+    reserved: &'a std::sync::Mutex<usize>,
+    size: usize,
+}
+
+impl Drop for MemoryReservation<'_> {
+    fn drop(&mut self) {
+        let mut reserved = self.reserved.lock().expect("mutex poisoned");
+        *reserved -= self.size;
+    }
+}
+
 impl Db {
     pub fn new(
         rules: DatabaseRules,
@@ -77,30 +536,759 @@ impl Db {
         read_buffer: Arc<ReadBufferDb>,
         wal_buffer: Option<Buffer>,
         sequence: AtomicU64,
-    ) -> Self {
-        Self {
+    ) -> Result<Self> {
+        let mut db = Self {
             rules,
             mutable_buffer,
             read_buffer,
+            wal_buffer: wal_buffer.map(|buffer| Arc::new(Mutex::new(buffer))),
+            write_notifier: new_write_notifier(),
+            sequence: Arc::new(sequence),
+            partition_template: CompiledPartitionTemplate::default(),
+            subscription_matchers: vec![],
+            replication_queue: Arc::default(),
+            applied_writes: Arc::default(),
+            reserved_memory: Arc::default(),
+            persisted_chunks: Arc::default(),
+            read_only_chunks: Arc::default(),
+            read_buffer_closed_at: Arc::default(),
+            object_store: None,
+            rollover_lock: Arc::default(),
+            write_sequences: Arc::default(),
+            deletes: Arc::default(),
+            catalog: Arc::default(),
+            expired_counts: Arc::default(),
+        };
+        db.compile_rules()?;
+
+        Ok(db)
+    }
+
+    /// Sets the object store and the path the database is stored under, so
+    /// the chunks of its catalog that have been dropped from memory can be
+    /// queried
+    pub fn with_object_store(
+        mut self,
+        store: Arc<ObjectStore>,
+        database_path: ObjectStorePath,
+    ) -> Self {
+        self.object_store = Some((store, database_path));
+        self
+    }
+
+    /// Returns a `Db` with the new rules that shares the data, WAL buffer,
+    /// sequence, replication queue, applied writes, reserved memory, persisted
+    /// chunks, deletes,
+    /// catalog, object store and expired counts of this one, so writes
+    /// continue where they left off. `mutable_buffer` and `wal_buffer` are
+    /// only used if this database doesn't have them yet. The existing WAL
+    /// buffer is dropped if the new rules don't configure one.
+    pub async fn with_rules(
+        &self,
+        rules: DatabaseRules,
+        mutable_buffer: Option<Arc<MutableBufferDb>>,
+        wal_buffer: Option<Buffer>,
+    ) -> Result<Self> {
+        let wal_buffer = match (&rules.wal_buffer_config, &self.wal_buffer) {
+            (Some(config), Some(existing)) => {
+                existing.lock().await.set_config(config);
+                Some(Arc::clone(existing))
+            }
+            (Some(_), None) => wal_buffer.map(|buffer| Arc::new(Mutex::new(buffer))),
+            (None, _) => None,
+        };
+
+        let mut db = Self {
+            rules,
+            mutable_buffer: self.mutable_buffer.clone().or(mutable_buffer),
+            read_buffer: Arc::clone(&self.read_buffer),
             wal_buffer,
-            sequence,
+            write_notifier: self.write_notifier.clone(),
+            sequence: Arc::clone(&self.sequence),
+            partition_template: CompiledPartitionTemplate::default(),
+            subscription_matchers: vec![],
+            replication_queue: Arc::clone(&self.replication_queue),
+            applied_writes: Arc::clone(&self.applied_writes),
+            reserved_memory: Arc::clone(&self.reserved_memory),
+            persisted_chunks: Arc::clone(&self.persisted_chunks),
+            read_only_chunks: Arc::clone(&self.read_only_chunks),
+            read_buffer_closed_at: Arc::clone(&self.read_buffer_closed_at),
+            object_store: self.object_store.clone(),
+            rollover_lock: Arc::clone(&self.rollover_lock),
+            write_sequences: Arc::clone(&self.write_sequences),
+            deletes: Arc::clone(&self.deletes),
+            catalog: Arc::clone(&self.catalog),
+            expired_counts: Arc::clone(&self.expired_counts),
+        };
+        db.compile_rules()?;
+
+        Ok(db)
+    }
+
+    /// Returns the size in bytes of the data in the mutable buffer and the
+    /// read buffer
+    pub fn memory_used(&self) -> usize {
+        let mutable_buffer = self
+            .mutable_buffer
+            .as_ref()
+            .map_or(0, |buffer| buffer.size());
+        let read_buffer = usize::try_from(self.read_buffer.size()).unwrap_or(usize::MAX);
+        mutable_buffer.saturating_add(read_buffer)
+    }
+
+    /// Reserves `size` bytes for a write that is about to be stored, so
+    /// writes that are stored concurrently can't exceed `limit` together. If
+    /// the write doesn't fit, the bytes in use, including the reservations of
+    /// other writes, are returned. The bytes are given back when the
+    /// reservation is dropped, which should be once the write is stored.
+    pub fn reserve_memory(
+        &self,
+        size: usize,
+        limit: usize,
+    ) -> std::result::Result<MemoryReservation<'_>, usize> {
+        let mut reserved = self.reserved_memory.lock().expect("mutex poisoned");
+        let used = self.memory_used().saturating_add(*reserved);
+        if used.saturating_add(size) > limit {
+            return Err(used);
+        }
+
+        *reserved += size;
+        Ok(MemoryReservation {
+            reserved: &self.reserved_memory,
+            size,
+        })
+    }
+
+    /// Returns true if the database buffers its writes in a WAL buffer
+    pub fn has_wal_buffer(&self) -> bool {
+        self.wal_buffer.is_some()
+    }
+
+    /// Compiles the partition template and the matchers of the subscriptions
+    /// in the rules. This has to be called after a `Db` has been deserialized.
+    pub fn compile_rules(&mut self) -> Result<()> {
+        self.partition_template = self
+            .rules
+            .partition_template
+            .compile()
+            .context(InvalidPartitionTemplate)?;
+        self.subscription_matchers = self
+            .rules
+            .subscriptions
+            .iter()
+            .map(|s| {
+                s.matcher.compile().context(InvalidSubscriptionMatcher {
+                    subscription: &s.name,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(())
+    }
+
+    /// Returns the compiled partition template of the rules
+    pub fn partition_template(&self) -> &CompiledPartitionTemplate {
+        &self.partition_template
+    }
+
+    /// Returns the compiled matcher for each subscription in the rules
+    pub fn subscription_matchers(&self) -> &[CompiledMatcher] {
+        &self.subscription_matchers
+    }
+
+    /// Appends the write to the WAL buffer, if the database has one, and
+    /// sends it to the subscribers of the buffer.
+    pub async fn append_to_wal_buffer(&self, write: &ReplicatedWrite) -> Result<()> {
+        if let Some(wal_buffer) = &self.wal_buffer {
+            let mut wal_buffer = wal_buffer.lock().await;
+            wal_buffer
+                .append(write.clone())
+                .await
+                .context(WalBufferAppend)?;
+
+            // this is sent while holding the lock so that a new subscriber
+            // gets the write either from the buffer or from the notification.
+            // Sending only fails if there are no subscribers.
+            let _ = self.write_notifier.send(Arc::new(write.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the closed segments of the WAL buffer that haven't been
+    /// persisted yet, oldest first
+    pub async fn unpersisted_wal_segments(&self) -> Vec<Arc<Segment>> {
+        match &self.wal_buffer {
+            Some(wal_buffer) => wal_buffer.lock().await.unpersisted_segments().await,
+            None => vec![],
+        }
+    }
+
+    /// Returns the number of segments in the WAL buffer and how far persisting
+    /// them lags behind, if the database has a WAL buffer
+    pub async fn wal_buffer_stats(&self) -> Option<BufferStats> {
+        match &self.wal_buffer {
+            Some(wal_buffer) => Some(wal_buffer.lock().await.stats().await),
+            None => None,
         }
     }
 
+    /// Subscribes to the writes in the WAL buffer, starting after the writes
+    /// in `last_seen`.
+    pub async fn subscribe_to_wal_buffer(
+        &self,
+        last_seen: &[WriterSequence],
+    ) -> Result<WalBufferSubscription> {
+        let wal_buffer = self.wal_buffer.as_ref().context(NoWalBuffer)?.lock().await;
+
+        Ok(WalBufferSubscription {
+            backlog: wal_buffer.writes_after(last_seen),
+            receiver: self.write_notifier.subscribe(),
+        })
+    }
+
+    /// Adds the tables of a snapshot that was loaded from object storage as a
+    /// closed chunk of its partition, so they are queried together with the
+    /// data written to this database.
+    pub async fn load_read_only_partition(
+        &self,
+        snapshot: &LoadedSnapshot,
+    ) -> Result<Arc<DBChunk>> {
+        let chunk = self.load_snapshot(snapshot).await?;
+
+        self.read_only_chunks
+            .lock()
+            .await
+            .insert((snapshot.partition_meta.key.clone(), chunk.id()));
+
+        Ok(chunk)
+    }
+
+    async fn load_snapshot(&self, snapshot: &LoadedSnapshot) -> Result<Arc<DBChunk>> {
+        let partition_key = &snapshot.partition_meta.key;
+        let chunk = self
+            .mutable_buffer
+            .as_ref()
+            .context(DatabaseNotReadable)?
+            .load_chunk(partition_key, &snapshot.tables)
+            .await
+            .context(LoadingReadOnlyPartition { partition_key })?;
+
+        Ok(Arc::new(DBChunk::ParquetFile(chunk)))
+    }
+
+    /// Returns the mutable buffer if this database stores writes locally
+    pub fn writable_buffer(&self) -> Option<&Arc<MutableBufferDb>> {
+        self.mutable_buffer
+            .as_ref()
+            .filter(|_| self.rules.store_locally)
+    }
+
     /// Rolls over the active chunk in the database's specified partition
     pub async fn rollover_partition(&self, partition_key: &str) -> Result<Arc<DBChunk>> {
-        if let Some(local_store) = self.mutable_buffer.as_ref() {
-            local_store
-                .rollover_partition(partition_key)
+        let local_store = self
+            .mutable_buffer
+            .as_ref()
+            .context(DatatbaseNotWriteable)?;
+
+        let _rollover = self.rollover_lock.write().await;
+        let chunk = local_store
+            .rollover_partition(partition_key)
+            .await
+            .context(RollingPartition)?;
+
+        self.write_sequences
+            .lock()
+            .await
+            .close_chunk(partition_key, chunk.id());
+
+        Ok(Arc::new(DBChunk::MutableBuffer(chunk)))
+    }
+
+    /// Returns the closed chunk with the specified id in the partition, if
+    /// any, from either the mutable buffer or the read buffer
+    pub async fn closed_chunk(&self, partition_key: &str, chunk_id: u64) -> Option<Arc<DBChunk>> {
+        if let Some(mutable_buffer) = &self.mutable_buffer {
+            if let Some(chunk) = mutable_buffer.closed_chunk(partition_key, chunk_id).await {
+                return Some(Arc::new(DBChunk::MutableBuffer(chunk)));
+            }
+        }
+
+        let chunk = self
+            .read_buffer
+            .chunk(partition_key, u32::try_from(chunk_id).ok()?)?;
+
+        Some(Arc::new(DBChunk::ReadBuffer(chunk)))
+    }
+
+    /// Drops the closed chunk with the specified id from the partition, so its
+    /// data is no longer queried and its memory is freed
+    pub async fn drop_chunk(&self, partition_key: &str, chunk_id: u64) -> Result<Arc<DBChunk>> {
+        let read_buffer_chunk = u32::try_from(chunk_id)
+            .ok()
+            .and_then(|id| self.read_buffer.remove_chunk(partition_key, id));
+
+        let chunk = match read_buffer_chunk {
+            Some(chunk) => DBChunk::ReadBuffer(chunk),
+            None => DBChunk::MutableBuffer(
+                self.mutable_buffer
+                    .as_ref()
+                    .context(DatatbaseNotWriteable)?
+                    .drop_chunk(partition_key, chunk_id)
+                    .await
+                    .context(DroppingChunk)?,
+            ),
+        };
+
+        self.forget_chunks(partition_key, &[chunk_id]).await;
+
+        Ok(Arc::new(chunk))
+    }
+
+    /// Drops the closed chunk with the specified id from the partition if it
+    /// is in the catalog, so its memory is freed and its data is queried from
+    /// object storage from then on
+    pub async fn evict_chunk(&self, partition_key: &str, chunk_id: u64) -> Result<Arc<DBChunk>> {
+        // held while dropping the chunk, so a query reads its data either
+        // from memory or from object storage
+        let _catalog = self.catalog.lock().await;
+
+        let key = (partition_key.to_string(), chunk_id);
+        if !self.persisted_chunks.lock().await.contains_key(&key) {
+            return ChunkNotPersisted {
+                partition_key,
+                chunk_id,
+            }
+            .fail();
+        }
+
+        self.drop_chunk(partition_key, chunk_id).await
+    }
+
+    /// Drops the partitions of the mutable buffer and the chunks of the read
+    /// buffer whose rows are all older than `cutoff`, in nanoseconds since
+    /// the epoch, returning what was dropped. Nothing is dropped if this
+    /// fails.
+    pub async fn drop_expired(&self, cutoff: i64) -> Result<Vec<Expired>> {
+        let mut expired = vec![];
+
+        if let Some(mutable_buffer) = &self.mutable_buffer {
+            // held so no write goes into a partition between checking and
+            // dropping it
+            let _rollover = self.rollover_lock.write().await;
+
+            let mut expired_keys = vec![];
+            for partition_key in mutable_buffer
+                .partition_keys()
                 .await
-                .context(RollingPartition)
-                .map(|c| Arc::new(DBChunk::MutableBuffer(c)))
-        } else {
-            DatatbaseNotWriteable {}.fail()
+                .context(MutableBufferRead)?
+            {
+                let tables = mutable_buffer
+                    .partition_table_stats(&partition_key)
+                    .await
+                    .context(MutableBufferRead)?;
+                if retention::is_expired(&tables, cutoff) {
+                    expired_keys.push(partition_key);
+                }
+            }
+
+            for partition_key in expired_keys {
+                if let Some(chunk_ids) = mutable_buffer.drop_partition(&partition_key).await {
+                    self.forget_chunks(&partition_key, &chunk_ids).await;
+                    self.write_sequences
+                        .lock()
+                        .await
+                        .open
+                        .remove(&partition_key);
+                    expired.push(Expired::MutableBufferPartition { partition_key });
+                }
+            }
+        }
+
+        for partition_key in self.read_buffer.partition_keys() {
+            for chunk in self.read_buffer.chunks(&partition_key) {
+                if !retention::is_expired(&chunk.table_stats(), cutoff) {
+                    continue;
+                }
+
+                if self
+                    .read_buffer
+                    .remove_chunk(&partition_key, chunk.id())
+                    .is_some()
+                {
+                    let chunk_id = u64::from(chunk.id());
+                    self.forget_chunks(&partition_key, &[chunk_id]).await;
+                    expired.push(Expired::ReadBufferChunk {
+                        partition_key: partition_key.clone(),
+                        chunk_id,
+                    });
+                }
+            }
+        }
+
+        Ok(expired)
+    }
+
+    // removes what is known about the chunks of the partition, once they
+    // have been dropped
+    async fn forget_chunks(&self, partition_key: &str, chunk_ids: &[u64]) {
+        let mut persisted_chunks = self.persisted_chunks.lock().await;
+        let mut read_only_chunks = self.read_only_chunks.lock().await;
+        let mut read_buffer_closed_at = self.read_buffer_closed_at.lock().await;
+        let mut write_sequences = self.write_sequences.lock().await;
+        let mut deletes = self.deletes.lock().await;
+
+        for &chunk_id in chunk_ids {
+            let key = (partition_key.to_string(), chunk_id);
+            persisted_chunks.remove(&key);
+            read_only_chunks.remove(&key);
+            read_buffer_closed_at.remove(&key);
+            write_sequences.closed.remove(&key);
+            deletes.remove(&key);
+        }
+    }
+
+    /// Converts the closed mutable buffer chunk with the specified id into a
+    /// read buffer chunk, choosing an encoding for each of its columns, and
+    /// then drops it from the mutable buffer. Its data continues to be
+    /// queried from the read buffer.
+    pub async fn load_chunk_to_read_buffer(
+        &self,
+        partition_key: &str,
+        chunk_id: u64,
+    ) -> Result<Arc<DBChunk>> {
+        let mutable_buffer = self.mutable_buffer.as_ref().context(DatabaseNotReadable)?;
+        let rb_chunk_id = u32::try_from(chunk_id)
+            .ok()
+            .context(ReadBufferChunkId { chunk_id })?;
+
+        // held until the chunk is converted, so a delete doesn't replace the
+        // chunk in the meantime
+        let mut deletes = self.deletes.lock().await;
+        let mb_chunk = mutable_buffer
+            .closed_chunk(partition_key, chunk_id)
+            .await
+            .context(ClosedChunkNotFound {
+                partition_key,
+                chunk_id,
+            })?;
+        let key = (partition_key.to_string(), chunk_id);
+
+        let tables = chunk_tables(&DBChunk::MutableBuffer(Arc::clone(&mb_chunk)), &[])?;
+        let rb_chunk =
+            read_buffer_chunk(partition_key, rb_chunk_id, tables)?.context(EmptyChunk {
+                partition_key,
+                chunk_id,
+            })?;
+
+        // the chunk is added before it is dropped from the mutable buffer so
+        // its data is queryable throughout
+        let rb_chunk = self.read_buffer.add_chunk(partition_key, rb_chunk);
+        if let Some(time_closed) = mb_chunk.time_closed {
+            self.read_buffer_closed_at
+                .lock()
+                .await
+                .insert(key.clone(), time_closed);
+        }
+        deletes.remove(&key);
+        mutable_buffer
+            .drop_chunk(partition_key, chunk_id)
+            .await
+            .context(DroppingChunk)?;
+
+        Ok(Arc::new(DBChunk::ReadBuffer(rb_chunk)))
+    }
+
+    // replaces the closed chunk of the mutable buffer or the chunk of the read
+    // buffer with the specified id by one without the rows the deletes match,
+    // so queries read the chunk as it is. Nothing is replaced if the chunk was
+    // dropped in the meantime. Callers hold the lock on the deletes.
+    async fn remove_deleted_rows(
+        &self,
+        partition_key: &str,
+        chunk_id: u64,
+        deletes: &[Arc<Delete>],
+    ) -> Result<()> {
+        if let Some(mutable_buffer) = &self.mutable_buffer {
+            if let Some(chunk) = mutable_buffer.closed_chunk(partition_key, chunk_id).await {
+                let tables = chunk_tables(&DBChunk::MutableBuffer(chunk), deletes)?;
+                mutable_buffer
+                    .replace_chunk(partition_key, chunk_id, &tables)
+                    .await
+                    .context(MutableBufferWrite)?;
+                return Ok(());
+            }
+        }
+
+        let rb_chunk_id = match u32::try_from(chunk_id) {
+            Ok(rb_chunk_id) => rb_chunk_id,
+            Err(_) => return Ok(()),
+        };
+        if let Some(chunk) = self.read_buffer.chunk(partition_key, rb_chunk_id) {
+            let tables = chunk_tables(&DBChunk::ReadBuffer(chunk), deletes)?;
+            match read_buffer_chunk(partition_key, rb_chunk_id, tables)? {
+                Some(chunk) => {
+                    self.read_buffer.replace_chunk(partition_key, chunk);
+                }
+                None => {
+                    self.read_buffer.remove_chunk(partition_key, rb_chunk_id);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records that the closed chunk with the specified id is in the catalog,
+    /// with its snapshot stored under `path` relative to the database. This
+    /// has to be called while holding the catalog lock, right after the
+    /// chunk was added to the catalog.
+    pub async fn mark_chunk_persisted(&self, partition_key: &str, chunk_id: u64, path: &str) {
+        let key = (partition_key.to_string(), chunk_id);
+        // the catalog records the sequences of persisted chunks
+        self.write_sequences.lock().await.closed.remove(&key);
+        self.persisted_chunks
+            .lock()
+            .await
+            .insert(key, path.to_string());
+    }
+
+    /// Returns the partition key and id of the closed chunks in memory that
+    /// are in the catalog
+    pub async fn persisted_chunks(&self) -> BTreeSet<(String, u64)> {
+        self.persisted_chunks.lock().await.keys().cloned().collect()
+    }
+
+    /// Returns the summaries of the chunks of the mutable buffer the
+    /// lifecycle rules apply to, which are all but those of the read only
+    /// partitions
+    pub async fn mutable_buffer_chunk_summaries(&self) -> Vec<ChunkSummary> {
+        let mutable_buffer = match &self.mutable_buffer {
+            Some(mutable_buffer) => mutable_buffer,
+            None => return vec![],
+        };
+
+        let read_only_chunks = self.read_only_chunks.lock().await;
+        mutable_buffer
+            .chunk_summaries()
+            .await
+            .into_iter()
+            .filter(|c| !read_only_chunks.contains(&(c.partition_key.clone(), c.id)))
+            .collect()
+    }
+
+    /// Returns the summaries of the chunks of the read buffer, with the time
+    /// they were closed in the mutable buffer
+    pub async fn read_buffer_chunk_summaries(&self) -> Vec<ChunkSummary> {
+        let closed_at = self.read_buffer_closed_at.lock().await;
+
+        let mut summaries = vec![];
+        for partition_key in self.read_buffer.partition_keys() {
+            for chunk in self.read_buffer.chunks(&partition_key) {
+                let id = u64::from(chunk.id());
+                let time_closed = closed_at.get(&(partition_key.clone(), id)).cloned();
+                summaries.push(ChunkSummary {
+                    partition_key: partition_key.clone(),
+                    id,
+                    size: usize::try_from(chunk.size()).unwrap_or(usize::MAX),
+                    time_of_first_write: None,
+                    time_closed,
+                });
+            }
+        }
+
+        summaries
+    }
+
+    // returns the chunks of the catalog that aren't in memory and could hold
+    // rows matching the predicate. Callers hold the catalog lock, so no chunk
+    // is dropped from memory until they have read the chunks that are.
+    async fn evicted_chunks(&self, catalog: &Catalog, predicate: &Predicate) -> Vec<CatalogChunk> {
+        let persisted_chunks = self.persisted_chunks.lock().await;
+        let in_memory: BTreeSet<_> = persisted_chunks.values().collect();
+
+        catalog
+            .chunks_matching(predicate)
+            .filter(|chunk| !in_memory.contains(&chunk.path))
+            .cloned()
+            .collect()
+    }
+
+    // reads the tables of the chunks of the catalog that the predicate
+    // selects from object storage into a new mutable buffer, without the rows
+    // their deletes match, so the storage API plans queries against them the
+    // same way as against the chunks in memory
+    async fn load_evicted_chunks(
+        &self,
+        chunks: &[CatalogChunk],
+        predicate: &Predicate,
+    ) -> Result<MutableBufferDb> {
+        let db = MutableBufferDb::new("persisted");
+        for chunk in chunks {
+            let mut tables = vec![];
+            for table in &chunk.tables {
+                if let Some(table_names) = &predicate.table_names {
+                    if !table_names.contains(&table.name) {
+                        continue;
+                    }
+                }
+
+                let batches = self.read_evicted_chunk(chunk, &table.name, &[]).await?;
+                if !batches.is_empty() {
+                    tables.push((table.name.clone(), batches));
+                }
+            }
+
+            if !tables.is_empty() {
+                db.load_chunk(&chunk.partition_key, &tables)
+                    .await
+                    .context(LoadingEvictedChunk { path: &chunk.path })?;
+            }
+        }
+
+        Ok(db)
+    }
+
+    // reads the table of a chunk of the catalog from object storage, without
+    // the rows its deletes match
+    async fn read_evicted_chunk(
+        &self,
+        chunk: &CatalogChunk,
+        table_name: &str,
+        columns: &[&str],
+    ) -> Result<Vec<RecordBatch>> {
+        let (store, database_path) = self.object_store.as_ref().context(NoObjectStore)?;
+        let (_, data_path) =
+            snapshot::snapshot_paths(&chunk.location(database_path), &chunk.partition_key);
+
+        let batches = snapshot::load_snapshot_table(&data_path, store, table_name)
+            .await
+            .context(ReadingPersistedChunk { path: &chunk.path })?;
+
+        let deletes: Vec<_> = chunk.deletes.iter().cloned().map(Arc::new).collect();
+        batches
+            .iter()
+            .map(|batch| {
+                apply_deletes(table_name, batch, &deletes)
+                    .and_then(|batch| project(batch, columns))
+                    .context(ApplyingDeletes { table_name })
+            })
+            .collect()
+    }
+
+    /// Returns the range of sequences of the writes from each writer that
+    /// went into the closed chunk, as long as it hasn't been persisted
+    pub async fn closed_chunk_sequences(
+        &self,
+        partition_key: &str,
+        chunk_id: u64,
+    ) -> Vec<WriterSequences> {
+        self.write_sequences
+            .lock()
+            .await
+            .closed
+            .get(&(partition_key.to_string(), chunk_id))
+            .map(SequenceRanges::to_writer_sequences)
+            .unwrap_or_default()
+    }
+
+    /// Sets the sequences of the writes in the chunks of each partition, for
+    /// writes that were stored in the mutable buffer before this `Db`
+    /// existed, such as writes replayed from the WAL.
+    pub async fn restore_write_sequences(&self, sequences: WriteSequences) {
+        *self.write_sequences.lock().await = sequences;
+    }
+
+    /// Adds deletes that apply to chunks that were stored in the mutable
+    /// buffer before this `Db` existed, such as deletes replayed from the
+    /// WAL, and removes the rows they match from the chunks.
+    pub async fn restore_deletes(&self, restored: ChunkDeletes) -> Result<()> {
+        let mut deletes = self.deletes.lock().await;
+        for ((partition_key, chunk_id), chunk_deletes) in restored {
+            self.remove_deleted_rows(&partition_key, chunk_id, &chunk_deletes)
+                .await?;
+            deletes
+                .entry((partition_key, chunk_id))
+                .or_default()
+                .extend(chunk_deletes);
         }
+
+        Ok(())
+    }
+
+    /// Returns the deletes that apply to the chunk with the specified id in
+    /// the partition
+    pub async fn chunk_deletes(&self, partition_key: &str, chunk_id: u64) -> Vec<Arc<Delete>> {
+        self.deletes
+            .lock()
+            .await
+            .get(&(partition_key.to_string(), chunk_id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Applies the delete to the data in the mutable buffer and the read
+    /// buffer. The open chunks of the mutable buffer are closed, so the delete
+    /// doesn't apply to the data that is written after it, and the chunks
+    /// that could have rows it matches are replaced by chunks without them.
+    /// The delete is still recorded for those chunks, so the catalog has it
+    /// once they are persisted.
+    pub async fn apply_delete(&self, delete: Delete) -> Result<()> {
+        let delete = Arc::new(delete);
+
+        let _rollover = self.rollover_lock.write().await;
+        let mut sequences = self.write_sequences.lock().await;
+        let mut deletes = self.deletes.lock().await;
+
+        if let Some(mutable_buffer) = &self.mutable_buffer {
+            delete_from_mutable_buffer(mutable_buffer, &delete, &mut sequences, &mut deletes)
+                .await?;
+        }
+
+        for partition_key in self.read_buffer.partition_keys() {
+            for chunk in self.read_buffer.chunks(&partition_key) {
+                if delete_touches(&delete, &chunk.table_stats()) {
+                    deletes
+                        .entry((partition_key.clone(), u64::from(chunk.id())))
+                        .or_default()
+                        .push(Arc::clone(&delete));
+                }
+            }
+        }
+
+        // the rows the earlier deletes matched are gone from the chunks
+        // already, so only this delete is applied
+        let touched: Vec<_> = deletes
+            .iter()
+            .filter(|(_, chunk_deletes)| {
+                chunk_deletes
+                    .last()
+                    .map_or(false, |last| Arc::ptr_eq(last, &delete))
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for (partition_key, chunk_id) in touched {
+            self.remove_deleted_rows(&partition_key, chunk_id, &[Arc::clone(&delete)])
+                .await?;
+        }
+
+        Ok(())
     }
 }
 
+fn new_write_notifier() -> broadcast::Sender<Arc<ReplicatedWrite>> {
+    broadcast::channel(WRITE_NOTIFICATION_CAPACITY).0
+}
+
+/// The writes a subscriber to the WAL buffer of a database gets. First the
+/// `backlog` of writes already in the buffer, then the writes that are
+/// appended afterwards from `receiver`.
+#[derive(Debug)]
+pub struct WalBufferSubscription {
+    pub backlog: Vec<Arc<ReplicatedWrite>>,
+    pub receiver: broadcast::Receiver<Arc<ReplicatedWrite>>,
+}
+
 impl PartialEq for Db {
     fn eq(&self, other: &Self) -> bool {
         self.rules == other.rules
@@ -119,8 +1307,10 @@ impl Db {
 #[derive(Debug)]
 pub enum DBChunk {
     MutableBuffer(Arc<mutable_buffer::chunk::Chunk>),
-    ReadBuffer,  // TODO add appropriate type here
-    ParquetFile, // TODO add appropriate type here
+    ReadBuffer(Arc<ReadBufferChunk>),
+    /// The tables of a Parquet snapshot, loaded into a closed mutable buffer
+    /// chunk so the mutable buffer's query planning applies to them
+    ParquetFile(Arc<mutable_buffer::chunk::Chunk>),
 }
 
 impl PartitionChunk for DBChunk {
@@ -128,17 +1318,17 @@ impl PartitionChunk for DBChunk {
 
     fn id(&self) -> u64 {
         match self {
-            Self::MutableBuffer(chunk) => chunk.id(),
-            Self::ReadBuffer => unimplemented!("read buffer not implemented"),
-            Self::ParquetFile => unimplemented!("parquet file not implemented"),
+            Self::MutableBuffer(chunk) | Self::ParquetFile(chunk) => chunk.id(),
+            Self::ReadBuffer(chunk) => u64::from(chunk.id()),
         }
     }
 
     fn table_stats(&self) -> Result<Vec<data_types::partition_metadata::Table>, Self::Error> {
         match self {
-            Self::MutableBuffer(chunk) => chunk.table_stats().context(MutableBufferChunk),
-            Self::ReadBuffer => unimplemented!("read buffer not implemented"),
-            Self::ParquetFile => unimplemented!("parquet file not implemented"),
+            Self::MutableBuffer(chunk) | Self::ParquetFile(chunk) => {
+                chunk.table_stats().context(MutableBufferChunk)
+            }
+            Self::ReadBuffer(chunk) => Ok(chunk.table_stats()),
         }
     }
 
@@ -149,12 +1339,52 @@ impl PartitionChunk for DBChunk {
         columns: &[&str],
     ) -> Result<(), Self::Error> {
         match self {
-            Self::MutableBuffer(chunk) => chunk
+            Self::MutableBuffer(chunk) | Self::ParquetFile(chunk) => chunk
                 .table_to_arrow(dst, table_name, columns)
                 .context(MutableBufferChunk),
-            Self::ReadBuffer => unimplemented!("read buffer not implemented"),
-            Self::ParquetFile => unimplemented!("parquet file not implemented"),
+            Self::ReadBuffer(chunk) => chunk
+                .table_to_arrow(dst, table_name, columns)
+                .context(ReadBufferChunk),
+        }
+    }
+
+    fn table_row_groups(&self, table_name: &str) -> Result<usize, Self::Error> {
+        match self {
+            Self::MutableBuffer(chunk) | Self::ParquetFile(chunk) => chunk
+                .table_row_groups(table_name)
+                .context(MutableBufferChunk),
+            Self::ReadBuffer(chunk) => Ok(chunk.table_row_groups(table_name)),
+        }
+    }
+
+    fn row_group_to_arrow(
+        &self,
+        dst: &mut Vec<arrow_deps::arrow::record_batch::RecordBatch>,
+        table_name: &str,
+        row_group: usize,
+        columns: &[&str],
+    ) -> Result<(), Self::Error> {
+        match self {
+            Self::MutableBuffer(chunk) | Self::ParquetFile(chunk) => chunk
+                .row_group_to_arrow(dst, table_name, row_group, columns)
+                .context(MutableBufferChunk),
+            Self::ReadBuffer(chunk) => chunk
+                .row_group_to_arrow(dst, table_name, row_group, columns)
+                .context(ReadBufferChunk),
+        }
+    }
+}
+
+impl Db {
+    // returns the mutable buffer the storage API reads. A database whose data
+    // is stored on a query group can only be queried with SQL, which
+    // coordinates the query across the group.
+    fn storage_api_buffer(&self) -> Result<&MutableBufferDb> {
+        if let Some(host_group_id) = &self.rules.primary_query_group {
+            return StoredOnQueryGroup { host_group_id }.fail();
         }
+
+        self.mutable_buffer.as_ref().context(DatabaseNotReadable)
     }
 }
 
@@ -166,86 +1396,233 @@ impl Database for Db {
     // this trait. For now, pass them directly on to the local store
 
     async fn store_replicated_write(&self, write: &ReplicatedWrite) -> Result<(), Self::Error> {
-        self.mutable_buffer
-            .as_ref()
-            .context(DatatbaseNotWriteable)?
-            .store_replicated_write(write)
-            .await
-            .context(MutableBufferWrite)
+        let buffer = self.writable_buffer().context(DatatbaseNotWriteable)?;
+        let deletes = write.deletes().context(InvalidDelete)?;
+
+        {
+            let _rollover = self.rollover_lock.read().await;
+            buffer
+                .store_replicated_write(write)
+                .await
+                .context(MutableBufferWrite)?;
+
+            let (writer, sequence) = write.writer_and_sequence();
+            let mut sequences = self.write_sequences.lock().await;
+            for partition_key in write.partition_keys() {
+                sequences.record(partition_key, writer, sequence);
+            }
+        }
+
+        for delete in deletes {
+            self.apply_delete(delete).await?;
+        }
+
+        Ok(())
     }
 
-    async fn table_names(
-        &self,
-        predicate: query::predicate::Predicate,
-    ) -> Result<query::exec::StringSetPlan, Self::Error> {
-        self.mutable_buffer
-            .as_ref()
-            .context(DatabaseNotReadable)?
+    async fn table_names(&self, predicate: Predicate) -> Result<StringSetPlan, Self::Error> {
+        let (in_memory, evicted) = {
+            // held while planning against the chunks in memory, so a chunk
+            // that is dropped from memory meanwhile isn't missed or read twice
+            let catalog = self.catalog.lock().await;
+            let evicted = self.evicted_chunks(&catalog, &predicate).await;
+
+            let mutable_buffer = self
+                .storage_api_buffer()?
+                .table_names(predicate.clone())
+                .await
+                .context(MutableBufferRead)?;
+            let read_buffer = read_buffer_query::table_names(&self.read_buffer, &predicate)
+                .await
+                .context(ReadBufferRead)?;
+            let in_memory = read_buffer_query::union_string_set_plans(mutable_buffer, read_buffer)
+                .context(ReadBufferRead)?;
+            (in_memory, evicted)
+        };
+
+        let (columns, evicted) = evicted_columns(evicted, &predicate);
+        let from_catalog: StringSet = columns.into_iter().map(|c| c.table_name).collect();
+        let in_memory = read_buffer_query::union_string_set_plans(in_memory, from_catalog.into())
+            .context(ReadBufferRead)?;
+
+        let evicted = self
+            .load_evicted_chunks(&evicted, &predicate)
+            .await?
             .table_names(predicate)
             .await
-            .context(MutableBufferRead)
+            .context(EvictedChunkRead)?;
+        read_buffer_query::union_string_set_plans(in_memory, evicted).context(ReadBufferRead)
     }
 
-    async fn tag_column_names(
-        &self,
-        predicate: query::predicate::Predicate,
-    ) -> Result<query::exec::StringSetPlan, Self::Error> {
-        self.mutable_buffer
-            .as_ref()
-            .context(DatabaseNotReadable)?
+    async fn tag_column_names(&self, predicate: Predicate) -> Result<StringSetPlan, Self::Error> {
+        let (in_memory, evicted) = {
+            let catalog = self.catalog.lock().await;
+            let evicted = self.evicted_chunks(&catalog, &predicate).await;
+
+            let mutable_buffer = self
+                .storage_api_buffer()?
+                .tag_column_names(predicate.clone())
+                .await
+                .context(MutableBufferRead)?;
+            let read_buffer = read_buffer_query::tag_column_names(&self.read_buffer, &predicate)
+                .await
+                .context(ReadBufferRead)?;
+            let in_memory = read_buffer_query::union_string_set_plans(mutable_buffer, read_buffer)
+                .context(ReadBufferRead)?;
+            (in_memory, evicted)
+        };
+
+        let (columns, evicted) = evicted_columns(evicted, &predicate);
+        let from_catalog: StringSet = columns.into_iter().flat_map(|c| c.tags).collect();
+        let in_memory = read_buffer_query::union_string_set_plans(in_memory, from_catalog.into())
+            .context(ReadBufferRead)?;
+
+        let evicted = self
+            .load_evicted_chunks(&evicted, &predicate)
+            .await?
             .tag_column_names(predicate)
             .await
-            .context(MutableBufferRead)
+            .context(EvictedChunkRead)?;
+        read_buffer_query::union_string_set_plans(in_memory, evicted).context(ReadBufferRead)
     }
 
-    async fn field_column_names(
-        &self,
-        predicate: query::predicate::Predicate,
-    ) -> Result<query::exec::FieldListPlan, Self::Error> {
-        self.mutable_buffer
-            .as_ref()
-            .context(DatabaseNotReadable)?
+    async fn field_column_names(&self, predicate: Predicate) -> Result<FieldListPlan, Self::Error> {
+        let (in_memory, evicted) = {
+            let catalog = self.catalog.lock().await;
+            let evicted = self.evicted_chunks(&catalog, &predicate).await;
+
+            let mutable_buffer = self
+                .storage_api_buffer()?
+                .field_column_names(predicate.clone())
+                .await
+                .context(MutableBufferRead)?;
+            let read_buffer = read_buffer_query::field_column_names(&self.read_buffer, &predicate)
+                .await
+                .context(ReadBufferRead)?;
+            let in_memory = read_buffer_query::union_field_list_plans(mutable_buffer, read_buffer);
+            (in_memory, evicted)
+        };
+
+        let (columns, evicted) = evicted_columns(evicted, &predicate);
+        let mut fields: BTreeMap<String, Field> = BTreeMap::new();
+        for column in columns.into_iter().flat_map(|c| c.fields) {
+            let field = fields.entry(column.name.clone()).or_insert(Field {
+                name: column.name,
+                data_type: column.field_type.data_type(),
+                last_timestamp: column.last_timestamp,
+            });
+            field.last_timestamp = field.last_timestamp.max(column.last_timestamp);
+        }
+        let in_memory = if fields.is_empty() {
+            in_memory
+        } else {
+            let from_catalog = FieldList {
+                fields: fields.into_iter().map(|(_, field)| field).collect(),
+            };
+            let plan = read_buffer_query::field_list_plan(&from_catalog).context(ReadBufferRead)?;
+            read_buffer_query::union_field_list_plans(in_memory, FieldListPlan::Plans(vec![plan]))
+        };
+
+        let evicted = self
+            .load_evicted_chunks(&evicted, &predicate)
+            .await?
             .field_column_names(predicate)
             .await
-            .context(MutableBufferRead)
+            .context(EvictedChunkRead)?;
+        Ok(read_buffer_query::union_field_list_plans(
+            in_memory, evicted,
+        ))
     }
 
     async fn column_values(
         &self,
         column_name: &str,
-        predicate: query::predicate::Predicate,
-    ) -> Result<query::exec::StringSetPlan, Self::Error> {
-        self.mutable_buffer
-            .as_ref()
-            .context(DatabaseNotReadable)?
+        predicate: Predicate,
+    ) -> Result<StringSetPlan, Self::Error> {
+        let (in_memory, evicted) = {
+            let catalog = self.catalog.lock().await;
+            let evicted = self.evicted_chunks(&catalog, &predicate).await;
+
+            let mutable_buffer = self
+                .storage_api_buffer()?
+                .column_values(column_name, predicate.clone())
+                .await
+                .context(MutableBufferRead)?;
+            let read_buffer =
+                read_buffer_query::column_values(&self.read_buffer, column_name, &predicate)
+                    .await
+                    .context(ReadBufferRead)?;
+            let in_memory = read_buffer_query::union_string_set_plans(mutable_buffer, read_buffer)
+                .context(ReadBufferRead)?;
+            (in_memory, evicted)
+        };
+
+        let evicted = self
+            .load_evicted_chunks(&evicted, &predicate)
+            .await?
             .column_values(column_name, predicate)
             .await
-            .context(MutableBufferRead)
+            .context(EvictedChunkRead)?;
+        read_buffer_query::union_string_set_plans(in_memory, evicted).context(ReadBufferRead)
     }
 
-    async fn query_series(
-        &self,
-        predicate: query::predicate::Predicate,
-    ) -> Result<query::exec::SeriesSetPlans, Self::Error> {
-        self.mutable_buffer
-            .as_ref()
-            .context(DatabaseNotReadable)?
+    async fn query_series(&self, predicate: Predicate) -> Result<SeriesSetPlans, Self::Error> {
+        let (mut plans, evicted) = {
+            let catalog = self.catalog.lock().await;
+            let evicted = self.evicted_chunks(&catalog, &predicate).await;
+
+            let mut plans = self
+                .storage_api_buffer()?
+                .query_series(predicate.clone())
+                .await
+                .context(MutableBufferRead)?;
+            let read_buffer = read_buffer_query::query_series(&self.read_buffer, &predicate)
+                .await
+                .context(ReadBufferRead)?;
+            plans.plans.extend(read_buffer.plans);
+            (plans, evicted)
+        };
+
+        let evicted = self
+            .load_evicted_chunks(&evicted, &predicate)
+            .await?
             .query_series(predicate)
             .await
-            .context(MutableBufferRead)
+            .context(EvictedChunkRead)?;
+        plans.plans.extend(evicted.plans);
+        Ok(plans)
     }
 
     async fn query_groups(
         &self,
-        predicate: query::predicate::Predicate,
-        gby_agg: query::group_by::GroupByAndAggregate,
-    ) -> Result<query::exec::SeriesSetPlans, Self::Error> {
-        self.mutable_buffer
-            .as_ref()
-            .context(DatabaseNotReadable)?
+        predicate: Predicate,
+        gby_agg: GroupByAndAggregate,
+    ) -> Result<SeriesSetPlans, Self::Error> {
+        let (mut plans, evicted) = {
+            let catalog = self.catalog.lock().await;
+            let evicted = self.evicted_chunks(&catalog, &predicate).await;
+
+            let mut plans = self
+                .storage_api_buffer()?
+                .query_groups(predicate.clone(), gby_agg.clone())
+                .await
+                .context(MutableBufferRead)?;
+            let read_buffer =
+                read_buffer_query::query_groups(&self.read_buffer, &predicate, gby_agg.clone())
+                    .await
+                    .context(ReadBufferRead)?;
+            plans.plans.extend(read_buffer.plans);
+            (plans, evicted)
+        };
+
+        let evicted = self
+            .load_evicted_chunks(&evicted, &predicate)
+            .await?
             .query_groups(predicate, gby_agg)
             .await
-            .context(MutableBufferRead)
+            .context(EvictedChunkRead)?;
+        plans.plans.extend(evicted.plans);
+        Ok(plans)
     }
 
     async fn table_to_arrow(
@@ -253,32 +1630,98 @@ impl Database for Db {
         table_name: &str,
         columns: &[&str],
     ) -> Result<Vec<arrow_deps::arrow::record_batch::RecordBatch>, Self::Error> {
-        self.mutable_buffer
-            .as_ref()
-            .context(DatabaseNotReadable)?
-            .table_to_arrow(table_name, columns)
-            .await
-            .context(MutableBufferRead)
+        let (mut batches, evicted) = {
+            // held while reading the chunks in memory, so a chunk that is
+            // dropped from memory meanwhile isn't missed or read twice
+            let catalog = self.catalog.lock().await;
+            let predicate = PredicateBuilder::default().table(table_name).build();
+            let evicted = self.evicted_chunks(&catalog, &predicate).await;
+            (
+                self.table_to_arrow_in_memory(table_name, columns).await?,
+                evicted,
+            )
+        };
+
+        for chunk in &evicted {
+            batches.extend(self.read_evicted_chunk(chunk, table_name, columns).await?);
+        }
+
+        Ok(batches)
     }
 
     async fn partition_keys(&self) -> Result<Vec<String>, Self::Error> {
-        self.mutable_buffer
+        let mut keys: BTreeSet<_> = self
+            .mutable_buffer
             .as_ref()
             .context(DatabaseNotReadable)?
             .partition_keys()
             .await
-            .context(MutableBufferRead)
+            .context(MutableBufferRead)?
+            .into_iter()
+            .collect();
+        keys.extend(self.read_buffer.partition_keys());
+
+        let catalog = self.catalog.lock().await;
+        for chunk in self.evicted_chunks(&catalog, &Predicate::default()).await {
+            keys.insert(chunk.partition_key);
+        }
+
+        Ok(keys.into_iter().collect())
     }
 
     async fn table_names_for_partition(
         &self,
         partition_key: &str,
     ) -> Result<Vec<String>, Self::Error> {
-        self.mutable_buffer
+        let mut names: BTreeSet<_> = self
+            .mutable_buffer
             .as_ref()
             .context(DatabaseNotReadable)?
             .table_names_for_partition(partition_key)
             .await
-            .context(MutableBufferRead)
+            .context(MutableBufferRead)?
+            .into_iter()
+            .collect();
+        for chunk in self.read_buffer.chunks(partition_key) {
+            names.extend(chunk.table_names(&[]));
+        }
+
+        let catalog = self.catalog.lock().await;
+        let predicate = PredicateBuilder::default()
+            .partition_key(partition_key)
+            .build();
+        let evicted = self.evicted_chunks(&catalog, &predicate).await;
+        for chunk in evicted {
+            names.extend(chunk.tables.into_iter().map(|table| table.name));
+        }
+
+        Ok(names.into_iter().collect())
+    }
+}
+
+impl Db {
+    // reads the table from the chunks of the mutable buffer and the read
+    // buffer. Deletes are applied to the chunks in memory when they happen,
+    // so the chunks have no deleted rows.
+    async fn table_to_arrow_in_memory(
+        &self,
+        table_name: &str,
+        columns: &[&str],
+    ) -> Result<Vec<RecordBatch>> {
+        let mut batches = self
+            .mutable_buffer
+            .as_ref()
+            .context(DatabaseNotReadable)?
+            .table_to_arrow(table_name, columns)
+            .await
+            .context(MutableBufferRead)?;
+
+        batches.extend(
+            self.read_buffer
+                .table_to_arrow(table_name, columns)
+                .context(ReadBufferChunk)?,
+        );
+
+        Ok(batches)
     }
 }
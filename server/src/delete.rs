@@ -0,0 +1,196 @@
+//! This module removes the rows that deletes match from the record batches of
+//! a chunk. The chunks in memory are replaced by chunks without those rows
+//! when a delete is applied, the chunks in object storage when they are read.
+
+use std::sync::Arc;
+
+use arrow_deps::arrow::{
+    array::{Array, BooleanArray, Float64Array, Int64Array, StringArray, UInt32Array, UInt64Array},
+    compute::kernels::take::take,
+    datatypes::Schema,
+    error::Result,
+    record_batch::RecordBatch,
+};
+use data_types::{delete::Delete, row_predicate::RowValue, TIME_COLUMN_NAME};
+
+/// Returns the rows of the batch of the table that none of the deletes
+/// match. Rows without a time are never deleted.
+pub fn apply_deletes(
+    table_name: &str,
+    batch: &RecordBatch,
+    deletes: &[Arc<Delete>],
+) -> Result<RecordBatch> {
+    let deletes: Vec<_> = deletes
+        .iter()
+        .filter(|delete| delete.matches_table(table_name))
+        .collect();
+    let times = batch
+        .schema()
+        .index_of(TIME_COLUMN_NAME)
+        .ok()
+        .and_then(|index| batch.column(index).as_any().downcast_ref::<Int64Array>());
+
+    let times = match times {
+        Some(times) if !deletes.is_empty() => times,
+        _ => return Ok(batch.clone()),
+    };
+
+    let kept: Vec<_> = (0..batch.num_rows())
+        .filter(|&row| {
+            times.is_null(row)
+                || !deletes.iter().any(|delete| {
+                    delete.matches(table_name, times.value(row), |column| {
+                        value_at(batch, column, row)
+                    })
+                })
+        })
+        .map(|row| row as u32)
+        .collect();
+
+    if kept.len() == batch.num_rows() {
+        return Ok(batch.clone());
+    }
+
+    let indices = UInt32Array::from(kept);
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| take(column, &indices, None))
+        .collect::<Result<Vec<_>>>()?;
+
+    RecordBatch::try_new(batch.schema(), columns)
+}
+
+/// Returns the columns of the batch with the specified names, in that order,
+/// or the batch itself if no columns are specified
+pub fn project(batch: RecordBatch, columns: &[&str]) -> Result<RecordBatch> {
+    if columns.is_empty() {
+        return Ok(batch);
+    }
+
+    let schema = batch.schema();
+    let indices = columns
+        .iter()
+        .map(|column| schema.index_of(column))
+        .collect::<Result<Vec<_>>>()?;
+
+    let fields = indices.iter().map(|&i| schema.field(i).clone()).collect();
+    let arrays = indices
+        .iter()
+        .map(|&i| Arc::clone(batch.column(i)))
+        .collect();
+
+    let schema = Schema::new_with_metadata(fields, schema.metadata().clone());
+    RecordBatch::try_new(Arc::new(schema), arrays)
+}
+
+// returns the value of the column in the row, or `None` if the batch doesn't
+// have the column or the value is null
+fn value_at<'a>(batch: &'a RecordBatch, column: &str, row: usize) -> Option<RowValue<'a>> {
+    let index = batch.schema().index_of(column).ok()?;
+    let array = batch.column(index);
+    if array.is_null(row) {
+        return None;
+    }
+
+    let array = array.as_any();
+    if let Some(values) = array.downcast_ref::<StringArray>() {
+        Some(RowValue::String(values.value(row)))
+    } else if let Some(values) = array.downcast_ref::<Int64Array>() {
+        Some(RowValue::I64(values.value(row)))
+    } else if let Some(values) = array.downcast_ref::<UInt64Array>() {
+        Some(RowValue::U64(values.value(row)))
+    } else if let Some(values) = array.downcast_ref::<Float64Array>() {
+        Some(RowValue::F64(values.value(row)))
+    } else if let Some(values) = array.downcast_ref::<BooleanArray>() {
+        Some(RowValue::Bool(values.value(row)))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_deps::arrow::datatypes::{DataType, Field};
+
+    fn batch() -> RecordBatch {
+        let schema = Schema::new(vec![
+            Field::new("host", DataType::Utf8, true),
+            Field::new("usage", DataType::Float64, true),
+            Field::new(TIME_COLUMN_NAME, DataType::Int64, true),
+        ]);
+
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(StringArray::from(vec![
+                    Some("a"),
+                    Some("b"),
+                    None,
+                    Some("a"),
+                ])),
+                Arc::new(Float64Array::from(vec![1.0, 2.0, 3.0, 4.0])),
+                Arc::new(Int64Array::from(vec![10, 10, 10, 30])),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn delete(table_name: Option<&str>, predicate: Option<&str>) -> Arc<Delete> {
+        Arc::new(Delete {
+            table_name: table_name.map(ToString::to_string),
+            start: 0,
+            stop: 20,
+            predicate: predicate.map(|p| p.parse().unwrap()),
+        })
+    }
+
+    fn hosts(batch: &RecordBatch) -> Vec<Option<&str>> {
+        let hosts = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        (0..hosts.len())
+            .map(|i| {
+                if hosts.is_null(i) {
+                    None
+                } else {
+                    Some(hosts.value(i))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn removes_matching_rows() {
+        let batch = batch();
+
+        let deleted = apply_deletes("cpu", &batch, &[delete(None, Some(r#"host="a""#))]).unwrap();
+        assert_eq!(hosts(&deleted), vec![Some("b"), None, Some("a")]);
+
+        // a missing value isn't equal to anything
+        let deleted = apply_deletes("cpu", &batch, &[delete(None, Some(r#"host!="a""#))]).unwrap();
+        assert_eq!(hosts(&deleted), vec![Some("a"), Some("a")]);
+
+        let deleted = apply_deletes("cpu", &batch, &[delete(None, Some("usage >= 2"))]).unwrap();
+        assert_eq!(hosts(&deleted), vec![Some("a"), Some("a")]);
+
+        let deleted = apply_deletes("cpu", &batch, &[delete(Some("cpu"), None)]).unwrap();
+        assert_eq!(hosts(&deleted), vec![Some("a")]);
+
+        let deleted = apply_deletes("cpu", &batch, &[delete(Some("mem"), None)]).unwrap();
+        assert_eq!(deleted.num_rows(), 4);
+    }
+
+    #[test]
+    fn projects_columns() {
+        let projected = project(batch(), &[TIME_COLUMN_NAME, "host"]).unwrap();
+        assert_eq!(projected.num_columns(), 2);
+        assert_eq!(projected.schema().field(0).name(), TIME_COLUMN_NAME);
+        assert_eq!(projected.schema().field(1).name(), "host");
+
+        assert!(project(batch(), &["region"]).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! This module contains a consistent hash ring that is used to pick which host
+//! in a `HostGroup` should receive the writes for a partition key.
+
+use crc32fast::Hasher;
+
+/// The number of points each host gets on the ring. More points give a more
+/// even distribution of keys across hosts at the cost of a larger ring.
+const VIRTUAL_NODES_PER_HOST: usize = 128;
+
+/// `HashRing` maps keys to hosts using consistent hashing. Each host is placed
+/// on the ring at a number of pseudo random points and a key belongs to the
+/// host with the first point at or after the hash of the key. Adding or
+/// removing a host only moves the keys that fall on that host's points, which
+/// is roughly `1 / hosts` of the keys.
+///
+/// The ring only depends on the set of hosts, not on the order they were
+/// given in, so every server builds the same ring for the same `HostGroup`.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct HashRing {
+    hosts: Vec<String>,
+    // the points on the ring, sorted by position. The second element is an
+    // index into hosts.
+    points: Vec<(u32, usize)>,
+}
+
+impl HashRing {
+    /// Creates a new ring for the passed in host connection strings.
+    pub fn new(hosts: &[String]) -> Self {
+        let mut hosts = hosts.to_vec();
+        hosts.sort();
+        hosts.dedup();
+
+        let mut points = Vec::with_capacity(hosts.len() * VIRTUAL_NODES_PER_HOST);
+        for (index, host) in hosts.iter().enumerate() {
+            for node in 0..VIRTUAL_NODES_PER_HOST {
+                points.push((hash(&format!("{}-{}", host, node)), index));
+            }
+        }
+
+        // hosts are sorted, so sorting by the host index breaks any ties
+        // between positions in the same way on every server
+        points.sort_unstable();
+
+        Self { hosts, points }
+    }
+
+    /// Returns the host that owns the passed in key or `None` if the ring has
+    /// no hosts.
+    pub fn host_for(&self, key: &str) -> Option<&str> {
+        if self.points.is_empty() {
+            return None;
+        }
+
+        let position = hash(key);
+        let point = match self.points.binary_search(&(position, 0)) {
+            Ok(i) | Err(i) => i,
+        };
+
+        // wrap around to the start of the ring
+        let (_, index) = self.points.get(point).unwrap_or(&self.points[0]);
+
+        Some(&self.hosts[*index])
+    }
+
+    /// Returns the hosts on this ring
+    pub fn hosts(&self) -> &[String] {
+        &self.hosts
+    }
+}
+
+fn hash(key: &str) -> u32 {
+    let mut hasher = Hasher::new();
+    hasher.update(key.as_bytes());
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn keys() -> Vec<String> {
+        (0..1000)
+            .map(|i| format!("2020-11-{}-host_{}", i % 30, i))
+            .collect()
+    }
+
+    #[test]
+    fn empty_ring_has_no_host() {
+        let ring = HashRing::new(&[]);
+        assert!(ring.host_for("foo").is_none());
+    }
+
+    #[test]
+    fn spreads_keys_across_all_hosts() {
+        let ring = HashRing::new(&hosts(&["serverA", "serverB", "serverC", "serverD"]));
+
+        for host in ring.hosts() {
+            let count = keys()
+                .iter()
+                .filter(|k| ring.host_for(k) == Some(host.as_str()))
+                .count();
+            assert!(count > 100, "host {} only got {} keys", host, count);
+        }
+    }
+
+    #[test]
+    fn host_order_does_not_matter() {
+        let ring = HashRing::new(&hosts(&["serverA", "serverB", "serverC"]));
+        let reversed = HashRing::new(&hosts(&["serverC", "serverB", "serverA"]));
+
+        assert_eq!(ring, reversed);
+    }
+
+    #[test]
+    fn adding_host_moves_few_keys() {
+        let ring = HashRing::new(&hosts(&["serverA", "serverB", "serverC", "serverD"]));
+        let bigger = HashRing::new(&hosts(&[
+            "serverA", "serverB", "serverC", "serverD", "serverE",
+        ]));
+
+        let keys = keys();
+        let mut moved = 0;
+        for key in &keys {
+            let before = ring.host_for(key).unwrap();
+            let after = bigger.host_for(key).unwrap();
+
+            if before != after {
+                // keys should only ever move to the new host
+                assert_eq!(after, "serverE");
+                moved += 1;
+            }
+        }
+
+        // roughly a fifth of the keys should move to the new host
+        assert!(moved > 0);
+        assert!(moved < keys.len() / 3, "{} keys moved", moved);
+    }
+}
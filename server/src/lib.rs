@@ -66,7 +66,17 @@
     clippy::use_self
 )]
 
+pub mod applied_writes;
 pub mod buffer;
+pub mod catalog;
 pub mod db;
+pub mod delete;
+pub mod hash_ring;
+pub mod lifecycle;
+pub mod metrics;
+pub mod read_buffer_query;
+pub mod record_batch_ipc;
+pub mod replication_queue;
+pub mod retention;
 pub mod server;
 pub mod snapshot;
@@ -0,0 +1,329 @@
+//! This module decides how the chunks of a database's mutable buffer move
+//! through their lifecycle, based on the `LifecycleRules` of the database:
+//! open chunks are closed once they get too large or too old, closed chunks
+//! are persisted to object storage and moved to the read buffer, and when the
+//! chunks in memory grow past their limit, persisted chunks are dropped from
+//! memory, oldest first. Queries read dropped chunks from object storage.
+
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Duration, Utc};
+use data_types::database_rules::LifecycleRules;
+use mutable_buffer::partition::ChunkSummary;
+
+/// Something that should happen to a chunk of the mutable buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LifecycleAction {
+    /// Close the open chunk of the partition
+    Rollover { partition_key: String },
+    /// Write the closed chunk to object storage
+    Persist {
+        partition_key: String,
+        chunk_id: u64,
+    },
+    /// Drop the persisted chunk from memory, from either the mutable buffer
+    /// or the read buffer
+    Evict {
+        partition_key: String,
+        chunk_id: u64,
+    },
+    /// Convert the closed chunk into a read buffer chunk
+    MoveToReadBuffer {
+        partition_key: String,
+        chunk_id: u64,
+    },
+}
+
+/// Returns the actions the rules call for, given the current `chunks` of the
+/// mutable buffer, the `read_buffer_chunks` and the `(partition key, chunk
+/// id)` of the chunks that have already been persisted. Chunks closed by a
+/// `Rollover` are only considered for persistence on the next call, and
+/// chunks are only moved to the read buffer on the call after they have been
+/// persisted. The `buffer_size_limit` applies to the chunks of both buffers,
+/// and a chunk that is evicted isn't moved to the read buffer first.
+pub fn plan_actions(
+    rules: &LifecycleRules,
+    chunks: &[ChunkSummary],
+    read_buffer_chunks: &[ChunkSummary],
+    persisted: &BTreeSet<(String, u64)>,
+    now: DateTime<Utc>,
+) -> Vec<LifecycleAction> {
+    let mut actions = vec![];
+    let is_persisted = |c: &ChunkSummary| persisted.contains(&(c.partition_key.clone(), c.id));
+
+    // the chunks to drop from memory, oldest first
+    let mut evicted = vec![];
+    if let Some(limit) = rules.buffer_size_limit {
+        let mut size: usize = chunks
+            .iter()
+            .chain(read_buffer_chunks)
+            .map(|c| c.size)
+            .sum();
+
+        let mut evictable: Vec<_> = chunks
+            .iter()
+            .chain(read_buffer_chunks)
+            .filter(|c| c.time_closed.is_some() && is_persisted(c))
+            .collect();
+        evictable.sort_by_key(|c| c.time_closed);
+
+        for chunk in evictable {
+            if size <= limit {
+                break;
+            }
+            size -= chunk.size;
+            evicted.push((chunk.partition_key.clone(), chunk.id));
+        }
+    }
+
+    for chunk in chunks {
+        match chunk.time_closed {
+            None => {
+                if should_rollover(rules, chunk, now) {
+                    actions.push(LifecycleAction::Rollover {
+                        partition_key: chunk.partition_key.clone(),
+                    });
+                }
+            }
+            Some(_) if rules.persist && !is_persisted(chunk) => {
+                actions.push(LifecycleAction::Persist {
+                    partition_key: chunk.partition_key.clone(),
+                    chunk_id: chunk.id,
+                });
+            }
+            Some(_)
+                if rules.move_to_read_buffer
+                    && !evicted.contains(&(chunk.partition_key.clone(), chunk.id)) =>
+            {
+                actions.push(LifecycleAction::MoveToReadBuffer {
+                    partition_key: chunk.partition_key.clone(),
+                    chunk_id: chunk.id,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+
+    actions.extend(
+        evicted
+            .into_iter()
+            .map(|(partition_key, chunk_id)| LifecycleAction::Evict {
+                partition_key,
+                chunk_id,
+            }),
+    );
+
+    actions
+}
+
+// an open chunk is closed once it has data and crossed one of the thresholds
+fn should_rollover(rules: &LifecycleRules, chunk: &ChunkSummary, now: DateTime<Utc>) -> bool {
+    let first_write = match chunk.time_of_first_write {
+        Some(time) if chunk.size > 0 => time,
+        _ => return false,
+    };
+
+    let too_large = rules
+        .mutable_size_threshold
+        .map_or(false, |threshold| chunk.size >= threshold);
+    let too_old = rules.mutable_linger_seconds.map_or(false, |seconds| {
+        now - first_write >= Duration::seconds(seconds.into())
+    });
+
+    too_large || too_old
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_chunk(key: &str, id: u64, size: usize, first_write: DateTime<Utc>) -> ChunkSummary {
+        ChunkSummary {
+            partition_key: key.to_string(),
+            id,
+            size,
+            time_of_first_write: Some(first_write),
+            time_closed: None,
+        }
+    }
+
+    fn closed_chunk(key: &str, id: u64, size: usize, closed: DateTime<Utc>) -> ChunkSummary {
+        ChunkSummary {
+            partition_key: key.to_string(),
+            id,
+            size,
+            time_of_first_write: Some(closed),
+            time_closed: Some(closed),
+        }
+    }
+
+    fn rollover(key: &str) -> LifecycleAction {
+        LifecycleAction::Rollover {
+            partition_key: key.to_string(),
+        }
+    }
+
+    fn persist(key: &str, chunk_id: u64) -> LifecycleAction {
+        LifecycleAction::Persist {
+            partition_key: key.to_string(),
+            chunk_id,
+        }
+    }
+
+    fn evict(key: &str, chunk_id: u64) -> LifecycleAction {
+        LifecycleAction::Evict {
+            partition_key: key.to_string(),
+            chunk_id,
+        }
+    }
+
+    fn move_to_read_buffer(key: &str, chunk_id: u64) -> LifecycleAction {
+        LifecycleAction::MoveToReadBuffer {
+            partition_key: key.to_string(),
+            chunk_id,
+        }
+    }
+
+    #[test]
+    fn rolls_over_large_or_old_chunks() {
+        let now = Utc::now();
+        let rules = LifecycleRules {
+            mutable_size_threshold: Some(100),
+            mutable_linger_seconds: Some(60),
+            ..Default::default()
+        };
+        let chunks = vec![
+            open_chunk("small", 0, 10, now),
+            open_chunk("large", 0, 100, now),
+            open_chunk("old", 0, 10, now - Duration::seconds(61)),
+            open_chunk("empty", 0, 0, now - Duration::seconds(61)),
+        ];
+
+        let actions = plan_actions(&rules, &chunks, &[], &BTreeSet::new(), now);
+        assert_eq!(actions, vec![rollover("large"), rollover("old")]);
+
+        // without thresholds chunks are never closed
+        let actions = plan_actions(
+            &LifecycleRules::default(),
+            &chunks,
+            &[],
+            &BTreeSet::new(),
+            now,
+        );
+        assert!(actions.is_empty());
+    }
+
+    #[test]
+    fn persists_closed_chunks_once() {
+        let now = Utc::now();
+        let rules = LifecycleRules {
+            persist: true,
+            ..Default::default()
+        };
+        let chunks = vec![
+            closed_chunk("a", 0, 10, now),
+            closed_chunk("a", 1, 10, now),
+            open_chunk("a", 2, 10, now),
+        ];
+        let persisted = vec![("a".to_string(), 0)].into_iter().collect();
+
+        let actions = plan_actions(&rules, &chunks, &[], &persisted, now);
+        assert_eq!(actions, vec![persist("a", 1)]);
+
+        let rules = LifecycleRules::default();
+        assert!(plan_actions(&rules, &chunks, &[], &persisted, now).is_empty());
+    }
+
+    #[test]
+    fn evicts_oldest_persisted_chunks_over_limit() {
+        let now = Utc::now();
+        let rules = LifecycleRules {
+            buffer_size_limit: Some(25),
+            ..Default::default()
+        };
+        let chunks = vec![
+            closed_chunk("a", 0, 10, now - Duration::seconds(10)),
+            closed_chunk("b", 0, 10, now - Duration::seconds(30)),
+            closed_chunk("b", 1, 10, now - Duration::seconds(20)),
+            closed_chunk("c", 0, 10, now - Duration::seconds(40)),
+            open_chunk("a", 1, 10, now),
+        ];
+        let persisted = vec![
+            ("a".to_string(), 0),
+            ("b".to_string(), 0),
+            ("b".to_string(), 1),
+        ]
+        .into_iter()
+        .collect();
+
+        // "c" is older but not persisted, so it can't be dropped
+        let actions = plan_actions(&rules, &chunks, &[], &persisted, now);
+        assert_eq!(actions, vec![evict("b", 0), evict("b", 1), evict("a", 0)]);
+
+        let rules = LifecycleRules {
+            buffer_size_limit: Some(30),
+            ..Default::default()
+        };
+        let actions = plan_actions(&rules, &chunks, &[], &persisted, now);
+        assert_eq!(actions, vec![evict("b", 0), evict("b", 1)]);
+    }
+
+    #[test]
+    fn evicts_read_buffer_chunks_over_limit() {
+        let now = Utc::now();
+        let rules = LifecycleRules {
+            buffer_size_limit: Some(15),
+            ..Default::default()
+        };
+        let chunks = vec![closed_chunk("a", 1, 10, now - Duration::seconds(10))];
+        let read_buffer_chunks = vec![
+            closed_chunk("a", 0, 10, now - Duration::seconds(20)),
+            closed_chunk("b", 0, 10, now - Duration::seconds(30)),
+        ];
+        let persisted = vec![("a".to_string(), 0), ("a".to_string(), 1)]
+            .into_iter()
+            .collect();
+
+        // the sizes of both buffers count towards the limit, "b" isn't
+        // persisted so it stays in memory
+        let actions = plan_actions(&rules, &chunks, &read_buffer_chunks, &persisted, now);
+        assert_eq!(actions, vec![evict("a", 0), evict("a", 1)]);
+    }
+
+    #[test]
+    fn moves_closed_chunks_after_persisting() {
+        let now = Utc::now();
+        let rules = LifecycleRules {
+            persist: true,
+            move_to_read_buffer: true,
+            ..Default::default()
+        };
+        let chunks = vec![
+            closed_chunk("a", 0, 10, now),
+            closed_chunk("a", 1, 10, now),
+            open_chunk("a", 2, 10, now),
+        ];
+        let persisted = vec![("a".to_string(), 0)].into_iter().collect();
+
+        let actions = plan_actions(&rules, &chunks, &[], &persisted, now);
+        assert_eq!(actions, vec![move_to_read_buffer("a", 0), persist("a", 1)]);
+
+        // chunks that are evicted aren't moved first
+        let rules = LifecycleRules {
+            buffer_size_limit: Some(0),
+            ..rules
+        };
+        let actions = plan_actions(&rules, &chunks, &[], &persisted, now);
+        assert_eq!(actions, vec![persist("a", 1), evict("a", 0)]);
+
+        let rules = LifecycleRules {
+            move_to_read_buffer: true,
+            ..Default::default()
+        };
+        let actions = plan_actions(&rules, &chunks, &[], &BTreeSet::new(), now);
+        assert_eq!(
+            actions,
+            vec![move_to_read_buffer("a", 0), move_to_read_buffer("a", 1)]
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! This module contains the metrics a server keeps about the writes and
+//! queries it handles, and an encoder for the Prometheus text format they are
+//! exposed in. Metrics that describe the current state of the server, such as
+//! buffer sizes, are read from the databases when the metrics are encoded.
+
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration, time::Instant};
+
+/// The upper bounds, in seconds, of the buckets of the duration histograms
+pub const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0,
+];
+
+/// A histogram of observed values, with a count for each bucket of the
+/// values less than or equal to its upper bound
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(DURATION_BUCKETS)
+    }
+}
+
+impl Histogram {
+    /// Creates an empty histogram with buckets for the upper bounds, which
+    /// have to be sorted
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(&mut self.counts) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    pub fn observe_duration(&mut self, duration: Duration) {
+        self.observe(duration.as_secs_f64())
+    }
+
+    /// Returns the number of observed values
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// Label names and values of a metric
+pub type Labels<'a> = &'a [(&'a str, &'a str)];
+
+/// `Encoder` writes metrics in the Prometheus text exposition format
+#[derive(Debug, Default)]
+pub struct Encoder {
+    out: String,
+}
+
+impl Encoder {
+    pub fn counter<'a>(
+        &mut self,
+        name: &str,
+        help: &str,
+        values: impl IntoIterator<Item = (Labels<'a>, u64)>,
+    ) {
+        self.header(name, help, "counter");
+        for (labels, value) in values {
+            self.sample(name, labels, None, value);
+        }
+    }
+
+    pub fn gauge<'a>(
+        &mut self,
+        name: &str,
+        help: &str,
+        values: impl IntoIterator<Item = (Labels<'a>, u64)>,
+    ) {
+        self.header(name, help, "gauge");
+        for (labels, value) in values {
+            self.sample(name, labels, None, value);
+        }
+    }
+
+    pub fn histogram<'a>(
+        &mut self,
+        name: &str,
+        help: &str,
+        values: impl IntoIterator<Item = (Labels<'a>, &'a Histogram)>,
+    ) {
+        self.header(name, help, "histogram");
+        let bucket = format!("{}_bucket", name);
+        for (labels, histogram) in values {
+            for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+                self.sample(&bucket, labels, Some(&bound.to_string()), count);
+            }
+            self.sample(&bucket, labels, Some("+Inf"), histogram.count);
+            self.sample(&format!("{}_sum", name), labels, None, histogram.sum);
+            self.sample(&format!("{}_count", name), labels, None, histogram.count);
+        }
+    }
+
+    /// Returns the encoded metrics
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    fn header(&mut self, name: &str, help: &str, metric_type: &str) {
+        writeln!(self.out, "# HELP {} {}", name, help).unwrap();
+        writeln!(self.out, "# TYPE {} {}", name, metric_type).unwrap();
+    }
+
+    // writes a line with the value of the metric, where `le` is the upper
+    // bound of a histogram bucket
+    fn sample(
+        &mut self,
+        name: &str,
+        labels: Labels<'_>,
+        le: Option<&str>,
+        value: impl std::fmt::Display,
+    ) {
+        let labels = labels.iter().copied().chain(le.map(|le| ("le", le)));
+        let labels: Vec<_> = labels
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+            .collect();
+
+        if labels.is_empty() {
+            writeln!(self.out, "{} {}", name, value).unwrap();
+        } else {
+            writeln!(self.out, "{}{{{}}} {}", name, labels.join(","), value).unwrap();
+        }
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+/// The number of lines and bytes written to a database
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WriteCounts {
+    pub lines: u64,
+    /// The size of the writes, as encoded for the WAL
+    pub bytes: u64,
+}
+
+/// The metrics a server keeps about the writes and the gRPC requests it
+/// handled
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    writes: Mutex<BTreeMap<String, WriteCounts>>,
+    write_errors: Mutex<BTreeMap<&'static str, u64>>,
+    rpc_durations: Mutex<BTreeMap<&'static str, Histogram>>,
+}
+
+impl ServerMetrics {
+    pub fn record_write(&self, db_name: &str, lines: usize, bytes: usize) {
+        let mut writes = self.writes.lock().expect("mutex poisoned");
+        let counts = writes.entry(db_name.to_string()).or_default();
+        counts.lines += lines as u64;
+        counts.bytes += bytes as u64;
+    }
+
+    /// Counts a failed write by the kind of error, such as
+    /// `database_not_found`
+    pub fn record_write_error(&self, kind: &'static str) {
+        *self
+            .write_errors
+            .lock()
+            .expect("mutex poisoned")
+            .entry(kind)
+            .or_default() += 1;
+    }
+
+    pub fn observe_rpc(&self, method: &'static str, duration: Duration) {
+        self.rpc_durations
+            .lock()
+            .expect("mutex poisoned")
+            .entry(method)
+            .or_default()
+            .observe_duration(duration);
+    }
+
+    /// Returns a guard that observes the duration of the gRPC request when
+    /// it is dropped
+    pub fn time_rpc(&self, method: &'static str) -> RpcTimer<'_> {
+        RpcTimer {
+            metrics: self,
+            method,
+            start: Instant::now(),
+        }
+    }
+
+    /// Returns the number of lines and bytes written to the database
+    pub fn writes(&self, db_name: &str) -> WriteCounts {
+        let writes = self.writes.lock().expect("mutex poisoned");
+        writes.get(db_name).copied().unwrap_or_default()
+    }
+
+    /// Returns the number of failed writes with the kind of error
+    pub fn write_errors(&self, kind: &str) -> u64 {
+        let write_errors = self.write_errors.lock().expect("mutex poisoned");
+        write_errors.get(kind).copied().unwrap_or_default()
+    }
+
+    pub fn encode(&self, encoder: &mut Encoder) {
+        let writes = self.writes.lock().expect("mutex poisoned").clone();
+        let databases: Vec<_> = writes
+            .keys()
+            .map(|db_name| [("db_name", db_name.as_str())])
+            .collect();
+        encoder.counter(
+            "iox_lines_written_total",
+            "Lines written to a database",
+            databases
+                .iter()
+                .zip(writes.values())
+                .map(|(labels, counts)| (&labels[..], counts.lines)),
+        );
+        encoder.counter(
+            "iox_bytes_written_total",
+            "Bytes written to a database, as encoded for the WAL",
+            databases
+                .iter()
+                .zip(writes.values())
+                .map(|(labels, counts)| (&labels[..], counts.bytes)),
+        );
+
+        let write_errors = self.write_errors.lock().expect("mutex poisoned").clone();
+        let kinds: Vec<_> = write_errors.keys().map(|kind| [("kind", *kind)]).collect();
+        encoder.counter(
+            "iox_write_errors_total",
+            "Writes that failed, by the kind of error",
+            kinds
+                .iter()
+                .zip(write_errors.values())
+                .map(|(labels, count)| (&labels[..], *count)),
+        );
+
+        let rpc_durations = self.rpc_durations.lock().expect("mutex poisoned").clone();
+        let methods: Vec<_> = rpc_durations
+            .keys()
+            .map(|method| [("method", *method)])
+            .collect();
+        encoder.histogram(
+            "iox_grpc_request_duration_seconds",
+            "Time taken to handle gRPC requests, by method",
+            methods
+                .iter()
+                .zip(rpc_durations.values())
+                .map(|(labels, histogram)| (&labels[..], histogram)),
+        );
+    }
+}
+
+/// Observes the duration of a gRPC request when it is dropped
+#[derive(Debug)]
+pub struct RpcTimer<'a> {
+    metrics: &'a ServerMetrics,
+    method: &'static str,
+    start: Instant,
+}
+
+impl Drop for RpcTimer<'_> {
+    fn drop(&mut self) {
+        self.metrics.observe_rpc(self.method, self.start.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_counts_values_per_bucket() {
+        let mut histogram = Histogram::new(&[1.0, 5.0]);
+        histogram.observe(0.5);
+        histogram.observe(1.0);
+        histogram.observe(3.0);
+        histogram.observe(10.0);
+
+        assert_eq!(histogram.counts, vec![2, 3]);
+        assert_eq!(histogram.count(), 4);
+        assert!((histogram.sum - 14.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn encodes_text_format() {
+        let mut histogram = Histogram::new(&[0.5]);
+        histogram.observe(0.25);
+
+        let mut encoder = Encoder::default();
+        encoder.counter("writes_total", "Writes", vec![(&[][..], 3)]);
+        encoder.gauge("size_bytes", "Size", vec![(&[("db_name", "a\"b")][..], 10)]);
+        encoder.histogram(
+            "duration_seconds",
+            "Duration",
+            vec![(&[("method", "read")][..], &histogram)],
+        );
+
+        let expected = r#"# HELP writes_total Writes
+# TYPE writes_total counter
+writes_total 3
+# HELP size_bytes Size
+# TYPE size_bytes gauge
+size_bytes{db_name="a\"b"} 10
+# HELP duration_seconds Duration
+# TYPE duration_seconds histogram
+duration_seconds_bucket{method="read",le="0.5"} 1
+duration_seconds_bucket{method="read",le="+Inf"} 1
+duration_seconds_sum{method="read"} 0.25
+duration_seconds_count{method="read"} 1
+"#;
+        assert_eq!(encoder.finish(), expected);
+    }
+
+    #[test]
+    fn records_writes_and_requests() {
+        let metrics = ServerMetrics::default();
+        metrics.record_write("foo", 2, 100);
+        metrics.record_write("foo", 1, 50);
+        metrics.record_write_error("database_not_found");
+        drop(metrics.time_rpc("read_filter"));
+
+        assert_eq!(
+            metrics.writes("foo"),
+            WriteCounts {
+                lines: 3,
+                bytes: 150
+            }
+        );
+        assert_eq!(metrics.writes("bar"), WriteCounts::default());
+        assert_eq!(metrics.write_errors("database_not_found"), 1);
+
+        let mut encoder = Encoder::default();
+        metrics.encode(&mut encoder);
+        let text = encoder.finish();
+        assert!(text.contains("iox_lines_written_total{db_name=\"foo\"} 3\n"));
+        assert!(text.contains("iox_bytes_written_total{db_name=\"foo\"} 150\n"));
+        assert!(text.contains("iox_write_errors_total{kind=\"database_not_found\"} 1\n"));
+        assert!(
+            text.contains("iox_grpc_request_duration_seconds_count{method=\"read_filter\"} 1\n")
+        );
+    }
+}
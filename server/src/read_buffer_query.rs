@@ -0,0 +1,634 @@
+//! This module plans the storage API queries against the chunks of the read
+//! buffer. The parts of a query's predicate the read buffer understands, the
+//! time range and string equality comparisons, are evaluated against the
+//! compressed chunks, so only the rows that can match are ever materialised.
+//!
+//! When the read buffer understands the whole predicate, table names, tag
+//! keys and tag values are answered from the chunks directly. Otherwise, and
+//! for field names and series, the matching rows are loaded into a temporary
+//! mutable buffer, which plans the query the same way as for its own chunks.
+
+use std::sync::Arc;
+
+use arrow_deps::{
+    arrow::{
+        array::{ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray, UInt64Array},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    },
+    datafusion::{
+        error::DataFusionError,
+        logical_plan::{Expr, LogicalPlan, LogicalPlanBuilder, Operator},
+        scalar::ScalarValue,
+    },
+};
+use data_types::TIME_COLUMN_NAME;
+use mutable_buffer::MutableBufferDb;
+use query::{
+    exec::{
+        fieldlist::FieldList, stringset::StringSet, FieldListPlan, SeriesSetPlans, StringSetPlan,
+    },
+    group_by::GroupByAndAggregate,
+    predicate::Predicate,
+    Database,
+};
+use read_buffer::{
+    chunk::Chunk,
+    column::{cmp, OwnedValue, Value},
+    row_group::Predicate as ReadBufferPredicate,
+    table::Table,
+    time_range_predicate, Database as ReadBufferDb,
+};
+use snafu::{ResultExt, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Error reading table {} from the read buffer: {}", table_name, source))]
+    ReadingTable {
+        table_name: String,
+        source: read_buffer::Error,
+    },
+
+    #[snafu(display("Error loading read buffer rows for planning: {}", source))]
+    LoadingRows {
+        source: mutable_buffer::database::Error,
+    },
+
+    #[snafu(display("Error planning read buffer query: {}", source))]
+    Planning {
+        source: mutable_buffer::database::Error,
+    },
+
+    #[snafu(display("Error building plan for known strings: {}", source))]
+    BuildingPlan { source: DataFusionError },
+
+    #[snafu(display("Field {} has unsupported type {:?}", field_name, data_type))]
+    UnsupportedFieldType {
+        field_name: String,
+        data_type: DataType,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Returns the names of the tables with rows matching the predicate
+pub async fn table_names(
+    read_buffer: &ReadBufferDb,
+    predicate: &Predicate,
+) -> Result<StringSetPlan> {
+    let query = Query::new(read_buffer, predicate);
+    if !query.exact {
+        let db = query.load_matching_rows().await?;
+        return db.table_names(predicate.clone()).await.context(Planning);
+    }
+
+    let mut names = StringSet::new();
+    query.for_each_table(|table, predicates| {
+        if table.satisfies_predicates(predicates) {
+            names.insert(table.name().to_string());
+        }
+    });
+    Ok(names.into())
+}
+
+/// Returns the names of the tag columns with a value in any row matching the
+/// predicate
+pub async fn tag_column_names(
+    read_buffer: &ReadBufferDb,
+    predicate: &Predicate,
+) -> Result<StringSetPlan> {
+    let query = Query::new(read_buffer, predicate);
+    if !query.exact {
+        let db = query.load_matching_rows().await?;
+        return db
+            .tag_column_names(predicate.clone())
+            .await
+            .context(Planning);
+    }
+
+    let mut names = StringSet::new();
+    query.for_each_table(|table, predicates| {
+        let found = names.iter().map(String::as_str).collect();
+        let keys: Vec<_> = table
+            .tag_keys(predicates, &found)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        names.extend(keys);
+    });
+    Ok(names.into())
+}
+
+/// Returns the names and types of the field columns of the tables with rows
+/// matching the predicate
+pub async fn field_column_names(
+    read_buffer: &ReadBufferDb,
+    predicate: &Predicate,
+) -> Result<FieldListPlan> {
+    let db = Query::new(read_buffer, predicate)
+        .load_matching_rows()
+        .await?;
+    db.field_column_names(predicate.clone())
+        .await
+        .context(Planning)
+}
+
+/// Returns the distinct values of the tag column in the rows matching the
+/// predicate
+pub async fn column_values(
+    read_buffer: &ReadBufferDb,
+    column_name: &str,
+    predicate: &Predicate,
+) -> Result<StringSetPlan> {
+    let query = Query::new(read_buffer, predicate);
+    if !query.exact {
+        let db = query.load_matching_rows().await?;
+        return db
+            .column_values(column_name, predicate.clone())
+            .await
+            .context(Planning);
+    }
+
+    let mut values = StringSet::new();
+    query.for_each_table(|table, predicates| {
+        if let Some(column_values) = table
+            .tag_values(predicates, &[column_name])
+            .get(column_name)
+        {
+            values.extend(column_values.iter().map(|value| value.to_string()));
+        }
+    });
+    Ok(values.into())
+}
+
+/// Returns the plans of the series in the rows matching the predicate
+pub async fn query_series(
+    read_buffer: &ReadBufferDb,
+    predicate: &Predicate,
+) -> Result<SeriesSetPlans> {
+    let db = Query::new(read_buffer, predicate)
+        .load_matching_rows()
+        .await?;
+    db.query_series(predicate.clone()).await.context(Planning)
+}
+
+/// Returns the plans of the grouped and aggregated series in the rows
+/// matching the predicate
+pub async fn query_groups(
+    read_buffer: &ReadBufferDb,
+    predicate: &Predicate,
+    gby_agg: GroupByAndAggregate,
+) -> Result<SeriesSetPlans> {
+    let db = Query::new(read_buffer, predicate)
+        .load_matching_rows()
+        .await?;
+    db.query_groups(predicate.clone(), gby_agg)
+        .await
+        .context(Planning)
+}
+
+/// Combines the string sets of the mutable buffer and the read buffer. Known
+/// strings are turned into a plan if the other set has to be planned.
+pub fn union_string_set_plans(a: StringSetPlan, b: StringSetPlan) -> Result<StringSetPlan> {
+    Ok(match (a, b) {
+        (StringSetPlan::Known(Err(e)), _) | (_, StringSetPlan::Known(Err(e))) => {
+            StringSetPlan::Known(Err(e))
+        }
+        (StringSetPlan::Known(Ok(a)), StringSetPlan::Known(Ok(b))) => {
+            let mut set = StringSet::clone(&a);
+            set.extend(b.iter().cloned());
+            set.into()
+        }
+        (StringSetPlan::Plan(mut a), StringSetPlan::Plan(b)) => {
+            a.extend(b);
+            a.into()
+        }
+        (StringSetPlan::Known(Ok(set)), StringSetPlan::Plan(mut plans))
+        | (StringSetPlan::Plan(mut plans), StringSetPlan::Known(Ok(set))) => {
+            if !set.is_empty() {
+                plans.push(string_set_plan(&set)?);
+            }
+            plans.into()
+        }
+    })
+}
+
+/// Combines the field list plans of the mutable buffer and the read buffer,
+/// which both always plan field lists
+pub fn union_field_list_plans(a: FieldListPlan, b: FieldListPlan) -> FieldListPlan {
+    match (a, b) {
+        (FieldListPlan::Plans(mut a), FieldListPlan::Plans(b)) => {
+            a.extend(b);
+            FieldListPlan::Plans(a)
+        }
+        (FieldListPlan::Plans(plans), known) | (known, FieldListPlan::Plans(plans))
+            if plans.is_empty() =>
+        {
+            known
+        }
+        // field lists are only known up front for errors, which take
+        // precedence over the plans of the other buffer
+        (known, _) => known,
+    }
+}
+
+/// Returns a plan producing a row for each field of the list, with a value
+/// for only that field, at its last timestamp, so executing the plan results
+/// in the list again
+pub fn field_list_plan(list: &FieldList) -> Result<LogicalPlan> {
+    let mut fields: Vec<_> = list
+        .fields
+        .iter()
+        .map(|field| Field::new(&field.name, field.data_type.clone(), true))
+        .collect();
+    fields.push(Field::new(TIME_COLUMN_NAME, DataType::Int64, false));
+    let schema = Arc::new(Schema::new(fields));
+
+    let rows = list.fields.len();
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(rows + 1);
+    for (index, field) in list.fields.iter().enumerate() {
+        let column: ArrayRef = match &field.data_type {
+            DataType::Int64 => Arc::new(Int64Array::from(only_at(rows, index, 0_i64))),
+            DataType::UInt64 => Arc::new(UInt64Array::from(only_at(rows, index, 0_i64))),
+            DataType::Float64 => Arc::new(Float64Array::from(only_at(rows, index, 0.0_f64))),
+            DataType::Boolean => Arc::new(BooleanArray::from(only_at(rows, index, false))),
+            DataType::Utf8 => Arc::new(StringArray::from(only_at(rows, index, ""))),
+            data_type => {
+                return UnsupportedFieldType {
+                    field_name: &field.name,
+                    data_type: data_type.clone(),
+                }
+                .fail()
+            }
+        };
+        columns.push(column);
+    }
+    let times: Vec<_> = list.fields.iter().map(|f| f.last_timestamp).collect();
+    columns.push(Arc::new(Int64Array::from(times)));
+
+    let batch = RecordBatch::try_new(Arc::clone(&schema), columns)
+        .map_err(DataFusionError::from)
+        .context(BuildingPlan)?;
+    LogicalPlanBuilder::scan_memory(vec![vec![batch]], schema, None)
+        .and_then(|builder| builder.build())
+        .context(BuildingPlan)
+}
+
+// returns `rows` values that are null except for the one at `index`
+fn only_at<T: Copy>(rows: usize, index: usize, value: T) -> Vec<Option<T>> {
+    (0..rows)
+        .map(|row| if row == index { Some(value) } else { None })
+        .collect()
+}
+
+// Returns a plan producing the strings of the set as a single Utf8 column
+fn string_set_plan(set: &StringSet) -> Result<LogicalPlan> {
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "value",
+        DataType::Utf8,
+        false,
+    )]));
+    let values: Vec<_> = set.iter().map(String::as_str).collect();
+    let batch = RecordBatch::try_new(
+        Arc::clone(&schema),
+        vec![Arc::new(StringArray::from(values))],
+    )
+    .map_err(DataFusionError::from)
+    .context(BuildingPlan)?;
+
+    LogicalPlanBuilder::scan_memory(vec![vec![batch]], schema, None)
+        .and_then(|builder| builder.build())
+        .context(BuildingPlan)
+}
+
+// A storage API predicate, with the comparisons of its expressions the read
+// buffer can evaluate
+struct Query<'a> {
+    read_buffer: &'a ReadBufferDb,
+    predicate: &'a Predicate,
+    comparisons: Vec<(&'a str, cmp::Operator, &'a str)>,
+    // if the read buffer can evaluate the whole predicate
+    exact: bool,
+}
+
+impl<'a> Query<'a> {
+    fn new(read_buffer: &'a ReadBufferDb, predicate: &'a Predicate) -> Self {
+        let mut comparisons = vec![];
+        let mut exact = predicate.field_columns.is_none();
+        for expr in &predicate.exprs {
+            exact &= add_comparisons(expr, &mut comparisons);
+        }
+
+        Self {
+            read_buffer,
+            predicate,
+            comparisons,
+            exact,
+        }
+    }
+
+    // the chunks of the partitions the predicate selects
+    fn chunks(&self) -> Vec<(String, Arc<Chunk>)> {
+        self.read_buffer
+            .partition_keys()
+            .into_iter()
+            .filter(|key| {
+                self.predicate
+                    .partition_key
+                    .as_ref()
+                    .map_or(true, |partition_key| partition_key == key)
+            })
+            .flat_map(|key| {
+                self.read_buffer
+                    .chunks(&key)
+                    .into_iter()
+                    .map(move |chunk| (key.clone(), chunk))
+            })
+            .collect()
+    }
+
+    // calls `f` with each table of the chunks the predicate selects that
+    // could have matching rows, and the read buffer predicates for it
+    fn for_each_table<F>(&self, mut f: F)
+    where
+        F: FnMut(&Table, &[ReadBufferPredicate<'_>]),
+    {
+        for (_, chunk) in self.chunks() {
+            for table_name in chunk.table_names(&[]) {
+                if let Some(table_names) = &self.predicate.table_names {
+                    if !table_names.contains(&table_name) {
+                        continue;
+                    }
+                }
+
+                let table = chunk.table(&table_name).expect("chunk has table");
+                if let Some(predicates) = self.table_predicates(table) {
+                    f(table, &predicates);
+                }
+            }
+        }
+    }
+
+    // Returns the read buffer predicates for the table, or `None` if no row
+    // of the table can match. Comparisons with a column the table doesn't
+    // have, or that isn't a string column, never match.
+    fn table_predicates(&self, table: &Table) -> Option<Vec<ReadBufferPredicate<'a>>> {
+        let mut predicates = match self.predicate.range {
+            Some(range) => time_range_predicate(range.start, range.end),
+            None => vec![],
+        };
+
+        let column_ranges = table.column_ranges();
+        for &(column_name, op, value) in &self.comparisons {
+            match column_ranges.get(column_name) {
+                Some((OwnedValue::String(_), _)) => {
+                    predicates.push((column_name, (op, Value::String(value))))
+                }
+                _ => return None,
+            }
+        }
+
+        Some(predicates)
+    }
+
+    // Loads the rows of the chunks the predicate selects that could match it
+    // into a new mutable buffer, with a closed chunk per read buffer chunk
+    async fn load_matching_rows(&self) -> Result<MutableBufferDb> {
+        let db = MutableBufferDb::new("read_buffer");
+        for (partition_key, chunk) in self.chunks() {
+            let mut tables = vec![];
+            for table_name in chunk.table_names(&[]) {
+                if let Some(table_names) = &self.predicate.table_names {
+                    if !table_names.contains(&table_name) {
+                        continue;
+                    }
+                }
+
+                let table = chunk.table(&table_name).expect("chunk has table");
+                if let Some(predicates) = self.table_predicates(table) {
+                    let batches =
+                        table
+                            .read_filter_to_arrow(&[], &predicates)
+                            .context(ReadingTable {
+                                table_name: &table_name,
+                            })?;
+                    if !batches.is_empty() {
+                        tables.push((table_name, batches));
+                    }
+                }
+            }
+
+            if !tables.is_empty() {
+                db.load_chunk(&partition_key, &tables)
+                    .await
+                    .context(LoadingRows)?;
+            }
+        }
+
+        Ok(db)
+    }
+}
+
+// Adds the comparisons of a column with a string literal, possibly ANDed
+// together, in `expr` to `comparisons`. Returns false if `expr` has other
+// expressions, which the read buffer can't evaluate.
+fn add_comparisons<'a>(
+    expr: &'a Expr,
+    comparisons: &mut Vec<(&'a str, cmp::Operator, &'a str)>,
+) -> bool {
+    match expr {
+        Expr::BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        } => {
+            // both sides are always visited to use all their comparisons
+            let left = add_comparisons(left, comparisons);
+            let right = add_comparisons(right, comparisons);
+            left && right
+        }
+        Expr::BinaryExpr { left, op, right } => {
+            let op = match op {
+                Operator::Eq => cmp::Operator::Equal,
+                Operator::NotEq => cmp::Operator::NotEqual,
+                _ => return false,
+            };
+
+            match (left.as_ref(), right.as_ref()) {
+                (Expr::Column(column_name), Expr::Literal(ScalarValue::Utf8(Some(value))))
+                | (Expr::Literal(ScalarValue::Utf8(Some(value))), Expr::Column(column_name)) => {
+                    comparisons.push((column_name.as_str(), op, value.as_str()));
+                    true
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_deps::datafusion::logical_plan::{col, lit};
+    use query::{exec::Executor, predicate::PredicateBuilder};
+    use read_buffer::row_group::RowGroup;
+    use std::convert::TryFrom;
+
+    type TestError = Box<dyn std::error::Error + Send + Sync + 'static>;
+    type Result<T = (), E = TestError> = std::result::Result<T, E>;
+
+    // loads the line protocol as a chunk of the read buffer
+    async fn load_lp(read_buffer: &ReadBufferDb, partition_key: &str, id: u32, lp: &str) {
+        let lines: Vec<_> = influxdb_line_protocol::parse_lines(lp)
+            .map(|l| l.unwrap())
+            .collect();
+        let mb = MutableBufferDb::new("source");
+        mb.store_replicated_write(
+            &data_types::data::lines_to_replicated_write(0, 0, &lines, &Default::default())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let mut chunk: Option<Chunk> = None;
+        for table_name in ["cpu", "mem"].iter() {
+            let batches = mb.table_to_arrow(table_name, &[]).await.unwrap();
+            for batch in batches {
+                let table = Table::new(table_name.to_string(), RowGroup::try_from(batch).unwrap());
+                match chunk.as_mut() {
+                    Some(chunk) => chunk.add_table(table),
+                    None => chunk = Some(Chunk::new(id, table)),
+                }
+            }
+        }
+        read_buffer.add_chunk(partition_key, chunk.unwrap());
+    }
+
+    async fn to_vec(plan: StringSetPlan) -> Vec<String> {
+        let set = Executor::new().to_string_set(plan).await.unwrap();
+        set.iter().cloned().collect()
+    }
+
+    #[tokio::test]
+    async fn answers_schema_queries_from_chunks() -> Result {
+        let read_buffer = ReadBufferDb::new();
+        load_lp(
+            &read_buffer,
+            "1970-01-01T00",
+            0,
+            "cpu,host=a,region=west user=1 10\ncpu,host=b user=2 20\nmem,host=c free=3 30",
+        )
+        .await;
+        load_lp(&read_buffer, "1970-01-01T01", 1, "cpu,host=d user=4 40").await;
+
+        let predicate = PredicateBuilder::default().timestamp_range(15, 100).build();
+        let plan = table_names(&read_buffer, &predicate).await?;
+        assert!(matches!(plan, StringSetPlan::Known(_)));
+        assert_eq!(to_vec(plan).await, vec!["cpu", "mem"]);
+
+        let predicate = PredicateBuilder::default()
+            .add_expr(col("region").eq(lit("west")))
+            .build();
+        assert_eq!(
+            to_vec(table_names(&read_buffer, &predicate).await?).await,
+            vec!["cpu"]
+        );
+        assert_eq!(
+            to_vec(tag_column_names(&read_buffer, &predicate).await?).await,
+            vec!["host", "region"]
+        );
+
+        let predicate = PredicateBuilder::default()
+            .add_expr(col("host").not_eq(lit("a")))
+            .partition_key("1970-01-01T00")
+            .build();
+        let plan = column_values(&read_buffer, "host", &predicate).await?;
+        assert_eq!(to_vec(plan).await, vec!["b", "c"]);
+
+        // the time range excludes the only row with a region
+        let predicate = PredicateBuilder::default().timestamp_range(15, 100).build();
+        assert_eq!(
+            to_vec(tag_column_names(&read_buffer, &predicate).await?).await,
+            vec!["host"]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn plans_other_predicates_from_matching_rows() -> Result {
+        let read_buffer = ReadBufferDb::new();
+        load_lp(
+            &read_buffer,
+            "1970-01-01T00",
+            0,
+            "cpu,host=a user=1 10\ncpu,host=b user=2 20\nmem,host=c free=3 30",
+        )
+        .await;
+
+        let predicate = PredicateBuilder::default()
+            .add_expr(col("user").gt(lit(1.0)))
+            .build();
+        let plan = column_values(&read_buffer, "host", &predicate).await?;
+        assert!(matches!(plan, StringSetPlan::Plan(_)));
+        assert_eq!(to_vec(plan).await, vec!["b"]);
+
+        let predicate = PredicateBuilder::default()
+            .add_expr(col("host").eq(lit("c")))
+            .build();
+        let fields = Executor::new()
+            .to_fieldlist(field_column_names(&read_buffer, &predicate).await?)
+            .await?;
+        let names: Vec<_> = fields.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["free"]);
+
+        let plans = query_series(&read_buffer, &predicate).await?;
+        assert_eq!(plans.plans.len(), 1);
+        assert_eq!(plans.plans[0].table_name.as_ref(), "mem");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn plans_known_field_lists() -> Result {
+        let list = FieldList {
+            fields: vec![
+                query::exec::fieldlist::Field {
+                    name: "free".to_string(),
+                    data_type: DataType::Int64,
+                    last_timestamp: 30,
+                },
+                query::exec::fieldlist::Field {
+                    name: "state".to_string(),
+                    data_type: DataType::Utf8,
+                    last_timestamp: 10,
+                },
+            ],
+        };
+
+        let plan = FieldListPlan::Plans(vec![field_list_plan(&list)?]);
+        assert_eq!(Executor::new().to_fieldlist(plan).await?, list);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unions_known_strings_with_plans() -> Result {
+        let known: StringSetPlan = vec!["a".to_string()]
+            .into_iter()
+            .collect::<StringSet>()
+            .into();
+        let planned: StringSetPlan = vec![string_set_plan(
+            &vec!["b".to_string()].into_iter().collect(),
+        )?]
+        .into();
+
+        let plan = union_string_set_plans(known, planned)?;
+        assert!(matches!(plan, StringSetPlan::Plan(_)));
+        assert_eq!(to_vec(plan).await, vec!["a", "b"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+//! This module contains helpers to send query results between IOx servers as
+//! record batches encoded in the Arrow IPC streaming format.
+
+use arrow_deps::arrow::{
+    error::Result,
+    ipc::{reader::StreamReader, writer::StreamWriter},
+    record_batch::RecordBatch,
+};
+
+/// Encodes the record batches as an Arrow IPC stream. All batches must have
+/// the same schema. No batches are encoded as no bytes.
+pub fn encode_record_batches(batches: &[RecordBatch]) -> Result<Vec<u8>> {
+    let mut data = vec![];
+
+    if let Some(first) = batches.first() {
+        let mut writer = StreamWriter::try_new(&mut data, &first.schema())?;
+        for batch in batches {
+            writer.write(batch)?;
+        }
+        writer.finish()?;
+    }
+
+    Ok(data)
+}
+
+/// Decodes the record batches of an Arrow IPC stream that was encoded by
+/// `encode_record_batches`.
+pub fn decode_record_batches(data: &[u8]) -> Result<Vec<RecordBatch>> {
+    if data.is_empty() {
+        return Ok(vec![]);
+    }
+
+    StreamReader::try_new(data)?.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_deps::arrow::{
+        array::{Int64Array, StringArray},
+        datatypes::{DataType, Field, Schema},
+        util::pretty::pretty_format_batches,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn round_trip() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("host", DataType::Utf8, false),
+            Field::new("value", DataType::Int64, false),
+        ]));
+        let batches: Vec<_> = (0..2)
+            .map(|i| {
+                RecordBatch::try_new(
+                    Arc::clone(&schema),
+                    vec![
+                        Arc::new(StringArray::from(vec!["a", "b"])),
+                        Arc::new(Int64Array::from(vec![i, i + 1])),
+                    ],
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let data = encode_record_batches(&batches).unwrap();
+        let decoded = decode_record_batches(&data).unwrap();
+
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(batches[0].schema(), decoded[0].schema());
+        assert_eq!(
+            pretty_format_batches(&batches).unwrap(),
+            pretty_format_batches(&decoded).unwrap()
+        );
+    }
+
+    #[test]
+    fn no_batches() {
+        let data = encode_record_batches(&[]).unwrap();
+        assert!(data.is_empty());
+        assert!(decode_record_batches(&data).unwrap().is_empty());
+    }
+}
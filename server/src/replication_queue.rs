@@ -0,0 +1,263 @@
+//! This module contains the in-memory queue of replicated writes that could
+//! not yet be sent to all of the host groups of a database.
+
+use data_types::{data::ReplicatedWrite, database_rules::HostGroupId};
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long a host group isn't retried after its first failed retry
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest a host group isn't retried after failed retries
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A replicated write and the host groups it still has to be sent to
+#[derive(Debug, Clone)]
+pub struct QueuedWrite {
+    pub write: Arc<ReplicatedWrite>,
+    pub host_group_ids: Vec<HostGroupId>,
+}
+
+/// `ReplicationQueue` holds the writes of a database that haven't been
+/// replicated to all of its host groups, either because those groups weren't
+/// needed to reach the replication count or because replicating to them
+/// failed. The queued writes are retried in the background. A host group
+/// that keeps failing is retried less often, doubling the time between
+/// retries from `INITIAL_BACKOFF` up to `MAX_BACKOFF`.
+///
+/// The queue doesn't enforce a maximum size itself, callers pass in the
+/// `replication_queue_max_size` of the database rules.
+#[derive(Debug, Default)]
+pub struct ReplicationQueue {
+    state: Mutex<QueueState>,
+    backoffs: Mutex<BTreeMap<HostGroupId, Backoff>>,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    writes: VecDeque<QueuedWrite>,
+    /// The number of slots reserved for writes that are being stored
+    reserved: usize,
+}
+
+impl QueueState {
+    fn is_full(&self, max_size: usize) -> bool {
+        self.writes.len() + self.reserved >= max_size
+    }
+}
+
+#[derive(Debug)]
+struct Backoff {
+    failures: u32,
+    retry_at: Instant,
+}
+
+impl ReplicationQueue {
+    /// Returns the number of queued writes
+    pub fn len(&self) -> usize {
+        self.state.lock().expect("mutex poisoned").writes.len()
+    }
+
+    /// Returns true if there are no queued writes
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if the queued writes and the reserved slots reach
+    /// `max_size`
+    pub fn is_full(&self, max_size: usize) -> bool {
+        self.state.lock().expect("mutex poisoned").is_full(max_size)
+    }
+
+    /// Reserves a slot for a write, so it can be queued once it is stored
+    /// without the queue filling up in the meantime. Returns `None` if the
+    /// queue is full. The slot is given back when the reservation is dropped.
+    pub fn reserve(&self, max_size: usize) -> Option<Reservation<'_>> {
+        let mut state = self.state.lock().expect("mutex poisoned");
+        if state.is_full(max_size) {
+            return None;
+        }
+
+        state.reserved += 1;
+        Some(Reservation { queue: self })
+    }
+
+    /// Adds the write to the back of the queue if it has less than `max_size`
+    /// writes. If the queue is full, the write is returned.
+    pub fn push(&self, write: QueuedWrite, max_size: usize) -> Result<(), QueuedWrite> {
+        let mut state = self.state.lock().expect("mutex poisoned");
+        if state.is_full(max_size) {
+            return Err(write);
+        }
+
+        state.writes.push_back(write);
+        Ok(())
+    }
+
+    /// Removes all writes from the queue, in the order they were queued
+    pub fn drain(&self) -> Vec<QueuedWrite> {
+        let mut state = self.state.lock().expect("mutex poisoned");
+        state.writes.drain(..).collect()
+    }
+
+    /// Puts writes that were taken out by `drain` back at the front of the
+    /// queue, ahead of any writes that were queued in the meantime.
+    pub fn requeue(&self, retry: Vec<QueuedWrite>) {
+        let mut state = self.state.lock().expect("mutex poisoned");
+        for write in retry.into_iter().rev() {
+            state.writes.push_front(write);
+        }
+    }
+
+    /// Returns true if the host group isn't backing off at `now`
+    pub fn should_retry(&self, host_group_id: &str, now: Instant) -> bool {
+        let backoffs = self.backoffs.lock().expect("mutex poisoned");
+        backoffs
+            .get(host_group_id)
+            .map_or(true, |backoff| backoff.retry_at <= now)
+    }
+
+    /// Backs off retrying the host group after it failed at `now`, twice as
+    /// long as the previous time
+    pub fn record_failure(&self, host_group_id: &str, now: Instant) {
+        let mut backoffs = self.backoffs.lock().expect("mutex poisoned");
+        let backoff = backoffs
+            .entry(host_group_id.to_string())
+            .or_insert(Backoff {
+                failures: 0,
+                retry_at: now,
+            });
+
+        let delay = INITIAL_BACKOFF
+            .checked_mul(1 << backoff.failures.min(16))
+            .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF));
+        backoff.failures += 1;
+        backoff.retry_at = now + delay;
+    }
+
+    /// Retries the host group right away again after it succeeded
+    pub fn record_success(&self, host_group_id: &str) {
+        let mut backoffs = self.backoffs.lock().expect("mutex poisoned");
+        backoffs.remove(host_group_id);
+    }
+}
+
+/// A slot in the `ReplicationQueue` reserved by `ReplicationQueue::reserve`
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    queue: &'a ReplicationQueue,
+}
+
+impl Reservation<'_> {
+    /// Adds the write to the back of the queue in the reserved slot
+    pub fn push(self, write: QueuedWrite) {
+        let mut state = self.queue.state.lock().expect("mutex poisoned");
+        state.writes.push_back(write);
+        // the slot is given back when self is dropped
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock().expect("mutex poisoned");
+        state.reserved -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(group: &str) -> QueuedWrite {
+        QueuedWrite {
+            write: Arc::new(ReplicatedWrite::default()),
+            host_group_ids: vec![group.to_string()],
+        }
+    }
+
+    fn groups(writes: &[QueuedWrite]) -> Vec<&str> {
+        writes
+            .iter()
+            .map(|w| w.host_group_ids[0].as_str())
+            .collect()
+    }
+
+    #[test]
+    fn push_respects_max_size() {
+        let queue = ReplicationQueue::default();
+        assert!(queue.push(queued("a"), 2).is_ok());
+        assert!(queue.push(queued("b"), 2).is_ok());
+        assert!(queue.is_full(2));
+
+        let rejected = queue.push(queued("c"), 2).unwrap_err();
+        assert_eq!(rejected.host_group_ids, vec!["c"]);
+        assert_eq!(queue.len(), 2);
+
+        assert!(queue.push(queued("c"), 0).is_err());
+    }
+
+    #[test]
+    fn reservations_count_towards_max_size() {
+        let queue = ReplicationQueue::default();
+        let first = queue.reserve(2).unwrap();
+        let second = queue.reserve(2).unwrap();
+        assert!(queue.reserve(2).is_none());
+        assert!(queue.is_full(2));
+        assert!(queue.is_empty());
+
+        first.push(queued("a"));
+        assert_eq!(queue.len(), 1);
+        assert!(queue.reserve(2).is_none());
+
+        // dropping a reservation gives its slot back
+        drop(second);
+        assert!(!queue.is_full(2));
+        assert!(queue.reserve(2).is_some());
+    }
+
+    #[test]
+    fn requeue_goes_to_front() {
+        let queue = ReplicationQueue::default();
+        queue.push(queued("a"), 10).unwrap();
+        queue.push(queued("b"), 10).unwrap();
+
+        let retry = queue.drain();
+        assert!(queue.is_empty());
+        assert_eq!(groups(&retry), vec!["a", "b"]);
+
+        queue.push(queued("c"), 10).unwrap();
+        queue.requeue(retry);
+
+        assert_eq!(groups(&queue.drain()), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn failed_groups_back_off_exponentially() {
+        let queue = ReplicationQueue::default();
+        let now = Instant::now();
+        assert!(queue.should_retry("a", now));
+
+        queue.record_failure("a", now);
+        assert!(!queue.should_retry("a", now));
+        assert!(queue.should_retry("a", now + INITIAL_BACKOFF));
+        assert!(queue.should_retry("b", now));
+
+        queue.record_failure("a", now);
+        assert!(!queue.should_retry("a", now + INITIAL_BACKOFF));
+        assert!(queue.should_retry("a", now + INITIAL_BACKOFF * 2));
+
+        for _ in 0..20 {
+            queue.record_failure("a", now);
+        }
+        assert!(!queue.should_retry("a", now + MAX_BACKOFF - INITIAL_BACKOFF));
+        assert!(queue.should_retry("a", now + MAX_BACKOFF));
+
+        queue.record_success("a");
+        assert!(queue.should_retry("a", now));
+    }
+}
@@ -0,0 +1,131 @@
+//! This module decides which data of a database has expired under its
+//! retention period. Data is dropped in whole partitions of the mutable
+//! buffer and whole chunks of the read buffer and object storage, once the
+//! newest of their rows, according to the time statistics of their tables, is
+//! older than the retention period.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, Duration, Utc};
+use data_types::partition_metadata::Table;
+
+/// Data that was dropped because it expired
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expired {
+    /// A partition of the mutable buffer, with all of its chunks
+    MutableBufferPartition { partition_key: String },
+    /// A chunk of the read buffer
+    ReadBufferChunk {
+        partition_key: String,
+        chunk_id: u64,
+    },
+    /// A chunk in the catalog, whose Parquet files were deleted from object
+    /// storage
+    PersistedChunk {
+        partition_key: String,
+        chunk_id: u64,
+    },
+}
+
+/// The number of partitions and chunks of a database that were dropped
+/// because they expired
+#[derive(Debug, Default)]
+pub struct ExpiredCounts {
+    mutable_buffer_partitions: AtomicU64,
+    read_buffer_chunks: AtomicU64,
+    persisted_chunks: AtomicU64,
+}
+
+impl ExpiredCounts {
+    /// Counts the dropped partition or chunk
+    pub fn record(&self, expired: &Expired) {
+        let count = match expired {
+            Expired::MutableBufferPartition { .. } => &self.mutable_buffer_partitions,
+            Expired::ReadBufferChunk { .. } => &self.read_buffer_chunks,
+            Expired::PersistedChunk { .. } => &self.persisted_chunks,
+        };
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn mutable_buffer_partitions(&self) -> u64 {
+        self.mutable_buffer_partitions.load(Ordering::Relaxed)
+    }
+
+    pub fn read_buffer_chunks(&self) -> u64 {
+        self.read_buffer_chunks.load(Ordering::Relaxed)
+    }
+
+    pub fn persisted_chunks(&self) -> u64 {
+        self.persisted_chunks.load(Ordering::Relaxed)
+    }
+}
+
+/// Returns the time, in nanoseconds since the epoch, rows have to be newer
+/// than or as new as to be kept under a retention period of
+/// `retention_seconds`
+pub fn cutoff(retention_seconds: u32, now: DateTime<Utc>) -> i64 {
+    (now - Duration::seconds(retention_seconds.into())).timestamp_nanos()
+}
+
+/// Returns true if the tables have rows and all of them are older than
+/// `cutoff`
+pub fn is_expired(tables: &[Table], cutoff: i64) -> bool {
+    tables
+        .iter()
+        .filter_map(Table::max_time)
+        .max()
+        .map_or(false, |max_time| max_time < cutoff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_types::partition_metadata::Statistics;
+
+    fn table(name: &str, times: Option<(i64, i64)>) -> Table {
+        Table {
+            name: name.to_string(),
+            columns: vec![],
+            time: times.map(|(min, max)| Statistics { min, max, count: 2 }),
+        }
+    }
+
+    #[test]
+    fn expires_tables_older_than_cutoff() {
+        let tables = vec![table("cpu", Some((10, 20))), table("mem", Some((5, 30)))];
+        assert!(is_expired(&tables, 31));
+        assert!(!is_expired(&tables, 30));
+        assert!(!is_expired(&tables, 25));
+
+        // data without rows never expires
+        assert!(!is_expired(&[], 31));
+        assert!(!is_expired(&[table("cpu", None)], 31));
+    }
+
+    #[test]
+    fn cutoff_is_retention_period_before_now() {
+        let now = Utc::now();
+        assert_eq!(cutoff(0, now), now.timestamp_nanos());
+        assert_eq!(cutoff(60, now), now.timestamp_nanos() - 60 * 1_000_000_000);
+    }
+
+    #[test]
+    fn counts_expired_data() {
+        let counts = ExpiredCounts::default();
+        counts.record(&Expired::MutableBufferPartition {
+            partition_key: "a".to_string(),
+        });
+        counts.record(&Expired::ReadBufferChunk {
+            partition_key: "a".to_string(),
+            chunk_id: 0,
+        });
+        counts.record(&Expired::ReadBufferChunk {
+            partition_key: "a".to_string(),
+            chunk_id: 1,
+        });
+
+        assert_eq!(counts.mutable_buffer_partitions(), 1);
+        assert_eq!(counts.read_buffer_chunks(), 2);
+        assert_eq!(counts.persisted_chunks(), 0);
+    }
+}
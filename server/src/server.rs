@@ -1,38 +1,83 @@
 //! This module contains code for organizing the running server
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     sync::{
-        atomic::{AtomicU32, AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
-use crate::db::Db;
+use crate::{
+    applied_writes::{self, Duplicate},
+    buffer::{self, Buffer},
+    catalog::{self, Catalog, CatalogChunk, TableColumns, TimeRange, WriterSequences},
+    db::{delete_from_mutable_buffer, ChunkDeletes, DBChunk, Db, WriteSequences},
+    hash_ring::HashRing,
+    lifecycle::{self, LifecycleAction},
+    metrics::{Encoder, ServerMetrics},
+    replication_queue::QueuedWrite,
+    retention::{self, Expired},
+    snapshot::{self, LoadedSnapshot, Snapshot, SnapshotRegistry},
+};
 use data_types::{
-    data::{lines_to_replicated_write, ReplicatedWrite},
-    database_rules::{DatabaseRules, HostGroup, HostGroupId, MatchTables},
+    data::{
+        compiled_lines_to_replicated_write, delete_to_replicated_write, filtered_replicated_write,
+        partitioned_replicated_write, ReplicatedWrite,
+    },
+    database_rules::{DatabaseRules, HostGroup, HostGroupId, PartitionId},
+    delete::Delete,
     {DatabaseName, DatabaseNameError},
 };
+use generated_types::{
+    i_ox_query_client::IOxQueryClient, i_ox_replication_client::IOxReplicationClient, QueryRequest,
+    ReplicateRequest,
+};
 use influxdb_line_protocol::ParsedLine;
 use mutable_buffer::MutableBufferDb;
 use object_store::{path::ObjectStorePath, ObjectStore};
-use query::{exec::Executor, Database, DatabaseStore};
+use query::{
+    exec::Executor,
+    frontend::sql::{table_names, SQLQueryPlanner},
+    Database, DatabaseStore,
+};
 use read_buffer::Database as ReadBufferDb;
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use chrono::Utc;
 use futures::stream::TryStreamExt;
 use serde::{Deserialize, Serialize};
-use snafu::{OptionExt, ResultExt, Snafu};
-use tokio::sync::RwLock;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use tokio::sync::{oneshot, Mutex, RwLock};
+use tonic::transport::{Channel, Endpoint};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
 
 type DatabaseError = Box<dyn std::error::Error + Send + Sync + 'static>;
 
 /// A server ID of 0 is reserved and indicates no ID has been configured.
 const SERVER_ID_NOT_SET: u32 = 0;
 
-const STARTING_SEQUENCE: u64 = 1;
+const STARTING_SEQUENCE: u64 = applied_writes::FIRST_SEQUENCE;
+
+/// How often the background replication retries sending the queued writes of
+/// each database
+pub const REPLICATION_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often closed WAL buffer segments are persisted to object storage
+pub const WAL_PERSISTENCE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the lifecycle rules of each database are applied to the chunks
+/// of its mutable buffer
+pub const CHUNK_LIFECYCLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often data older than the retention period of each database is dropped
+pub const RETENTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often loading the stored databases is retried when it failed at startup
+pub const DATABASE_LOAD_RETRY_INTERVAL: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -44,6 +89,8 @@ pub enum Error {
     InvalidDatabaseName { source: DatabaseNameError },
     #[snafu(display("database error: {}", source))]
     UnknownDatabaseError { source: DatabaseError },
+    #[snafu(display("invalid database rules: {}", source))]
+    InvalidDatabaseRules { source: crate::db::Error },
     #[snafu(display("no local buffer for database: {}", db))]
     NoLocalBuffer { db: String },
     #[snafu(display("host group not found: {}", id))]
@@ -57,6 +104,73 @@ pub enum Error {
     },
     #[snafu(display("error replicating to remote: {}", source))]
     ErrorReplicating { source: DatabaseError },
+    #[snafu(display(
+        "error replicating to hosts {:?} of host group {}: {}",
+        hosts,
+        id,
+        source
+    ))]
+    PartiallyReplicated {
+        id: HostGroupId,
+        hosts: Vec<String>,
+        source: Box<Error>,
+    },
+    #[snafu(display(
+        "replicated to {} of {} required host groups: {}",
+        acknowledged,
+        required,
+        source
+    ))]
+    ReplicationCountNotMet {
+        required: usize,
+        acknowledged: usize,
+        source: Box<Error>,
+    },
+    #[snafu(display(
+        "write {} from writer {} is already being applied to database {}",
+        sequence,
+        writer,
+        db_name
+    ))]
+    WriteInProgress {
+        db_name: String,
+        writer: u32,
+        sequence: u64,
+    },
+    #[snafu(display("replication queue full for database: {}", db_name))]
+    ReplicationQueueFull { db_name: String },
+    #[snafu(display(
+        "database over memory limit: {} holds {} bytes, a write of {} bytes would exceed the limit of {} bytes",
+        db_name,
+        size,
+        write_size,
+        limit
+    ))]
+    DatabaseOverMemoryLimit {
+        db_name: String,
+        size: usize,
+        write_size: usize,
+        limit: usize,
+    },
+    #[snafu(display("error planning query: {}", source))]
+    PlanningQuery { source: query::frontend::sql::Error },
+    #[snafu(display("error executing query: {}", source))]
+    ExecutingQuery { source: DatabaseError },
+    #[snafu(display(
+        "error querying position {} of query group {}: {}",
+        position,
+        host_group_id,
+        source
+    ))]
+    QueryingRemote {
+        host_group_id: HostGroupId,
+        position: usize,
+        source: Box<Error>,
+    },
+    #[snafu(display("error converting query results: {}", source))]
+    ConvertingQueryResults {
+        source: arrow_deps::arrow::error::ArrowError,
+    },
     #[snafu(display("unable to use server until id is set"))]
     IdNotSet,
     #[snafu(display("error serializing configuration {}", source))]
@@ -65,6 +179,55 @@ pub enum Error {
     ErrorDeserializing { source: serde_json::Error },
     #[snafu(display("store error: {}", source))]
     StoreError { source: object_store::Error },
+    #[snafu(display("error loading read only partition {}: {}", partition_id, source))]
+    LoadingReadOnlyPartition {
+        partition_id: PartitionId,
+        source: DatabaseError,
+    },
+    #[snafu(display("invalid partition template: {}", source))]
+    InvalidPartitionTemplate {
+        source: data_types::database_rules::Error,
+    },
+    #[snafu(display(
+        "WAL segment size {} is larger than the WAL buffer size {}",
+        segment_size,
+        buffer_size
+    ))]
+    WalSegmentLargerThanBuffer { segment_size: u64, buffer_size: u64 },
+    #[snafu(display("error managing chunk lifecycle: {}", source))]
+    ManagingChunkLifecycle { source: DatabaseError },
+    #[snafu(display(
+        "snapshot of chunk {} of partition {} did not complete",
+        chunk_id,
+        partition_key
+    ))]
+    ChunkSnapshotFailed {
+        partition_key: String,
+        chunk_id: u64,
+    },
+    #[snafu(display("error replaying WAL of database {}: {}", db_name, source))]
+    ReplayingWal {
+        db_name: String,
+        source: DatabaseError,
+    },
+    #[snafu(display("error reading catalog of database {}: {}", db_name, source))]
+    ReadingCatalog {
+        db_name: String,
+        source: catalog::Error,
+    },
+    #[snafu(display("error updating catalog of database {}: {}", db_name, source))]
+    UpdatingCatalog {
+        db_name: String,
+        source: catalog::Error,
+    },
+    #[snafu(display("invalid delete: {}", source))]
+    InvalidDelete {
+        source: data_types::row_predicate::Error,
+    },
+    #[snafu(display("databases of the server are not loaded yet"))]
+    DatabasesNotLoaded,
+    #[snafu(display("object store is unreachable: {}", source))]
+    ObjectStoreUnreachable { source: object_store::Error },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -76,15 +239,81 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 pub struct Server<M: ConnectionManager> {
     id: AtomicU32,
     config: RwLock<Config>,
+    /// Held while a database is created, updated or dropped, so those don't
+    /// overlap while they read and write object storage, without holding the
+    /// lock on the config that writes and queries need meanwhile
+    database_changes: Mutex<()>,
     connection_manager: Arc<M>,
     pub store: Arc<ObjectStore>,
     executor: Arc<Executor>,
+    snapshots: SnapshotRegistry<DBChunk>,
+    snapshot_row_group_size: AtomicUsize,
+    metrics: Arc<ServerMetrics>,
+    databases_loaded: AtomicBool,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
 struct Config {
     databases: BTreeMap<DatabaseName<'static>, Arc<Db>>,
     host_groups: BTreeMap<HostGroupId, HostGroup>,
+    /// The consistent hash rings for the hosts in each host group. These are
+    /// derived from `host_groups` and rebuilt whenever it changes. Writes
+    /// take a snapshot of them, so they don't hold the config lock while
+    /// replicating.
+    #[serde(skip)]
+    host_group_rings: Arc<HostGroupRings>,
+}
+
+type HostGroupRings = BTreeMap<HostGroupId, Arc<HashRing>>;
+
+impl Config {
+    fn insert_host_group(&mut self, group: HostGroup) {
+        Arc::make_mut(&mut self.host_group_rings)
+            .insert(group.id.clone(), Arc::new(HashRing::new(&group.hosts)));
+        self.host_groups.insert(group.id.clone(), group);
+    }
+
+    // checks that the rules can be applied with the host groups of this
+    // configuration
+    fn validate_rules(&self, rules: &DatabaseRules) -> Result<()> {
+        let host_groups = rules
+            .replication
+            .iter()
+            .chain(rules.primary_query_group.iter())
+            .chain(rules.secondary_query_groups.iter())
+            .chain(rules.subscriptions.iter().map(|s| &s.host_group_id));
+        for id in host_groups {
+            ensure!(
+                self.host_groups.contains_key(id),
+                HostGroupNotFound { id: id.clone() }
+            );
+        }
+
+        rules.validate().context(InvalidPartitionTemplate)?;
+
+        if let Some(config) = &rules.wal_buffer_config {
+            let buffer_size = config.buffer_size.unwrap_or(buffer::DEFAULT_BUFFER_SIZE);
+            let segment_size = config.segment_size.unwrap_or(buffer::DEFAULT_SEGMENT_SIZE);
+            ensure!(
+                segment_size <= buffer_size,
+                WalSegmentLargerThanBuffer {
+                    segment_size,
+                    buffer_size
+                }
+            );
+        }
+
+        Ok(())
+    }
+
+    fn rebuild_host_group_rings(&mut self) {
+        self.host_group_rings = Arc::new(
+            self.host_groups
+                .iter()
+                .map(|(id, group)| (id.clone(), Arc::new(HashRing::new(&group.hosts))))
+                .collect(),
+        );
+    }
 }
 
 impl<M: ConnectionManager> Server<M> {
@@ -92,18 +321,175 @@ impl<M: ConnectionManager> Server<M> {
         Self {
             id: AtomicU32::new(SERVER_ID_NOT_SET),
             config: RwLock::new(Config::default()),
+            database_changes: Mutex::new(()),
             store,
             connection_manager: Arc::new(connection_manager),
             executor: Arc::new(Executor::new()),
+            snapshots: SnapshotRegistry::default(),
+            snapshot_row_group_size: AtomicUsize::new(snapshot::DEFAULT_ROW_GROUP_SIZE),
+            metrics: Arc::default(),
+            databases_loaded: AtomicBool::new(false),
         }
     }
 
     /// sets the id of the server, which is used for replication and the base
-    /// path in object storage.
+    /// path in object storage, and loads the databases stored for it with
+    /// `load_databases`. Databases that fail to load are logged. If the stored
+    /// databases can't be read at all, the server isn't ready until
+    /// `load_databases` succeeds.
     ///
     /// A valid server ID Must be non-zero.
     pub async fn set_id(&self, id: u32) {
-        self.id.store(id, Ordering::Release)
+        self.id.store(id, Ordering::Release);
+
+        match self.load_databases().await {
+            Ok(errors) => {
+                for (db_name, e) in errors {
+                    error!(%db_name, "unable to load database: {}", e);
+                }
+            }
+            Err(e) => error!("unable to load the stored databases: {}", e),
+        }
+    }
+
+    /// Returns true once the databases stored for the server's id are loaded
+    pub fn databases_loaded(&self) -> bool {
+        self.databases_loaded.load(Ordering::Acquire)
+    }
+
+    /// sets the maximum number of rows in each row group of the Parquet files
+    /// written by snapshots, which bounds the memory a snapshot needs.
+    pub fn set_snapshot_row_group_size(&self, rows: usize) {
+        self.snapshot_row_group_size.store(rows, Ordering::Release)
+    }
+
+    /// Returns the maximum number of rows in each row group of the Parquet
+    /// files written by snapshots.
+    pub fn snapshot_row_group_size(&self) -> usize {
+        self.snapshot_row_group_size.load(Ordering::Acquire)
+    }
+
+    /// Keeps track of the snapshot, so operators can follow its progress and
+    /// cancel it.
+    pub fn register_snapshot(&self, snapshot: Arc<Snapshot<DBChunk>>) {
+        self.snapshots.register(snapshot)
+    }
+
+    /// Returns the running snapshots and the most recently finished ones,
+    /// oldest first.
+    pub fn snapshots(&self) -> Vec<Arc<Snapshot<DBChunk>>> {
+        self.snapshots.list()
+    }
+
+    /// Returns the metrics of the writes and gRPC requests the server handled
+    pub fn metrics(&self) -> &Arc<ServerMetrics> {
+        &self.metrics
+    }
+
+    /// Returns the metrics of the server and the current sizes of the
+    /// buffers of its databases in the Prometheus text format
+    pub async fn encode_metrics(&self) -> String {
+        let databases: Vec<_> = {
+            let config = self.config.read().await;
+            config
+                .databases
+                .iter()
+                .map(|(name, db)| (name.to_string(), Arc::clone(db)))
+                .collect()
+        };
+
+        // the gauges of each database, with the labels of the database
+        let mut mutable_buffer_sizes = vec![];
+        let mut read_buffer_sizes = vec![];
+        let mut wal_stats = vec![];
+        for (db_name, db) in &databases {
+            let labels = [("db_name", db_name.as_str())];
+            if let Some(mutable_buffer) = &db.mutable_buffer {
+                mutable_buffer_sizes.push((labels, mutable_buffer.size() as u64));
+            }
+            read_buffer_sizes.push((labels, db.read_buffer.size()));
+            if let Some(stats) = db.wal_buffer_stats().await {
+                wal_stats.push((labels, stats));
+            }
+        }
+
+        let mut encoder = Encoder::default();
+        self.metrics.encode(&mut encoder);
+
+        encoder.gauge(
+            "iox_mutable_buffer_size_bytes",
+            "Estimated size of a database's mutable buffer",
+            mutable_buffer_sizes
+                .iter()
+                .map(|(labels, size)| (&labels[..], *size)),
+        );
+        encoder.gauge(
+            "iox_read_buffer_size_bytes",
+            "Size of a database's read buffer",
+            read_buffer_sizes
+                .iter()
+                .map(|(labels, size)| (&labels[..], *size)),
+        );
+        encoder.gauge(
+            "iox_wal_buffer_segments",
+            "Segments in a database's WAL buffer, including the open one",
+            wal_stats
+                .iter()
+                .map(|(labels, stats)| (&labels[..], stats.segments as u64)),
+        );
+        encoder.gauge(
+            "iox_wal_buffer_unpersisted_segments",
+            "Closed segments of a database's WAL buffer not yet persisted to object storage",
+            wal_stats
+                .iter()
+                .map(|(labels, stats)| (&labels[..], stats.unpersisted_segments as u64)),
+        );
+        encoder.gauge(
+            "iox_wal_buffer_unpersisted_bytes",
+            "Size of the closed segments of a database's WAL buffer not yet persisted",
+            wal_stats
+                .iter()
+                .map(|(labels, stats)| (&labels[..], stats.unpersisted_bytes)),
+        );
+
+        let snapshot_durations = self.snapshots.durations();
+        encoder.histogram(
+            "iox_snapshot_duration_seconds",
+            "Time taken by snapshots of chunks to object storage, including failed ones",
+            vec![(&[][..], &snapshot_durations)],
+        );
+
+        encoder.finish()
+    }
+
+    /// Returns the snapshot with the specified id, if it is running or
+    /// finished recently.
+    pub fn snapshot(&self, id: Uuid) -> Option<Arc<Snapshot<DBChunk>>> {
+        self.snapshots.get(id)
+    }
+
+    /// Returns an error describing why the server can't handle writes and
+    /// queries yet: its id isn't set, the databases stored for it aren't
+    /// loaded or the object store can't be reached. The object store is
+    /// checked by listing the configuration of the server, which is fine to
+    /// be missing, so probes don't download anything.
+    pub async fn check_ready(&self) -> Result<()> {
+        let id = self.require_id().await?;
+        if !self.databases_loaded() {
+            return Err(Error::DatabasesNotLoaded);
+        }
+
+        let location = config_location(id);
+        let listed = match self.store.list(Some(&location)).await {
+            // only the first page is needed to know the store responds
+            Ok(mut pages) => pages.try_next().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        match listed {
+            Ok(()) => Ok(()),
+            Err(e) if e.is_not_found() => Ok(()),
+            Err(e) => Err(Error::ObjectStoreUnreachable { source: e }),
+        }
     }
 
     /// Returns the current server ID, or an error if not yet set.
@@ -115,35 +501,336 @@ impl<M: ConnectionManager> Server<M> {
     }
 
     /// Tells the server the set of rules for a database. Currently, this is not
-    /// persisted and is for in-memory processing rules only.
+    /// persisted and is for in-memory processing rules only. The rules are
+    /// rejected if they reference host groups the server doesn't know, their
+    /// partition template doesn't compile or their WAL buffer sizes don't fit
+    /// together.
+    ///
+    /// The database starts out empty, except for its read only partitions.
+    /// Writes and sequences continue after the WAL segments this server
+    /// persisted for it before, which are only replayed by `load_databases`.
     pub async fn create_database(
         &self,
         db_name: impl Into<String>,
         rules: DatabaseRules,
+    ) -> Result<()> {
+        let _changes = self.database_changes.lock().await;
+        self.add_database(db_name.into(), rules, false).await
+    }
+
+    // creates the database. If `recover` is set and the database stores
+    // writes locally, its mutable buffer is rebuilt from the WAL segments
+    // this server persisted for it before. The chunks in its catalog are
+    // queried from object storage.
+    // Writes that are in a chunk of the catalog or in the snapshot of a read
+    // only partition aren't replayed. Replayed deletes apply to the replayed
+    // writes before them, the catalog records the deletes that apply to its
+    // chunks. The caller holds the lock on the database changes, so nothing
+    // else changes the database in the meantime. The config is only locked
+    // to add the database once it is loaded.
+    async fn add_database(
+        &self,
+        db_name: String,
+        rules: DatabaseRules,
+        recover: bool,
     ) -> Result<()> {
         // Return an error if this server hasn't yet been setup with an id
-        self.require_id().await?;
+        let id = self.require_id().await?;
 
-        let db_name = DatabaseName::new(db_name.into()).context(InvalidDatabaseName)?;
+        let db_name = DatabaseName::new(db_name).context(InvalidDatabaseName)?;
+        self.config.read().await.validate_rules(&rules)?;
 
-        let mutable_buffer = if rules.store_locally {
-            Some(Arc::new(MutableBufferDb::new(db_name.to_string())))
-        } else {
-            None
+        let mutable_buffer = new_mutable_buffer(&db_name, &rules);
+
+        let database_path = database_location(id, &db_name);
+        let catalog = catalog::read_catalog(&self.store, &database_path)
+            .await
+            .context(ReadingCatalog { db_name: &*db_name })?;
+        let recover = recover && mutable_buffer.is_some() && rules.store_locally;
+
+        let mut read_only_partitions = Vec::with_capacity(rules.read_only_partitions.len());
+        for partition_id in &rules.read_only_partitions {
+            let loaded = self
+                .read_partition_snapshot(partition_id)
+                .await
+                .context(LoadingReadOnlyPartition { partition_id })?;
+            read_only_partitions.push((partition_id.clone(), loaded));
+        }
+
+        // rebuild the mutable buffer from the WAL segments a previous run of
+        // this server persisted for the database, or just continue after them
+        let replay = {
+            let mut snapshot_sequences: BTreeMap<&str, BTreeMap<u32, u64>> = BTreeMap::new();
+            for (_, loaded) in &read_only_partitions {
+                let meta = &loaded.partition_meta;
+                let sequences = snapshot_sequences.entry(meta.key.as_str()).or_default();
+                for (&writer, &max) in &meta.max_sequences {
+                    let sequence = sequences.entry(writer).or_default();
+                    *sequence = (*sequence).max(max);
+                }
+            }
+
+            let mutable_buffer = mutable_buffer.as_deref().filter(|_| recover);
+            self.replay_persisted_wal(id, &db_name, mutable_buffer, &snapshot_sequences, &catalog)
+                .await
+                .context(ReplayingWal { db_name: &*db_name })?
         };
 
         let read_buffer = Arc::new(ReadBufferDb::new());
 
-        let sequence = AtomicU64::new(STARTING_SEQUENCE);
-        let wal_buffer = None;
-        let db = Db::new(rules, mutable_buffer, read_buffer, wal_buffer, sequence);
+        let sequence = AtomicU64::new(replay.next_sequence);
+        let wal_buffer = rules
+            .wal_buffer_config
+            .as_ref()
+            .map(|config| Buffer::new_from_config(config, replay.next_segment_id));
+        let db = Db::new(rules, mutable_buffer, read_buffer, wal_buffer, sequence)
+            .context(InvalidDatabaseRules)?
+            .with_object_store(Arc::clone(&self.store), database_path);
+        db.restore_write_sequences(replay.sequences).await;
+        for (writer, sequence) in replay.applied {
+            db.applied_writes.record(writer, sequence);
+        }
+        db.restore_deletes(replay.deletes)
+            .await
+            .map_err(|e| Box::new(e) as DatabaseError)
+            .context(ReplayingWal { db_name: &*db_name })?;
+        // the chunks of the catalog stay in object storage, queries read
+        // the ones they need from there
+        *db.catalog.lock().await = catalog;
+
+        for (partition_id, loaded) in read_only_partitions {
+            db.load_read_only_partition(&loaded)
+                .await
+                .map_err(|e| Box::new(e) as DatabaseError)
+                .context(LoadingReadOnlyPartition { partition_id })?;
+        }
+
+        let mut config = self.config.write().await;
+        // the host groups could have changed while the database was loaded
+        config.validate_rules(&db.rules)?;
+        config.databases.insert(db_name, Arc::new(db));
+
+        Ok(())
+    }
+
+    /// Replaces the rules of a database, or creates it if it doesn't exist
+    /// yet. The rules are validated like in `create_database`. An existing
+    /// database keeps its data, WAL buffer and sequence. Read only partitions
+    /// that were added to the rules are loaded, removed ones stay loaded until
+    /// the server restarts.
+    pub async fn update_database(
+        &self,
+        db_name: impl Into<String>,
+        rules: DatabaseRules,
+    ) -> Result<()> {
+        let id = self.require_id().await?;
+
+        let db_name = DatabaseName::new(db_name.into()).context(InvalidDatabaseName)?;
+
+        // held until the database is replaced, so concurrent updates can't
+        // replace each other's databases
+        let _changes = self.database_changes.lock().await;
+        let existing = self.config.read().await.databases.get(&db_name).cloned();
+        let existing = match existing {
+            Some(db) => db,
+            None => return self.add_database(db_name.to_string(), rules, false).await,
+        };
+        self.config.read().await.validate_rules(&rules)?;
+
+        let mutable_buffer = match &existing.mutable_buffer {
+            Some(_) => None,
+            None => new_mutable_buffer(&db_name, &rules),
+        };
+
+        // a new WAL buffer continues after the segments persisted before
+        let wal_buffer = match &rules.wal_buffer_config {
+            Some(wal_config) if !existing.has_wal_buffer() => {
+                let next_segment_id = self
+                    .read_persisted_wal(id, &db_name)
+                    .await
+                    .context(ReplayingWal { db_name: &*db_name })?
+                    .iter()
+                    .map(|segment| segment.id() + 1)
+                    .max()
+                    .unwrap_or(1);
+                Some(Buffer::new_from_config(wal_config, next_segment_id))
+            }
+            _ => None,
+        };
+
+        let db = existing
+            .with_rules(rules, mutable_buffer, wal_buffer)
+            .await
+            .context(InvalidDatabaseRules)?;
+
+        for partition_id in &db.rules.read_only_partitions {
+            if !existing.rules.read_only_partitions.contains(partition_id) {
+                self.load_read_only_partition(&db, partition_id)
+                    .await
+                    .context(LoadingReadOnlyPartition { partition_id })?;
+            }
+        }
 
         let mut config = self.config.write().await;
+        config.validate_rules(&db.rules)?;
         config.databases.insert(db_name, Arc::new(db));
 
         Ok(())
     }
 
+    /// Drops the database and deletes the WAL segments and the catalog
+    /// persisted for it, so that a database created with the same name later
+    /// starts out empty.
+    /// Snapshots of its partitions are kept, as other databases might load
+    /// them as read only partitions.
+    pub async fn drop_database(&self, db_name: &str) -> Result<()> {
+        let id = self.require_id().await?;
+
+        let db_name = DatabaseName::new(db_name).context(InvalidDatabaseName)?;
+
+        // the persisted data is deleted before the lock is released, so a
+        // database created with the same name in the meantime doesn't lose
+        // its data
+        let _changes = self.database_changes.lock().await;
+        self.config
+            .write()
+            .await
+            .databases
+            .remove(&db_name)
+            .context(DatabaseNotFound { db_name: &*db_name })?;
+
+        let prefix = buffer::object_store_path_for_segments(&database_location(id, &db_name));
+        self.delete_all(&prefix).await?;
+
+        catalog::delete_catalog(&self.store, &database_location(id, &db_name))
+            .await
+            .context(UpdatingCatalog { db_name: &*db_name })?;
+
+        Ok(())
+    }
+
+    // loads the snapshot of a partition from the object store into the db
+    async fn load_read_only_partition(
+        &self,
+        db: &Db,
+        partition_id: &str,
+    ) -> Result<(), DatabaseError> {
+        let loaded = self.read_partition_snapshot(partition_id).await?;
+        db.load_read_only_partition(&loaded).await?;
+
+        Ok(())
+    }
+
+    // reads the snapshot of a partition from the object store
+    async fn read_partition_snapshot(
+        &self,
+        partition_id: &str,
+    ) -> Result<LoadedSnapshot, DatabaseError> {
+        let (database_path, partition_key) = snapshot::parse_partition_id(partition_id)?;
+        let (metadata_path, data_path) = snapshot::snapshot_paths(&database_path, &partition_key);
+
+        Ok(
+            snapshot::load_snapshot(&metadata_path, &data_path, &self.store, &partition_key)
+                .await?,
+        )
+    }
+
+    // replays the WAL segments persisted for the database into its mutable
+    // buffer in the order the writes were applied. Writes to a partition in
+    // `snapshot_sequences` up to the largest sequence of their writer in its
+    // snapshot and writes that are in a chunk of the catalog are skipped, as
+    // their data is loaded from Parquet. Deletes apply to the chunks that were
+    // replayed before them. Without a mutable buffer, only where the database
+    // continues after the segments is returned.
+    async fn replay_persisted_wal(
+        &self,
+        id: u32,
+        db_name: &DatabaseName<'_>,
+        mutable_buffer: Option<&MutableBufferDb>,
+        snapshot_sequences: &BTreeMap<&str, BTreeMap<u32, u64>>,
+        catalog: &Catalog,
+    ) -> Result<WalReplay, DatabaseError> {
+        let mut replay = WalReplay::default();
+        // in the order they were applied, which is the order they were
+        // appended to the WAL buffer, so deletes only apply to the writes that
+        // were applied before them
+        let mut writes = vec![];
+        let mut seen = BTreeSet::new();
+        for segment in self.read_persisted_wal(id, db_name).await? {
+            replay.next_segment_id = replay.next_segment_id.max(segment.id() + 1);
+            for write in segment.writes() {
+                if seen.insert(write.writer_and_sequence()) {
+                    writes.push(Arc::clone(write));
+                }
+            }
+        }
+
+        for write in writes {
+            let (writer, sequence) = write.writer_and_sequence();
+            if writer == id {
+                replay.next_sequence = replay.next_sequence.max(sequence + 1);
+            }
+
+            let mutable_buffer = match mutable_buffer {
+                Some(mutable_buffer) => mutable_buffer,
+                None => continue,
+            };
+            replay.applied.push((writer, sequence));
+
+            let write = partitioned_replicated_write(&write, |key| {
+                let in_snapshot = snapshot_sequences
+                    .get(key)
+                    .and_then(|sequences| sequences.get(&writer))
+                    .map_or(false, |&max| sequence <= max);
+                !in_snapshot && !catalog.covers(key, writer, sequence)
+            });
+            if let Some(write) = write {
+                mutable_buffer.store_replicated_write(&write).await?;
+                for partition_key in write.partition_keys() {
+                    replay.sequences.record(partition_key, writer, sequence);
+                }
+
+                for delete in write.deletes()? {
+                    delete_from_mutable_buffer(
+                        mutable_buffer,
+                        &Arc::new(delete),
+                        &mut replay.sequences,
+                        &mut replay.deletes,
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        Ok(replay)
+    }
+
+    // reads the WAL segments persisted for the database, ordered by id
+    async fn read_persisted_wal(
+        &self,
+        id: u32,
+        db_name: &DatabaseName<'_>,
+    ) -> Result<Vec<buffer::Segment>, DatabaseError> {
+        let prefix = buffer::object_store_path_for_segments(&database_location(id, db_name));
+        let mut paths: Vec<_> = self.store.list(Some(&prefix)).await?.try_concat().await?;
+        paths.sort();
+
+        let mut segments = Vec::with_capacity(paths.len());
+        for path in paths {
+            let data = self
+                .store
+                .get(&path)
+                .await?
+                .map_ok(|b| bytes::BytesMut::from(&b[..]))
+                .try_concat()
+                .await?;
+            segments.push(buffer::Segment::from_file_bytes(&data)?);
+        }
+        segments.sort_by_key(|segment| segment.id());
+
+        Ok(segments)
+    }
+
     /// Creates a host group with a set of connection strings to hosts. These
     /// host connection strings should be something that the connection
     /// manager can use to return a remote server to work with.
@@ -152,9 +839,7 @@ impl<M: ConnectionManager> Server<M> {
         self.require_id().await?;
 
         let mut config = self.config.write().await;
-        config
-            .host_groups
-            .insert(id.clone(), HostGroup { id, hosts });
+        config.insert_host_group(HostGroup { id, hosts });
 
         Ok(())
     }
@@ -186,6 +871,93 @@ impl<M: ConnectionManager> Server<M> {
     /// Loads the configuration for this server from the configured store. This
     /// replaces any in-memory configuration that might already be set.
     pub async fn load_configuration(&mut self, id: u32) -> Result<()> {
+        let mut loaded_config = self.read_configuration(id).await?;
+        loaded_config.rebuild_host_group_rings();
+        for db in loaded_config.databases.values_mut() {
+            if let Some(db) = Arc::get_mut(db) {
+                db.compile_rules().context(InvalidDatabaseRules)?;
+            }
+        }
+
+        let mut config = self.config.write().await;
+        *config = loaded_config;
+
+        Ok(())
+    }
+
+    /// Recreates the host groups and databases of the configuration stored
+    /// for this server's id, as it is done when the server starts. Each
+    /// database is created from its rules again, so its read only partitions
+    /// get loaded, and its mutable buffer is rebuilt from the WAL segments
+    /// persisted for it, leaving out the writes in the chunks of its catalog. A
+    /// database that fails to load doesn't stop the others from loading;
+    /// the errors are returned along with the name of the database. Databases
+    /// that are loaded already are left as they are. If no
+    /// configuration was stored yet, nothing is loaded. Once this returns
+    /// successfully, the server is ready as far as its databases are
+    /// concerned.
+    pub async fn load_databases(&self) -> Result<Vec<(String, Error)>> {
+        let id = self.require_id().await?;
+
+        let stored = match self.read_configuration(id).await {
+            Ok(stored) => stored,
+            Err(Error::StoreError { source }) if source.is_not_found() => {
+                self.databases_loaded.store(true, Ordering::Release);
+                return Ok(vec![]);
+            }
+            Err(e) => return Err(e),
+        };
+
+        {
+            let mut config = self.config.write().await;
+            for (_, group) in stored.host_groups {
+                config.insert_host_group(group);
+            }
+        }
+
+        let mut errors = vec![];
+        for (db_name, db) in stored.databases {
+            let _changes = self.database_changes.lock().await;
+            // a database that was loaded before, or created since, already
+            // has its data, which loading it again would replace
+            if self.config.read().await.databases.contains_key(&db_name) {
+                continue;
+            }
+
+            if let Err(e) = self
+                .add_database(db_name.to_string(), db.rules.clone(), true)
+                .await
+            {
+                errors.push((db_name.to_string(), e));
+            }
+        }
+        self.databases_loaded.store(true, Ordering::Release);
+
+        Ok(errors)
+    }
+
+    /// Retries loading the stored databases until it succeeds, for when the
+    /// object store couldn't be reached at startup. The server isn't ready
+    /// until then.
+    pub async fn background_load_databases(&self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match self.load_databases().await {
+                Ok(errors) => {
+                    for (db_name, e) in errors {
+                        warn!(%db_name, "unable to load database: {}", e);
+                    }
+                    info!("loaded the stored databases");
+                    return;
+                }
+                Err(e) => warn!("unable to load the stored databases: {}", e),
+            }
+        }
+    }
+
+    // reads the configuration stored for the server id
+    async fn read_configuration(&self, id: u32) -> Result<Config> {
         let location = config_location(id);
 
         let read_data = self
@@ -198,12 +970,7 @@ impl<M: ConnectionManager> Server<M> {
             .await
             .context(StoreError)?;
 
-        let loaded_config: Config =
-            serde_json::from_slice(&read_data).context(ErrorDeserializing)?;
-        let mut config = self.config.write().await;
-        *config = loaded_config;
-
-        Ok(())
+        serde_json::from_slice(&read_data).context(ErrorDeserializing)
     }
 
     /// `write_lines` takes in raw line protocol and converts it to a
@@ -211,80 +978,840 @@ impl<M: ConnectionManager> Server<M> {
     /// on the configuration of the `db`. This is step #1 from the crate
     /// level documentation.
     pub async fn write_lines(&self, db_name: &str, lines: &[ParsedLine<'_>]) -> Result<()> {
+        let result = self.try_write_lines(db_name, lines).await;
+        match &result {
+            Ok(bytes) => self.metrics.record_write(db_name, lines.len(), *bytes),
+            Err(e) => self.metrics.record_write_error(write_error_kind(e)),
+        }
+
+        result.map(|_| ())
+    }
+
+    // writes the lines into the database, returning the size of the write as
+    // encoded for the WAL
+    async fn try_write_lines(&self, db_name: &str, lines: &[ParsedLine<'_>]) -> Result<usize> {
         let id = self.require_id().await?;
 
         let db_name = DatabaseName::new(db_name).context(InvalidDatabaseName)?;
-        // TODO: update server structure to not have to hold this lock to write to the
-        // DB.       i.e. wrap DB in an arc or rethink how db is structured as
-        // well
-        let config = self.config.read().await;
-        let db = config
-            .databases
-            .get(&db_name)
+        let db = self
+            .db(&db_name)
+            .await
+            .context(DatabaseNotFound { db_name: &*db_name })?;
+
+        let sequence = db.next_sequence();
+        let write =
+            compiled_lines_to_replicated_write(id, sequence, lines, db.partition_template());
+        let bytes = write.data.len();
+
+        self.handle_replicated_write(&db_name, &db, write).await?;
+
+        Ok(bytes)
+    }
+
+    /// Deletes the rows matched by the delete from the database. The delete is
+    /// replicated and subscribed to like a write, and applies to the rows
+    /// written before it.
+    pub async fn delete(&self, db_name: &str, delete: &Delete) -> Result<()> {
+        let id = self.require_id().await?;
+
+        let db_name = DatabaseName::new(db_name).context(InvalidDatabaseName)?;
+        let db = self
+            .db(&db_name)
+            .await
             .context(DatabaseNotFound { db_name: &*db_name })?;
 
         let sequence = db.next_sequence();
-        let write = lines_to_replicated_write(id, sequence, lines, &db.rules);
+        let write = delete_to_replicated_write(id, sequence, delete);
 
-        self.handle_replicated_write(&db_name, db, write).await?;
+        self.handle_replicated_write(&db_name, &db, write).await?;
 
         Ok(())
     }
 
+    /// Stores the write in the database and sends it to the host groups it
+    /// is replicated and subscribed to. This works on the `db` it is passed
+    /// and a snapshot of the host groups, so no lock on the server
+    /// configuration is held while the write is handled. A config change that
+    /// happens in the meantime applies to the writes after it. Subscribers
+    /// that fail to receive the write are logged, but don't fail it.
     pub async fn handle_replicated_write(
         &self,
         db_name: &DatabaseName<'_>,
         db: &Db,
         write: ReplicatedWrite,
     ) -> Result<()> {
-        if let Some(buf) = &db.mutable_buffer {
-            buf.store_replicated_write(&write)
+        // a write that is retried or delivered more than once is only
+        // applied the first time, but still replicated, in case replicating
+        // it failed before. A duplicate that arrives while the write is being
+        // applied is rejected, as applying the write might still fail.
+        let (writer, sequence) = write.writer_and_sequence();
+        let pending = match db.applied_writes.begin(writer, sequence) {
+            Ok(pending) => {
+                if pending.is_stale() {
+                    warn!(
+                        %db_name,
+                        writer,
+                        sequence,
+                        "applying write that is too old to know if it was applied before"
+                    );
+                }
+                Some(pending)
+            }
+            Err(Duplicate::Applied) => {
+                debug!(%db_name, writer, sequence, "not applying duplicate write");
+                None
+            }
+            Err(Duplicate::Pending) => {
+                return WriteInProgress {
+                    db_name: &**db_name,
+                    writer,
+                    sequence,
+                }
+                .fail()
+            }
+        };
+
+        // reject the write before it is sent or stored anywhere if it couldn't
+        // be queued for host groups that miss it. The slot stays reserved
+        // until the write is queued, so concurrent writes can't take it.
+        let queue_max_size = db.rules.replication_queue_max_size;
+        let mut reservation = None;
+        if !db.rules.replication.is_empty() && queue_max_size > 0 {
+            let queue = &db.replication_queue;
+            let db_name = &**db_name;
+            reservation = Some(
+                queue
+                    .reserve(queue_max_size)
+                    .context(ReplicationQueueFull { db_name })?,
+            );
+        }
+
+        // reserve the memory of the write before it is sent or stored
+        // anywhere, so writes that are handled concurrently can't take the
+        // database over its memory limit together and a single database can't
+        // exhaust the memory of the server. Deletes are still accepted, as
+        // they don't add rows to the buffers.
+        let limit = db
+            .rules
+            .lifecycle_rules
+            .as_ref()
+            .and_then(|rules| rules.buffer_size_hard);
+        let memory = match (limit, db.writable_buffer()) {
+            (Some(limit), Some(_)) if pending.is_some() && !write.partition_keys().is_empty() => {
+                let write_size = write.data.len();
+                match db.reserve_memory(write_size, limit) {
+                    Ok(reservation) => Some(reservation),
+                    Err(size) => {
+                        return DatabaseOverMemoryLimit {
+                            db_name: &**db_name,
+                            size,
+                            write_size,
+                            limit,
+                        }
+                        .fail()
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        // the write is only stored once enough host groups have it, so a
+        // write that is retried because the replication count wasn't met
+        // isn't stored twice
+        let rings = self.host_group_rings().await;
+        let missed = self.replicate(&rings, db_name, db, &write).await?;
+
+        if let Some(pending) = pending {
+            // appended to the WAL before it is stored, so a write that fails
+            // to be stored ends up in the WAL again when it is retried, which
+            // the replay deduplicates, but never gets stored twice
+            db.append_to_wal_buffer(&write)
                 .await
                 .map_err(|e| Box::new(e) as DatabaseError)
                 .context(UnknownDatabaseError {})?;
+
+            if db.writable_buffer().is_some() {
+                db.store_replicated_write(&write)
+                    .await
+                    .map_err(|e| Box::new(e) as DatabaseError)
+                    .context(UnknownDatabaseError {})?;
+
+                for delete in write.deletes().context(InvalidDelete)? {
+                    self.add_delete_to_catalog(db_name, db, &delete).await?;
+                }
+            }
+
+            pending.applied();
         }
 
-        for host_group_id in &db.rules.replication {
-            self.replicate_to_host_group(host_group_id, db_name, &write)
-                .await?;
+        // the buffers account for the stored write now
+        drop(memory);
+
+        if !missed.is_empty() {
+            let queued = QueuedWrite {
+                write: Arc::new(write.clone()),
+                host_group_ids: missed,
+            };
+            match reservation {
+                Some(reservation) => reservation.push(queued),
+                None => warn!(
+                    %db_name,
+                    host_groups=?queued.host_group_ids,
+                    "dropping replicated write for host groups"
+                ),
+            }
         }
 
-        for subscription in &db.rules.subscriptions {
-            match subscription.matcher.tables {
-                MatchTables::All => {
-                    self.replicate_to_host_group(&subscription.host_group_id, db_name, &write)
-                        .await?
-                }
-                MatchTables::Table(_) => unimplemented!(),
-                MatchTables::Regex(_) => unimplemented!(),
+        for (subscription, matcher) in db
+            .rules
+            .subscriptions
+            .iter()
+            .zip(db.subscription_matchers())
+        {
+            // subscribers that only want part of the write get a new write
+            // with the same writer and sequence number
+            let result = if subscription.matcher.matches_all() {
+                self.replicate_to_host_group(&rings, &subscription.host_group_id, db_name, &write)
+                    .await
+            } else if let Some(sub_write) = filtered_replicated_write(&write, matcher) {
+                self.replicate_to_host_group(
+                    &rings,
+                    &subscription.host_group_id,
+                    db_name,
+                    &sub_write,
+                )
+                .await
+            } else {
+                Ok(())
+            };
+
+            // the write is stored already, so failing it would only make the
+            // client retry a write that succeeded
+            if let Err(e) = result {
+                warn!(
+                    %db_name,
+                    subscription = %subscription.name,
+                    host_group_id = %subscription.host_group_id,
+                    "error sending write to subscriber: {}",
+                    e
+                );
             }
         }
 
         Ok(())
     }
 
-    // replicates to a single host in the group based on hashing rules. If that host
-    // is unavailable an error will be returned. The request may still succeed
-    // if enough of the other host groups have returned a success.
-    async fn replicate_to_host_group(
+    // replicates the write to the host groups in the rules of the db. Success
+    // is returned once `replication_count` groups have acknowledged the write,
+    // or all of them if the count is zero. The groups that weren't needed for
+    // that and the groups that failed are returned, to get the write from the
+    // replication queue in the background. If the queue size is zero every
+    // group is tried right away.
+    async fn replicate(
+        &self,
+        rings: &HostGroupRings,
+        db_name: &DatabaseName<'_>,
+        db: &Db,
+        write: &ReplicatedWrite,
+    ) -> Result<Vec<HostGroupId>> {
+        let rules = &db.rules;
+        let queue_max_size = rules.replication_queue_max_size;
+        let required = match usize::from(rules.replication_count) {
+            0 => rules.replication.len(),
+            count => count.min(rules.replication.len()),
+        };
+
+        let mut acknowledged = 0;
+        let mut missed = vec![];
+        let mut last_error = None;
+        for host_group_id in &rules.replication {
+            if acknowledged >= required && queue_max_size > 0 {
+                missed.push(host_group_id.clone());
+                continue;
+            }
+
+            match self
+                .replicate_to_host_group(rings, host_group_id, db_name, write)
+                .await
+            {
+                Ok(()) => acknowledged += 1,
+                Err(e) => {
+                    warn!(%db_name, %host_group_id, "error replicating write: {}", e);
+                    missed.push(host_group_id.clone());
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if acknowledged < required {
+            // there can only be too few acknowledgements if a group failed
+            let source = last_error.expect("replication failed without an error");
+            return Err(Error::ReplicationCountNotMet {
+                required,
+                acknowledged,
+                source: Box::new(source),
+            });
+        }
+
+        Ok(missed)
+    }
+
+    /// Retries sending the queued writes of every database to the host groups
+    /// that haven't received them yet. Writes that fail again stay queued,
+    /// and their host groups aren't retried until they are done backing off.
+    pub async fn retry_replication(&self) {
+        self.retry_replication_at(Instant::now()).await
+    }
+
+    async fn retry_replication_at(&self, now: Instant) {
+        let databases: Vec<_> = {
+            let config = self.config.read().await;
+            config
+                .databases
+                .iter()
+                .map(|(name, db)| (name.clone(), Arc::clone(db)))
+                .collect()
+        };
+
+        let rings = self.host_group_rings().await;
+        for (db_name, db) in databases {
+            let queue = &db.replication_queue;
+            let mut retry = vec![];
+
+            for mut queued in queue.drain() {
+                let mut missed = vec![];
+                for host_group_id in queued.host_group_ids.drain(..) {
+                    if !queue.should_retry(&host_group_id, now) {
+                        missed.push(host_group_id);
+                        continue;
+                    }
+
+                    match self
+                        .replicate_to_host_group(&rings, &host_group_id, &db_name, &queued.write)
+                        .await
+                    {
+                        Ok(()) => queue.record_success(&host_group_id),
+                        Err(e) => {
+                            warn!(%db_name, %host_group_id, "error retrying replicated write: {}", e);
+                            queue.record_failure(&host_group_id, now);
+                            missed.push(host_group_id);
+                        }
+                    }
+                }
+
+                if !missed.is_empty() {
+                    queued.host_group_ids = missed;
+                    retry.push(queued);
+                }
+            }
+
+            queue.requeue(retry);
+        }
+    }
+
+    /// Calls `retry_replication` every `interval`. This never returns, so it
+    /// should be spawned as a background task.
+    pub async fn background_replication(&self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.retry_replication().await;
+        }
+    }
+
+    /// Persists the closed segments of every database's WAL buffer that
+    /// haven't been persisted yet to the object store, and marks them
+    /// persisted so the buffer can drop them when it fills up. Segments that
+    /// fail to upload are tried again on the next call.
+    pub async fn persist_wal_segments(&self) {
+        let id = match self.require_id().await {
+            Ok(id) => id,
+            Err(_) => return,
+        };
+
+        let databases: Vec<_> = {
+            let config = self.config.read().await;
+            config
+                .databases
+                .iter()
+                .map(|(name, db)| (name.clone(), Arc::clone(db)))
+                .collect()
+        };
+
+        for (db_name, db) in databases {
+            for segment in db.unpersisted_wal_segments().await {
+                let location = buffer::object_store_path_for_segment(
+                    &database_location(id, &db_name),
+                    segment.id(),
+                );
+                let data = Bytes::from(segment.to_file_bytes(id));
+                let len = data.len();
+                let stream_data = std::io::Result::Ok(data);
+
+                match self
+                    .store
+                    .put(
+                        &location,
+                        futures::stream::once(async move { stream_data }),
+                        len,
+                    )
+                    .await
+                {
+                    Ok(()) => segment.set_persisted_at(Utc::now()).await,
+                    Err(e) => {
+                        warn!(
+                            %db_name,
+                            segment_id = segment.id(),
+                            "error persisting WAL segment: {}",
+                            e
+                        );
+                        // keep the segments of this database in order
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Calls `persist_wal_segments` every `interval`. This never returns, so
+    /// it should be spawned as a background task.
+    pub async fn background_wal_persistence(&self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.persist_wal_segments().await;
+        }
+    }
+
+    /// Applies the lifecycle rules of every database that has them to the
+    /// chunks of its mutable buffer: open chunks that are too large or too
+    /// old are closed, closed chunks are persisted as Parquet under
+    /// `<id>/<db>/chunks/<uuid>`, and persisted chunks are dropped from
+    /// memory, oldest first, while the mutable buffer is over its size
+    /// limit. Actions that fail are tried again on the next call.
+    pub async fn manage_chunk_lifecycle(&self) {
+        let id = match self.require_id().await {
+            Ok(id) => id,
+            Err(_) => return,
+        };
+
+        let databases: Vec<_> = {
+            let config = self.config.read().await;
+            config
+                .databases
+                .iter()
+                .map(|(name, db)| (name.clone(), Arc::clone(db)))
+                .collect()
+        };
+
+        for (db_name, db) in databases {
+            let rules = match &db.rules.lifecycle_rules {
+                Some(rules) if db.mutable_buffer.is_some() => rules,
+                _ => continue,
+            };
+
+            let chunks = db.mutable_buffer_chunk_summaries().await;
+            let read_buffer_chunks = db.read_buffer_chunk_summaries().await;
+            let persisted = db.persisted_chunks().await;
+
+            let actions = lifecycle::plan_actions(
+                rules,
+                &chunks,
+                &read_buffer_chunks,
+                &persisted,
+                Utc::now(),
+            );
+            for action in actions {
+                if let Err(e) = self
+                    .apply_lifecycle_action(id, &db_name, &db, &action)
+                    .await
+                {
+                    warn!(%db_name, ?action, "error managing chunk lifecycle: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Calls `manage_chunk_lifecycle` every `interval`. This never returns,
+    /// so it should be spawned as a background task.
+    pub async fn background_chunk_lifecycle(&self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.manage_chunk_lifecycle().await;
+        }
+    }
+
+    /// Drops the data of every database with a retention period that is
+    /// older than it: the partitions of the mutable buffer and the chunks of
+    /// the read buffer and of the catalog whose newest row is older than the
+    /// retention period. The Parquet files of the dropped catalog chunks are
+    /// deleted from object storage. Every drop is logged and counted in the
+    /// database's `expired_counts`.
+    pub async fn enforce_retention(&self) {
+        let id = match self.require_id().await {
+            Ok(id) => id,
+            Err(_) => return,
+        };
+
+        let databases: Vec<_> = {
+            let config = self.config.read().await;
+            config
+                .databases
+                .iter()
+                .map(|(name, db)| (name.clone(), Arc::clone(db)))
+                .collect()
+        };
+
+        let now = Utc::now();
+        for (db_name, db) in databases {
+            let cutoff = match db.rules.retention_seconds {
+                Some(seconds) => retention::cutoff(seconds, now),
+                None => continue,
+            };
+
+            let mut expired = match db.drop_expired(cutoff).await {
+                Ok(expired) => expired,
+                Err(e) => {
+                    warn!(%db_name, "error dropping expired data from memory: {}", e);
+                    vec![]
+                }
+            };
+            match self
+                .drop_expired_persisted_chunks(id, &db_name, &db, cutoff)
+                .await
+            {
+                Ok(chunks) => expired.extend(chunks),
+                Err(e) => warn!(%db_name, "error dropping expired persisted chunks: {}", e),
+            }
+
+            for expired in expired {
+                db.expired_counts.record(&expired);
+                info!(%db_name, ?expired, "dropped expired data");
+            }
+        }
+    }
+
+    /// Calls `enforce_retention` every `interval`. This never returns, so it
+    /// should be spawned as a background task.
+    pub async fn background_retention(&self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.enforce_retention().await;
+        }
+    }
+
+    // writes a new version of the catalog of the database without the chunks
+    // whose rows are all older than `cutoff`, then deletes their files, so a
+    // reader of the catalog never finds a chunk whose files are gone. Files
+    // that can't be deleted are left behind.
+    async fn drop_expired_persisted_chunks(
+        &self,
+        id: u32,
+        db_name: &DatabaseName<'_>,
+        db: &Db,
+        cutoff: i64,
+    ) -> Result<Vec<Expired>> {
+        let database_path = database_location(id, db_name);
+
+        let expired_chunks = {
+            let mut catalog = db.catalog.lock().await;
+            let (updated, expired_chunks) = catalog.without_expired(cutoff);
+            if expired_chunks.is_empty() {
+                return Ok(vec![]);
+            }
+
+            catalog::write_catalog(&self.store, &database_path, &updated)
+                .await
+                .context(UpdatingCatalog {
+                    db_name: &**db_name,
+                })?;
+            *catalog = updated;
+            expired_chunks
+        };
+
+        let mut expired = Vec::with_capacity(expired_chunks.len());
+        for chunk in expired_chunks {
+            let location = chunk.location(&database_path);
+            if let Err(e) = self.delete_all(&location).await {
+                warn!(
+                    %db_name,
+                    location = %self.store.convert_path(&location),
+                    "error deleting files of expired chunk: {}",
+                    e
+                );
+            }
+
+            expired.push(Expired::PersistedChunk {
+                partition_key: chunk.partition_key,
+                chunk_id: chunk.chunk_id,
+            });
+        }
+
+        Ok(expired)
+    }
+
+    // deletes every object in the store under the prefix
+    async fn delete_all(&self, prefix: &ObjectStorePath) -> Result<()> {
+        let paths: Vec<_> = self
+            .store
+            .list(Some(prefix))
+            .await
+            .context(StoreError)?
+            .try_concat()
+            .await
+            .context(StoreError)?;
+        for path in paths {
+            self.store.delete(&path).await.context(StoreError)?;
+        }
+
+        Ok(())
+    }
+
+    async fn apply_lifecycle_action(
+        &self,
+        id: u32,
+        db_name: &DatabaseName<'_>,
+        db: &Db,
+        action: &LifecycleAction,
+    ) -> Result<()> {
+        match action {
+            LifecycleAction::Rollover { partition_key } => {
+                db.rollover_partition(partition_key)
+                    .await
+                    .map_err(|e| Box::new(e) as DatabaseError)
+                    .context(ManagingChunkLifecycle)?;
+            }
+            LifecycleAction::Persist {
+                partition_key,
+                chunk_id,
+            } => {
+                let chunk = match db.closed_chunk(partition_key, *chunk_id).await {
+                    Some(chunk) => chunk,
+                    None => return Ok(()),
+                };
+
+                let chunk_uuid = Uuid::new_v4().to_string();
+                let mut database_path = database_location(id, db_name);
+                database_path.push_all(&["chunks", &chunk_uuid]);
+                let (metadata_path, data_path) =
+                    snapshot::snapshot_paths(&database_path, partition_key);
+
+                let sequences = db.closed_chunk_sequences(partition_key, *chunk_id).await;
+
+                let (tx, rx) = oneshot::channel();
+                let snapshot = snapshot::snapshot_chunk(
+                    metadata_path,
+                    data_path,
+                    Arc::clone(&self.store),
+                    partition_key,
+                    Arc::clone(&chunk),
+                    WriterSequences::max_by_writer(&sequences),
+                    self.snapshot_row_group_size(),
+                    Some(tx),
+                )
+                .map_err(|e| Box::new(e) as DatabaseError)
+                .context(ManagingChunkLifecycle)?;
+                self.register_snapshot(Arc::clone(&snapshot));
+
+                // the snapshot drops the sender without notifying if it fails
+                rx.await.ok().context(ChunkSnapshotFailed {
+                    partition_key,
+                    chunk_id: *chunk_id,
+                })?;
+
+                let tables = snapshot.partition_meta.tables.clone();
+                let time_range = TimeRange::of_chunk(&*chunk, &tables)
+                    .map_err(|e| Box::new(e) as DatabaseError)
+                    .context(ManagingChunkLifecycle)?;
+                let columns = TableColumns::of_chunk(&*chunk, &tables)
+                    .map_err(|e| Box::new(e) as DatabaseError)
+                    .context(ManagingChunkLifecycle)?;
+                let mut catalog_chunk = CatalogChunk::new(
+                    partition_key.as_str(),
+                    *chunk_id,
+                    format!("chunks/{}", chunk_uuid),
+                    time_range,
+                    tables,
+                    sequences,
+                );
+                catalog_chunk.columns = Some(columns);
+                self.add_to_catalog(id, db_name, db, catalog_chunk).await?;
+
+                info!(
+                    %db_name,
+                    %partition_key,
+                    chunk_id,
+                    location = %self.store.convert_path(&database_path),
+                    "persisted chunk"
+                );
+            }
+            LifecycleAction::Evict {
+                partition_key,
+                chunk_id,
+            } => {
+                db.evict_chunk(partition_key, *chunk_id)
+                    .await
+                    .map_err(|e| Box::new(e) as DatabaseError)
+                    .context(ManagingChunkLifecycle)?;
+            }
+            LifecycleAction::MoveToReadBuffer {
+                partition_key,
+                chunk_id,
+            } => {
+                db.load_chunk_to_read_buffer(partition_key, *chunk_id)
+                    .await
+                    .map_err(|e| Box::new(e) as DatabaseError)
+                    .context(ManagingChunkLifecycle)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // writes a new version of the catalog of the database that includes the
+    // chunk and marks the chunk as persisted. The catalog lock is held until
+    // then, so updates of a database's catalog don't overlap and queries
+    // don't read the chunk from both memory and object storage.
+    async fn add_to_catalog(
+        &self,
+        id: u32,
+        db_name: &DatabaseName<'_>,
+        db: &Db,
+        mut chunk: CatalogChunk,
+    ) -> Result<()> {
+        let mut catalog = db.catalog.lock().await;
+        // read while holding the lock, so a delete applied to the chunk after
+        // this is added to the catalog by `add_delete_to_catalog`
+        chunk.deletes = db
+            .chunk_deletes(&chunk.partition_key, chunk.chunk_id)
+            .await
+            .iter()
+            .map(|delete| delete.as_ref().clone())
+            .collect();
+        let (partition_key, chunk_id, path) = (
+            chunk.partition_key.clone(),
+            chunk.chunk_id,
+            chunk.path.clone(),
+        );
+        let updated = catalog.with_chunk(chunk);
+        catalog::write_catalog(&self.store, &database_location(id, db_name), &updated)
+            .await
+            .context(UpdatingCatalog {
+                db_name: &**db_name,
+            })?;
+        *catalog = updated;
+        db.mark_chunk_persisted(&partition_key, chunk_id, &path)
+            .await;
+
+        Ok(())
+    }
+
+    // writes a new version of the catalog of the database where the delete
+    // applies to the chunks that were persisted before it. Nothing is written
+    // if the database hasn't persisted any chunks.
+    async fn add_delete_to_catalog(
         &self,
+        db_name: &DatabaseName<'_>,
+        db: &Db,
+        delete: &Delete,
+    ) -> Result<()> {
+        let id = self.require_id().await?;
+
+        let mut catalog = db.catalog.lock().await;
+        if catalog.chunks.is_empty() {
+            return Ok(());
+        }
+
+        let updated = catalog.with_delete(delete);
+        catalog::write_catalog(&self.store, &database_location(id, db_name), &updated)
+            .await
+            .context(UpdatingCatalog {
+                db_name: &**db_name,
+            })?;
+        *catalog = updated;
+
+        Ok(())
+    }
+
+    // replicates to the hosts in the group that own the partition keys in the
+    // write, based on consistent hashing of the partition key. Deletes apply
+    // to all partitions, so every host in the group gets them. If the write
+    // has entries for partitions owned by different hosts, each host gets a
+    // write with only its entries, but the same writer and sequence number. If
+    // one of those hosts is unavailable an error will be returned. The request
+    // may still succeed if enough of the other host groups have returned a
+    // success.
+    async fn replicate_to_host_group(
+        &self,
+        rings: &HostGroupRings,
         host_group_id: &str,
         db_name: &DatabaseName<'_>,
         write: &ReplicatedWrite,
     ) -> Result<()> {
-        let config = self.config.read().await;
-        let group = config
-            .host_groups
+        let ring = rings
             .get(host_group_id)
             .context(HostGroupNotFound { id: host_group_id })?;
 
-        // TODO: handle hashing rules to determine which host in the group should get
-        // the write.       for now, just write to the first one.
-        let host = group
-            .hosts
-            .get(0)
+        let partition_keys = write.partition_keys();
+        let mut hosts = partition_keys
+            .iter()
+            .map(|key| ring.host_for(key))
+            .collect::<Option<BTreeSet<_>>>()
             .context(NoHostInGroup { id: host_group_id })?;
+        if write.has_deletes() {
+            hosts.extend(ring.hosts().iter().map(String::as_str));
+        }
 
+        // the common case is that all partitions in the write go to the same
+        // host, so the write can be sent as is
+        if hosts.len() <= 1 {
+            let host = match hosts.into_iter().next() {
+                Some(host) => host,
+                None => ring
+                    .host_for("")
+                    .context(NoHostInGroup { id: host_group_id })?,
+            };
+
+            return self.replicate_to_host(host, db_name, write).await;
+        }
+
+        // every host gets its partitions even if sending to another one
+        // failed. The write is only acknowledged for the group if all of them
+        // got it; when it is retried, the hosts that already have it ignore
+        // it as a duplicate of its writer and sequence.
+        let mut failed = vec![];
+        let mut last_error = None;
+        for host in hosts {
+            let host_write =
+                partitioned_replicated_write(write, |key| ring.host_for(key) == Some(host));
+
+            if let Some(host_write) = host_write {
+                if let Err(e) = self.replicate_to_host(host, db_name, &host_write).await {
+                    warn!(%db_name, %host_group_id, %host, "error replicating write: {}", e);
+                    failed.push(host.to_string());
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        match last_error {
+            None => Ok(()),
+            Some(e) => Err(Error::PartiallyReplicated {
+                id: host_group_id.to_string(),
+                hosts: failed,
+                source: Box::new(e),
+            }),
+        }
+    }
+
+    async fn replicate_to_host(
+        &self,
+        host: &str,
+        db_name: &DatabaseName<'_>,
+        write: &ReplicatedWrite,
+    ) -> Result<()> {
         let connection = self
             .connection_manager
             .remote_server(host)
@@ -301,6 +1828,11 @@ impl<M: ConnectionManager> Server<M> {
         Ok(())
     }
 
+    // returns a snapshot of the hash rings of the host groups
+    async fn host_group_rings(&self) -> Arc<HostGroupRings> {
+        Arc::clone(&self.config.read().await.host_group_rings)
+    }
+
     pub async fn db(&self, name: &DatabaseName<'_>) -> Option<Arc<Db>> {
         let config = self.config.read().await;
         config.databases.get(&name).cloned()
@@ -310,6 +1842,155 @@ impl<M: ConnectionManager> Server<M> {
         let config = self.config.read().await;
         config.databases.get(&name).map(|d| d.rules.clone())
     }
+
+    /// Runs a SQL query against the database. If the database has a
+    /// `primary_query_group`, this server coordinates the query: the rows of
+    /// each table in the query are read from every host in the primary
+    /// group, which each answer from the data they store locally. If a host
+    /// fails, the host in the same position of the `secondary_query_groups`
+    /// is read instead, in order. If `query_local` is set, the local rows are
+    /// added to those.
+    ///
+    /// Only the scans of the tables are sent to the hosts; the query itself
+    /// runs on this server over the rows of all of them, so aggregates,
+    /// grouping, ordering and limits see every row.
+    pub async fn query_sql(
+        &self,
+        db_name: &DatabaseName<'_>,
+        sql: &str,
+    ) -> Result<Vec<RecordBatch>> {
+        let db = self.db(db_name).await.context(DatabaseNotFound {
+            db_name: &**db_name,
+        })?;
+
+        let primary_query_group = match &db.rules.primary_query_group {
+            Some(id) => id,
+            None => return self.query_local(&db, sql).await,
+        };
+
+        let (primary, secondaries) = {
+            let config = self.config.read().await;
+            let group = |id: &HostGroupId| {
+                config
+                    .host_groups
+                    .get(id)
+                    .cloned()
+                    .context(HostGroupNotFound { id })
+            };
+
+            let primary = group(primary_query_group)?;
+            let secondaries = db
+                .rules
+                .secondary_query_groups
+                .iter()
+                .map(group)
+                .collect::<Result<Vec<_>>>()?;
+
+            (primary, secondaries)
+        };
+
+        let mut tables = vec![];
+        for table in table_names(sql).context(PlanningQuery)? {
+            let scan = format!("select * from {}", table);
+            let remote_scans = (0..primary.hosts.len()).map(|position| {
+                self.query_position(db_name, &scan, position, &primary, &secondaries)
+            });
+            let remote_results = futures::future::try_join_all(remote_scans).await?;
+
+            let mut batches: Vec<_> = remote_results.into_iter().flatten().collect();
+            if db.rules.query_local {
+                let local = db
+                    .table_to_arrow(&table, &[])
+                    .await
+                    .map_err(|e| Box::new(e) as DatabaseError)
+                    .context(ExecutingQuery)?;
+                batches.extend(local);
+            }
+
+            tables.push((table, batches));
+        }
+
+        let planner = SQLQueryPlanner::default();
+        let physical_plan = planner
+            .query_batches(sql, tables, self.executor.as_ref())
+            .await
+            .context(PlanningQuery)?;
+
+        collect(physical_plan)
+            .await
+            .map_err(|e| Box::new(e) as DatabaseError)
+            .context(ExecutingQuery)
+    }
+
+    /// Runs a SQL query against the data that is stored locally for the
+    /// database, without querying any other servers.
+    pub async fn query_local(&self, db: &Db, sql: &str) -> Result<Vec<RecordBatch>> {
+        let planner = SQLQueryPlanner::default();
+        let physical_plan = planner
+            .query(db, sql, self.executor.as_ref())
+            .await
+            .context(PlanningQuery)?;
+
+        collect(physical_plan)
+            .await
+            .map_err(|e| Box::new(e) as DatabaseError)
+            .context(ExecutingQuery)
+    }
+
+    // queries the host at the position in the primary group, failing over to
+    // the host at the same position in each of the secondary groups
+    async fn query_position(
+        &self,
+        db_name: &DatabaseName<'_>,
+        sql: &str,
+        position: usize,
+        primary: &HostGroup,
+        secondaries: &[HostGroup],
+    ) -> Result<Vec<RecordBatch>> {
+        let mut last_error = None;
+
+        let groups = std::iter::once(primary).chain(secondaries);
+        for group in groups {
+            let host = match group.hosts.get(position) {
+                Some(host) => host,
+                None => continue,
+            };
+
+            match self.query_host(host, db_name, sql).await {
+                Ok(batches) => return Ok(batches),
+                Err(e) => {
+                    warn!(%db_name, %host, position, "error querying host: {}", e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(Error::QueryingRemote {
+            host_group_id: primary.id.clone(),
+            position,
+            source: Box::new(last_error.expect("primary group has a host at the position")),
+        })
+    }
+
+    async fn query_host(
+        &self,
+        host: &str,
+        db_name: &DatabaseName<'_>,
+        sql: &str,
+    ) -> Result<Vec<RecordBatch>> {
+        let connection = self
+            .connection_manager
+            .remote_server(host)
+            .await
+            .map_err(|e| Box::new(e) as DatabaseError)
+            .context(UnableToGetConnection { server: host })?;
+
+        connection
+            .query(db_name, sql)
+            .await
+            .map_err(|e| Box::new(e) as DatabaseError)
+            .context(ExecutingQuery)
+    }
 }
 
 #[async_trait]
@@ -379,27 +2060,87 @@ pub trait RemoteServer {
         db: &str,
         replicated_write: &ReplicatedWrite,
     ) -> Result<(), Self::Error>;
+
+    /// Runs a SQL query against the data the remote server stores locally
+    /// for the database.
+    async fn query(&self, db: &str, sql: &str) -> Result<Vec<RecordBatch>, Self::Error>;
 }
 
-/// The connection manager maps a host identifier to a remote server.
+/// The connection manager maps a host identifier to a remote server. The
+/// host identifier is the address of the gRPC API of the remote server and
+/// the connection to each host is shared by everything that talks to it.
 #[derive(Debug)]
-pub struct ConnectionManagerImpl {}
+pub struct ConnectionManagerImpl {
+    remote_servers: RwLock<BTreeMap<String, Arc<RemoteServerImpl>>>,
+}
+
+impl ConnectionManagerImpl {
+    pub fn new() -> Self {
+        Self {
+            remote_servers: RwLock::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl Default for ConnectionManagerImpl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 #[async_trait]
 impl ConnectionManager for ConnectionManagerImpl {
     type Error = Error;
     type RemoteServer = RemoteServerImpl;
 
-    async fn remote_server(&self, _connect: &str) -> Result<Arc<Self::RemoteServer>, Self::Error> {
-        unimplemented!()
+    async fn remote_server(&self, connect: &str) -> Result<Arc<Self::RemoteServer>, Self::Error> {
+        if let Some(remote) = self.remote_servers.read().await.get(connect) {
+            return Ok(remote.clone());
+        }
+
+        let remote = Arc::new(RemoteServerImpl::connect(connect).await?);
+
+        // if another request connected to the same host in the meantime, its
+        // connection is kept and this one is dropped
+        let mut remote_servers = self.remote_servers.write().await;
+        let remote = remote_servers
+            .entry(connect.to_string())
+            .or_insert(remote)
+            .clone();
+
+        Ok(remote)
     }
 }
 
-/// An implementation for communicating with other IOx servers. This should
-/// be moved into and implemented in an influxdb_iox_client create at a later
-/// date.
+/// An implementation for communicating with other IOx servers over gRPC. This
+/// should be moved into and implemented in an influxdb_iox_client create at a
+/// later date.
 #[derive(Debug)]
-pub struct RemoteServerImpl {}
+pub struct RemoteServerImpl {
+    channel: Channel,
+}
+
+impl RemoteServerImpl {
+    /// Connects to the gRPC API of the IOx server at `connect`, for example
+    /// `http://127.0.0.1:8082`. If no scheme is given, `http` is used.
+    pub async fn connect(connect: &str) -> Result<Self> {
+        let uri = if connect.contains("://") {
+            connect.to_string()
+        } else {
+            format!("http://{}", connect)
+        };
+
+        let channel = Endpoint::from_shared(uri)
+            .map_err(|e| Box::new(e) as DatabaseError)
+            .context(UnableToGetConnection { server: connect })?
+            .connect()
+            .await
+            .map_err(|e| Box::new(e) as DatabaseError)
+            .context(UnableToGetConnection { server: connect })?;
+
+        Ok(Self { channel })
+    }
+}
 
 #[async_trait]
 impl RemoteServer for RemoteServerImpl {
@@ -407,10 +2148,51 @@ impl RemoteServer for RemoteServerImpl {
 
     async fn replicate(
         &self,
-        _db: &str,
-        _replicated_write: &ReplicatedWrite,
+        db: &str,
+        replicated_write: &ReplicatedWrite,
     ) -> Result<(), Self::Error> {
-        unimplemented!()
+        let request = ReplicateRequest {
+            db_name: db.to_string(),
+            replicated_write: replicated_write.data.clone(),
+        };
+
+        // clients share the underlying channel, so creating one is cheap
+        IOxReplicationClient::new(self.channel.clone())
+            .replicate(request)
+            .await
+            .map_err(|e| Box::new(e) as DatabaseError)
+            .context(ErrorReplicating {})?;
+
+        Ok(())
+    }
+
+    async fn query(&self, db: &str, sql: &str) -> Result<Vec<RecordBatch>, Self::Error> {
+        let request = QueryRequest {
+            db_name: db.to_string(),
+            sql: sql.to_string(),
+        };
+
+        let response = IOxQueryClient::new(self.channel.clone())
+            .query(request)
+            .await
+            .map_err(|e| Box::new(e) as DatabaseError)
+            .context(ExecutingQuery)?
+            .into_inner();
+
+        decode_record_batches(&response.record_batches).context(ConvertingQueryResults)
+    }
+}
+
+// databases that don't store writes locally still need a mutable buffer to
+// query their read only partitions
+fn new_mutable_buffer(
+    db_name: &DatabaseName<'_>,
+    rules: &DatabaseRules,
+) -> Option<Arc<MutableBufferDb>> {
+    if rules.store_locally || !rules.read_only_partitions.is_empty() {
+        Some(Arc::new(MutableBufferDb::new(db_name.to_string())))
+    } else {
+        None
     }
 }
 
@@ -421,235 +2203,2187 @@ fn config_location(id: u32) -> ObjectStorePath {
     path
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use arrow_deps::{assert_table_eq, datafusion::physical_plan::collect};
-    use async_trait::async_trait;
-    use data_types::database_rules::{MatchTables, Matcher, Subscription};
-    use futures::TryStreamExt;
-    use influxdb_line_protocol::parse_lines;
-    use object_store::{memory::InMemory, ObjectStoreIntegration};
-    use query::frontend::sql::SQLQueryPlanner;
-    use snafu::Snafu;
-    use std::sync::Mutex;
+// the kind of error a failed write is counted as in the metrics
+fn write_error_kind(e: &Error) -> &'static str {
+    match e {
+        Error::IdNotSet => "id_not_set",
+        Error::InvalidDatabaseName { .. } | Error::DatabaseNotFound { .. } => "database_not_found",
+        Error::DatabaseOverMemoryLimit { .. } => "over_memory_limit",
+        Error::ReplicationQueueFull { .. } => "replication_queue_full",
+        Error::ReplicationCountNotMet { .. } => "replication_count_not_met",
+        Error::WriteInProgress { .. } => "write_in_progress",
+        _ => "internal",
+    }
+}
+
+/// Where a database continues after the WAL segments persisted for it were
+/// replayed
+#[derive(Debug, Clone)]
+struct WalReplay {
+    /// The id of the first segment of the new WAL buffer
+    next_segment_id: u64,
+    /// The sequence number of the next write from this server
+    next_sequence: u64,
+    /// The sequences of the replayed writes, by chunk
+    sequences: WriteSequences,
+    /// The replayed deletes, by the chunk they apply to
+    deletes: ChunkDeletes,
+    /// The writer and sequence of every replayed write, in order
+    applied: Vec<(u32, u64)>,
+}
+
+impl Default for WalReplay {
+    fn default() -> Self {
+        Self {
+            next_segment_id: 1,
+            next_sequence: STARTING_SEQUENCE,
+            sequences: WriteSequences::default(),
+            deletes: ChunkDeletes::new(),
+            applied: vec![],
+        }
+    }
+}
+
+// location in the store under which the data of a database is persisted
+fn database_location(id: u32, db_name: &DatabaseName<'_>) -> ObjectStorePath {
+    let id = id.to_string();
+    let mut path = ObjectStorePath::default();
+    path.push_all(&[id.as_str(), &**db_name]);
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{self, DBChunk};
+    use crate::replication_queue::INITIAL_BACKOFF;
+    use arrow_deps::{
+        arrow::{
+            array::StringArray,
+            datatypes::{DataType, Field, Schema},
+            util::pretty::pretty_format_batches,
+        },
+        assert_table_eq,
+        datafusion::physical_plan::collect,
+    };
+    use async_trait::async_trait;
+    use data_types::data::lines_to_replicated_write;
+    use data_types::database_rules::{
+        LifecycleRules, MatchTables, Matcher, PartitionTemplate, Subscription, TemplatePart,
+        WalBufferConfig, WalBufferRollover,
+    };
+    use futures::TryStreamExt;
+    use influxdb_line_protocol::parse_lines;
+    use object_store::{memory::InMemory, ObjectStoreIntegration};
+    use query::{frontend::sql::SQLQueryPlanner, predicate::Predicate, PartitionChunk};
+    use snafu::Snafu;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Mutex,
+    };
+
+    type TestError = Box<dyn std::error::Error + Send + Sync + 'static>;
+    type Result<T = (), E = TestError> = std::result::Result<T, E>;
+
+    #[tokio::test]
+    async fn server_api_calls_return_error_with_no_id_set() -> Result {
+        let manager = TestConnectionManager::new();
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let mut server = Server::new(manager, store);
+
+        let rules = DatabaseRules::default();
+        let resp = server.create_database("foo", rules).await.unwrap_err();
+        assert!(matches!(resp, Error::IdNotSet));
+
+        let lines = parsed_lines("cpu foo=1 10");
+        let resp = server.write_lines("foo", &lines).await.unwrap_err();
+        assert!(matches!(resp, Error::IdNotSet));
+
+        let resp = server
+            .create_host_group("group1".to_string(), vec!["serverA".to_string()])
+            .await
+            .unwrap_err();
+        assert!(matches!(resp, Error::IdNotSet));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn database_name_validation() -> Result {
+        let manager = TestConnectionManager::new();
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let server = Server::new(manager, store);
+        server.set_id(1).await;
+
+        let reject: [&str; 5] = [
+            "bananas!",
+            r#""bananas\"are\"great"#,
+            "bananas:good",
+            "bananas/cavendish",
+            "bananas\n",
+        ];
+
+        for &name in &reject {
+            let rules = DatabaseRules {
+                store_locally: true,
+                ..Default::default()
+            };
+            let got = server.create_database(name, rules).await.unwrap_err();
+            if !matches!(got, Error::InvalidDatabaseName { .. }) {
+                panic!("expected invalid name error");
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn writes_local() -> Result {
+        let manager = TestConnectionManager::new();
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let server = Server::new(manager, store);
+        server.set_id(1).await;
+        let rules = DatabaseRules {
+            store_locally: true,
+            ..Default::default()
+        };
+        server.create_database("foo", rules).await?;
+
+        let line = "cpu bar=1 10";
+        let lines: Vec<_> = parse_lines(line).map(|l| l.unwrap()).collect();
+        server.write_lines("foo", &lines).await.unwrap();
+
+        let db_name = DatabaseName::new("foo").unwrap();
+        let db = server.db(&db_name).await.unwrap();
+
+        let buff = db.mutable_buffer.as_ref().unwrap();
+
+        let planner = SQLQueryPlanner::default();
+        let executor = server.executor();
+        let physical_plan = planner
+            .query(buff.as_ref(), "select * from cpu", executor.as_ref())
+            .await
+            .unwrap();
+
+        let batches = collect(physical_plan).await.unwrap();
+        let expected = vec![
+            "+-----+------+",
+            "| bar | time |",
+            "+-----+------+",
+            "| 1   | 10   |",
+            "+-----+------+",
+        ];
+        assert_table_eq!(expected, &batches);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replicate_to_single_group() -> Result {
+        let mut manager = TestConnectionManager::new();
+        let remote = Arc::new(TestRemoteServer::default());
+        let remote_id = "serverA";
+        manager
+            .remotes
+            .insert(remote_id.to_string(), remote.clone());
+
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+
+        let mut server = Server::new(manager, store);
+        server.set_id(1).await;
+        let host_group_id = "az1".to_string();
+        let rules = DatabaseRules {
+            replication: vec![host_group_id.clone()],
+            replication_count: 1,
+            ..Default::default()
+        };
+        server
+            .create_host_group(host_group_id.clone(), vec![remote_id.to_string()])
+            .await
+            .unwrap();
+        let db_name = "foo";
+        server.create_database(db_name, rules).await.unwrap();
+
+        let lines = parsed_lines("cpu bar=1 10");
+        server.write_lines("foo", &lines).await.unwrap();
+
+        let writes = remote.writes.lock().unwrap().get(db_name).unwrap().clone();
+
+        let write_text = r#"
+writer:1, sequence:1, checksum:226387645
+partition_key:
+  table:cpu
+    bar:1 time:10
+"#;
+
+        assert_eq!(write_text, writes[0].to_string());
+
+        // ensure sequence number goes up
+        let lines = parsed_lines("mem,server=A,region=west user=232 12");
+        server.write_lines("foo", &lines).await.unwrap();
+
+        let writes = remote.writes.lock().unwrap().get(db_name).unwrap().clone();
+        assert_eq!(2, writes.len());
+
+        let write_text = r#"
+writer:1, sequence:2, checksum:3759030699
+partition_key:
+  table:mem
+    server:A region:west user:232 time:12
+"#;
+
+        assert_eq!(write_text, writes[1].to_string());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replicate_spreads_partitions_across_group() -> Result {
+        let mut manager = TestConnectionManager::new();
+        let remote_ids = vec![
+            "serverA".to_string(),
+            "serverB".to_string(),
+            "serverC".to_string(),
+        ];
+        let remotes: Vec<_> = remote_ids
+            .iter()
+            .map(|id| {
+                let remote = Arc::new(TestRemoteServer::default());
+                manager.remotes.insert(id.clone(), remote.clone());
+                remote
+            })
+            .collect();
+
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+
+        let mut server = Server::new(manager, store);
+        server.set_id(1).await;
+        let host_group_id = "az1".to_string();
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Column("host".to_string())],
+            },
+            replication: vec![host_group_id.clone()],
+            replication_count: 1,
+            ..Default::default()
+        };
+        server
+            .create_host_group(host_group_id.clone(), remote_ids.clone())
+            .await
+            .unwrap();
+        let db_name = "foo";
+        server.create_database(db_name, rules).await.unwrap();
+
+        let lp: String = (0..20)
+            .map(|i| format!("cpu,host=h{} bar=1 10\n", i))
+            .collect();
+        let lines = parsed_lines(&lp);
+        server.write_lines(db_name, &lines).await.unwrap();
+
+        // every partition should have been sent exactly once, to the host that
+        // owns it on the ring
+        let ring = HashRing::new(&remote_ids);
+        let mut replicated_keys = BTreeSet::new();
+        for (id, remote) in remote_ids.iter().zip(&remotes) {
+            let writes = remote.writes.lock().unwrap();
+            for write in writes.get(db_name).into_iter().flatten() {
+                assert_eq!((1, 1), write.writer_and_sequence());
+
+                for key in write.partition_keys() {
+                    assert_eq!(Some(id.as_str()), ring.host_for(key));
+                    assert!(replicated_keys.insert(key.to_string()));
+                }
+            }
+        }
+        assert_eq!(20, replicated_keys.len());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replicate_to_group_continues_past_failed_host() -> Result {
+        let mut manager = TestConnectionManager::new();
+        let remote_ids = vec!["serverA".to_string(), "serverB".to_string()];
+        let remotes: Vec<_> = remote_ids
+            .iter()
+            .map(|id| {
+                let remote = Arc::new(TestRemoteServer::default());
+                manager.remotes.insert(id.clone(), remote.clone());
+                remote
+            })
+            .collect();
+        remotes[0].set_available(false);
+
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let mut server = Server::new(manager, store);
+        server.set_id(1).await;
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Column("host".to_string())],
+            },
+            replication: vec!["az1".to_string()],
+            replication_count: 1,
+            replication_queue_max_size: 10,
+            ..Default::default()
+        };
+        server
+            .create_host_group("az1".to_string(), remote_ids.clone())
+            .await?;
+        server.create_database("foo", rules).await?;
+
+        let lp: String = (0..20)
+            .map(|i| format!("cpu,host=h{} bar=1 10\n", i))
+            .collect();
+        let err = server
+            .write_lines("foo", &parsed_lines(&lp))
+            .await
+            .unwrap_err();
+
+        // the available host got its partitions, only the failed one is
+        // reported
+        match err {
+            Error::ReplicationCountNotMet { source, .. } => match *source {
+                Error::PartiallyReplicated { id, hosts, .. } => {
+                    assert_eq!(id, "az1");
+                    assert_eq!(hosts, vec!["serverA".to_string()]);
+                }
+                e => panic!("unexpected error: {}", e),
+            },
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(remotes[1].write_count("foo"), 1);
+
+        Ok(())
+    }
+
+    // creates a server with the database "foo" that replicates to a host group
+    // with a single host for each of the passed in remotes
+    async fn replicating_server(
+        remotes: &[Arc<TestRemoteServer>],
+        replication_count: u8,
+        replication_queue_max_size: usize,
+    ) -> Result<Server<TestConnectionManager>> {
+        let mut manager = TestConnectionManager::new();
+        for (i, remote) in remotes.iter().enumerate() {
+            manager
+                .remotes
+                .insert(format!("server{}", i), Arc::clone(remote));
+        }
+
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let mut server = Server::new(manager, store);
+        server.set_id(1).await;
+
+        let mut replication = vec![];
+        for i in 0..remotes.len() {
+            let host_group_id = format!("az{}", i);
+            server
+                .create_host_group(host_group_id.clone(), vec![format!("server{}", i)])
+                .await?;
+            replication.push(host_group_id);
+        }
+
+        let rules = DatabaseRules {
+            replication,
+            replication_count,
+            replication_queue_max_size,
+            ..Default::default()
+        };
+        server.create_database("foo", rules).await?;
+
+        Ok(server)
+    }
+
+    fn test_remotes(count: usize) -> Vec<Arc<TestRemoteServer>> {
+        (0..count)
+            .map(|_| Arc::new(TestRemoteServer::default()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn replication_queues_groups_after_replication_count() -> Result {
+        let remotes = test_remotes(3);
+        let server = replicating_server(&remotes, 1, 10).await?;
+
+        let lines = parsed_lines("cpu bar=1 10");
+        server.write_lines("foo", &lines).await?;
+
+        let counts: Vec<_> = remotes.iter().map(|r| r.write_count("foo")).collect();
+        assert_eq!(counts, vec![1, 0, 0]);
+
+        let db = server.db(&DatabaseName::new("foo")?).await.unwrap();
+        assert_eq!(db.replication_queue.len(), 1);
+
+        server.retry_replication().await;
+
+        let counts: Vec<_> = remotes.iter().map(|r| r.write_count("foo")).collect();
+        assert_eq!(counts, vec![1, 1, 1]);
+        assert!(db.replication_queue.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replication_retries_failed_groups() -> Result {
+        let remotes = test_remotes(2);
+        let server = replicating_server(&remotes, 1, 10).await?;
+        remotes[0].set_available(false);
+
+        let lines = parsed_lines("cpu bar=1 10");
+        server.write_lines("foo", &lines).await?;
+        assert_eq!(remotes[0].write_count("foo"), 0);
+        assert_eq!(remotes[1].write_count("foo"), 1);
+
+        let db = server.db(&DatabaseName::new("foo")?).await.unwrap();
+        assert_eq!(db.replication_queue.len(), 1);
+
+        server.retry_replication().await;
+        assert_eq!(remotes[0].write_count("foo"), 0);
+        assert_eq!(db.replication_queue.len(), 1);
+
+        // the failed group backs off before it is retried
+        remotes[0].set_available(true);
+        server.retry_replication().await;
+        assert_eq!(remotes[0].write_count("foo"), 0);
+
+        server
+            .retry_replication_at(Instant::now() + INITIAL_BACKOFF)
+            .await;
+        assert_eq!(remotes[0].write_count("foo"), 1);
+        assert_eq!(remotes[1].write_count("foo"), 1);
+        assert!(db.replication_queue.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replication_count_zero_requires_every_group() -> Result {
+        let remotes = test_remotes(2);
+        let server = replicating_server(&remotes, 0, 10).await?;
+
+        let lines = parsed_lines("cpu bar=1 10");
+        server.write_lines("foo", &lines).await?;
+        let counts: Vec<_> = remotes.iter().map(|r| r.write_count("foo")).collect();
+        assert_eq!(counts, vec![1, 1]);
+
+        let db = server.db(&DatabaseName::new("foo")?).await.unwrap();
+        assert!(db.replication_queue.is_empty());
+
+        remotes[1].set_available(false);
+        let err = server.write_lines("foo", &lines).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ReplicationCountNotMet {
+                required: 2,
+                acknowledged: 1,
+                ..
+            }
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replication_count_not_met_does_not_store_locally() -> Result {
+        let remotes = test_remotes(1);
+        let server = replicating_server(&remotes, 1, 10).await?;
+        let rules = DatabaseRules {
+            store_locally: true,
+            replication: vec!["az0".to_string()],
+            replication_count: 1,
+            replication_queue_max_size: 10,
+            ..Default::default()
+        };
+        server.create_database("bar", rules).await?;
+        remotes[0].set_available(false);
+
+        let lines = parsed_lines("cpu bar=1 10");
+        server.write_lines("bar", &lines).await.unwrap_err();
+
+        // the retried write is stored once
+        remotes[0].set_available(true);
+        server.write_lines("bar", &lines).await?;
+
+        let db = server.db(&DatabaseName::new("bar")?).await.unwrap();
+        let expected = vec![
+            "+-----+------+",
+            "| bar | time |",
+            "+-----+------+",
+            "| 1   | 10   |",
+            "+-----+------+",
+        ];
+        let batches = server.query_local(&db, "select * from cpu").await?;
+        assert_table_eq!(expected, &batches);
+        assert!(db.replication_queue.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replication_count_not_met() -> Result {
+        let remotes = test_remotes(2);
+        let server = replicating_server(&remotes, 2, 10).await?;
+        remotes[1].set_available(false);
+
+        let lines = parsed_lines("cpu bar=1 10");
+        let err = server.write_lines("foo", &lines).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ReplicationCountNotMet {
+                required: 2,
+                acknowledged: 1,
+                ..
+            }
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn duplicate_replicated_writes_are_applied_once() -> Result {
+        let server = Server::new(
+            TestConnectionManager::new(),
+            Arc::new(ObjectStore::new_in_memory(InMemory::new())),
+        );
+        server.set_id(1).await;
+        let rules = DatabaseRules {
+            store_locally: true,
+            ..Default::default()
+        };
+        server.create_database("foo", rules.clone()).await?;
+        let db_name = DatabaseName::new("foo").unwrap();
+        let db = server.db(&db_name).await.unwrap();
+
+        let lines = parsed_lines("cpu bar=1 10");
+        let write = lines_to_replicated_write(2, 1, &lines, &rules)?;
+        server
+            .handle_replicated_write(&db_name, &db, write.clone())
+            .await?;
+        server.handle_replicated_write(&db_name, &db, write).await?;
+
+        // a write from another writer with the same sequence isn't a
+        // duplicate
+        let lines = parsed_lines("cpu bar=2 20");
+        let write = lines_to_replicated_write(3, 1, &lines, &rules)?;
+        server.handle_replicated_write(&db_name, &db, write).await?;
+
+        let expected = vec![
+            "+-----+------+",
+            "| bar | time |",
+            "+-----+------+",
+            "| 1   | 10   |",
+            "| 2   | 20   |",
+            "+-----+------+",
+        ];
+        let batches = server.query_local(&db, "select * from cpu").await?;
+        assert_table_eq!(expected, &batches);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn duplicate_replicated_writes_are_replicated_again() -> Result {
+        let remotes = test_remotes(1);
+        let server = replicating_server(&remotes, 1, 0).await?;
+        let db_name = DatabaseName::new("foo").unwrap();
+        let db = server.db(&db_name).await.unwrap();
+
+        let lines = parsed_lines("cpu bar=1 10");
+        let write = lines_to_replicated_write(2, 1, &lines, &db.rules)?;
+        server
+            .handle_replicated_write(&db_name, &db, write.clone())
+            .await?;
+        assert!(db.applied_writes.contains(2, 1));
+
+        // a retry might be because replicating the write failed
+        server
+            .handle_replicated_write(&db_name, &db, write.clone())
+            .await?;
+        assert_eq!(remotes[0].write_count("foo"), 2);
+
+        // a duplicate of a write that is being applied is rejected
+        let write = lines_to_replicated_write(2, 2, &lines, &db.rules)?;
+        let pending = db.applied_writes.begin(2, 2).unwrap();
+        let err = server
+            .handle_replicated_write(&db_name, &db, write.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::WriteInProgress { sequence: 2, .. }));
+        assert_eq!(remotes[0].write_count("foo"), 2);
+
+        // and applied if applying it failed
+        drop(pending);
+        server.handle_replicated_write(&db_name, &db, write).await?;
+        assert!(db.applied_writes.contains(2, 2));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replication_rejects_writes_when_queue_full() -> Result {
+        let remotes = test_remotes(2);
+        let server = replicating_server(&remotes, 1, 1).await?;
+        remotes[1].set_available(false);
+
+        let lines = parsed_lines("cpu bar=1 10");
+        server.write_lines("foo", &lines).await?;
+
+        let err = server.write_lines("foo", &lines).await.unwrap_err();
+        assert!(matches!(err, Error::ReplicationQueueFull { .. }));
+        assert_eq!(remotes[0].write_count("foo"), 1);
+
+        // once the queue drains writes are accepted again
+        remotes[1].set_available(true);
+        server.retry_replication().await;
+        server.write_lines("foo", &lines).await?;
+        assert_eq!(remotes[0].write_count("foo"), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replication_without_queue_drops_failures() -> Result {
+        let remotes = test_remotes(3);
+        let server = replicating_server(&remotes, 1, 0).await?;
+        remotes[1].set_available(false);
+
+        let lines = parsed_lines("cpu bar=1 10");
+        server.write_lines("foo", &lines).await?;
+
+        let counts: Vec<_> = remotes.iter().map(|r| r.write_count("foo")).collect();
+        assert_eq!(counts, vec![1, 0, 1]);
+
+        let db = server.db(&DatabaseName::new("foo")?).await.unwrap();
+        assert!(db.replication_queue.is_empty());
+
+        Ok(())
+    }
+
+    // creates a server with the database "foo" that has the primary query
+    // group az1 with serverA and serverB and the secondary query group az2
+    // with serverC and serverD. Each remote answers queries with its name.
+    async fn query_coordinator(unavailable: &[&str]) -> Result<Server<TestConnectionManager>> {
+        let mut manager = TestConnectionManager::new();
+        for host in &["serverA", "serverB", "serverC", "serverD"] {
+            let remote = TestRemoteServer::default();
+            *remote.query_results.lock().unwrap() = vec![host_batch(host)];
+            remote.set_available(!unavailable.contains(host));
+            manager.remotes.insert(host.to_string(), Arc::new(remote));
+        }
+
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let mut server = Server::new(manager, store);
+        server.set_id(1).await;
+        server
+            .create_host_group(
+                "az1".to_string(),
+                vec!["serverA".to_string(), "serverB".to_string()],
+            )
+            .await?;
+        server
+            .create_host_group(
+                "az2".to_string(),
+                vec!["serverC".to_string(), "serverD".to_string()],
+            )
+            .await?;
+
+        let rules = DatabaseRules {
+            store_locally: true,
+            query_local: true,
+            primary_query_group: Some("az1".to_string()),
+            secondary_query_groups: vec!["az2".to_string()],
+            ..Default::default()
+        };
+        server.create_database("foo", rules).await?;
+
+        let lines = parsed_lines("cpu,host=local val=1 10");
+        server.write_lines("foo", &lines).await?;
+
+        Ok(server)
+    }
+
+    // returns a record batch with a single host column
+    fn host_batch(host: &str) -> RecordBatch {
+        let schema = Schema::new(vec![Field::new("host", DataType::Utf8, false)]);
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![Arc::new(StringArray::from(vec![host]))],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn query_fails_over_to_secondary_group() -> Result {
+        let server = query_coordinator(&["serverB"]).await?;
+
+        let db_name = DatabaseName::new("foo")?;
+        let batches = server.query_sql(&db_name, "select host from cpu").await?;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+
+        let results = pretty_format_batches(&batches)?;
+        assert!(results.contains("serverA"));
+        assert!(!results.contains("serverB"));
+        assert!(!results.contains("serverC"));
+        assert!(results.contains("serverD"));
+        assert!(results.contains("local"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_aggregates_rows_of_every_host() -> Result {
+        let server = query_coordinator(&[]).await?;
+
+        let db_name = DatabaseName::new("foo")?;
+        let batches = server
+            .query_sql(
+                &db_name,
+                "select count(*) as hosts, count(val) as vals from cpu",
+            )
+            .await?;
+
+        let expected = vec![
+            "+-------+------+",
+            "| hosts | vals |",
+            "+-------+------+",
+            "| 3     | 1    |",
+            "+-------+------+",
+        ];
+        assert_table_eq!(expected, &batches);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_fails_if_position_unavailable() -> Result {
+        let server = query_coordinator(&["serverB", "serverD"]).await?;
+
+        let db_name = DatabaseName::new("foo")?;
+        let err = server
+            .query_sql(&db_name, "select host from cpu")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::QueryingRemote { position: 1, .. }));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sends_all_to_subscriber() -> Result {
+        let mut manager = TestConnectionManager::new();
+        let remote = Arc::new(TestRemoteServer::default());
+        let remote_id = "serverA";
+        manager
+            .remotes
+            .insert(remote_id.to_string(), remote.clone());
+
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+
+        let mut server = Server::new(manager, store);
+        server.set_id(1).await;
+        let host_group_id = "az1".to_string();
+        let rules = DatabaseRules {
+            subscriptions: vec![Subscription {
+                name: "query_server_1".to_string(),
+                host_group_id: host_group_id.clone(),
+                matcher: Matcher {
+                    tables: MatchTables::All,
+                    predicate: None,
+                },
+            }],
+            ..Default::default()
+        };
+        server
+            .create_host_group(host_group_id.clone(), vec![remote_id.to_string()])
+            .await
+            .unwrap();
+        let db_name = "foo";
+        server.create_database(db_name, rules).await.unwrap();
+
+        let lines = parsed_lines("cpu bar=1 10");
+        server.write_lines("foo", &lines).await.unwrap();
+
+        let writes = remote.writes.lock().unwrap().get(db_name).unwrap().clone();
+
+        let write_text = r#"
+writer:1, sequence:1, checksum:226387645
+partition_key:
+  table:cpu
+    bar:1 time:10
+"#;
+
+        assert_eq!(write_text, writes[0].to_string());
+
+        // ensure sequence number goes up
+        let lines = parsed_lines("mem,server=A,region=west user=232 12");
+        server.write_lines("foo", &lines).await.unwrap();
+
+        let writes = remote.writes.lock().unwrap().get(db_name).unwrap().clone();
+        assert_eq!(2, writes.len());
+
+        let write_text = r#"
+writer:1, sequence:2, checksum:3759030699
+partition_key:
+  table:mem
+    server:A region:west user:232 time:12
+"#;
+
+        assert_eq!(write_text, writes[1].to_string());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stores_write_when_subscriber_fails() -> Result {
+        let mut manager = TestConnectionManager::new();
+        let remote = Arc::new(TestRemoteServer::default());
+        let remote_id = "serverA";
+        manager
+            .remotes
+            .insert(remote_id.to_string(), remote.clone());
+
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+
+        let mut server = Server::new(manager, store);
+        server.set_id(1).await;
+        let host_group_id = "az1".to_string();
+        let rules = DatabaseRules {
+            store_locally: true,
+            subscriptions: vec![Subscription {
+                name: "query_server_1".to_string(),
+                host_group_id: host_group_id.clone(),
+                matcher: Matcher {
+                    tables: MatchTables::All,
+                    predicate: None,
+                },
+            }],
+            ..Default::default()
+        };
+        server
+            .create_host_group(host_group_id, vec![remote_id.to_string()])
+            .await?;
+        server.create_database("foo", rules).await?;
+
+        // the write succeeds even though the subscriber didn't get it
+        remote.set_available(false);
+        let lines = parsed_lines("cpu bar=1 10");
+        server.write_lines("foo", &lines).await?;
+        assert_eq!(remote.write_count("foo"), 0);
+
+        let db = server.db(&DatabaseName::new("foo").unwrap()).await.unwrap();
+        assert_eq!(db.partition_keys().await?.len(), 1);
+        assert!(db.applied_writes.contains(1, 1));
+
+        remote.set_available(true);
+        server.write_lines("foo", &lines).await?;
+        assert_eq!(remote.write_count("foo"), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sends_matching_rows_to_subscribers() -> Result {
+        let mut manager = TestConnectionManager::new();
+        let cpu_remote = Arc::new(TestRemoteServer::default());
+        let west_remote = Arc::new(TestRemoteServer::default());
+        manager
+            .remotes
+            .insert("serverA".to_string(), cpu_remote.clone());
+        manager
+            .remotes
+            .insert("serverB".to_string(), west_remote.clone());
+
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+
+        let mut server = Server::new(manager, store);
+        server.set_id(1).await;
+        let rules = DatabaseRules {
+            subscriptions: vec![
+                Subscription {
+                    name: "cpu_only".to_string(),
+                    host_group_id: "az1".to_string(),
+                    matcher: Matcher {
+                        tables: MatchTables::Regex("^cpu$".to_string()),
+                        predicate: None,
+                    },
+                },
+                Subscription {
+                    name: "west_only".to_string(),
+                    host_group_id: "az2".to_string(),
+                    matcher: Matcher {
+                        tables: MatchTables::All,
+                        predicate: Some("region = 'west'".to_string()),
+                    },
+                },
+            ],
+            ..Default::default()
+        };
+        server
+            .create_host_group("az1".to_string(), vec!["serverA".to_string()])
+            .await
+            .unwrap();
+        server
+            .create_host_group("az2".to_string(), vec!["serverB".to_string()])
+            .await
+            .unwrap();
+        let db_name = "foo";
+        server.create_database(db_name, rules).await.unwrap();
+
+        let lines = parsed_lines(
+            "cpu,region=west user=1 10\ncpu,region=east user=2 10\nmem,region=east free=3 10",
+        );
+        server.write_lines(db_name, &lines).await.unwrap();
+
+        let writes = cpu_remote
+            .writes
+            .lock()
+            .unwrap()
+            .get(db_name)
+            .unwrap()
+            .clone();
+        assert_eq!(1, writes.len());
+        assert_eq!((1, 1), writes[0].writer_and_sequence());
+        let write_text = writes[0].to_string();
+        assert!(write_text.contains("region:west user:1 time:10"));
+        assert!(write_text.contains("region:east user:2 time:10"));
+        assert!(!write_text.contains("table:mem"));
+
+        let writes = west_remote
+            .writes
+            .lock()
+            .unwrap()
+            .get(db_name)
+            .unwrap()
+            .clone();
+        assert_eq!(1, writes.len());
+        assert_eq!((1, 1), writes[0].writer_and_sequence());
+        let write_text = writes[0].to_string();
+        assert!(write_text.contains("region:west user:1 time:10"));
+        assert!(!write_text.contains("region:east"));
+
+        // writes without matching rows aren't sent to the subscriber
+        let lines = parsed_lines("mem,region=east free=4 12");
+        server.write_lines(db_name, &lines).await.unwrap();
+        assert_eq!(1, cpu_remote.writes.lock().unwrap()[db_name].len());
+        assert_eq!(1, west_remote.writes.lock().unwrap()[db_name].len());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn config_changes_do_not_wait_for_writes() -> Result {
+        let mut manager = TestConnectionManager::new();
+        let remote = Arc::new(TestRemoteServer::default());
+        manager
+            .remotes
+            .insert("serverA".to_string(), Arc::clone(&remote));
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+
+        let mut server = Server::new(manager, store);
+        server.set_id(1).await;
+        server
+            .create_host_group("az1".to_string(), vec!["serverA".to_string()])
+            .await?;
+        let rules = DatabaseRules {
+            replication: vec!["az1".to_string()],
+            replication_count: 1,
+            ..Default::default()
+        };
+        server.create_database("foo", rules).await?;
+        let server = Arc::new(server);
+
+        // hold up the write while it is being replicated
+        let pause = remote.paused.write().await;
+        let writer = Arc::clone(&server);
+        let write = tokio::spawn(async move {
+            writer
+                .write_lines("foo", &parsed_lines("cpu bar=1 10"))
+                .await
+        });
+        while remote.waiting_replications.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            server.create_database("bar", DatabaseRules::default()),
+        )
+        .await??;
+
+        drop(pause);
+        write.await??;
+        assert_eq!(remote.write_count("foo"), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_database_validates_rules() -> Result {
+        let manager = TestConnectionManager::new();
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+
+        let server = Server::new(manager, store);
+        server.set_id(1).await;
+
+        let rules = DatabaseRules {
+            replication: vec!["az1".to_string()],
+            ..Default::default()
+        };
+        let err = server.create_database("foo", rules).await.unwrap_err();
+        assert!(matches!(err, Error::HostGroupNotFound { .. }));
+
+        let rules = DatabaseRules {
+            secondary_query_groups: vec!["az2".to_string()],
+            ..Default::default()
+        };
+        let err = server.create_database("foo", rules).await.unwrap_err();
+        assert!(matches!(err, Error::HostGroupNotFound { .. }));
+
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::TimeFormat("%Y-%Q".to_string())],
+            },
+            ..Default::default()
+        };
+        let err = server.create_database("foo", rules).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidPartitionTemplate { .. }));
+
+        let rules = DatabaseRules {
+            wal_buffer_config: Some(WalBufferConfig {
+                buffer_size: Some(1024),
+                segment_size: None,
+                buffer_rollover: WalBufferRollover::ReturnError,
+            }),
+            ..Default::default()
+        };
+        let err = server.create_database("foo", rules).await.unwrap_err();
+        assert!(matches!(err, Error::WalSegmentLargerThanBuffer { .. }));
+
+        assert!(server
+            .db(&DatabaseName::new("foo").unwrap())
+            .await
+            .is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_database_keeps_data() -> Result {
+        let manager = TestConnectionManager::new();
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+
+        let server = Server::new(manager, store);
+        server.set_id(1).await;
+
+        let rules = DatabaseRules {
+            store_locally: true,
+            ..Default::default()
+        };
+        server.update_database("foo", rules).await?;
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=1 10"))
+            .await?;
+
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            store_locally: true,
+            wal_buffer_config: Some(WalBufferConfig {
+                buffer_size: None,
+                segment_size: None,
+                buffer_rollover: WalBufferRollover::ReturnError,
+            }),
+            ..Default::default()
+        };
+        server.update_database("foo", rules.clone()).await?;
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=2 20"))
+            .await?;
+
+        let db = server.db(&DatabaseName::new("foo").unwrap()).await.unwrap();
+        assert_eq!(db.rules, rules);
+        assert!(db.has_wal_buffer());
+        assert_eq!(db.next_sequence(), 3);
+
+        let mut partition_keys = db.partition_keys().await?;
+        partition_keys.sort();
+        assert_eq!(partition_keys, vec!["", "cpu"]);
+
+        let expected = vec![
+            "+-----+------+",
+            "| bar | time |",
+            "+-----+------+",
+            "| 1   | 10   |",
+            "| 2   | 20   |",
+            "+-----+------+",
+        ];
+        let batches = server.query_local(&db, "select * from cpu").await?;
+        assert_table_eq!(expected, &batches);
+
+        // invalid rules leave the database as it was
+        let bad_rules = DatabaseRules {
+            replication: vec!["az1".to_string()],
+            ..rules.clone()
+        };
+        let err = server.update_database("foo", bad_rules).await.unwrap_err();
+        assert!(matches!(err, Error::HostGroupNotFound { .. }));
+        let db = server.db(&DatabaseName::new("foo").unwrap()).await.unwrap();
+        assert_eq!(db.rules, rules);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn drop_database_deletes_persisted_wal() -> Result {
+        let manager = TestConnectionManager::new();
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+
+        let server = Server::new(manager, Arc::clone(&store));
+        server.set_id(1).await;
+
+        let rules = DatabaseRules {
+            store_locally: true,
+            wal_buffer_config: Some(WalBufferConfig {
+                buffer_size: None,
+                segment_size: Some(1),
+                buffer_rollover: WalBufferRollover::ReturnError,
+            }),
+            ..Default::default()
+        };
+        server.create_database("foo", rules.clone()).await?;
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=1 10"))
+            .await?;
+        server.persist_wal_segments().await;
+
+        server.drop_database("foo").await?;
+        assert!(server
+            .db(&DatabaseName::new("foo").unwrap())
+            .await
+            .is_none());
+        assert!(matches!(
+            server.drop_database("foo").await,
+            Err(Error::DatabaseNotFound { .. })
+        ));
+
+        let mut prefix = ObjectStorePath::default();
+        prefix.push_all(&["1", "foo", "wal"]);
+        let paths: Vec<_> = store.list(Some(&prefix)).await?.try_concat().await?;
+        assert!(paths.is_empty());
+
+        // a database created with the same name starts out empty
+        server.create_database("foo", rules).await?;
+        let db = server.db(&DatabaseName::new("foo").unwrap()).await.unwrap();
+        assert!(db.partition_keys().await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_database_with_invalid_matcher() -> Result {
+        let manager = TestConnectionManager::new();
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+
+        let mut server = Server::new(manager, store);
+        server.set_id(1).await;
+        server
+            .create_host_group("az1".to_string(), vec!["serverA".to_string()])
+            .await?;
+        let rules = DatabaseRules {
+            subscriptions: vec![Subscription {
+                name: "bad".to_string(),
+                host_group_id: "az1".to_string(),
+                matcher: Matcher {
+                    tables: MatchTables::Regex("cpu(".to_string()),
+                    predicate: None,
+                },
+            }],
+            ..Default::default()
+        };
+
+        let err = server.create_database("foo", rules).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidDatabaseRules { .. }));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rebuilds_database_from_persisted_wal() -> Result {
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            store_locally: true,
+            wal_buffer_config: Some(WalBufferConfig {
+                buffer_size: Some(1 << 20),
+                segment_size: Some(1),
+                buffer_rollover: WalBufferRollover::ReturnError,
+            }),
+            read_only_partitions: vec![],
+            ..Default::default()
+        };
+
+        let server = Server::new(TestConnectionManager::new(), Arc::clone(&store));
+        server.set_id(1).await;
+        server.create_database("foo", rules.clone()).await?;
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=1 10\nmem bar=2 10"))
+            .await?;
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=3 20"))
+            .await?;
+        server.persist_wal_segments().await;
+        server.store_configuration().await?;
+
+        // creating the database again doesn't replay the persisted writes,
+        // but continues after them
+        let server = Server::new(TestConnectionManager::new(), Arc::clone(&store));
+        server.set_id(1).await;
+        server.create_database("foo", rules.clone()).await?;
+        let db = server.db(&DatabaseName::new("foo").unwrap()).await.unwrap();
+        assert!(db.partition_keys().await?.is_empty());
+        assert_eq!(db.next_sequence(), 3);
+
+        // a restarted server with the same id recovers the persisted writes
+        let server = Server::new(TestConnectionManager::new(), Arc::clone(&store));
+        server.set_id(1).await;
+        assert!(server.load_databases().await?.is_empty());
+        let db = server.db(&DatabaseName::new("foo").unwrap()).await.unwrap();
+
+        let expected = vec![
+            "+-----+------+",
+            "| bar | time |",
+            "+-----+------+",
+            "| 1   | 10   |",
+            "| 3   | 20   |",
+            "+-----+------+",
+        ];
+        let batches = server.query_local(&db, "select * from cpu").await?;
+        assert_table_eq!(expected, &batches);
+
+        // the replayed writes aren't applied again if they are retried
+        assert!(db.applied_writes.contains(1, 1));
+        assert!(db.applied_writes.contains(1, 2));
+
+        // new writes continue after the persisted sequences and segments
+        assert_eq!(db.next_sequence(), 3);
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=4 30"))
+            .await?;
+        let segment = db.unpersisted_wal_segments().await.pop().unwrap();
+        assert_eq!(segment.id(), 3);
+
+        // snapshot the partition with the replayed write to mem
+        let mem = db.rollover_partition("mem").await?;
+        let sequences = db.closed_chunk_sequences("mem", mem.id()).await;
+        let mut database_path = ObjectStorePath::default();
+        database_path.push_all(&["1", "foo"]);
+        let (metadata_path, data_path) = snapshot::snapshot_paths(&database_path, "mem");
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        snapshot::snapshot_chunk(
+            metadata_path,
+            data_path,
+            Arc::clone(&store),
+            "mem",
+            mem,
+            WriterSequences::max_by_writer(&sequences),
+            snapshot::DEFAULT_ROW_GROUP_SIZE,
+            Some(tx),
+        )?;
+        rx.await?;
+
+        server
+            .write_lines("foo", &parsed_lines("mem bar=5 40"))
+            .await?;
+        server.persist_wal_segments().await;
+
+        let rules = DatabaseRules {
+            read_only_partitions: vec!["1/foo/mem".to_string()],
+            ..rules
+        };
+        server.update_database("foo", rules).await?;
+        server.store_configuration().await?;
+
+        // only the writes to mem that aren't in the snapshot are replayed
+        let server = Server::new(TestConnectionManager::new(), Arc::clone(&store));
+        server.set_id(1).await;
+        assert!(server.load_databases().await?.is_empty());
+        let db = server.db(&DatabaseName::new("foo").unwrap()).await.unwrap();
+
+        let expected = vec![
+            "+-----+------+",
+            "| bar | time |",
+            "+-----+------+",
+            "| 2   | 10   |",
+            "| 5   | 40   |",
+            "+-----+------+",
+        ];
+        let batches = server
+            .query_local(&db, "select * from mem order by time")
+            .await?;
+        assert_table_eq!(expected, &batches);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn queries_read_only_partitions() -> Result {
+        let manager = TestConnectionManager::new();
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let server = Server::new(manager, Arc::clone(&store));
+        server.set_id(1).await;
+
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            store_locally: true,
+            ..Default::default()
+        };
+        server.create_database("source", rules).await?;
+        let lines = parsed_lines("cpu,host=a bar=1 10\ncpu,host=b bar=2 20");
+        server.write_lines("source", &lines).await?;
+
+        // snapshot the partition like the snapshot HTTP route does
+        let source = server
+            .db(&DatabaseName::new("source").unwrap())
+            .await
+            .unwrap();
+        let chunk = source.rollover_partition("cpu").await?;
+        let mut database_path = ObjectStorePath::default();
+        database_path.push("source");
+        let (metadata_path, data_path) = snapshot::snapshot_paths(&database_path, "cpu");
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        snapshot::snapshot_chunk(
+            metadata_path,
+            data_path,
+            store,
+            "cpu",
+            chunk,
+            snapshot::DEFAULT_ROW_GROUP_SIZE,
+            Some(tx),
+        )?;
+        rx.await?;
+
+        let rules = DatabaseRules {
+            read_only_partitions: vec!["source/cpu".to_string()],
+            ..Default::default()
+        };
+        server.create_database("historical", rules).await?;
+        let db = server
+            .db(&DatabaseName::new("historical").unwrap())
+            .await
+            .unwrap();
+
+        let expected = vec![
+            "+-----+------+------+",
+            "| bar | host | time |",
+            "+-----+------+------+",
+            "| 1   | a    | 10   |",
+            "| 2   | b    | 20   |",
+            "+-----+------+------+",
+        ];
+        let batches = server.query_local(&db, "select * from cpu").await?;
+        assert_table_eq!(expected, &batches);
+
+        // the storage gRPC API plans its queries through the Database trait
+        let plan = db.tag_column_names(Predicate::default()).await?;
+        let tag_names = server.executor().to_string_set(plan).await?;
+        assert_eq!(tag_names.iter().collect::<Vec<_>>(), vec!["host"]);
+
+        // the database doesn't store writes itself
+        let lines = parsed_lines("cpu,host=c bar=3 30");
+        server.write_lines("historical", &lines).await?;
+        let batches = server.query_local(&db, "select * from cpu").await?;
+        assert_table_eq!(expected, &batches);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reports_readiness() -> Result {
+        let server = Server::new(
+            TestConnectionManager::new(),
+            Arc::new(ObjectStore::new_in_memory(InMemory::new())),
+        );
+        assert!(matches!(server.check_ready().await, Err(Error::IdNotSet)));
+
+        // setting the id loads the databases, no configuration is stored for
+        // it yet
+        server.set_id(1).await;
+        assert!(server.databases_loaded());
+        server.check_ready().await?;
+
+        // a store that can't be listed isn't reachable, even though reading
+        // the missing configuration from it looks like there is none
+        let missing = std::env::temp_dir().join(format!("iox-missing-{}", std::process::id()));
+        let server = Server::new(
+            TestConnectionManager::new(),
+            Arc::new(ObjectStore::new_file(object_store::disk::File::new(
+                missing,
+            ))),
+        );
+        server.set_id(1).await;
+        assert!(server.databases_loaded());
+        assert!(matches!(
+            server.check_ready().await,
+            Err(Error::ObjectStoreUnreachable { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn encodes_metrics() -> Result {
+        let server = Server::new(
+            TestConnectionManager::new(),
+            Arc::new(ObjectStore::new_in_memory(InMemory::new())),
+        );
+        server.set_id(1).await;
+        let rules = DatabaseRules {
+            store_locally: true,
+            wal_buffer_config: Some(WalBufferConfig {
+                buffer_size: None,
+                segment_size: Some(1),
+                buffer_rollover: WalBufferRollover::ReturnError,
+            }),
+            ..Default::default()
+        };
+        server.create_database("foo", rules).await?;
+
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=1 10\ncpu bar=2 20"))
+            .await?;
+        server
+            .write_lines("bar", &parsed_lines("cpu bar=1 10"))
+            .await
+            .unwrap_err();
+
+        let counts = server.metrics().writes("foo");
+        assert_eq!(counts.lines, 2);
+        assert!(counts.bytes > 0);
+        assert_eq!(server.metrics().write_errors("database_not_found"), 1);
+
+        let text = server.encode_metrics().await;
+        assert!(text.contains("iox_lines_written_total{db_name=\"foo\"} 2\n"));
+        assert!(text.contains("iox_write_errors_total{kind=\"database_not_found\"} 1\n"));
+        assert!(text.contains("iox_read_buffer_size_bytes{db_name=\"foo\"} 0\n"));
+        assert!(!text.contains("iox_mutable_buffer_size_bytes{db_name=\"foo\"} 0\n"));
+
+        // the write closed the first segment, which isn't persisted yet
+        assert!(text.contains("iox_wal_buffer_segments{db_name=\"foo\"} 2\n"));
+        assert!(text.contains("iox_wal_buffer_unpersisted_segments{db_name=\"foo\"} 1\n"));
+        server.persist_wal_segments().await;
+        let text = server.encode_metrics().await;
+        assert!(text.contains("iox_wal_buffer_unpersisted_segments{db_name=\"foo\"} 0\n"));
+        assert!(text.contains("iox_snapshot_duration_seconds_count 0\n"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_writes_over_memory_limit() -> Result {
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let server = Server::new(TestConnectionManager::new(), store);
+        server.set_id(1).await;
+
+        let mut rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            store_locally: true,
+            ..Default::default()
+        };
+
+        // the limit fits the buffer after the first write, but not the
+        // second write on top of it
+        server.create_database("sizes", rules.clone()).await?;
+        server
+            .write_lines("sizes", &parsed_lines("cpu bar=1 10"))
+            .await?;
+        let sizes = server
+            .db(&DatabaseName::new("sizes").unwrap())
+            .await
+            .unwrap();
+        let write_size = lines_to_replicated_write(1, 1, &parsed_lines("cpu bar=2 20"), &rules)?
+            .data
+            .len();
+        let limit = sizes.memory_used() + write_size - 1;
+
+        rules.lifecycle_rules = Some(LifecycleRules {
+            buffer_size_hard: Some(limit),
+            ..Default::default()
+        });
+        server.create_database("foo", rules).await?;
+
+        // the buffer is empty, so the first write is accepted
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=1 10"))
+            .await?;
+
+        let err = server
+            .write_lines("foo", &parsed_lines("cpu bar=2 20"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::DatabaseOverMemoryLimit { limit: l, .. } if l == limit
+        ));
+
+        // the memory of a write is reserved while it is stored, so concurrent
+        // writes can't exceed the limit together
+        let db = server.db(&DatabaseName::new("foo").unwrap()).await.unwrap();
+        assert!(db.reserve_memory(1, limit).is_ok());
+        let reservation = db.reserve_memory(limit - db.memory_used(), limit).unwrap();
+        assert_eq!(db.reserve_memory(1, limit).unwrap_err(), limit);
+        drop(reservation);
+
+        // writes are accepted again once memory is freed
+        db.rollover_partition("cpu").await.unwrap();
+        db.drop_chunk("cpu", 0).await.unwrap();
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=2 20"))
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn manages_chunk_lifecycle() -> Result {
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let server = Server::new(TestConnectionManager::new(), Arc::clone(&store));
+        server.set_id(1).await;
+
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            store_locally: true,
+            lifecycle_rules: Some(LifecycleRules {
+                mutable_size_threshold: Some(1),
+                persist: true,
+                buffer_size_limit: Some(0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        server.create_database("foo", rules).await?;
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=1 10"))
+            .await?;
+        let db = server.db(&DatabaseName::new("foo").unwrap()).await.unwrap();
+        let mutable_buffer = db.mutable_buffer.clone().unwrap();
+
+        // the open chunk is over the size threshold, so it gets closed
+        server.manage_chunk_lifecycle().await;
+        let closed = mutable_buffer.closed_chunk("cpu", 0).await.unwrap();
+        assert!(closed.time_closed.is_some());
+        assert!(db.persisted_chunks().await.is_empty());
+
+        // then persisted
+        server.manage_chunk_lifecycle().await;
+        let expected: BTreeSet<_> = vec![("cpu".to_string(), 0)].into_iter().collect();
+        assert_eq!(db.persisted_chunks().await, expected);
+        let snapshots = server.snapshots();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(
+            snapshots[0].status().state,
+            snapshot::SnapshotState::Completed
+        );
+
+        // and dropped from memory, as the buffer is over its limit
+        server.manage_chunk_lifecycle().await;
+        assert!(mutable_buffer.closed_chunk("cpu", 0).await.is_none());
+        assert!(db.persisted_chunks().await.is_empty());
+        assert_eq!(mutable_buffer.size(), 0);
+
+        // but its data is still queried, from object storage
+        let expected = vec![
+            "+-----+------+",
+            "| bar | time |",
+            "+-----+------+",
+            "| 1   | 10   |",
+            "+-----+------+",
+        ];
+        let batches = server.query_local(&db, "select * from cpu").await?;
+        assert_table_eq!(expected, &batches);
+        assert_eq!(db.partition_keys().await?, vec!["cpu"]);
+
+        // the persisted chunk can be loaded as a read only partition
+        let mut prefix = ObjectStorePath::default();
+        prefix.push_all(&["1", "foo", "chunks"]);
+        let paths: Vec<_> = store.list(Some(&prefix)).await?.try_concat().await?;
+        let meta_path = paths
+            .iter()
+            .map(|path| store.convert_path(path))
+            .find(|path| path.ends_with("/meta/cpu.json"))
+            .unwrap();
+        let partition_id = meta_path.replace("/meta/cpu.json", "/cpu");
+
+        let rules = DatabaseRules {
+            read_only_partitions: vec![partition_id],
+            ..Default::default()
+        };
+        server.create_database("bar", rules).await?;
+        let db = server.db(&DatabaseName::new("bar").unwrap()).await.unwrap();
+
+        let batches = server.query_local(&db, "select * from cpu").await?;
+        assert_table_eq!(expected, &batches);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn drops_expired_data() -> Result {
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let server = Server::new(TestConnectionManager::new(), Arc::clone(&store));
+        server.set_id(1).await;
+
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            store_locally: true,
+            lifecycle_rules: Some(LifecycleRules {
+                persist: true,
+                ..Default::default()
+            }),
+            retention_seconds: Some(3600),
+            ..Default::default()
+        };
+        let db_name = DatabaseName::new("foo").unwrap();
+        server.create_database("foo", rules).await?;
+
+        let recent = format!("mem bar=1 {}", Utc::now().timestamp_nanos());
+        let lp = format!("cpu bar=1 10\ndisk bar=1 10\n{}", recent);
+        server.write_lines("foo", &parsed_lines(&lp)).await?;
+        let db = server.db(&db_name).await.unwrap();
+        for partition_key in &["cpu", "disk", "mem"] {
+            db.rollover_partition(partition_key).await?;
+        }
+        server.manage_chunk_lifecycle().await;
+        db.load_chunk_to_read_buffer("disk", 0).await?;
+        assert_eq!(db.catalog.lock().await.chunks.len(), 3);
+
+        server.enforce_retention().await;
+
+        // the cpu partition is dropped from the mutable buffer and the disk
+        // chunk from the read buffer
+        let partition_keys = db.partition_keys().await?;
+        assert!(!partition_keys.contains(&"cpu".to_string()));
+        assert!(partition_keys.contains(&"mem".to_string()));
+        assert!(db.read_buffer.chunks("disk").is_empty());
+
+        // only the mem chunk is left in the catalog and object storage
+        let catalog = db.catalog.lock().await.clone();
+        assert_eq!(catalog.chunks.len(), 1);
+        assert_eq!(catalog.chunks[0].partition_key, "mem");
+        let stored = catalog::read_catalog(&store, &database_location(1, &db_name)).await?;
+        assert_eq!(stored, catalog);
+
+        let mem_location =
+            store.convert_path(&catalog.chunks[0].location(&database_location(1, &db_name)));
+        let mut prefix = ObjectStorePath::default();
+        prefix.push_all(&["1", "foo", "chunks"]);
+        let paths: Vec<_> = store.list(Some(&prefix)).await?.try_concat().await?;
+        assert!(!paths.is_empty());
+        for path in paths {
+            assert!(store.convert_path(&path).starts_with(&mem_location));
+        }
+
+        assert_eq!(db.expired_counts.mutable_buffer_partitions(), 1);
+        assert_eq!(db.expired_counts.read_buffer_chunks(), 1);
+        assert_eq!(db.expired_counts.persisted_chunks(), 2);
+
+        // nothing else expires
+        server.enforce_retention().await;
+        assert_eq!(db.expired_counts.persisted_chunks(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recovers_persisted_chunks_from_catalog() -> Result {
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            store_locally: true,
+            wal_buffer_config: Some(WalBufferConfig {
+                buffer_size: Some(1 << 20),
+                segment_size: Some(1),
+                buffer_rollover: WalBufferRollover::ReturnError,
+            }),
+            lifecycle_rules: Some(LifecycleRules {
+                persist: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let db_name = DatabaseName::new("foo").unwrap();
+
+        let server = Server::new(TestConnectionManager::new(), Arc::clone(&store));
+        server.set_id(1).await;
+        server.create_database("foo", rules).await?;
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=1 10"))
+            .await?;
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=2 20"))
+            .await?;
+        let db = server.db(&db_name).await.unwrap();
+        db.rollover_partition("cpu").await?;
+        server.manage_chunk_lifecycle().await;
+        server
+            .write_lines("foo", &parsed_lines("cpu bar=3 30"))
+            .await?;
+        server.persist_wal_segments().await;
+        server.store_configuration().await?;
+
+        // the persisted chunk is in the catalog, with the writes it holds
+        let catalog = catalog::read_catalog(&store, &database_location(1, &db_name)).await?;
+        assert_eq!(catalog.version, 1);
+        assert_eq!(catalog.chunks.len(), 1);
+        let chunk = &catalog.chunks[0];
+        assert_eq!(chunk.partition_key, "cpu");
+        assert_eq!(chunk.time_range, Some(TimeRange { min: 10, max: 20 }));
+        assert_eq!(
+            chunk.sequences,
+            vec![catalog::WriterSequences {
+                writer: 1,
+                min: 1,
+                max: 2
+            }]
+        );
+
+        // a restarted server only replays the write that isn't in the chunk,
+        // and reads the chunk from Parquet when it is queried
+        let server = Server::new(TestConnectionManager::new(), Arc::clone(&store));
+        server.set_id(1).await;
+        assert!(server.load_databases().await?.is_empty());
+        let db = server.db(&db_name).await.unwrap();
+        assert!(db.persisted_chunks().await.is_empty());
+        assert_eq!(*db.catalog.lock().await, catalog);
+
+        let expected = vec![
+            "+-----+------+",
+            "| bar | time |",
+            "+-----+------+",
+            "| 1   | 10   |",
+            "| 2   | 20   |",
+            "| 3   | 30   |",
+            "+-----+------+",
+        ];
+        let batches = server
+            .query_local(&db, "select * from cpu order by time")
+            .await?;
+        assert_table_eq!(expected, &batches);
+
+        // the catalog records the columns of the chunk, so table, tag and
+        // field names are answered without reading it
+        assert_eq!(
+            chunk.columns,
+            Some(vec![TableColumns {
+                table_name: "cpu".to_string(),
+                tags: vec![],
+                fields: vec![catalog::FieldColumn {
+                    name: "bar".to_string(),
+                    field_type: catalog::FieldType::F64,
+                    last_timestamp: 20,
+                }],
+            }])
+        );
+        let (_, data_path) = snapshot::snapshot_paths(
+            &chunk.location(&database_location(1, &db_name)),
+            &chunk.partition_key,
+        );
+        let paths: Vec<_> = store.list(Some(&data_path)).await?.try_concat().await?;
+        assert!(!paths.is_empty());
+        for path in &paths {
+            store.delete(path).await?;
+        }
 
-    type TestError = Box<dyn std::error::Error + Send + Sync + 'static>;
-    type Result<T = (), E = TestError> = std::result::Result<T, E>;
+        let plan = db.table_names(Predicate::default()).await?;
+        let table_names = server.executor().to_string_set(plan).await?;
+        assert_eq!(table_names.iter().collect::<Vec<_>>(), vec!["cpu"]);
+        let plan = db.field_column_names(Predicate::default()).await?;
+        let fields = server.executor().to_fieldlist(plan).await?;
+        assert_eq!(fields.fields.len(), 1);
+        assert_eq!(fields.fields[0].name, "bar");
+        assert_eq!(fields.fields[0].last_timestamp, 30);
+
+        Ok(())
+    }
 
     #[tokio::test]
-    async fn server_api_calls_return_error_with_no_id_set() -> Result {
-        let manager = TestConnectionManager::new();
+    async fn queries_chunks_loaded_to_read_buffer() -> Result {
         let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
-        let mut server = Server::new(manager, store);
+        let server = Server::new(TestConnectionManager::new(), store);
+        server.set_id(1).await;
 
-        let rules = DatabaseRules::default();
-        let resp = server.create_database("foo", rules).await.unwrap_err();
-        assert!(matches!(resp, Error::IdNotSet));
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            store_locally: true,
+            ..Default::default()
+        };
+        server.create_database("foo", rules).await?;
+        let lines = parsed_lines(
+            "cpu,region=west user=23.5,active=2i 10\ncpu,region=east user=21.5,active=4i 20",
+        );
+        server.write_lines("foo", &lines).await?;
+        let db = server.db(&DatabaseName::new("foo").unwrap()).await.unwrap();
+
+        let chunk = db.rollover_partition("cpu").await.unwrap();
+        let chunk = db
+            .load_chunk_to_read_buffer("cpu", chunk.id())
+            .await
+            .unwrap();
+        assert!(matches!(chunk.as_ref(), DBChunk::ReadBuffer(_)));
+        assert_eq!(chunk.table_stats().unwrap()[0].name, "cpu");
+        assert!(db
+            .mutable_buffer
+            .as_ref()
+            .unwrap()
+            .closed_chunk("cpu", 0)
+            .await
+            .is_none());
 
-        let lines = parsed_lines("cpu foo=1 10");
-        let resp = server.write_lines("foo", &lines).await.unwrap_err();
-        assert!(matches!(resp, Error::IdNotSet));
+        // the read buffer chunk is queried together with the open chunk
+        server
+            .write_lines(
+                "foo",
+                &parsed_lines("cpu,region=south user=22.5,active=6i 30"),
+            )
+            .await?;
 
-        let resp = server
-            .create_host_group("group1".to_string(), vec!["serverA".to_string()])
-            .await
-            .unwrap_err();
-        assert!(matches!(resp, Error::IdNotSet));
+        let expected = vec![
+            "+--------+--------+------+------+",
+            "| active | region | time | user |",
+            "+--------+--------+------+------+",
+            "| 2      | west   | 10   | 23.5 |",
+            "| 4      | east   | 20   | 21.5 |",
+            "| 6      | south  | 30   | 22.5 |",
+            "+--------+--------+------+------+",
+        ];
+        let batches = server
+            .query_local(&db, "select * from cpu order by time")
+            .await?;
+        assert_table_eq!(expected, &batches);
+
+        // only closed chunks can be loaded
+        let err = db.load_chunk_to_read_buffer("cpu", 1).await.unwrap_err();
+        assert!(matches!(err, db::Error::ClosedChunkNotFound { .. }));
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn database_name_validation() -> Result {
+    async fn create_database_with_missing_read_only_partition() -> Result {
         let manager = TestConnectionManager::new();
         let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
         let server = Server::new(manager, store);
         server.set_id(1).await;
 
-        let reject: [&str; 5] = [
-            "bananas!",
-            r#""bananas\"are\"great"#,
-            "bananas:good",
-            "bananas/cavendish",
-            "bananas\n",
-        ];
+        let rules = DatabaseRules {
+            read_only_partitions: vec!["source/cpu".to_string()],
+            ..Default::default()
+        };
+        let err = server
+            .create_database("historical", rules)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::LoadingReadOnlyPartition { .. }));
 
-        for &name in &reject {
-            let rules = DatabaseRules {
-                store_locally: true,
-                ..Default::default()
-            };
-            let got = server.create_database(name, rules).await.unwrap_err();
-            if !matches!(got, Error::InvalidDatabaseName { .. }) {
-                panic!("expected invalid name error");
-            }
-        }
+        let db_name = DatabaseName::new("historical").unwrap();
+        assert!(server.db(&db_name).await.is_none());
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn writes_local() -> Result {
+    async fn persists_closed_wal_segments() -> Result {
         let manager = TestConnectionManager::new();
         let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
-        let server = Server::new(manager, store);
+        let server = Server::new(manager, Arc::clone(&store));
         server.set_id(1).await;
+
+        // every write closes a segment and the buffer holds two of them
+        let lines = parsed_lines("cpu bar=1 10");
+        let write_size = lines_to_replicated_write(1, 1, &lines, &DatabaseRules::default())?
+            .data
+            .len() as u64;
         let rules = DatabaseRules {
             store_locally: true,
+            wal_buffer_config: Some(WalBufferConfig {
+                buffer_size: Some(2 * write_size),
+                segment_size: Some(1),
+                buffer_rollover: WalBufferRollover::ReturnError,
+            }),
             ..Default::default()
         };
         server.create_database("foo", rules).await?;
 
-        let line = "cpu bar=1 10";
-        let lines: Vec<_> = parse_lines(line).map(|l| l.unwrap()).collect();
-        server.write_lines("foo", &lines).await.unwrap();
+        server.write_lines("foo", &lines).await?;
+        server.write_lines("foo", &lines).await?;
+        assert!(server.write_lines("foo", &lines).await.is_err());
 
-        let db_name = DatabaseName::new("foo").unwrap();
-        let db = server.db(&db_name).await.unwrap();
+        server.persist_wal_segments().await;
 
-        let buff = db.mutable_buffer.as_ref().unwrap();
+        let db = server.db(&DatabaseName::new("foo").unwrap()).await.unwrap();
+        assert!(db.unpersisted_wal_segments().await.is_empty());
 
-        let planner = SQLQueryPlanner::default();
-        let executor = server.executor();
-        let physical_plan = planner
-            .query(buff.as_ref(), "select * from cpu", executor.as_ref())
-            .await
-            .unwrap();
+        let mut location = ObjectStorePath::default();
+        location.push_all(&["1", "foo", "wal", "00000000000000000001.segment"]);
+        let data = store
+            .get(&location)
+            .await?
+            .map_ok(|b| bytes::BytesMut::from(&b[..]))
+            .try_concat()
+            .await?;
+        let segment = flatbuffers::get_root::<generated_types::wal::Segment<'_>>(&data);
+        assert_eq!(segment.number(), 1);
+        assert_eq!(segment.writer_id(), 1);
+        assert_eq!(segment.writes().unwrap().len(), 1);
 
-        let batches = collect(physical_plan).await.unwrap();
-        let expected = vec![
-            "+-----+------+",
-            "| bar | time |",
-            "+-----+------+",
-            "| 1   | 10   |",
-            "+-----+------+",
-        ];
-        assert_table_eq!(expected, &batches);
+        // the persisted segments can be dropped to make room for new writes
+        server.write_lines("foo", &lines).await?;
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn replicate_to_single_group() -> Result {
-        let mut manager = TestConnectionManager::new();
-        let remote = Arc::new(TestRemoteServer::default());
-        let remote_id = "serverA";
-        manager
-            .remotes
-            .insert(remote_id.to_string(), remote.clone());
-
+    async fn replays_deletes_in_the_order_they_were_applied() -> Result {
         let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
-
-        let mut server = Server::new(manager, store);
-        server.set_id(1).await;
-        let host_group_id = "az1".to_string();
         let rules = DatabaseRules {
-            replication: vec![host_group_id.clone()],
-            replication_count: 1,
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            store_locally: true,
+            wal_buffer_config: Some(WalBufferConfig {
+                buffer_size: Some(1 << 20),
+                segment_size: Some(1),
+                buffer_rollover: WalBufferRollover::ReturnError,
+            }),
             ..Default::default()
         };
+        let db_name = DatabaseName::new("foo").unwrap();
+
+        let server = Server::new(TestConnectionManager::new(), Arc::clone(&store));
+        server.set_id(1).await;
+        server.create_database("foo", rules.clone()).await?;
+        let db = server.db(&db_name).await.unwrap();
+
+        // a write from another writer with a larger sequence than the delete
+        // that is applied after it
+        let write =
+            lines_to_replicated_write(2, 100, &parsed_lines("cpu,host=a bar=1 10"), &rules)?;
+        server.handle_replicated_write(&db_name, &db, write).await?;
+        let delete = data_types::delete::Delete {
+            table_name: Some("cpu".to_string()),
+            start: 0,
+            stop: 15,
+            predicate: None,
+        };
+        server.delete("foo", &delete).await?;
         server
-            .create_host_group(host_group_id.clone(), vec![remote_id.to_string()])
-            .await
-            .unwrap();
-        let db_name = "foo";
-        server.create_database(db_name, rules).await.unwrap();
+            .write_lines("foo", &parsed_lines("cpu,host=b bar=2 20"))
+            .await?;
 
-        let lines = parsed_lines("cpu bar=1 10");
-        server.write_lines("foo", &lines).await.unwrap();
+        // the storage gRPC API doesn't see the deleted rows either
+        let plan = db.column_values("host", Predicate::default()).await?;
+        let hosts = server.executor().to_string_set(plan).await?;
+        assert_eq!(hosts.iter().collect::<Vec<_>>(), vec!["b"]);
 
-        let writes = remote.writes.lock().unwrap().get(db_name).unwrap().clone();
+        server.persist_wal_segments().await;
+        server.store_configuration().await?;
 
-        let write_text = r#"
-writer:1, sequence:1, checksum:226387645
-partition_key:
-  table:cpu
-    bar:1 time:10
-"#;
+        let server = Server::new(TestConnectionManager::new(), Arc::clone(&store));
+        server.set_id(1).await;
+        assert!(server.load_databases().await?.is_empty());
+        let db = server.db(&db_name).await.unwrap();
 
-        assert_eq!(write_text, writes[0].to_string());
+        let expected = vec![
+            "+-----+------+------+",
+            "| bar | host | time |",
+            "+-----+------+------+",
+            "| 2   | b    | 20   |",
+            "+-----+------+------+",
+        ];
+        let batches = server
+            .query_local(&db, "select * from cpu order by time")
+            .await?;
+        assert_table_eq!(expected, &batches);
 
-        // ensure sequence number goes up
-        let lines = parsed_lines("mem,server=A,region=west user=232 12");
-        server.write_lines("foo", &lines).await.unwrap();
+        Ok(())
+    }
 
-        let writes = remote.writes.lock().unwrap().get(db_name).unwrap().clone();
-        assert_eq!(2, writes.len());
+    #[tokio::test]
+    async fn removes_deleted_rows_from_chunks_in_memory() -> Result {
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            store_locally: true,
+            ..Default::default()
+        };
+        let db_name = DatabaseName::new("foo").unwrap();
 
-        let write_text = r#"
-writer:1, sequence:2, checksum:3759030699
-partition_key:
-  table:mem
-    server:A region:west user:232 time:12
-"#;
+        let server = Server::new(
+            TestConnectionManager::new(),
+            Arc::new(ObjectStore::new_in_memory(InMemory::new())),
+        );
+        server.set_id(1).await;
+        server.create_database("foo", rules).await?;
+        let db = server.db(&db_name).await.unwrap();
 
-        assert_eq!(write_text, writes[1].to_string());
+        server
+            .write_lines(
+                "foo",
+                &parsed_lines("cpu,host=a bar=1 10\ncpu,host=b bar=2 20"),
+            )
+            .await?;
+        let chunk = db.rollover_partition("cpu").await?;
+        db.load_chunk_to_read_buffer("cpu", chunk.id()).await?;
+        server
+            .write_lines(
+                "foo",
+                &parsed_lines("cpu,host=a bar=3 30\ncpu,host=c bar=4 40"),
+            )
+            .await?;
+
+        let delete = data_types::delete::Delete {
+            table_name: Some("cpu".to_string()),
+            start: 0,
+            stop: 35,
+            predicate: Some("host = 'a'".parse().unwrap()),
+        };
+        server.delete("foo", &delete).await?;
+
+        // the read buffer chunk is replaced by one with the same id
+        let summaries = db.read_buffer_chunk_summaries().await;
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].id, chunk.id());
+
+        let mut rows = 0;
+        for &chunk_id in &[chunk.id(), chunk.id() + 1] {
+            let chunk = db.closed_chunk("cpu", chunk_id).await.unwrap();
+            let mut batches = vec![];
+            chunk.table_to_arrow(&mut batches, "cpu", &[])?;
+            rows += batches.iter().map(|batch| batch.num_rows()).sum::<usize>();
+        }
+        assert_eq!(rows, 2);
+
+        let plan = db.column_values("host", Predicate::default()).await?;
+        let hosts = server.executor().to_string_set(plan).await?;
+        assert_eq!(hosts.iter().collect::<Vec<_>>(), vec!["b", "c"]);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn sends_all_to_subscriber() -> Result {
-        let mut manager = TestConnectionManager::new();
-        let remote = Arc::new(TestRemoteServer::default());
-        let remote_id = "serverA";
-        manager
-            .remotes
-            .insert(remote_id.to_string(), remote.clone());
-
+    async fn loads_stored_databases() -> Result {
         let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
 
-        let mut server = Server::new(manager, store);
+        let server = Server::new(TestConnectionManager::new(), Arc::clone(&store));
         server.set_id(1).await;
-        let host_group_id = "az1".to_string();
+
+        // nothing has been stored for this id yet
+        assert!(server.load_databases().await?.is_empty());
+
         let rules = DatabaseRules {
-            subscriptions: vec![Subscription {
-                name: "query_server_1".to_string(),
-                host_group_id: host_group_id.clone(),
-                matcher: Matcher {
-                    tables: MatchTables::All,
-                    predicate: None,
-                },
-            }],
+            store_locally: true,
+            wal_buffer_config: Some(WalBufferConfig {
+                buffer_size: Some(1 << 20),
+                segment_size: Some(1),
+                buffer_rollover: WalBufferRollover::ReturnError,
+            }),
             ..Default::default()
         };
+        server.create_database("foo", rules).await?;
         server
-            .create_host_group(host_group_id.clone(), vec![remote_id.to_string()])
-            .await
-            .unwrap();
-        let db_name = "foo";
-        server.create_database(db_name, rules).await.unwrap();
+            .write_lines("foo", &parsed_lines("cpu bar=1 10"))
+            .await?;
+        server.persist_wal_segments().await;
 
-        let lines = parsed_lines("cpu bar=1 10");
-        server.write_lines("foo", &lines).await.unwrap();
+        {
+            let mut config = server.config.write().await;
+            config.insert_host_group(HostGroup {
+                id: "az1".to_string(),
+                hosts: vec!["serverA".to_string()],
+            });
+        }
+        let rules = DatabaseRules {
+            replication: vec!["az1".to_string()],
+            ..Default::default()
+        };
+        server.create_database("bar", rules).await?;
+        server.store_configuration().await?;
 
-        let writes = remote.writes.lock().unwrap().get(db_name).unwrap().clone();
+        // a database that can't be loaded anymore
+        {
+            let mut config = server.config.write().await;
+            let rules = DatabaseRules {
+                read_only_partitions: vec!["missing/cpu".to_string()],
+                ..Default::default()
+            };
+            let db = Db::new(
+                rules,
+                None,
+                Arc::new(ReadBufferDb::new()),
+                None,
+                AtomicU64::new(STARTING_SEQUENCE),
+            )?;
+            config
+                .databases
+                .insert(DatabaseName::new("broken").unwrap(), Arc::new(db));
+        }
+        server.store_configuration().await?;
 
-        let write_text = r#"
-writer:1, sequence:1, checksum:226387645
-partition_key:
-  table:cpu
-    bar:1 time:10
-"#;
+        let restarted = Server::new(TestConnectionManager::new(), Arc::clone(&store));
+        restarted.set_id(1).await;
 
-        assert_eq!(write_text, writes[0].to_string());
+        // setting the id loads the databases that can be loaded
+        assert!(restarted.databases_loaded());
+        let loaded = restarted
+            .db(&DatabaseName::new("foo").unwrap())
+            .await
+            .unwrap();
 
-        // ensure sequence number goes up
-        let lines = parsed_lines("mem,server=A,region=west user=232 12");
-        server.write_lines("foo", &lines).await.unwrap();
+        let errors = restarted.load_databases().await?;
 
-        let writes = remote.writes.lock().unwrap().get(db_name).unwrap().clone();
-        assert_eq!(2, writes.len());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "broken");
+        assert!(matches!(errors[0].1, Error::LoadingReadOnlyPartition { .. }));
 
-        let write_text = r#"
-writer:1, sequence:2, checksum:3759030699
-partition_key:
-  table:mem
-    server:A region:west user:232 time:12
-"#;
+        let foo = restarted
+            .db(&DatabaseName::new("foo").unwrap())
+            .await
+            .unwrap();
+        let expected = vec![
+            "+-----+------+",
+            "| bar | time |",
+            "+-----+------+",
+            "| 1   | 10   |",
+            "+-----+------+",
+        ];
+        let batches = restarted.query_local(&foo, "select * from cpu").await?;
+        assert_table_eq!(expected, &batches);
 
-        assert_eq!(write_text, writes[1].to_string());
+        // the database that was loaded already isn't loaded again
+        assert!(Arc::ptr_eq(&loaded, &foo));
+
+        assert!(restarted
+            .db(&DatabaseName::new("bar").unwrap())
+            .await
+            .is_some());
+        let config = restarted.config.read().await;
+        assert!(config.host_groups.contains_key("az1"));
+        assert!(config.host_group_rings.contains_key("az1"));
 
         Ok(())
     }
@@ -690,7 +4424,7 @@ partition_key:
             .await
             .unwrap();
 
-        let config = r#"{"databases":{"foo":{"partition_template":{"parts":[]},"store_locally":false,"replication":["az1"],"replication_count":1,"replication_queue_max_size":0,"subscriptions":[],"query_local":false,"primary_query_group":null,"secondary_query_groups":[],"read_only_partitions":[],"wal_buffer_config":null}},"host_groups":{"az1":{"id":"az1","hosts":["serverA"]}}}"#;
+        let config = r#"{"databases":{"foo":{"partition_template":{"parts":[]},"store_locally":false,"replication":["az1"],"replication_count":1,"replication_queue_max_size":0,"subscriptions":[],"query_local":false,"primary_query_group":null,"secondary_query_groups":[],"read_only_partitions":[],"wal_buffer_config":null,"lifecycle_rules":null,"retention_seconds":null}},"host_groups":{"az1":{"id":"az1","hosts":["serverA"]}}}"#;
         let read_data = std::str::from_utf8(&*read_data).unwrap();
         println!("\n\n{}\n", read_data);
         assert_eq!(read_data, config);
@@ -749,6 +4483,21 @@ partition_key:
     #[derive(Debug, Default)]
     struct TestRemoteServer {
         writes: Mutex<BTreeMap<String, Vec<ReplicatedWrite>>>,
+        unavailable: AtomicBool,
+        query_results: Mutex<Vec<RecordBatch>>,
+        // replication waits while the write lock of this is held
+        paused: tokio::sync::RwLock<()>,
+        waiting_replications: AtomicUsize,
+    }
+
+    impl TestRemoteServer {
+        fn set_available(&self, available: bool) {
+            self.unavailable.store(!available, Ordering::SeqCst);
+        }
+
+        fn write_count(&self, db: &str) -> usize {
+            self.writes.lock().unwrap().get(db).map_or(0, Vec::len)
+        }
     }
 
     #[async_trait]
@@ -760,12 +4509,34 @@ partition_key:
             db: &str,
             replicated_write: &ReplicatedWrite,
         ) -> Result<(), Self::Error> {
+            if self.unavailable.load(Ordering::SeqCst) {
+                return General {
+                    message: "remote unavailable",
+                }
+                .fail();
+            }
+
+            self.waiting_replications.fetch_add(1, Ordering::SeqCst);
+            let _ = self.paused.read().await;
+            self.waiting_replications.fetch_sub(1, Ordering::SeqCst);
+
             let mut writes = self.writes.lock().unwrap();
             let entries = writes.entry(db.to_string()).or_insert_with(Vec::new);
             entries.push(replicated_write.clone());
 
             Ok(())
         }
+
+        async fn query(&self, _db: &str, _sql: &str) -> Result<Vec<RecordBatch>, Self::Error> {
+            if self.unavailable.load(Ordering::SeqCst) {
+                return General {
+                    message: "remote unavailable",
+                }
+                .fail();
+            }
+
+            Ok(self.query_results.lock().unwrap().clone())
+        }
     }
 
     fn parsed_lines(lp: &str) -> Vec<ParsedLine<'_>> {
@@ -1,18 +1,46 @@
 //! This module contains code for snapshotting a database chunk to Parquet
-//! files in object storage.
+//! files in object storage, and for loading those snapshots back.
 use arrow_deps::{
-    arrow::record_batch::RecordBatch,
-    parquet::{self, arrow::ArrowWriter, file::writer::TryClone},
+    arrow::{
+        self,
+        array::UInt32Array,
+        compute::kernels::take::take,
+        datatypes::{Schema, SchemaRef},
+        record_batch::RecordBatch,
+    },
+    parquet::{
+        self,
+        arrow::{
+            arrow_reader::{ArrowReader, ParquetFileArrowReader},
+            ArrowWriter,
+        },
+        file::{
+            metadata::KeyValue,
+            properties::WriterProperties,
+            reader::{FileReader, SerializedFileReader},
+            serialized_reader::SliceableCursor,
+            writer::TryClone,
+        },
+    },
+};
+use data_types::{
+    partition_metadata::{Partition as PartitionMeta, Table},
+    COLUMN_TYPE_METADATA_PREFIX,
 };
-use data_types::partition_metadata::{Partition as PartitionMeta, Table};
 use object_store::{path::ObjectStorePath, ObjectStore};
 use query::PartitionChunk;
 
-use std::io::{Cursor, Seek, SeekFrom, Write};
+use crate::metrics::Histogram;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Seek, SeekFrom, Write};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
-use snafu::{ResultExt, Snafu};
+use futures::{channel::mpsc, SinkExt, TryStreamExt};
+use serde::Serialize;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
 use tokio::sync::oneshot;
 use tracing::{error, info};
 use uuid::Uuid;
@@ -40,6 +68,18 @@ pub enum Error {
         source: parquet::errors::ParquetError,
     },
 
+    #[snafu(display(
+        "Row groups of table {} have different schemas, which one Parquet file can't store",
+        table_name
+    ))]
+    SchemaMismatch { table_name: String },
+
+    #[snafu(display("Error splitting record batch into row groups: {}", source))]
+    SlicingRecordBatch { source: arrow::error::ArrowError },
+
+    #[snafu(display("Error joining Parquet encoding task: {}", source))]
+    EncodingTaskFailed { source: tokio::task::JoinError },
+
     #[snafu(display("Error closing Parquet Writer: {}", source))]
     ClosingParquetWriter {
         source: parquet::errors::ParquetError,
@@ -50,10 +90,45 @@ pub enum Error {
 
     #[snafu(display("Stopped early"))]
     StoppedEarly,
+
+    #[snafu(display(
+        "Invalid partition id '{}': expected <database path>/<partition key>",
+        partition_id
+    ))]
+    InvalidPartitionId { partition_id: String },
+
+    #[snafu(display("Error reading from object store: {}", source))]
+    ReadingFromObjectStore { source: object_store::Error },
+
+    #[snafu(display("Error parsing partition metadata: {}", source))]
+    ParsingPartitionMetadata { source: serde_json::Error },
+
+    #[snafu(display("Error opening Parquet file for table {}: {}", table_name, source))]
+    OpeningParquetReader {
+        table_name: String,
+        source: parquet::errors::ParquetError,
+    },
+
+    #[snafu(display("Error reading Parquet file for table {}: {}", table_name, source))]
+    ReadingParquet {
+        table_name: String,
+        source: arrow::error::ArrowError,
+    },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// The number of rows in each record batch read from a Parquet file
+const PARQUET_READ_BATCH_SIZE: usize = 8192;
+
+/// The default maximum number of rows in each row group of the Parquet files
+/// written by a snapshot. The encoded bytes of one row group are all a
+/// snapshot holds in memory before uploading them.
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 65_536;
+
+/// The number of finished snapshots a `SnapshotRegistry` keeps track of
+const MAX_FINISHED_SNAPSHOTS: usize = 100;
+
 #[derive(Debug)]
 pub struct Snapshot<T>
 where
@@ -65,6 +140,8 @@ where
     pub data_path: ObjectStorePath,
     store: Arc<ObjectStore>,
     partition: Arc<T>,
+    row_group_size: usize,
+    started: Instant,
     status: Mutex<Status>,
 }
 
@@ -79,6 +156,8 @@ where
         store: Arc<ObjectStore>,
         partition: Arc<T>,
         tables: Vec<Table>,
+        max_sequences: BTreeMap<u32, u64>,
+        row_group_size: usize,
     ) -> Self {
         let table_states = vec![TableState::NotStarted; tables.len()];
 
@@ -92,11 +171,14 @@ where
             partition_meta: PartitionMeta {
                 key: partition_key.into(),
                 tables,
+                max_sequences,
             },
             metadata_path,
             data_path,
             store,
             partition,
+            row_group_size,
+            started: Instant::now(),
             status: Mutex::new(status),
         }
     }
@@ -130,6 +212,7 @@ where
     fn mark_meta_written(&self) {
         let mut status = self.status.lock().expect("mutex poisoned");
         status.meta_written = true;
+        status.duration = Some(self.started.elapsed());
     }
 
     pub fn finished(&self) -> bool {
@@ -146,18 +229,59 @@ where
         status.stop_on_next_update
     }
 
+    /// Asks the snapshot to stop before it writes its next table or the
+    /// partition metadata. Returns false if the snapshot isn't running.
+    pub fn cancel(&self) -> bool {
+        let mut status = self.status.lock().expect("mutex poisoned");
+        if status.state() != SnapshotState::Running {
+            return false;
+        }
+
+        status.stop_on_next_update = true;
+        true
+    }
+
+    /// Returns true until the snapshot has completed, failed or been
+    /// cancelled
+    pub fn is_running(&self) -> bool {
+        let status = self.status.lock().expect("mutex poisoned");
+        status.state() == SnapshotState::Running
+    }
+
+    /// Returns the progress of the snapshot
+    pub fn status(&self) -> SnapshotStatus {
+        let status = self.status.lock().expect("mutex poisoned");
+
+        SnapshotStatus {
+            id: self.id,
+            partition_key: self.partition_meta.key.clone(),
+            location: self.data_path(),
+            state: status.state(),
+            tables: self
+                .partition_meta
+                .tables
+                .iter()
+                .zip(&status.table_states)
+                .map(|(table, state)| TableStatus {
+                    name: table.name.clone(),
+                    state: state.clone(),
+                })
+                .collect(),
+            meta_written: status.meta_written,
+            error: status.error.as_ref().map(ToString::to_string),
+        }
+    }
+
     async fn run(&self, notify: Option<oneshot::Sender<()>>) -> Result<()> {
-        while let Some((pos, table_name)) = self.next_table() {
-            let mut batches = Vec::new();
-            self.partition
-                .table_to_arrow(&mut batches, table_name, &[])
-                .map_err(|e| Box::new(e) as _)
-                .context(PartitionError)?;
+        if self.should_stop() {
+            return StoppedEarly.fail();
+        }
 
+        while let Some((pos, table_name)) = self.next_table() {
             let mut location = self.data_path.clone();
             let file_name = format!("{}.parquet", table_name);
             location.push(&file_name);
-            self.write_batches(batches, &location).await?;
+            self.write_table(table_name, &location).await?;
             self.mark_table_finished(pos);
 
             if self.should_stop() {
@@ -192,46 +316,51 @@ where
         Ok(())
     }
 
-    async fn write_batches(
-        &self,
-        batches: Vec<RecordBatch>,
-        file_name: &ObjectStorePath,
-    ) -> Result<()> {
-        let mem_writer = MemWriter::default();
-        {
-            let mut writer = ArrowWriter::try_new(mem_writer.clone(), batches[0].schema(), None)
-                .context(OpeningParquetWriter)?;
-            for batch in batches.into_iter() {
-                writer.write(&batch).context(WritingParquetToMemory)?;
+    // Converts the table to Arrow and encodes it to Parquet on a blocking
+    // thread, one row group of the chunk at a time, and uploads the encoded
+    // bytes as they are produced so that only a row group's worth of the
+    // table is held in memory.
+    async fn write_table(&self, table_name: &str, file_name: &ObjectStorePath) -> Result<()> {
+        let (mut tx, rx) = mpsc::channel(1);
+        let partition = Arc::clone(&self.partition);
+        let table_name = table_name.to_string();
+        let row_group_size = self.row_group_size;
+
+        let encoder = tokio::task::spawn_blocking(move || {
+            let result = encode_parquet(&*partition, &table_name, row_group_size, &mut tx);
+            if let Err(e) = &result {
+                // make the upload fail rather than leave a truncated file
+                let e = std::io::Error::new(std::io::ErrorKind::Other, e.to_string());
+                let _ = futures::executor::block_on(tx.send(Err(e)));
             }
-            writer.close().context(ClosingParquetWriter)?;
-        } // drop the reference to the MemWriter that the SerializedFileWriter has
+            result
+        });
 
-        let data = mem_writer
-            .into_inner()
-            .expect("Nothing else should have a reference here");
-
-        let len = data.len();
-        let data = Bytes::from(data);
-        let stream_data = Result::Ok(data);
-
-        self.store
-            .put(
-                &file_name,
-                futures::stream::once(async move { stream_data }),
-                len,
-            )
+        let uploaded = self
+            .store
+            .put_multipart(&file_name, rx)
             .await
-            .context(WritingToObjectStore)
+            .context(WritingToObjectStore);
+
+        encoder.await.context(EncodingTaskFailed)??;
+        uploaded
     }
 
     fn set_error(&self, e: Error) {
         let mut status = self.status.lock().expect("mutex poisoned");
         status.error = Some(e);
+        status.duration = Some(self.started.elapsed());
+    }
+
+    // returns how long the snapshot ran, only the first time it is called
+    // after the snapshot finished
+    fn take_duration(&self) -> Option<Duration> {
+        self.status.lock().expect("mutex poisoned").duration.take()
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TableState {
     NotStarted,
     Running,
@@ -244,14 +373,135 @@ pub struct Status {
     meta_written: bool,
     stop_on_next_update: bool,
     error: Option<Error>,
+    /// How long the snapshot ran, until the registry observes it
+    duration: Option<Duration>,
+}
+
+impl Status {
+    fn state(&self) -> SnapshotState {
+        match &self.error {
+            Some(Error::StoppedEarly) => SnapshotState::Cancelled,
+            Some(_) => SnapshotState::Failed,
+            None if self.meta_written => SnapshotState::Completed,
+            None => SnapshotState::Running,
+        }
+    }
+}
+
+/// The overall state of a snapshot
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotState {
+    Running,
+    /// All tables and the partition metadata have been written
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// The progress of a snapshot, as reported to operators
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct SnapshotStatus {
+    pub id: Uuid,
+    pub partition_key: String,
+    /// Where the Parquet files of the tables are written
+    pub location: String,
+    pub state: SnapshotState,
+    pub tables: Vec<TableStatus>,
+    pub meta_written: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct TableStatus {
+    pub name: String,
+    pub state: TableState,
+}
+
+/// Keeps track of the running snapshots and of the most recently finished
+/// ones, in the order they were started
+#[derive(Debug)]
+pub struct SnapshotRegistry<T>
+where
+    T: Send + Sync + 'static + PartitionChunk,
+{
+    snapshots: Mutex<Vec<Arc<Snapshot<T>>>>,
+    /// The durations of the finished snapshots, in seconds
+    durations: Mutex<Histogram>,
+}
+
+impl<T> Default for SnapshotRegistry<T>
+where
+    T: Send + Sync + 'static + PartitionChunk,
+{
+    fn default() -> Self {
+        Self {
+            snapshots: Mutex::new(vec![]),
+            durations: Mutex::default(),
+        }
+    }
+}
+
+impl<T> SnapshotRegistry<T>
+where
+    T: Send + Sync + 'static + PartitionChunk,
+{
+    /// Adds the snapshot to the registry, forgetting the oldest finished
+    /// snapshots once there are more than `MAX_FINISHED_SNAPSHOTS` of them
+    pub fn register(&self, snapshot: Arc<Snapshot<T>>) {
+        let mut snapshots = self.snapshots.lock().expect("mutex poisoned");
+        snapshots.push(snapshot);
+
+        // finished snapshots are observed before they are forgotten
+        self.observe_durations(&snapshots);
+        let finished = snapshots.iter().filter(|s| !s.is_running()).count();
+        let mut excess = finished.saturating_sub(MAX_FINISHED_SNAPSHOTS);
+        snapshots.retain(|s| {
+            if excess > 0 && !s.is_running() {
+                excess -= 1;
+                return false;
+            }
+            true
+        });
+    }
+
+    /// Returns the snapshot with the specified id, if it is known
+    pub fn get(&self, id: Uuid) -> Option<Arc<Snapshot<T>>> {
+        let snapshots = self.snapshots.lock().expect("mutex poisoned");
+        snapshots.iter().find(|s| s.id == id).cloned()
+    }
+
+    /// Returns all known snapshots, oldest first
+    pub fn list(&self) -> Vec<Arc<Snapshot<T>>> {
+        self.snapshots.lock().expect("mutex poisoned").clone()
+    }
+
+    /// Returns the histogram of how long the registered snapshots ran
+    pub fn durations(&self) -> Histogram {
+        let snapshots = self.snapshots.lock().expect("mutex poisoned");
+        self.observe_durations(&snapshots);
+        self.durations.lock().expect("mutex poisoned").clone()
+    }
+
+    fn observe_durations(&self, snapshots: &[Arc<Snapshot<T>>]) {
+        let mut durations = self.durations.lock().expect("mutex poisoned");
+        for duration in snapshots.iter().filter_map(|s| s.take_duration()) {
+            durations.observe_duration(duration);
+        }
+    }
 }
 
+/// Starts writing the chunk to object storage in the background. The
+/// partition metadata records `max_sequences`, the largest sequence of the
+/// writes in the chunk from each writer.
 pub fn snapshot_chunk<T>(
     metadata_path: ObjectStorePath,
     data_path: ObjectStorePath,
     store: Arc<ObjectStore>,
     partition_key: &str,
     chunk: Arc<T>,
+    max_sequences: BTreeMap<u32, u64>,
+    row_group_size: usize,
     notify: Option<oneshot::Sender<()>>,
 ) -> Result<Arc<Snapshot<T>>>
 where
@@ -269,6 +519,8 @@ where
         store,
         chunk,
         table_stats,
+        max_sequences,
+        row_group_size,
     );
     let snapshot = Arc::new(snapshot);
 
@@ -289,46 +541,303 @@ where
     Ok(return_snapshot)
 }
 
+/// Returns the paths of the directory for partition metadata and of the
+/// directory for the Parquet files of the partition `partition_key`, for a
+/// database whose snapshots are stored under `database_path`.
+pub fn snapshot_paths(
+    database_path: &ObjectStorePath,
+    partition_key: &str,
+) -> (ObjectStorePath, ObjectStorePath) {
+    let mut metadata_path = database_path.clone();
+    let mut data_path = database_path.clone();
+    metadata_path.push("meta");
+    data_path.push_all(&["data", partition_key]);
+
+    (metadata_path, data_path)
+}
+
+/// Splits a `PartitionId` of the form `<database path>/<partition key>` into
+/// the path the database's snapshots are stored under and the partition key.
+pub fn parse_partition_id(partition_id: &str) -> Result<(ObjectStorePath, String)> {
+    let trimmed = partition_id.trim_matches('/');
+    let mut parts = trimmed.rsplitn(2, '/');
+
+    let partition_key = parts.next().filter(|key| !key.is_empty());
+    let database_path = parts.next();
+    let (partition_key, database_path) = partition_key
+        .zip(database_path)
+        .context(InvalidPartitionId { partition_id })?;
+
+    let mut path = ObjectStorePath::default();
+    for part in database_path.split('/').filter(|part| !part.is_empty()) {
+        path.push(part);
+    }
+
+    Ok((path, partition_key.to_string()))
+}
+
+/// A snapshot of a partition that was loaded back from object storage
+#[derive(Debug)]
+pub struct LoadedSnapshot {
+    pub partition_meta: PartitionMeta,
+    /// The record batches of each table in the partition
+    pub tables: Vec<(String, Vec<RecordBatch>)>,
+}
+
+/// Loads the partition metadata and the Parquet files of every table in it
+/// that `snapshot_chunk` wrote for `partition_key`.
+pub async fn load_snapshot(
+    metadata_path: &ObjectStorePath,
+    data_path: &ObjectStorePath,
+    store: &ObjectStore,
+    partition_key: &str,
+) -> Result<LoadedSnapshot> {
+    let mut partition_meta_path = metadata_path.clone();
+    partition_meta_path.push(&format!("{}.json", partition_key));
+    let json_data = read_object(store, &partition_meta_path).await?;
+    let partition_meta: PartitionMeta =
+        serde_json::from_slice(&json_data).context(ParsingPartitionMetadata)?;
+
+    let mut tables = Vec::with_capacity(partition_meta.tables.len());
+    for table in &partition_meta.tables {
+        let batches = load_snapshot_table(data_path, store, &table.name).await?;
+        tables.push((table.name.clone(), batches));
+    }
+
+    Ok(LoadedSnapshot {
+        partition_meta,
+        tables,
+    })
+}
+
+/// Loads the Parquet file of a single table that `snapshot_chunk` wrote to
+/// `data_path`, without reading the partition metadata or the other tables.
+pub async fn load_snapshot_table(
+    data_path: &ObjectStorePath,
+    store: &ObjectStore,
+    table_name: &str,
+) -> Result<Vec<RecordBatch>> {
+    let mut location = data_path.clone();
+    location.push(&format!("{}.parquet", table_name));
+    let data = read_object(store, &location).await?;
+
+    read_batches(table_name, data)
+}
+
+async fn read_object(store: &ObjectStore, location: &ObjectStorePath) -> Result<Vec<u8>> {
+    let data = store
+        .get(location)
+        .await
+        .context(ReadingFromObjectStore)?
+        .map_ok(|b| bytes::BytesMut::from(&b[..]))
+        .try_concat()
+        .await
+        .context(ReadingFromObjectStore)?;
+
+    Ok(data.to_vec())
+}
+
+// Reads the batches of a Parquet file, with the column types that
+// `encode_parquet` stored in its key-value metadata as schema metadata
+fn read_batches(table_name: &str, data: Vec<u8>) -> Result<Vec<RecordBatch>> {
+    let file_reader = SerializedFileReader::new(SliceableCursor::new(data))
+        .context(OpeningParquetReader { table_name })?;
+
+    let mut metadata = HashMap::new();
+    if let Some(key_values) = file_reader.metadata().file_metadata().key_value_metadata() {
+        for key_value in key_values {
+            match &key_value.value {
+                Some(value) if key_value.key.starts_with(COLUMN_TYPE_METADATA_PREFIX) => {
+                    metadata.insert(key_value.key.clone(), value.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut arrow_reader = ParquetFileArrowReader::new(Arc::new(file_reader));
+    arrow_reader
+        .get_record_reader(PARQUET_READ_BATCH_SIZE)
+        .context(OpeningParquetReader { table_name })?
+        .map(|batch| {
+            let batch = batch?;
+            let schema =
+                Schema::new_with_metadata(batch.schema().fields().clone(), metadata.clone());
+            RecordBatch::try_new(Arc::new(schema), batch.columns().to_vec())
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .context(ReadingParquet { table_name })
+}
+
+// Writes the table of the chunk as Parquet, converting one row group of the
+// chunk at a time to Arrow, splitting those into Parquet row groups of at most
+// `row_group_size` rows and sending the bytes encoded for each Parquet row
+// group to `parts`. Stops early, without error, if the receiver has gone away.
+fn encode_parquet<T>(
+    partition: &T,
+    table_name: &str,
+    row_group_size: usize,
+    parts: &mut mpsc::Sender<std::io::Result<Bytes>>,
+) -> Result<()>
+where
+    T: PartitionChunk,
+{
+    let row_groups = partition
+        .table_row_groups(table_name)
+        .map_err(|e| Box::new(e) as _)
+        .context(PartitionError)?;
+
+    let sink = DrainingWriter::default();
+    // opened with the schema of the first batch, as the schema is only known
+    // once a row group has been converted
+    let mut writer = None;
+
+    for row_group in 0..row_groups {
+        let mut batches = Vec::new();
+        partition
+            .row_group_to_arrow(&mut batches, table_name, row_group, &[])
+            .map_err(|e| Box::new(e) as _)
+            .context(PartitionError)?;
+
+        for batch in batches {
+            if writer.is_none() {
+                let schema = batch.schema();
+                writer = Some((
+                    open_parquet_writer(sink.clone(), Arc::clone(&schema))?,
+                    schema,
+                ));
+            }
+            let (writer, schema) = writer.as_mut().expect("writer was just opened");
+            ensure!(
+                batch.schema().fields() == schema.fields(),
+                SchemaMismatch { table_name }
+            );
+
+            let num_rows = batch.num_rows();
+            let mut offset = 0;
+
+            while offset < num_rows {
+                let len = row_group_size.min(num_rows - offset);
+                if len == num_rows {
+                    writer.write(&batch).context(WritingParquetToMemory)?;
+                } else {
+                    let row_group = slice_batch(&batch, offset, len)?;
+                    writer.write(&row_group).context(WritingParquetToMemory)?;
+                }
+                offset += len;
+
+                if !send_part(parts, sink.drain()) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // a table without any rows is still written, as a file without columns
+    let mut writer = match writer {
+        Some((writer, _)) => writer,
+        None => open_parquet_writer(sink.clone(), Arc::new(Schema::empty()))?,
+    };
+    writer.close().context(ClosingParquetWriter)?;
+    send_part(parts, sink.drain());
+
+    Ok(())
+}
+
+fn open_parquet_writer(
+    sink: DrainingWriter,
+    schema: SchemaRef,
+) -> Result<ArrowWriter<DrainingWriter>> {
+    // Parquet only has a place for the column types of tags and fields in the
+    // file's key-value metadata
+    let key_values = schema
+        .metadata()
+        .iter()
+        .map(|(key, value)| KeyValue {
+            key: key.clone(),
+            value: Some(value.clone()),
+        })
+        .collect();
+    let props = WriterProperties::builder()
+        .set_key_value_metadata(Some(key_values))
+        .build();
+
+    ArrowWriter::try_new(sink, schema, Some(props)).context(OpeningParquetWriter)
+}
+
+// copies `len` rows of the batch, starting at `offset`, into a new batch
+fn slice_batch(batch: &RecordBatch, offset: usize, len: usize) -> Result<RecordBatch> {
+    let indices = UInt32Array::from((offset as u32..(offset + len) as u32).collect::<Vec<_>>());
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| take(column, &indices, None))
+        .collect::<Result<Vec<_>, _>>()
+        .context(SlicingRecordBatch)?;
+
+    RecordBatch::try_new(batch.schema(), columns).context(SlicingRecordBatch)
+}
+
+// returns false if the receiving end has been dropped
+fn send_part(parts: &mut mpsc::Sender<std::io::Result<Bytes>>, data: Vec<u8>) -> bool {
+    if data.is_empty() {
+        return true;
+    }
+    futures::executor::block_on(parts.send(Ok(Bytes::from(data)))).is_ok()
+}
+
+/// The sink the Parquet writer writes to. It keeps track of how many bytes
+/// have been written, which is all the writer needs to know about its
+/// position, and hands out the bytes written since the last `drain`.
 #[derive(Debug, Default, Clone)]
-struct MemWriter {
-    mem: Arc<Mutex<Cursor<Vec<u8>>>>,
+struct DrainingWriter {
+    inner: Arc<Mutex<DrainingBuffer>>,
+}
+
+#[derive(Debug, Default)]
+struct DrainingBuffer {
+    buf: Vec<u8>,
+    position: u64,
 }
 
-impl MemWriter {
-    /// Returns the inner buffer as long as there are no other references to the
-    /// Arc.
-    pub fn into_inner(self) -> Option<Vec<u8>> {
-        Arc::try_unwrap(self.mem)
-            .ok()
-            .and_then(|mutex| mutex.into_inner().ok())
-            .map(|cursor| cursor.into_inner())
+impl DrainingWriter {
+    /// Returns the bytes written since the last call
+    fn drain(&self) -> Vec<u8> {
+        let mut inner = self.inner.lock().expect("mutex poisoned");
+        std::mem::take(&mut inner.buf)
     }
 }
 
-impl Write for MemWriter {
+impl Write for DrainingWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let mut inner = self.mem.lock().unwrap();
-        inner.write(buf)
+        let mut inner = self.inner.lock().expect("mutex poisoned");
+        inner.buf.extend_from_slice(buf);
+        inner.position += buf.len() as u64;
+        Ok(buf.len())
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
-        let mut inner = self.mem.lock().unwrap();
-        inner.flush()
+        Ok(())
     }
 }
 
-impl Seek for MemWriter {
+impl Seek for DrainingWriter {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
-        let mut inner = self.mem.lock().unwrap();
-        inner.seek(pos)
+        let inner = self.inner.lock().expect("mutex poisoned");
+        match pos {
+            SeekFrom::Current(0) => Ok(inner.position),
+            SeekFrom::Start(offset) if offset == inner.position => Ok(offset),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "bytes that have been drained can't be revisited",
+            )),
+        }
     }
 }
 
-impl TryClone for MemWriter {
+impl TryClone for DrainingWriter {
     fn try_clone(&self) -> std::io::Result<Self> {
-        Ok(Self {
-            mem: self.mem.clone(),
-        })
+        Ok(self.clone())
     }
 }
 
@@ -337,6 +846,7 @@ mod tests {
     use super::*;
     use data_types::data::lines_to_replicated_write;
     use data_types::database_rules::DatabaseRules;
+    use data_types::{column_type_metadata_key, FIELD_COLUMN_TYPE, TAG_COLUMN_TYPE};
     use futures::TryStreamExt;
     use influxdb_line_protocol::parse_lines;
     use mutable_buffer::chunk::Chunk as ChunkWB;
@@ -352,7 +862,7 @@ mem,host=A,region=west used=45 1
         "#;
 
         let lines: Vec<_> = parse_lines(lp).map(|l| l.unwrap()).collect();
-        let write = lines_to_replicated_write(1, 1, &lines, &DatabaseRules::default());
+        let write = lines_to_replicated_write(1, 1, &lines, &DatabaseRules::default()).unwrap();
         let mut chunk = ChunkWB::new(11);
 
         for e in write.write_buffer_batch().unwrap().entries().unwrap() {
@@ -374,6 +884,8 @@ mem,host=A,region=west used=45 1
             store.clone(),
             "testaroo",
             chunk.clone(),
+            vec![(1, 5)].into_iter().collect(),
+            DEFAULT_ROW_GROUP_SIZE,
             Some(tx),
         )
         .unwrap();
@@ -402,14 +914,17 @@ mem,host=A,region=west used=45 1
             Table {
                 name: "foo".to_string(),
                 columns: vec![],
+                time: None,
             },
             Table {
                 name: "bar".to_string(),
                 columns: vec![],
+                time: None,
             },
             Table {
                 name: "asdf".to_string(),
                 columns: vec![],
+                time: None,
             },
         ];
 
@@ -421,7 +936,16 @@ mem,host=A,region=west used=45 1
         let mut data_path = ObjectStorePath::default();
         data_path.push("data");
 
-        let snapshot = Snapshot::new("testaroo", metadata_path, data_path, store, chunk, tables);
+        let snapshot = Snapshot::new(
+            "testaroo",
+            metadata_path,
+            data_path,
+            store,
+            chunk,
+            tables,
+            BTreeMap::new(),
+            DEFAULT_ROW_GROUP_SIZE,
+        );
 
         let (pos, name) = snapshot.next_table().unwrap();
         assert_eq!(0, pos);
@@ -445,4 +969,225 @@ mem,host=A,region=west used=45 1
         snapshot.mark_table_finished(2);
         assert!(snapshot.finished());
     }
+
+    #[test]
+    fn snapshot_status_and_cancel() {
+        let tables = vec![
+            Table {
+                name: "foo".to_string(),
+                columns: vec![],
+                time: None,
+            },
+            Table {
+                name: "bar".to_string(),
+                columns: vec![],
+                time: None,
+            },
+        ];
+
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let chunk = Arc::new(ChunkWB::new(11));
+        let mut metadata_path = ObjectStorePath::default();
+        metadata_path.push("meta");
+        let mut data_path = ObjectStorePath::default();
+        data_path.push("data");
+
+        let snapshot = Arc::new(Snapshot::new(
+            "testaroo",
+            metadata_path,
+            data_path,
+            store,
+            chunk,
+            tables,
+            BTreeMap::new(),
+            DEFAULT_ROW_GROUP_SIZE,
+        ));
+        let registry = SnapshotRegistry::default();
+        registry.register(Arc::clone(&snapshot));
+
+        let (pos, _) = snapshot.next_table().unwrap();
+        snapshot.mark_table_finished(pos);
+
+        let status = registry.get(snapshot.id).unwrap().status();
+        assert_eq!(status.partition_key, "testaroo");
+        assert_eq!(status.state, SnapshotState::Running);
+        assert_eq!(
+            status.tables,
+            vec![
+                TableStatus {
+                    name: "foo".to_string(),
+                    state: TableState::Finished,
+                },
+                TableStatus {
+                    name: "bar".to_string(),
+                    state: TableState::NotStarted,
+                },
+            ]
+        );
+        assert!(registry.get(Uuid::new_v4()).is_none());
+
+        assert_eq!(registry.durations().count(), 0);
+
+        assert!(snapshot.cancel());
+        assert!(snapshot.should_stop());
+        snapshot.set_error(Error::StoppedEarly);
+
+        // finished snapshots are observed once
+        assert_eq!(registry.durations().count(), 1);
+        assert_eq!(registry.durations().count(), 1);
+
+        let status = snapshot.status();
+        assert_eq!(status.state, SnapshotState::Cancelled);
+        assert_eq!(status.error, Some("Stopped early".to_string()));
+        assert!(!snapshot.is_running());
+        assert!(!snapshot.cancel());
+        assert_eq!(registry.list().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn load_snapshot_round_trip() {
+        let lp = r#"
+cpu,host=A,region=west user=23.2,system=55.1 1
+cpu,host=B,region=east user=10.0,system=74.1 1
+mem,host=A,region=west used=45,state="ok" 1
+        "#;
+
+        let lines: Vec<_> = parse_lines(lp).map(|l| l.unwrap()).collect();
+        let write = lines_to_replicated_write(1, 1, &lines, &DatabaseRules::default()).unwrap();
+        let mut chunk = ChunkWB::new(11);
+
+        for e in write.write_buffer_batch().unwrap().entries().unwrap() {
+            chunk.write_entry(&e).unwrap();
+        }
+
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let mut database_path = ObjectStorePath::default();
+        database_path.push("mydb");
+        let (metadata_path, data_path) = snapshot_paths(&database_path, "testaroo");
+
+        snapshot_chunk(
+            metadata_path.clone(),
+            data_path.clone(),
+            store.clone(),
+            "testaroo",
+            Arc::new(chunk),
+            vec![(1, 1)].into_iter().collect(),
+            1,
+            Some(tx),
+        )
+        .unwrap();
+
+        rx.await.unwrap();
+
+        let (path, partition_key) = parse_partition_id("/mydb/testaroo/").unwrap();
+        assert_eq!(path, database_path);
+        assert_eq!(partition_key, "testaroo");
+
+        let loaded = load_snapshot(&metadata_path, &data_path, &store, &partition_key)
+            .await
+            .unwrap();
+        assert_eq!(loaded.partition_meta.key, "testaroo");
+        assert_eq!(loaded.partition_meta.max_sequences[&1], 1);
+
+        let mut tables: Vec<_> = loaded
+            .tables
+            .iter()
+            .map(|(name, batches)| {
+                let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+                (name.as_str(), rows)
+            })
+            .collect();
+        tables.sort();
+        assert_eq!(tables, vec![("cpu", 2), ("mem", 1)]);
+
+        // tags and string fields keep their column types
+        let (_, mem) = loaded
+            .tables
+            .iter()
+            .find(|(name, _)| name == "mem")
+            .unwrap();
+        let schema = mem[0].schema();
+        let column_type = |column| schema.metadata()[&column_type_metadata_key(column)].as_str();
+        assert_eq!(column_type("host"), TAG_COLUMN_TYPE);
+        assert_eq!(column_type("state"), FIELD_COLUMN_TYPE);
+
+        // each row of cpu went into its own row group
+        let mut location = data_path;
+        location.push("cpu.parquet");
+        let data = read_object(&store, &location).await.unwrap();
+        let reader = SerializedFileReader::new(SliceableCursor::new(data)).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 2);
+    }
+
+    #[tokio::test]
+    async fn snapshot_writes_each_read_buffer_row_group() {
+        use crate::db::DBChunk;
+        use read_buffer::{chunk::Chunk as ChunkRB, row_group::RowGroup, table::Table as TableRB};
+        use std::convert::TryFrom;
+
+        let row_group = |lp: &str| {
+            let lines: Vec<_> = parse_lines(lp).map(|l| l.unwrap()).collect();
+            let write = lines_to_replicated_write(1, 1, &lines, &DatabaseRules::default()).unwrap();
+            let mut chunk = ChunkWB::new(0);
+            for e in write.write_buffer_batch().unwrap().entries().unwrap() {
+                chunk.write_entry(&e).unwrap();
+            }
+
+            let mut batches = vec![];
+            chunk.table_to_arrow(&mut batches, "cpu", &[]).unwrap();
+            RowGroup::try_from(batches.pop().unwrap()).unwrap()
+        };
+
+        let mut table = TableRB::new(
+            "cpu".to_string(),
+            row_group("cpu,host=A user=23.2 1\ncpu,host=B user=10.0 1"),
+        );
+        table.add_segment(row_group("cpu,host=C user=3.2 10"));
+        let chunk = DBChunk::ReadBuffer(Arc::new(ChunkRB::new(11, table)));
+
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let mut database_path = ObjectStorePath::default();
+        database_path.push("mydb");
+        let (metadata_path, data_path) = snapshot_paths(&database_path, "testaroo");
+
+        snapshot_chunk(
+            metadata_path,
+            data_path.clone(),
+            store.clone(),
+            "testaroo",
+            Arc::new(chunk),
+            vec![(1, 1)].into_iter().collect(),
+            DEFAULT_ROW_GROUP_SIZE,
+            Some(tx),
+        )
+        .unwrap();
+
+        rx.await.unwrap();
+
+        let batches = load_snapshot_table(&data_path, &store, "cpu")
+            .await
+            .unwrap();
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(rows, 3);
+
+        let mut location = data_path;
+        location.push("cpu.parquet");
+        let data = read_object(&store, &location).await.unwrap();
+        let reader = SerializedFileReader::new(SliceableCursor::new(data)).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 2);
+    }
+
+    #[test]
+    fn partition_id_parsing() {
+        let (path, partition_key) = parse_partition_id("1/mydb/2020-11-19").unwrap();
+        let mut expected = ObjectStorePath::default();
+        expected.push_all(&["1", "mydb"]);
+        assert_eq!(path, expected);
+        assert_eq!(partition_key, "2020-11-19");
+
+        assert!(parse_partition_id("2020-11-19").is_err());
+        assert!(parse_partition_id("/").is_err());
+    }
 }
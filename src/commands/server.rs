@@ -7,7 +7,11 @@ use std::sync::Arc;
 
 use crate::server::http_routes;
 use crate::server::rpc::service;
-use server::server::{ConnectionManagerImpl as ConnectionManager, Server as AppServer};
+use server::server::{
+    ConnectionManagerImpl as ConnectionManager, Server as AppServer, CHUNK_LIFECYCLE_INTERVAL,
+    DATABASE_LOAD_RETRY_INTERVAL, REPLICATION_RETRY_INTERVAL, RETENTION_INTERVAL,
+    WAL_PERSISTENCE_INTERVAL,
+};
 
 use hyper::Server;
 use object_store::{self, gcp::GoogleCloudStorage, ObjectStore};
@@ -100,17 +104,65 @@ pub async fn main(logging_level: LoggingLevel, ignore_config_file: bool) -> Resu
     };
     let object_storage = Arc::new(object_store);
 
-    let connection_manager = ConnectionManager {};
+    let connection_manager = ConnectionManager::new();
     let app_server = Arc::new(AppServer::new(connection_manager, object_storage));
+    app_server.set_snapshot_row_group_size(config.snapshot_row_group_size);
 
     // if this ID isn't set the server won't be usable until this is set via an API
     // call
     if let Some(id) = config.writer_id {
+        // setting the ID recreates the databases stored for it. A database
+        // that can't be loaded is reported, but doesn't keep the server from
+        // starting. If the stored databases can't be read at all, loading them
+        // is retried in the background and the server reports not ready until
+        // then
         app_server.set_id(id).await;
+        if !app_server.databases_loaded() {
+            let load_server = app_server.clone();
+            tokio::spawn(async move {
+                load_server
+                    .background_load_databases(DATABASE_LOAD_RETRY_INTERVAL)
+                    .await
+            });
+        }
     } else {
         warn!("server ID not set. ID must be set via the INFLUXDB_IOX_ID config or API before writing or querying data.");
     }
 
+    // Retry replicating writes to host groups that missed them in the background
+    let replication_server = app_server.clone();
+    tokio::spawn(async move {
+        replication_server
+            .background_replication(REPLICATION_RETRY_INTERVAL)
+            .await
+    });
+
+    // Persist closed WAL buffer segments to object storage in the background
+    let persistence_server = app_server.clone();
+    tokio::spawn(async move {
+        persistence_server
+            .background_wal_persistence(WAL_PERSISTENCE_INTERVAL)
+            .await
+    });
+
+    // Close, persist and evict mutable buffer chunks according to the
+    // lifecycle rules of each database in the background
+    let lifecycle_server = app_server.clone();
+    tokio::spawn(async move {
+        lifecycle_server
+            .background_chunk_lifecycle(CHUNK_LIFECYCLE_INTERVAL)
+            .await
+    });
+
+    // Drop data older than the retention period of each database in the
+    // background
+    let retention_server = app_server.clone();
+    tokio::spawn(async move {
+        retention_server
+            .background_retention(RETENTION_INTERVAL)
+            .await
+    });
+
     // Construct and start up gRPC server
 
     let grpc_bind_addr = config.grpc_bind_address;
@@ -118,7 +170,7 @@ pub async fn main(logging_level: LoggingLevel, ignore_config_file: bool) -> Resu
         .await
         .context(StartListeningGrpc { grpc_bind_addr })?;
 
-    let grpc_server = service::make_server(socket, app_server.clone());
+    let grpc_server = service::make_server(socket, app_server.clone(), Some(app_server.clone()));
 
     info!(bind_address=?grpc_bind_addr, "gRPC server listening");
 
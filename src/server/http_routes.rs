@@ -12,22 +12,29 @@
 use super::{org_and_bucket_to_database, OrgBucketMappingError};
 
 // Influx crates
-use arrow_deps::{arrow, datafusion::physical_plan::collect};
-use data_types::{database_rules::DatabaseRules, DatabaseName};
+use arrow_deps::arrow;
+use data_types::{
+    database_rules::DatabaseRules, delete::Delete, row_predicate::RowPredicate, DatabaseName,
+};
 use influxdb_line_protocol::parse_lines;
 use object_store::path::ObjectStorePath;
-use query::{frontend::sql::SQLQueryPlanner, Database, DatabaseStore};
-use server::server::{ConnectionManager, Server as AppServer};
+use query::{Database, PartitionChunk};
+use server::{
+    catalog::WriterSequences,
+    server::{ConnectionManager, Server as AppServer},
+};
 
 // External crates
 use bytes::{Bytes, BytesMut};
+use chrono::DateTime;
 use futures::{self, StreamExt};
-use http::header::CONTENT_ENCODING;
+use http::header::{CONTENT_ENCODING, CONTENT_TYPE};
 use hyper::{Body, Method, Request, Response, StatusCode};
 use routerify::{prelude::*, Middleware, RequestInfo, Router, RouterService};
 use serde::Deserialize;
-use snafu::{OptionExt, ResultExt, Snafu};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
 use tracing::{debug, error, info};
+use uuid::Uuid;
 
 use std::{fmt::Debug, str, sync::Arc};
 
@@ -60,6 +67,18 @@ pub enum ApplicationError {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
 
+    #[snafu(display(
+        "Internal error deleting points from org {}, bucket {}:  {}",
+        org,
+        bucket_name,
+        source
+    ))]
+    DeletingPoints {
+        org: String,
+        bucket_name: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
     #[snafu(display("Error planning query {}: {}", query, source))]
     PlanningSQLQuery {
         query: String,
@@ -116,6 +135,20 @@ pub enum ApplicationError {
         source: influxdb_line_protocol::Error,
     },
 
+    #[snafu(display("Invalid time '{}' in delete request: {}", time, source))]
+    InvalidDeleteTime {
+        time: String,
+        source: chrono::ParseError,
+    },
+
+    #[snafu(display("Invalid predicate in delete request: {}", source))]
+    InvalidDeletePredicate {
+        source: data_types::row_predicate::Error,
+    },
+
+    #[snafu(display("Invalid delete request: {}", source))]
+    InvalidDelete { source: server::server::Error },
+
     #[snafu(display("Error decompressing body as gzip: {}", source))]
     ReadingBodyAsGzip { source: std::io::Error },
 
@@ -134,6 +167,15 @@ pub enum ApplicationError {
     #[snafu(display("Error creating database: {}", source))]
     ErrorCreatingDatabase { source: server::server::Error },
 
+    #[snafu(display("Error dropping database {}: {}", name, source))]
+    ErrorDroppingDatabase {
+        name: String,
+        source: server::server::Error,
+    },
+
+    #[snafu(display("Error storing configuration: {}", source))]
+    ErrorStoringConfiguration { source: server::server::Error },
+
     #[snafu(display("Invalid database name: {}", source))]
     DatabaseNameError {
         source: data_types::DatabaseNameError,
@@ -141,6 +183,21 @@ pub enum ApplicationError {
 
     #[snafu(display("Database {} not found", name))]
     DatabaseNotFound { name: String },
+
+    #[snafu(display("Invalid snapshot id {}: {}", id, source))]
+    InvalidSnapshotId { id: String, source: uuid::Error },
+
+    #[snafu(display("Snapshot {} not found", id))]
+    SnapshotNotFound { id: Uuid },
+
+    #[snafu(display("Snapshot {} is not running", id))]
+    SnapshotNotRunning { id: Uuid },
+
+    #[snafu(display("{}", source))]
+    DatabaseOverMemoryLimit { source: server::server::Error },
+
+    #[snafu(display("Server not ready: {}", source))]
+    NotReady { source: server::server::Error },
 }
 
 impl ApplicationError {
@@ -149,6 +206,7 @@ impl ApplicationError {
             Self::BucketByName { .. } => self.internal_error(),
             Self::BucketMappingError { .. } => self.internal_error(),
             Self::WritingPoints { .. } => self.internal_error(),
+            Self::DeletingPoints { .. } => self.internal_error(),
             Self::PlanningSQLQuery { .. } => self.bad_request(),
             Self::Query { .. } => self.internal_error(),
             Self::QueryError { .. } => self.bad_request(),
@@ -162,13 +220,23 @@ impl ApplicationError {
             Self::ReadingBody { .. } => self.bad_request(),
             Self::ReadingBodyAsUtf8 { .. } => self.bad_request(),
             Self::ParsingLineProtocol { .. } => self.bad_request(),
+            Self::InvalidDeleteTime { .. } => self.bad_request(),
+            Self::InvalidDeletePredicate { .. } => self.bad_request(),
+            Self::InvalidDelete { .. } => self.bad_request(),
             Self::ReadingBodyAsGzip { .. } => self.bad_request(),
             Self::RouteNotFound { .. } => self.not_found(),
             Self::DatabaseError { .. } => self.internal_error(),
             Self::JsonGenerationError { .. } => self.internal_error(),
             Self::ErrorCreatingDatabase { .. } => self.bad_request(),
+            Self::ErrorDroppingDatabase { .. } => self.internal_error(),
+            Self::ErrorStoringConfiguration { .. } => self.internal_error(),
             Self::DatabaseNameError { .. } => self.bad_request(),
             Self::DatabaseNotFound { .. } => self.not_found(),
+            Self::InvalidSnapshotId { .. } => self.bad_request(),
+            Self::SnapshotNotFound { .. } => self.not_found(),
+            Self::SnapshotNotRunning { .. } => self.conflict(),
+            Self::DatabaseOverMemoryLimit { .. } => self.service_unavailable(),
+            Self::NotReady { .. } => self.service_unavailable(),
         })
     }
 
@@ -179,6 +247,13 @@ impl ApplicationError {
             .unwrap()
     }
 
+    fn conflict(&self) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::CONFLICT)
+            .body(self.body())
+            .unwrap()
+    }
+
     fn internal_error(&self) -> Response<Body> {
         Response::builder()
             .status(StatusCode::INTERNAL_SERVER_ERROR)
@@ -186,6 +261,13 @@ impl ApplicationError {
             .unwrap()
     }
 
+    fn service_unavailable(&self) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(self.body())
+            .unwrap()
+    }
+
     fn not_found(&self) -> Response<Body> {
         Response::builder()
             .status(StatusCode::NOT_FOUND)
@@ -217,12 +299,20 @@ where
             Ok(res)
         })) // this endpoint is for API backward compatibility with InfluxDB 2.x
         .post("/api/v2/write", write_handler::<M>)
+        .post("/api/v2/delete", delete_handler::<M>)
         .get("/ping", ping)
+        .get("/health", health)
+        .get("/ready", ready_handler::<M>)
+        .get("/metrics", metrics_handler::<M>)
         .get("/api/v2/read", read_handler::<M>)
         .put("/iox/api/v1/databases/:name", create_database_handler::<M>)
         .get("/iox/api/v1/databases/:name", get_database_handler::<M>)
+        .delete("/iox/api/v1/databases/:name", drop_database_handler::<M>)
         .get("/api/v1/partitions", list_partitions_handler::<M>)
         .post("/api/v1/snapshot", snapshot_partition_handler::<M>)
+        .get("/api/v1/snapshots", list_snapshots_handler::<M>)
+        .get("/api/v1/snapshots/:id", get_snapshot_handler::<M>)
+        .post("/api/v1/snapshots/:id/cancel", cancel_snapshot_handler::<M>)
         // Specify the error handler to handle any errors caused by
         // a route or any middleware.
         .err_handler_with_info(error_handler)
@@ -343,7 +433,11 @@ where
 
     let lines = parse_lines(body)
         .collect::<Result<Vec<_>, influxdb_line_protocol::Error>>()
-        .context(ParsingLineProtocol)?;
+        .context(ParsingLineProtocol)
+        .map_err(|e| {
+            server.metrics().record_write_error("invalid_line_protocol");
+            e
+        })?;
 
     debug!(
         "Inserting {} lines into database {} (org {} bucket {})",
@@ -353,13 +447,130 @@ where
         write_info.bucket
     );
 
+    // the client may retry writes rejected for memory once the database
+    // has freed some
     server
         .write_lines(&db_name, &lines)
         .await
-        .map_err(|e| Box::new(e) as _)
-        .context(WritingPoints {
-            org: write_info.org.clone(),
-            bucket_name: write_info.bucket.clone(),
+        .map_err(|e| match e {
+            e @ server::server::Error::DatabaseOverMemoryLimit { .. } => {
+                ApplicationError::DatabaseOverMemoryLimit { source: e }
+            }
+            e => ApplicationError::WritingPoints {
+                org: write_info.org.clone(),
+                bucket_name: write_info.bucket.clone(),
+                source: Box::new(e),
+            },
+        })?;
+
+    Ok(Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap())
+}
+
+#[derive(Debug, Deserialize)]
+/// Query string of the request to the /delete endpoint
+struct DeleteInfo {
+    org: String,
+    bucket: String,
+}
+
+#[derive(Debug, Deserialize)]
+/// Body of the request to the /delete endpoint
+struct DeleteRequest {
+    /// The time of the first point to delete, in RFC3339 format
+    start: String,
+    /// The time of the last point to delete, in RFC3339 format
+    stop: String,
+    /// The predicate points have to match to be deleted, for example
+    /// `_measurement="cpu" AND host="a"`. All points in the time range are
+    /// deleted if it is empty.
+    #[serde(default)]
+    predicate: String,
+}
+
+#[tracing::instrument(level = "debug")]
+async fn delete_handler<M>(req: Request<Body>) -> Result<Response<Body>, ApplicationError>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    match delete::<M>(req).await {
+        Err(e) => {
+            error!(error = ?e, error_message = ?e.to_string(), "Error while handling request");
+            e.response()
+        }
+        res => res,
+    }
+}
+
+#[tracing::instrument(level = "debug")]
+async fn delete<M>(req: Request<Body>) -> Result<Response<Body>, ApplicationError>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    let server = req
+        .data::<Arc<AppServer<M>>>()
+        .expect("server state")
+        .clone();
+
+    let query = req.uri().query().context(ExpectedQueryString)?;
+
+    let delete_info: DeleteInfo =
+        serde_urlencoded::from_str(query).context(InvalidQueryString {
+            query_string: String::from(query),
+        })?;
+
+    let db_name = org_and_bucket_to_database(&delete_info.org, &delete_info.bucket)
+        .context(BucketMappingError)?;
+
+    let body = parse_body(req).await?;
+    let request: DeleteRequest =
+        serde_json::from_slice(body.as_ref()).context(InvalidRequestBody)?;
+
+    let parse_time = |time: &str| {
+        DateTime::parse_from_rfc3339(time)
+            .map(|time| time.timestamp_nanos())
+            .context(InvalidDeleteTime { time })
+    };
+    let predicate = if request.predicate.trim().is_empty() {
+        None
+    } else {
+        Some(
+            request
+                .predicate
+                .parse::<RowPredicate>()
+                .context(InvalidDeletePredicate)?,
+        )
+    };
+    let delete = Delete {
+        table_name: None,
+        start: parse_time(&request.start)?,
+        stop: parse_time(&request.stop)?,
+        predicate,
+    };
+
+    debug!(?delete, %db_name, "Deleting points");
+
+    server
+        .delete(&db_name, &delete)
+        .await
+        .map_err(|e| match e {
+            server::server::Error::DatabaseNotFound { .. } => ApplicationError::BucketNotFound {
+                org: delete_info.org.clone(),
+                bucket: delete_info.bucket.clone(),
+            },
+            e @ server::server::Error::InvalidDatabaseName { .. } => {
+                ApplicationError::InvalidDelete { source: e }
+            }
+            e @ server::server::Error::InvalidDelete { .. } => {
+                ApplicationError::InvalidDelete { source: e }
+            }
+            e => ApplicationError::DeletingPoints {
+                org: delete_info.org.clone(),
+                bucket_name: delete_info.bucket.clone(),
+                source: Box::new(e),
+            },
         })?;
 
     Ok(Response::builder()
@@ -409,26 +620,29 @@ async fn read<M: ConnectionManager + Send + Sync + Debug + 'static>(
         query_string: query,
     })?;
 
-    let planner = SQLQueryPlanner::default();
-    let executor = server.executor();
-
     let db_name = org_and_bucket_to_database(&read_info.org, &read_info.bucket)
         .context(BucketMappingError)?;
 
-    let db = server.db(&db_name).await.context(BucketNotFound {
+    server.db(&db_name).await.context(BucketNotFound {
         org: read_info.org.clone(),
         bucket: read_info.bucket.clone(),
     })?;
 
-    let physical_plan = planner
-        .query(db.as_ref(), &read_info.sql_query, executor.as_ref())
+    // the server coordinates the query across its query groups, if the
+    // database has any
+    let batches = server
+        .query_sql(&db_name, &read_info.sql_query)
         .await
-        .context(PlanningSQLQuery { query })?;
-
-    let batches = collect(physical_plan)
-        .await
-        .map_err(|e| Box::new(e) as _)
-        .context(Query { db_name })?;
+        .map_err(|e| match e {
+            server::server::Error::PlanningQuery { source } => ApplicationError::PlanningSQLQuery {
+                query: query.to_string(),
+                source,
+            },
+            e => ApplicationError::Query {
+                db_name: db_name.to_string(),
+                source: Box::new(e),
+            },
+        })?;
 
     let results = arrow::util::pretty::pretty_format_batches(&batches).unwrap();
 
@@ -466,13 +680,64 @@ async fn create_database<M: ConnectionManager + Send + Sync + Debug + 'static>(
         .clone();
     let body = parse_body(req).await?;
 
-    let rules: DatabaseRules = serde_json::from_slice(body.as_ref())
-        .context(InvalidRequestBody)
-        .unwrap();
+    let rules: DatabaseRules = serde_json::from_slice(body.as_ref()).context(InvalidRequestBody)?;
     server
-        .create_database(db_name, rules)
+        .update_database(db_name, rules)
         .await
         .context(ErrorCreatingDatabase)?;
+    // keep the stored configuration in sync so the database is loaded again
+    // when the server restarts
+    server
+        .store_configuration()
+        .await
+        .context(ErrorStoringConfiguration)?;
+
+    Ok(Response::new(Body::empty()))
+}
+
+#[tracing::instrument(level = "debug")]
+async fn drop_database_handler<M>(req: Request<Body>) -> Result<Response<Body>, ApplicationError>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    match drop_database::<M>(req).await {
+        Err(e) => {
+            error!(error = ?e, error_message = ?e.to_string(), "Error while handling request");
+
+            e.response()
+        }
+        res => res,
+    }
+}
+
+#[tracing::instrument(level = "debug")]
+async fn drop_database<M: ConnectionManager + Send + Sync + Debug + 'static>(
+    req: Request<Body>,
+) -> Result<Response<Body>, ApplicationError> {
+    let server = req
+        .data::<Arc<AppServer<M>>>()
+        .expect("server state")
+        .clone();
+
+    // with routerify, we shouldn't have gotten here without this being set
+    let db_name_str = req
+        .param("name")
+        .expect("db name must have been set")
+        .clone();
+    let db_name = DatabaseName::new(&db_name_str).context(DatabaseNameError)?;
+    server
+        .db(&db_name)
+        .await
+        .context(DatabaseNotFound { name: &db_name_str })?;
+
+    server
+        .drop_database(&db_name_str)
+        .await
+        .context(ErrorDroppingDatabase { name: &db_name_str })?;
+    server
+        .store_configuration()
+        .await
+        .context(ErrorStoringConfiguration)?;
 
     Ok(Response::new(Body::empty()))
 }
@@ -529,6 +794,49 @@ async fn ping(req: Request<Body>) -> Result<Response<Body>, ApplicationError> {
     Ok(Response::new(Body::from(response_body.to_string())))
 }
 
+// Route to check that the server is alive, whether or not it is ready
+#[tracing::instrument(level = "debug")]
+async fn health(req: Request<Body>) -> Result<Response<Body>, ApplicationError> {
+    Ok(Response::new(Body::from("OK")))
+}
+
+// Route to check that the server is ready to handle writes and queries, which
+// responds with 503 and the reason while it is not
+#[tracing::instrument(level = "debug")]
+async fn ready_handler<M>(req: Request<Body>) -> Result<Response<Body>, ApplicationError>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    let server = req
+        .data::<Arc<AppServer<M>>>()
+        .expect("server state")
+        .clone();
+
+    server.check_ready().await.context(NotReady)?;
+
+    Ok(Response::new(Body::from("OK")))
+}
+
+// Route to scrape the metrics of the server in the Prometheus text format
+#[tracing::instrument(level = "debug")]
+async fn metrics_handler<M>(req: Request<Body>) -> Result<Response<Body>, ApplicationError>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    let server = req
+        .data::<Arc<AppServer<M>>>()
+        .expect("server state")
+        .clone();
+
+    let body = server.encode_metrics().await;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(body))
+        .unwrap())
+}
+
 #[derive(Deserialize, Debug)]
 /// Arguments in the query string of the request to /partitions
 struct DatabaseInfo {
@@ -636,28 +944,139 @@ async fn snapshot_partition<M: ConnectionManager + Send + Sync + Debug + 'static
         bucket: &snapshot.bucket,
     })?;
 
-    let mut metadata_path = ObjectStorePath::default();
-    metadata_path.push(&db_name.to_string());
-    let mut data_path = metadata_path.clone();
-    metadata_path.push("meta");
-    data_path.push_all(&["data", &snapshot.partition]);
-
+    let mut database_path = ObjectStorePath::default();
+    database_path.push(&db_name.to_string());
     let partition_key = &snapshot.partition;
+    let (metadata_path, data_path) =
+        server::snapshot::snapshot_paths(&database_path, partition_key);
+
     let chunk = db.rollover_partition(partition_key).await.unwrap();
+    let sequences = db.closed_chunk_sequences(partition_key, chunk.id()).await;
     let snapshot = server::snapshot::snapshot_chunk(
         metadata_path,
         data_path,
         server.store.clone(),
         partition_key,
         chunk,
+        WriterSequences::max_by_writer(&sequences),
+        server.snapshot_row_group_size(),
         None,
     )
     .unwrap();
+    server.register_snapshot(Arc::clone(&snapshot));
 
     let ret = format!("{}", snapshot.id);
     Ok(Response::new(Body::from(ret)))
 }
 
+#[tracing::instrument(level = "debug")]
+async fn list_snapshots_handler<M>(req: Request<Body>) -> Result<Response<Body>, ApplicationError>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    match list_snapshots::<M>(req).await {
+        Err(e) => {
+            error!(error = ?e, error_message = ?e.to_string(), "Error while handling request");
+
+            e.response()
+        }
+        res => res,
+    }
+}
+
+#[tracing::instrument(level = "debug")]
+async fn list_snapshots<M: ConnectionManager + Send + Sync + Debug + 'static>(
+    req: Request<Body>,
+) -> Result<Response<Body>, ApplicationError> {
+    let server = req
+        .data::<Arc<AppServer<M>>>()
+        .expect("server state")
+        .clone();
+
+    let statuses: Vec<_> = server.snapshots().iter().map(|s| s.status()).collect();
+
+    snapshot_response(&statuses)
+}
+
+#[tracing::instrument(level = "debug")]
+async fn get_snapshot_handler<M>(req: Request<Body>) -> Result<Response<Body>, ApplicationError>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    match get_snapshot::<M>(req).await {
+        Err(e) => {
+            error!(error = ?e, error_message = ?e.to_string(), "Error while handling request");
+
+            e.response()
+        }
+        res => res,
+    }
+}
+
+#[tracing::instrument(level = "debug")]
+async fn get_snapshot<M: ConnectionManager + Send + Sync + Debug + 'static>(
+    req: Request<Body>,
+) -> Result<Response<Body>, ApplicationError> {
+    let server = req
+        .data::<Arc<AppServer<M>>>()
+        .expect("server state")
+        .clone();
+
+    let id = snapshot_id(&req)?;
+    let snapshot = server.snapshot(id).context(SnapshotNotFound { id })?;
+
+    snapshot_response(&snapshot.status())
+}
+
+#[tracing::instrument(level = "debug")]
+async fn cancel_snapshot_handler<M>(req: Request<Body>) -> Result<Response<Body>, ApplicationError>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    match cancel_snapshot::<M>(req).await {
+        Err(e) => {
+            error!(error = ?e, error_message = ?e.to_string(), "Error while handling request");
+
+            e.response()
+        }
+        res => res,
+    }
+}
+
+#[tracing::instrument(level = "debug")]
+async fn cancel_snapshot<M: ConnectionManager + Send + Sync + Debug + 'static>(
+    req: Request<Body>,
+) -> Result<Response<Body>, ApplicationError> {
+    let server = req
+        .data::<Arc<AppServer<M>>>()
+        .expect("server state")
+        .clone();
+
+    let id = snapshot_id(&req)?;
+    let snapshot = server.snapshot(id).context(SnapshotNotFound { id })?;
+    ensure!(snapshot.cancel(), SnapshotNotRunning { id });
+
+    snapshot_response(&snapshot.status())
+}
+
+// parses the snapshot id from the path of the request
+fn snapshot_id(req: &Request<Body>) -> Result<Uuid, ApplicationError> {
+    // with routerify, we shouldn't have gotten here without this being set
+    let id = req.param("id").expect("snapshot id must have been set");
+
+    Uuid::parse_str(id).context(InvalidSnapshotId { id })
+}
+
+fn snapshot_response(status: &impl serde::Serialize) -> Result<Response<Body>, ApplicationError> {
+    let data = serde_json::to_string(status).context(JsonGenerationError)?;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .status(StatusCode::OK)
+        .body(Body::from(data))
+        .expect("builder should be successful"))
+}
+
 pub fn router_service<M: ConnectionManager + Send + Sync + Debug + 'static>(
     server: Arc<AppServer<M>>,
 ) -> RouterService<Body, ApplicationError> {
@@ -670,14 +1089,18 @@ mod tests {
     use super::*;
     use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
-    use arrow_deps::{arrow::record_batch::RecordBatch, assert_table_eq};
+    use arrow_deps::{
+        arrow::record_batch::RecordBatch, assert_table_eq, datafusion::physical_plan::collect,
+    };
     use http::header;
-    use query::exec::Executor;
+    use query::{exec::Executor, frontend::sql::SQLQueryPlanner};
     use reqwest::{Client, Response};
 
     use hyper::Server;
 
-    use data_types::database_rules::DatabaseRules;
+    use data_types::database_rules::{
+        DatabaseRules, LifecycleRules, PartitionTemplate, TemplatePart,
+    };
     use data_types::DatabaseName;
     use object_store::{memory::InMemory, ObjectStore};
     use server::{db::Db, server::ConnectionManagerImpl};
@@ -688,7 +1111,7 @@ mod tests {
     #[tokio::test]
     async fn test_ping() -> Result<()> {
         let test_storage = Arc::new(AppServer::new(
-            ConnectionManagerImpl {},
+            ConnectionManagerImpl::new(),
             Arc::new(ObjectStore::new_in_memory(InMemory::new())),
         ));
         let server_url = test_server(test_storage.clone());
@@ -701,10 +1124,79 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_health_and_ready() -> Result<()> {
+        // a file store rooted at a file can't read anything
+        let root = tempfile::tempdir()?;
+        let root_path = root.path().join("store");
+        std::fs::write(&root_path, "")?;
+        let test_storage = Arc::new(AppServer::new(
+            ConnectionManagerImpl::new(),
+            Arc::new(ObjectStore::new_file(object_store::disk::File::new(
+                &root_path,
+            ))),
+        ));
+        let server_url = test_server(test_storage.clone());
+        let client = Client::new();
+        let ready_url = format!("{}/ready", server_url);
+
+        let response = client.get(&format!("{}/health", server_url)).send().await;
+        check_response("health", response, StatusCode::OK, "OK").await;
+
+        let response = client.get(&ready_url).send().await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.text().await?.contains("id is set"));
+
+        // setting the id tries to load the databases
+        test_storage.set_id(1).await;
+        let response = client.get(&ready_url).send().await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.text().await?.contains("not loaded"));
+
+        std::fs::remove_file(&root_path)?;
+        std::fs::create_dir(&root_path)?;
+        assert!(test_storage.load_databases().await.unwrap().is_empty());
+        let response = client.get(&ready_url).send().await;
+        check_response("ready", response, StatusCode::OK, "OK").await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_not_ready_when_object_store_unreachable() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let root_path = root.path().to_path_buf();
+        let test_storage = Arc::new(AppServer::new(
+            ConnectionManagerImpl::new(),
+            Arc::new(ObjectStore::new_file(object_store::disk::File::new(
+                &root_path,
+            ))),
+        ));
+        test_storage.set_id(1).await;
+        assert!(test_storage.load_databases().await.unwrap().is_empty());
+        let server_url = test_server(test_storage.clone());
+        let client = Client::new();
+        let ready_url = format!("{}/ready", server_url);
+
+        let response = client.get(&ready_url).send().await;
+        check_response("ready", response, StatusCode::OK, "OK").await;
+
+        // a file store rooted at a file can't read anything
+        std::fs::remove_dir(&root_path)?;
+        std::fs::write(&root_path, "")?;
+
+        let response = client.get(&ready_url).send().await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.text().await?.contains("unreachable"));
+
+        std::fs::remove_file(&root_path)?;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_write() -> Result<()> {
         let test_storage = Arc::new(AppServer::new(
-            ConnectionManagerImpl {},
+            ConnectionManagerImpl::new(),
             Arc::new(ObjectStore::new_in_memory(InMemory::new())),
         ));
         test_storage.set_id(1).await;
@@ -755,6 +1247,161 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_delete() -> Result<()> {
+        let test_storage = Arc::new(AppServer::new(
+            ConnectionManagerImpl::new(),
+            Arc::new(ObjectStore::new_in_memory(InMemory::new())),
+        ));
+        test_storage.set_id(1).await;
+        let rules = DatabaseRules {
+            store_locally: true,
+            ..Default::default()
+        };
+        test_storage
+            .create_database("MyOrg_MyBucket", rules)
+            .await
+            .unwrap();
+        let server_url = test_server(test_storage.clone());
+        let write_url = format!("{}/api/v2/write?bucket=MyBucket&org=MyOrg", server_url);
+        let delete_url = format!("{}/api/v2/delete?bucket=MyBucket&org=MyOrg", server_url);
+
+        let client = Client::new();
+
+        let lp_data = "h2o_temperature,location=santa_monica surface_degrees=65.2 1568756160\n\
+                       h2o_temperature,location=coyote_creek surface_degrees=50.4 1568756160\n\
+                       h2o_temperature,location=santa_monica surface_degrees=63.6 1600000000000000000";
+        let response = client.post(&write_url).body(lp_data).send().await;
+        check_response("write", response, StatusCode::NO_CONTENT, "").await;
+
+        let delete = r#"{
+            "start": "1970-01-01T00:00:00Z",
+            "stop": "1970-01-01T00:00:02Z",
+            "predicate": "_measurement=\"h2o_temperature\" AND location=\"santa_monica\""
+        }"#;
+        let response = client.post(&delete_url).body(delete).send().await;
+        check_response("delete", response, StatusCode::NO_CONTENT, "").await;
+
+        // the delete doesn't apply to points written after it
+        let lp_data = "h2o_temperature,location=santa_monica surface_degrees=70.1 1568756161";
+        let response = client.post(&write_url).body(lp_data).send().await;
+        check_response("write", response, StatusCode::NO_CONTENT, "").await;
+
+        let test_db = test_storage
+            .db(&DatabaseName::new("MyOrg_MyBucket").unwrap())
+            .await
+            .expect("Database exists");
+
+        let batches = run_query(
+            test_db.as_ref(),
+            "select location, surface_degrees, time from h2o_temperature order by time",
+        )
+        .await;
+        let expected = vec![
+            "+--------------+-----------------+---------------------+",
+            "| location     | surface_degrees | time                |",
+            "+--------------+-----------------+---------------------+",
+            "| coyote_creek | 50.4            | 1568756160          |",
+            "| santa_monica | 70.1            | 1568756161          |",
+            "| santa_monica | 63.6            | 1600000000000000000 |",
+            "+--------------+-----------------+---------------------+",
+        ];
+        assert_table_eq!(expected, &batches);
+
+        let delete = r#"{"start": "1970-01-01T00:00:00Z", "stop": "yesterday"}"#;
+        let response = client.post(&delete_url).body(delete).send().await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let delete = r#"{"start": "1970-01-01T00:00:00Z", "stop": "1970-01-01T00:00:02Z", "predicate": "location"}"#;
+        let response = client.post(&delete_url).body(delete).send().await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let missing_url = format!("{}/api/v2/delete?bucket=Missing&org=MyOrg", server_url);
+        let delete = r#"{"start": "1970-01-01T00:00:00Z", "stop": "1970-01-01T00:00:02Z"}"#;
+        let response = client.post(&missing_url).body(delete).send().await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_metrics() -> Result<()> {
+        let test_storage = Arc::new(AppServer::new(
+            ConnectionManagerImpl::new(),
+            Arc::new(ObjectStore::new_in_memory(InMemory::new())),
+        ));
+        test_storage.set_id(1).await;
+        let rules = DatabaseRules {
+            store_locally: true,
+            ..Default::default()
+        };
+        test_storage
+            .create_database("MyOrg_MyBucket", rules)
+            .await
+            .unwrap();
+        let server_url = test_server(test_storage.clone());
+
+        let client = Client::new();
+        let write_url = format!("{}/api/v2/write?bucket=MyBucket&org=MyOrg", server_url);
+
+        let response = client.post(&write_url).body("cpu bar=1 10").send().await;
+        check_response("write", response, StatusCode::NO_CONTENT, "").await;
+        let response = client.post(&write_url).body("cpu bar=").send().await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = client
+            .get(&format!("{}/metrics", server_url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/plain; version=0.0.4"
+        );
+
+        let text = response.text().await?;
+        assert!(text.contains("iox_lines_written_total{db_name=\"MyOrg_MyBucket\"} 1\n"));
+        assert!(text.contains("iox_write_errors_total{kind=\"invalid_line_protocol\"} 1\n"));
+        assert!(text.contains("# TYPE iox_mutable_buffer_size_bytes gauge\n"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_over_memory_limit() -> Result<()> {
+        let test_storage = Arc::new(AppServer::new(
+            ConnectionManagerImpl::new(),
+            Arc::new(ObjectStore::new_in_memory(InMemory::new())),
+        ));
+        test_storage.set_id(1).await;
+        let rules = DatabaseRules {
+            store_locally: true,
+            lifecycle_rules: Some(LifecycleRules {
+                buffer_size_hard: Some(1),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        test_storage
+            .create_database("MyOrg_MyBucket", rules)
+            .await
+            .unwrap();
+        let server_url = test_server(test_storage.clone());
+
+        let client = Client::new();
+        let write_url = format!("{}/api/v2/write?bucket=MyBucket&org=MyOrg", server_url);
+
+        // the write doesn't fit in the limit
+        let response = client.post(&write_url).body("cpu bar=1 10").send().await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response
+            .text()
+            .await?
+            .contains("database over memory limit"));
+
+        Ok(())
+    }
+
     fn gzip_str(s: &str) -> Vec<u8> {
         use flate2::{write::GzEncoder, Compression};
         use std::io::Write;
@@ -766,7 +1413,7 @@ mod tests {
     #[tokio::test]
     async fn test_gzip_write() -> Result<()> {
         let test_storage = Arc::new(AppServer::new(
-            ConnectionManagerImpl {},
+            ConnectionManagerImpl::new(),
             Arc::new(ObjectStore::new_in_memory(InMemory::new())),
         ));
         test_storage.set_id(1).await;
@@ -821,7 +1468,7 @@ mod tests {
     #[tokio::test]
     async fn create_database() {
         let server = Arc::new(AppServer::new(
-            ConnectionManagerImpl {},
+            ConnectionManagerImpl::new(),
             Arc::new(ObjectStore::new_in_memory(InMemory::new())),
         ));
         server.set_id(1).await;
@@ -846,12 +1493,71 @@ mod tests {
         server.db(&database_name).await.unwrap();
         let db_rules = server.db_rules(&database_name).await.unwrap();
         assert_eq!(db_rules.store_locally, true);
+        // the database is loaded again from the stored configuration
+        let restarted = AppServer::new(ConnectionManagerImpl::new(), Arc::clone(&server.store));
+        restarted.set_id(1).await;
+        assert!(restarted.load_databases().await.unwrap().is_empty());
+        restarted.db(&database_name).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn update_and_drop_database() {
+        let server = Arc::new(AppServer::new(
+            ConnectionManagerImpl::new(),
+            Arc::new(ObjectStore::new_in_memory(InMemory::new())),
+        ));
+        server.set_id(1).await;
+        let server_url = test_server(server.clone());
+
+        let database_name = DatabaseName::new("foo_bar").unwrap();
+        let url = format!("{}/iox/api/v1/databases/{}", server_url, database_name);
+        let client = Client::new();
+
+        let response = client
+            .put(&url)
+            .body(r#"{"store_locally": true}"#)
+            .send()
+            .await;
+        check_response("create_database", response, StatusCode::OK, "").await;
+
+        let response = client
+            .put(&url)
+            .body(r#"{"store_locally": true, "query_local": true}"#)
+            .send()
+            .await;
+        check_response("update_database", response, StatusCode::OK, "").await;
+        let db_rules = server.db_rules(&database_name).await.unwrap();
+        assert_eq!(db_rules.query_local, true);
+
+        // rules referencing an unknown host group are rejected
+        let response = client
+            .put(&url)
+            .body(r#"{"store_locally": true, "replication": ["az1"]}"#)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let db_rules = server.db_rules(&database_name).await.unwrap();
+        assert!(db_rules.replication.is_empty());
+
+        let response = client.delete(&url).send().await;
+        check_response("drop_database", response, StatusCode::OK, "").await;
+        assert!(server.db(&database_name).await.is_none());
+
+        let response = client.delete(&url).send().await;
+        check_response("drop_database", response, StatusCode::NOT_FOUND, "").await;
+
+        // the dropped database isn't loaded again from the stored configuration
+        let restarted = AppServer::new(ConnectionManagerImpl::new(), Arc::clone(&server.store));
+        restarted.set_id(1).await;
+        assert!(restarted.load_databases().await.unwrap().is_empty());
+        assert!(restarted.db(&database_name).await.is_none());
     }
 
     #[tokio::test]
     async fn get_database() {
         let server = Arc::new(AppServer::new(
-            ConnectionManagerImpl {},
+            ConnectionManagerImpl::new(),
             Arc::new(ObjectStore::new_in_memory(InMemory::new())),
         ));
         server.set_id(1).await;
@@ -878,6 +1584,109 @@ mod tests {
         check_response("create_database", response, StatusCode::OK, &data).await;
     }
 
+    #[tokio::test]
+    async fn snapshot_status() -> Result<()> {
+        let server = Arc::new(AppServer::new(
+            ConnectionManagerImpl::new(),
+            Arc::new(ObjectStore::new_in_memory(InMemory::new())),
+        ));
+        server.set_id(1).await;
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            store_locally: true,
+            ..Default::default()
+        };
+        server.create_database("MyOrg_MyBucket", rules).await?;
+        let server_url = test_server(server.clone());
+
+        let client = Client::new();
+        let response = client
+            .post(&format!(
+                "{}/api/v2/write?bucket=MyBucket&org=MyOrg",
+                server_url
+            ))
+            .body("h2o,location=santa_monica surface_degrees=65.2 1568756160")
+            .send()
+            .await;
+        check_response("write", response, StatusCode::NO_CONTENT, "").await;
+
+        let db = server
+            .db(&DatabaseName::new("MyOrg_MyBucket").unwrap())
+            .await
+            .expect("Database exists");
+        assert_eq!(db.partition_keys().await?, vec!["h2o"]);
+
+        let id = client
+            .post(&format!(
+                "{}/api/v1/snapshot?bucket=MyBucket&org=MyOrg&partition=h2o",
+                server_url
+            ))
+            .send()
+            .await?
+            .text()
+            .await?;
+
+        // wait for the snapshot to finish
+        let wait_for_snapshot = async {
+            loop {
+                let body = client
+                    .get(&format!("{}/api/v1/snapshots/{}", server_url, id))
+                    .send()
+                    .await?
+                    .text()
+                    .await?;
+                let status: serde_json::Value = serde_json::from_str(&body)?;
+                if status["state"] != "running" {
+                    return Result::<serde_json::Value>::Ok(status);
+                }
+                tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+            }
+        };
+        let status = tokio::time::timeout(std::time::Duration::from_secs(10), wait_for_snapshot)
+            .await
+            .expect("snapshot should finish")?;
+        assert_eq!(status["state"], "completed");
+        assert_eq!(status["meta_written"], true);
+        assert_eq!(status["tables"][0]["name"], "h2o");
+        assert_eq!(status["tables"][0]["state"], "finished");
+
+        let body = client
+            .get(&format!("{}/api/v1/snapshots", server_url))
+            .send()
+            .await?
+            .text()
+            .await?;
+        let list: serde_json::Value = serde_json::from_str(&body)?;
+        assert_eq!(list[0]["id"], id.as_str());
+
+        // a finished snapshot can't be cancelled
+        let response = client
+            .post(&format!("{}/api/v1/snapshots/{}/cancel", server_url, id))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = client
+            .get(&format!(
+                "{}/api/v1/snapshots/{}",
+                server_url,
+                Uuid::new_v4()
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = client
+            .get(&format!("{}/api/v1/snapshots/not-a-uuid", server_url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
     /// checks a http response against expected results
     async fn check_response(
         description: &str,
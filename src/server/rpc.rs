@@ -10,5 +10,8 @@ pub(crate) const TAG_KEY_FIELD: &[u8] = &[255];
 
 pub mod data;
 pub mod expr;
+pub mod health;
 pub mod input;
+pub mod remote_query;
+pub mod replication;
 pub mod service;
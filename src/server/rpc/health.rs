@@ -0,0 +1,187 @@
+//! This module contains the standard gRPC health service, which reports a
+//! server as serving once it is ready to handle writes and queries
+
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use generated_types::health::{
+    health_check_response::ServingStatus, health_server::Health, HealthCheckRequest,
+    HealthCheckResponse,
+};
+use server::server::{ConnectionManager, Server as AppServer};
+
+use tokio::sync::mpsc;
+use tonic::Status;
+use tracing::debug;
+
+/// The services served on the gRPC port that the health of can be checked,
+/// besides the empty name that stands for the server as a whole
+const SERVICES: &[&str] = &[
+    "grpc.health.v1.Health",
+    "influxdata.platform.storage.IOxTesting",
+    "influxdata.platform.storage.Storage",
+    "influxdata.platform.storage.IOxReplication",
+    "influxdata.platform.storage.IOxQuery",
+];
+
+/// How often the readiness of the server is checked for clients watching
+/// its health
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reports the health of the services of `server`. All of them are serving
+/// when the server is ready, see `Server::check_ready`.
+#[derive(Debug)]
+pub struct HealthService<M: ConnectionManager> {
+    server: Arc<AppServer<M>>,
+}
+
+impl<M> HealthService<M>
+where
+    M: ConnectionManager,
+{
+    /// Create a new HealthService that reports the health of `server`
+    pub fn new(server: Arc<AppServer<M>>) -> Self {
+        Self { server }
+    }
+}
+
+#[tonic::async_trait]
+impl<M> Health for HealthService<M>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    async fn check(
+        &self,
+        req: tonic::Request<HealthCheckRequest>,
+    ) -> Result<tonic::Response<HealthCheckResponse>, Status> {
+        let service = req.into_inner().service;
+        if !is_known(&service) {
+            return Err(Status::not_found(format!("unknown service: {}", service)));
+        }
+
+        let status = serving_status(&self.server).await;
+        Ok(tonic::Response::new(HealthCheckResponse {
+            status: status as i32,
+        }))
+    }
+
+    type WatchStream = mpsc::Receiver<Result<HealthCheckResponse, Status>>;
+
+    async fn watch(
+        &self,
+        req: tonic::Request<HealthCheckRequest>,
+    ) -> Result<tonic::Response<Self::WatchStream>, Status> {
+        let service = req.into_inner().service;
+        let known = is_known(&service);
+        let server = Arc::clone(&self.server);
+        let (mut tx, rx) = mpsc::channel(4);
+
+        // sends the current status and then every change to it, until the
+        // client goes away
+        tokio::spawn(async move {
+            let mut last = None;
+            loop {
+                let status = if known {
+                    serving_status(&server).await
+                } else {
+                    ServingStatus::ServiceUnknown
+                };
+
+                if last != Some(status) {
+                    let response = HealthCheckResponse {
+                        status: status as i32,
+                    };
+                    if tx.send(Ok(response)).await.is_err() {
+                        debug!(%service, "health watch closed");
+                        return;
+                    }
+                    last = Some(status);
+                }
+
+                tokio::time::delay_for(WATCH_INTERVAL).await;
+            }
+        });
+
+        Ok(tonic::Response::new(rx))
+    }
+}
+
+fn is_known(service: &str) -> bool {
+    service.is_empty() || SERVICES.contains(&service)
+}
+
+async fn serving_status<M>(server: &AppServer<M>) -> ServingStatus
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    match server.check_ready().await {
+        Ok(()) => ServingStatus::Serving,
+        Err(e) => {
+            debug!("server not ready: {}", e);
+            ServingStatus::NotServing
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::rpc::service::make_server;
+    use generated_types::health::health_client::HealthClient;
+    use object_store::{memory::InMemory, ObjectStore};
+    use server::server::ConnectionManagerImpl;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use tonic::Code;
+
+    type TestError = Box<dyn std::error::Error + Send + Sync + 'static>;
+    type Result<T = (), E = TestError> = std::result::Result<T, E>;
+
+    fn request(service: &str) -> HealthCheckRequest {
+        HealthCheckRequest {
+            service: service.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_serving_once_ready() -> Result {
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        let server = Arc::new(AppServer::new(ConnectionManagerImpl::new(), store));
+
+        // Get a random port from the kernel by asking for port 0.
+        let bind_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
+        let socket = tokio::net::TcpListener::bind(bind_addr).await?;
+        let bind_addr = socket.local_addr()?;
+        tokio::task::spawn(make_server(
+            socket,
+            Arc::clone(&server),
+            Some(Arc::clone(&server)),
+        ));
+
+        let mut client = HealthClient::connect(format!("http://{}", bind_addr)).await?;
+
+        let response = client.check(request("")).await?.into_inner();
+        assert_eq!(response.status, ServingStatus::NotServing as i32);
+
+        let mut watch = client
+            .watch(request("influxdata.platform.storage.Storage"))
+            .await?
+            .into_inner();
+        let response = watch.message().await?.expect("status sent");
+        assert_eq!(response.status, ServingStatus::NotServing as i32);
+
+        // setting the id loads the databases, no configuration is stored for
+        // it yet
+        server.set_id(1).await;
+        let response = client
+            .check(request("influxdata.platform.storage.Storage"))
+            .await?
+            .into_inner();
+        assert_eq!(response.status, ServingStatus::Serving as i32);
+        let response = watch.message().await?.expect("status sent");
+        assert_eq!(response.status, ServingStatus::Serving as i32);
+
+        let status = client.check(request("unknown")).await.unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+//! This module contains the gRPC service other IOx servers use to query the
+//! data stored locally on this server when they coordinate a query
+
+use std::{fmt::Debug, sync::Arc};
+
+use data_types::{DatabaseName, DatabaseNameError};
+use generated_types::{i_ox_query_server::IOxQuery, QueryRequest, QueryResponse};
+use server::{
+    record_batch_ipc::encode_record_batches,
+    server::{ConnectionManager, Server as AppServer},
+};
+
+use snafu::{OptionExt, ResultExt, Snafu};
+use tonic::Status;
+use tracing::error;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid database name: {}", source))]
+    InvalidDatabaseName { source: DatabaseNameError },
+
+    #[snafu(display("Database not found: {}", db_name))]
+    DatabaseNotFound { db_name: String },
+
+    #[snafu(display("Error querying database '{}': {}", db_name, source))]
+    Querying {
+        db_name: String,
+        source: server::server::Error,
+    },
+
+    #[snafu(display("Error encoding query results: {}", source))]
+    EncodingResults {
+        source: arrow_deps::arrow::error::ArrowError,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<Error> for tonic::Status {
+    /// Converts a result from the business logic into the appropriate tonic
+    /// status
+    fn from(err: Error) -> Self {
+        error!("Error handling remote query request: {}", err);
+        err.to_status()
+    }
+}
+
+impl Error {
+    /// Converts a result from the business logic into the appropriate tonic
+    /// status
+    fn to_status(&self) -> tonic::Status {
+        match &self {
+            Self::InvalidDatabaseName { .. } => Status::invalid_argument(self.to_string()),
+            Self::DatabaseNotFound { .. } => Status::not_found(self.to_string()),
+            Self::Querying {
+                source: server::server::Error::PlanningQuery { .. },
+                ..
+            } => Status::invalid_argument(self.to_string()),
+            Self::Querying { .. } => Status::internal(self.to_string()),
+            Self::EncodingResults { .. } => Status::internal(self.to_string()),
+        }
+    }
+}
+
+/// Answers queries from the IOx servers that coordinate queries across a
+/// query group with the data stored on this server
+#[derive(Debug)]
+pub struct QueryService<M: ConnectionManager> {
+    server: Arc<AppServer<M>>,
+}
+
+impl<M> QueryService<M>
+where
+    M: ConnectionManager,
+{
+    /// Create a new QueryService that queries `server`
+    pub fn new(server: Arc<AppServer<M>>) -> Self {
+        Self { server }
+    }
+}
+
+#[tonic::async_trait]
+impl<M> IOxQuery for QueryService<M>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    async fn query(
+        &self,
+        req: tonic::Request<QueryRequest>,
+    ) -> Result<tonic::Response<QueryResponse>, Status> {
+        let QueryRequest { db_name, sql } = req.into_inner();
+
+        let record_batches = query_impl(&self.server, db_name, &sql).await?;
+
+        Ok(tonic::Response::new(QueryResponse { record_batches }))
+    }
+}
+
+async fn query_impl<M>(server: &AppServer<M>, db_name: String, sql: &str) -> Result<Vec<u8>>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    let db_name = DatabaseName::new(db_name).context(InvalidDatabaseName)?;
+
+    let db = server
+        .db(&db_name)
+        .await
+        .context(DatabaseNotFound { db_name: &*db_name })?;
+
+    // only the local data is queried, the coordinator takes care of querying
+    // the other servers
+    let batches = server
+        .query_local(&db, sql)
+        .await
+        .context(Querying { db_name: &*db_name })?;
+
+    encode_record_batches(&batches).context(EncodingResults)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::rpc::service::make_server;
+    use arrow_deps::arrow::util::pretty::pretty_format_batches;
+    use data_types::database_rules::DatabaseRules;
+    use influxdb_line_protocol::{parse_lines, ParsedLine};
+    use object_store::{memory::InMemory, ObjectStore};
+    use server::server::ConnectionManagerImpl;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    type TestError = Box<dyn std::error::Error + Send + Sync + 'static>;
+    type Result<T = (), E = TestError> = std::result::Result<T, E>;
+
+    type TestServer = AppServer<ConnectionManagerImpl>;
+
+    fn new_server() -> TestServer {
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        AppServer::new(ConnectionManagerImpl::new(), store)
+    }
+
+    /// Starts serving gRPC for `server` on a random local port and returns the
+    /// address to connect to it
+    async fn serve(server: Arc<TestServer>) -> Result<String> {
+        // Get a random port from the kernel by asking for port 0.
+        let bind_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
+        let socket = tokio::net::TcpListener::bind(bind_addr).await?;
+        let bind_addr = socket.local_addr()?;
+
+        tokio::task::spawn(make_server(socket, server.clone(), Some(server)));
+
+        Ok(format!("http://{}", bind_addr))
+    }
+
+    #[tokio::test]
+    async fn coordinator_queries_remote_servers() -> Result {
+        let mut addrs = vec![];
+        for (id, lp) in &[(2, "cpu,host=a val=1 10"), (3, "cpu,host=b val=2 10")] {
+            let data_server = new_server();
+            data_server.set_id(*id).await;
+            let rules = DatabaseRules {
+                store_locally: true,
+                ..Default::default()
+            };
+            data_server.create_database("foo", rules).await?;
+            data_server.write_lines("foo", &parsed_lines(lp)).await?;
+
+            addrs.push(serve(Arc::new(data_server)).await?);
+        }
+
+        let mut coordinator = new_server();
+        coordinator.set_id(1).await;
+        coordinator
+            .create_host_group("query_group".to_string(), addrs)
+            .await?;
+        let rules = DatabaseRules {
+            primary_query_group: Some("query_group".to_string()),
+            ..Default::default()
+        };
+        coordinator.create_database("foo", rules).await?;
+
+        let db_name = DatabaseName::new("foo")?;
+        let batches = coordinator
+            .query_sql(&db_name, "select host, val from cpu")
+            .await?;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+
+        let results = pretty_format_batches(&batches)?;
+        assert!(results.contains("| a "), "{}", results);
+        assert!(results.contains("| b "), "{}", results);
+
+        Ok(())
+    }
+
+    fn parsed_lines(lp: &str) -> Vec<ParsedLine<'_>> {
+        parse_lines(lp).map(|l| l.unwrap()).collect()
+    }
+}
@@ -0,0 +1,674 @@
+//! This module contains the gRPC service that receives replicated writes sent
+//! by other IOx servers, streams writes to subscribers and deletes data
+
+use std::{fmt::Debug, sync::Arc};
+
+use data_types::{
+    data::{filtered_replicated_write, ReplicatedWrite},
+    database_rules::{CompiledMatcher, MatchTables, Matcher},
+    delete::Delete,
+    row_predicate::RowPredicate,
+    DatabaseName, DatabaseNameError,
+};
+use generated_types::{
+    i_ox_replication_server::IOxReplication, subscribe_request::Tables, DeleteRequest,
+    DeleteResponse, ReplicateRequest, ReplicateResponse, SubscribeRequest, SubscribeResponse,
+};
+use server::{
+    buffer::WriterSequence,
+    db::WalBufferSubscription,
+    server::{ConnectionManager, Server as AppServer},
+};
+
+use snafu::{OptionExt, ResultExt, Snafu};
+use tokio::sync::{broadcast, mpsc};
+use tonic::Status;
+use tracing::{debug, error, info};
+
+/// The number of writes that are buffered for a subscriber before waiting for
+/// it to receive them
+const SUBSCRIPTION_CHANNEL_SIZE: usize = 16;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid database name: {}", source))]
+    InvalidDatabaseName { source: DatabaseNameError },
+
+    #[snafu(display("Database not found: {}", db_name))]
+    DatabaseNotFound { db_name: String },
+
+    #[snafu(display("Replicated write for database '{}' is empty", db_name))]
+    EmptyReplicatedWrite { db_name: String },
+
+    #[snafu(display("Invalid replicated write for database '{}': {}", db_name, source))]
+    InvalidReplicatedWrite {
+        db_name: String,
+        source: data_types::data::VerifyError,
+    },
+
+    #[snafu(display(
+        "Error handling replicated write for database '{}': {}",
+        db_name,
+        source
+    ))]
+    HandlingReplicatedWrite {
+        db_name: String,
+        source: server::server::Error,
+    },
+
+    #[snafu(display("Invalid subscription: {}", source))]
+    InvalidMatcher {
+        source: data_types::database_rules::Error,
+    },
+
+    #[snafu(display("Cannot subscribe to database '{}': {}", db_name, source))]
+    Subscribing {
+        db_name: String,
+        source: server::db::Error,
+    },
+
+    #[snafu(display("Invalid delete predicate: {}", source))]
+    InvalidDeletePredicate {
+        source: data_types::row_predicate::Error,
+    },
+
+    #[snafu(display("Error deleting from database '{}': {}", db_name, source))]
+    Deleting {
+        db_name: String,
+        source: server::server::Error,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<Error> for tonic::Status {
+    /// Converts a result from the business logic into the appropriate tonic
+    /// status
+    fn from(err: Error) -> Self {
+        error!("Error handling replication request: {}", err);
+        err.to_status()
+    }
+}
+
+impl Error {
+    /// Converts a result from the business logic into the appropriate tonic
+    /// status
+    fn to_status(&self) -> tonic::Status {
+        match &self {
+            Self::InvalidDatabaseName { .. } => Status::invalid_argument(self.to_string()),
+            Self::DatabaseNotFound { .. } => Status::not_found(self.to_string()),
+            Self::EmptyReplicatedWrite { .. } => Status::invalid_argument(self.to_string()),
+            Self::InvalidReplicatedWrite { .. } => Status::invalid_argument(self.to_string()),
+            Self::HandlingReplicatedWrite {
+                source: server::server::Error::DatabaseOverMemoryLimit { .. },
+                ..
+            } => Status::resource_exhausted(self.to_string()),
+            Self::HandlingReplicatedWrite {
+                source: server::server::Error::WriteInProgress { .. },
+                ..
+            } => Status::unavailable(self.to_string()),
+            Self::HandlingReplicatedWrite { .. } => Status::internal(self.to_string()),
+            Self::InvalidMatcher { .. } => Status::invalid_argument(self.to_string()),
+            Self::Subscribing { .. } => Status::failed_precondition(self.to_string()),
+            Self::InvalidDeletePredicate { .. } => Status::invalid_argument(self.to_string()),
+            Self::Deleting { .. } => Status::internal(self.to_string()),
+        }
+    }
+}
+
+/// Receives the writes that other servers replicate to this one and handles
+/// them as if they were written to this server directly
+#[derive(Debug)]
+pub struct ReplicationService<M: ConnectionManager> {
+    server: Arc<AppServer<M>>,
+}
+
+impl<M> ReplicationService<M>
+where
+    M: ConnectionManager,
+{
+    /// Create a new ReplicationService that hands writes to `server`
+    pub fn new(server: Arc<AppServer<M>>) -> Self {
+        Self { server }
+    }
+}
+
+#[tonic::async_trait]
+impl<M> IOxReplication for ReplicationService<M>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    async fn replicate(
+        &self,
+        req: tonic::Request<ReplicateRequest>,
+    ) -> Result<tonic::Response<ReplicateResponse>, Status> {
+        let ReplicateRequest {
+            db_name,
+            replicated_write,
+        } = req.into_inner();
+
+        replicate_impl(&self.server, db_name, replicated_write).await?;
+
+        Ok(tonic::Response::new(ReplicateResponse {}))
+    }
+
+    type SubscribeStream = mpsc::Receiver<Result<SubscribeResponse, Status>>;
+
+    async fn subscribe(
+        &self,
+        req: tonic::Request<SubscribeRequest>,
+    ) -> Result<tonic::Response<Self::SubscribeStream>, Status> {
+        let (tx, rx) = mpsc::channel(SUBSCRIPTION_CHANNEL_SIZE);
+
+        subscribe_impl(&self.server, req.into_inner(), tx).await?;
+
+        Ok(tonic::Response::new(rx))
+    }
+
+    async fn delete(
+        &self,
+        req: tonic::Request<DeleteRequest>,
+    ) -> Result<tonic::Response<DeleteResponse>, Status> {
+        delete_impl(&self.server, req.into_inner()).await?;
+
+        Ok(tonic::Response::new(DeleteResponse {}))
+    }
+}
+
+async fn replicate_impl<M>(
+    server: &AppServer<M>,
+    db_name: String,
+    replicated_write: Vec<u8>,
+) -> Result<()>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    let db_name = DatabaseName::new(db_name).context(InvalidDatabaseName)?;
+
+    if replicated_write.is_empty() {
+        return EmptyReplicatedWrite { db_name }.fail();
+    }
+
+    let write = ReplicatedWrite::from(replicated_write.as_slice());
+    write
+        .verify()
+        .context(InvalidReplicatedWrite { db_name: &*db_name })?;
+
+    let db = server
+        .db(&db_name)
+        .await
+        .context(DatabaseNotFound { db_name: &*db_name })?;
+
+    debug!(%db_name, writer_and_sequence=?write.writer_and_sequence(), "received replicated write");
+
+    server
+        .handle_replicated_write(&db_name, &db, write)
+        .await
+        .context(HandlingReplicatedWrite { db_name })
+}
+
+async fn subscribe_impl<M>(
+    server: &AppServer<M>,
+    req: SubscribeRequest,
+    tx: mpsc::Sender<Result<SubscribeResponse, Status>>,
+) -> Result<()>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    let SubscribeRequest {
+        db_name,
+        tables,
+        predicate,
+        last_seen,
+    } = req;
+
+    let db_name = DatabaseName::new(db_name).context(InvalidDatabaseName)?;
+
+    let tables = match tables {
+        None => MatchTables::All,
+        Some(Tables::TableName(name)) => MatchTables::Table(name),
+        Some(Tables::TableRegex(regex)) => MatchTables::Regex(regex),
+    };
+    let predicate = if predicate.is_empty() {
+        None
+    } else {
+        Some(predicate)
+    };
+    let matcher = Matcher { tables, predicate };
+    let compiled_matcher = matcher.compile().context(InvalidMatcher)?;
+
+    let last_seen: Vec<_> = last_seen
+        .into_iter()
+        .map(|s| WriterSequence {
+            id: s.writer_id,
+            sequence: s.sequence,
+        })
+        .collect();
+
+    let db = server
+        .db(&db_name)
+        .await
+        .context(DatabaseNotFound { db_name: &*db_name })?;
+
+    let subscription = db
+        .subscribe_to_wal_buffer(&last_seen)
+        .await
+        .context(Subscribing { db_name: &*db_name })?;
+
+    info!(%db_name, ?matcher, backlog=subscription.backlog.len(), "new subscriber");
+
+    let matcher = SubscriptionMatcher {
+        matches_all: matcher.matches_all(),
+        compiled_matcher,
+    };
+    tokio::task::spawn(stream_writes(matcher, subscription, tx));
+
+    Ok(())
+}
+
+async fn delete_impl<M>(server: &AppServer<M>, req: DeleteRequest) -> Result<()>
+where
+    M: ConnectionManager + Send + Sync + Debug + 'static,
+{
+    let DeleteRequest {
+        db_name,
+        table_name,
+        start,
+        stop,
+        predicate,
+    } = req;
+
+    let db_name = DatabaseName::new(db_name).context(InvalidDatabaseName)?;
+    server
+        .db(&db_name)
+        .await
+        .context(DatabaseNotFound { db_name: &*db_name })?;
+
+    let predicate = if predicate.is_empty() {
+        None
+    } else {
+        Some(
+            predicate
+                .parse::<RowPredicate>()
+                .context(InvalidDeletePredicate)?,
+        )
+    };
+    let delete = Delete {
+        table_name: Some(table_name).filter(|name| !name.is_empty()),
+        start,
+        stop,
+        predicate,
+    };
+
+    info!(%db_name, ?delete, "deleting");
+
+    server
+        .delete(&db_name, &delete)
+        .await
+        .context(Deleting { db_name: &*db_name })
+}
+
+struct SubscriptionMatcher {
+    matches_all: bool,
+    compiled_matcher: CompiledMatcher,
+}
+
+impl SubscriptionMatcher {
+    // returns the part of the write the subscriber is interested in, if any
+    fn matching_write(&self, write: &ReplicatedWrite) -> Option<Vec<u8>> {
+        if self.matches_all {
+            Some(write.data.clone())
+        } else {
+            filtered_replicated_write(write, &self.compiled_matcher).map(|w| w.data)
+        }
+    }
+}
+
+// sends the matching writes of the backlog and then of every new write to the
+// subscriber until it disconnects
+async fn stream_writes(
+    matcher: SubscriptionMatcher,
+    subscription: WalBufferSubscription,
+    mut tx: mpsc::Sender<Result<SubscribeResponse, Status>>,
+) {
+    let WalBufferSubscription {
+        backlog,
+        mut receiver,
+    } = subscription;
+
+    for write in backlog {
+        if let Some(replicated_write) = matcher.matching_write(&write) {
+            let response = SubscribeResponse { replicated_write };
+            if tx.send(Ok(response)).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    loop {
+        let write = match receiver.recv().await {
+            Ok(write) => write,
+            Err(broadcast::RecvError::Lagged(missed)) => {
+                // the subscriber can't catch up from here without missing
+                // writes, so it has to subscribe again from what it has seen
+                let status = Status::resource_exhausted(format!(
+                    "subscriber fell behind by {} writes, subscribe again",
+                    missed
+                ));
+                let _ = tx.send(Err(status)).await;
+                return;
+            }
+            Err(broadcast::RecvError::Closed) => return,
+        };
+
+        if let Some(replicated_write) = matcher.matching_write(&write) {
+            let response = SubscribeResponse { replicated_write };
+            if tx.send(Ok(response)).await.is_err() {
+                debug!("subscriber disconnected");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::rpc::service::make_server;
+    use data_types::database_rules::{
+        DatabaseRules, PartitionTemplate, TemplatePart, WalBufferConfig, WalBufferRollover,
+    };
+    use generated_types::{
+        i_ox_replication_client::IOxReplicationClient, WriterSequence as ProtoWriterSequence,
+    };
+    use influxdb_line_protocol::{parse_lines, ParsedLine};
+    use object_store::{memory::InMemory, ObjectStore};
+    use query::Database;
+    use server::server::ConnectionManagerImpl;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    type TestError = Box<dyn std::error::Error + Send + Sync + 'static>;
+    type Result<T = (), E = TestError> = std::result::Result<T, E>;
+
+    type TestServer = AppServer<ConnectionManagerImpl>;
+
+    fn new_server() -> TestServer {
+        let store = Arc::new(ObjectStore::new_in_memory(InMemory::new()));
+        AppServer::new(ConnectionManagerImpl::new(), store)
+    }
+
+    /// Starts serving gRPC for `server` on a random local port and returns the
+    /// address to connect to it
+    async fn serve(server: Arc<TestServer>) -> Result<String> {
+        // Get a random port from the kernel by asking for port 0.
+        let bind_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
+        let socket = tokio::net::TcpListener::bind(bind_addr).await?;
+        let bind_addr = socket.local_addr()?;
+
+        tokio::task::spawn(make_server(socket, server.clone(), Some(server)));
+
+        Ok(format!("http://{}", bind_addr))
+    }
+
+    /// Returns a server that replicates writes to the database "foo" on the
+    /// host at `replica_addr`
+    async fn writer(replica_addr: String) -> Result<TestServer> {
+        let mut writer = new_server();
+        writer.set_id(1).await;
+        writer
+            .create_host_group("replicas".to_string(), vec![replica_addr])
+            .await?;
+
+        let rules = DatabaseRules {
+            partition_template: PartitionTemplate {
+                parts: vec![TemplatePart::Table],
+            },
+            replication: vec!["replicas".to_string()],
+            replication_count: 1,
+            ..Default::default()
+        };
+        writer.create_database("foo", rules).await?;
+
+        Ok(writer)
+    }
+
+    #[tokio::test]
+    async fn replicates_write_to_remote_server() -> Result {
+        let replica = new_server();
+        replica.set_id(2).await;
+        let rules = DatabaseRules {
+            store_locally: true,
+            ..Default::default()
+        };
+        replica.create_database("foo", rules).await?;
+        let replica = Arc::new(replica);
+        let replica_addr = serve(replica.clone()).await?;
+
+        let writer = writer(replica_addr).await?;
+        let lines = parsed_lines("cpu,host=a bar=1 10\nmem,host=a free=2 10");
+        writer.write_lines("foo", &lines).await?;
+
+        let db = replica.db(&DatabaseName::new("foo")?).await.unwrap();
+        let mut partition_keys = db.partition_keys().await?;
+        partition_keys.sort();
+        assert_eq!(partition_keys, vec!["cpu", "mem"]);
+        assert_eq!(db.table_names_for_partition("cpu").await?, vec!["cpu"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replicating_to_missing_database_fails() -> Result {
+        let replica = new_server();
+        replica.set_id(2).await;
+        let replica_addr = serve(Arc::new(replica)).await?;
+
+        let writer = writer(replica_addr).await?;
+        let lines = parsed_lines("cpu,host=a bar=1 10");
+        let err = writer.write_lines("foo", &lines).await.unwrap_err();
+        assert!(
+            err.to_string().contains("error replicating to remote"),
+            "{}",
+            err
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_replicated_write() -> Result {
+        let replica = new_server();
+        replica.set_id(2).await;
+        replica
+            .create_database("foo", DatabaseRules::default())
+            .await?;
+
+        let lines = parsed_lines("cpu,host=a bar=1 10");
+        let write = data_types::data::lines_to_replicated_write(1, 1, &lines, &Default::default())?;
+        let truncated = write.data[..write.data.len() / 2].to_vec();
+
+        for bytes in &[truncated, vec![0xff; 16]] {
+            let err = replicate_impl(&replica, "foo".to_string(), bytes.clone())
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidReplicatedWrite { .. }),
+                "{}",
+                err
+            );
+            assert_eq!(err.to_status().code(), tonic::Code::InvalidArgument);
+        }
+
+        Ok(())
+    }
+
+    /// Returns a server with the database "foo" that has a WAL buffer and the
+    /// address of its gRPC API
+    async fn buffering_server() -> Result<(Arc<TestServer>, String)> {
+        let server = new_server();
+        server.set_id(1).await;
+        let rules = DatabaseRules {
+            store_locally: true,
+            wal_buffer_config: Some(WalBufferConfig {
+                buffer_size: None,
+                segment_size: None,
+                buffer_rollover: WalBufferRollover::DropOldSegment,
+            }),
+            ..Default::default()
+        };
+        server.create_database("foo", rules).await?;
+
+        let server = Arc::new(server);
+        let addr = serve(server.clone()).await?;
+
+        Ok((server, addr))
+    }
+
+    #[tokio::test]
+    async fn subscribe_streams_backlog_then_live_writes() -> Result {
+        let (server, addr) = buffering_server().await?;
+
+        for lp in &[
+            "cpu,region=west user=1 10",
+            "mem,region=west free=1 10",
+            "cpu,region=east user=2 10",
+        ] {
+            server.write_lines("foo", &parsed_lines(lp)).await?;
+        }
+
+        let mut client = IOxReplicationClient::connect(addr).await?;
+        let request = SubscribeRequest {
+            db_name: "foo".to_string(),
+            tables: Some(Tables::TableName("cpu".to_string())),
+            predicate: String::new(),
+            last_seen: vec![ProtoWriterSequence {
+                writer_id: 1,
+                sequence: 1,
+            }],
+        };
+        let mut stream = client.subscribe(request).await?.into_inner();
+
+        // the backlog only has the cpu write after the last seen one
+        let write = next_write(&mut stream).await?;
+        assert_eq!(write.writer_and_sequence(), (1, 3));
+
+        // mem doesn't match, so the next write is the live cpu one
+        server
+            .write_lines("foo", &parsed_lines("mem,region=east free=2 12"))
+            .await?;
+        server
+            .write_lines("foo", &parsed_lines("cpu,region=west user=3 12"))
+            .await?;
+
+        let write = next_write(&mut stream).await?;
+        assert_eq!(write.writer_and_sequence(), (1, 5));
+        assert!(write.to_string().contains("region:west user:3 time:12"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn subscribe_with_predicate() -> Result {
+        let (server, addr) = buffering_server().await?;
+
+        let lines = parsed_lines("cpu,region=west user=1 10\ncpu,region=east user=2 10");
+        server.write_lines("foo", &lines).await?;
+
+        let mut client = IOxReplicationClient::connect(addr).await?;
+        let request = SubscribeRequest {
+            db_name: "foo".to_string(),
+            tables: None,
+            predicate: "region = 'east'".to_string(),
+            last_seen: vec![],
+        };
+        let mut stream = client.subscribe(request).await?.into_inner();
+
+        let write = next_write(&mut stream).await?;
+        assert_eq!(write.writer_and_sequence(), (1, 1));
+        let write_text = write.to_string();
+        assert!(write_text.contains("region:east user:2 time:10"));
+        assert!(!write_text.contains("region:west"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn subscribe_without_wal_buffer_fails() -> Result {
+        let server = new_server();
+        server.set_id(1).await;
+        server
+            .create_database("foo", DatabaseRules::default())
+            .await?;
+        let addr = serve(Arc::new(server)).await?;
+
+        let mut client = IOxReplicationClient::connect(addr).await?;
+        let request = SubscribeRequest {
+            db_name: "foo".to_string(),
+            ..Default::default()
+        };
+        let status = client.subscribe(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_removes_matching_rows() -> Result {
+        let server = new_server();
+        server.set_id(1).await;
+        let rules = DatabaseRules {
+            store_locally: true,
+            ..Default::default()
+        };
+        server.create_database("foo", rules).await?;
+        let server = Arc::new(server);
+        let addr = serve(server.clone()).await?;
+
+        let lines =
+            parsed_lines("cpu,host=a user=1 10\ncpu,host=b user=2 10\ncpu,host=a user=3 30");
+        server.write_lines("foo", &lines).await?;
+
+        let mut client = IOxReplicationClient::connect(addr).await?;
+        let request = DeleteRequest {
+            db_name: "foo".to_string(),
+            table_name: "cpu".to_string(),
+            start: 0,
+            stop: 20,
+            predicate: r#"host="a""#.to_string(),
+        };
+        client.delete(request).await?;
+
+        let db = server.db(&DatabaseName::new("foo")?).await.unwrap();
+        let batches = db.table_to_arrow("cpu", &["host", "time"]).await?;
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(rows, 2);
+
+        let request = DeleteRequest {
+            db_name: "foo".to_string(),
+            predicate: "host=".to_string(),
+            ..Default::default()
+        };
+        let status = client.delete(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        let request = DeleteRequest {
+            db_name: "bar".to_string(),
+            ..Default::default()
+        };
+        let status = client.delete(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        Ok(())
+    }
+
+    async fn next_write(
+        stream: &mut tonic::Streaming<SubscribeResponse>,
+    ) -> Result<ReplicatedWrite> {
+        let response = stream.message().await?.expect("subscription ended");
+        Ok(ReplicatedWrite::from(response.replicated_write.as_slice()))
+    }
+
+    fn parsed_lines(lp: &str) -> Vec<ParsedLine<'_>> {
+        parse_lines(lp).map(|l| l.unwrap()).collect()
+    }
+}
@@ -5,6 +5,9 @@
 use std::{collections::HashMap, sync::Arc};
 
 use generated_types::{
+    health::health_server::HealthServer,
+    i_ox_query_server::IOxQueryServer,
+    i_ox_replication_server::IOxReplicationServer,
     i_ox_testing_server::{IOxTesting, IOxTestingServer},
     storage_server::{Storage, StorageServer},
     CapabilitiesResponse, Capability, Int64ValuesResponse, MeasurementFieldsRequest,
@@ -25,7 +28,10 @@ use query::group_by::GroupByAndAggregate;
 
 use crate::server::org_and_bucket_to_database;
 use crate::server::rpc::expr::{self, AddRPCNode, Loggable, SpecialTagKeys};
+use crate::server::rpc::health::HealthService;
 use crate::server::rpc::input::GrpcInputs;
+use crate::server::rpc::remote_query::QueryService;
+use crate::server::rpc::replication::ReplicationService;
 use data_types::DatabaseName;
 
 use query::{
@@ -34,6 +40,10 @@ use query::{
     Database, DatabaseStore,
 };
 
+use server::{
+    metrics::ServerMetrics,
+    server::{ConnectionManager, Server as AppServer},
+};
 use snafu::{OptionExt, ResultExt, Snafu};
 
 use tokio::{net::TcpListener, sync::mpsc};
@@ -224,15 +234,17 @@ impl Error {
 #[derive(Debug)]
 pub struct GrpcService<T: DatabaseStore> {
     db_store: Arc<T>,
+    metrics: Arc<ServerMetrics>,
 }
 
 impl<T> GrpcService<T>
 where
     T: DatabaseStore + 'static,
 {
-    /// Create a new GrpcService connected to `db_store`
-    pub fn new(db_store: Arc<T>) -> Self {
-        Self { db_store }
+    /// Create a new GrpcService connected to `db_store`, which records the
+    /// duration of each request in `metrics`
+    pub fn new(db_store: Arc<T>, metrics: Arc<ServerMetrics>) -> Self {
+        Self { db_store, metrics }
     }
 }
 
@@ -263,6 +275,7 @@ where
         &self,
         req: tonic::Request<ReadFilterRequest>,
     ) -> Result<tonic::Response<Self::ReadFilterStream>, Status> {
+        let _timer = self.metrics.time_rpc("read_filter");
         let (tx, rx) = mpsc::channel(4);
 
         let read_filter_request = req.into_inner();
@@ -295,6 +308,7 @@ where
         &self,
         req: tonic::Request<ReadGroupRequest>,
     ) -> Result<tonic::Response<Self::ReadGroupStream>, Status> {
+        let _timer = self.metrics.time_rpc("read_group");
         let (tx, rx) = mpsc::channel(4);
 
         let read_group_request = req.into_inner();
@@ -353,6 +367,7 @@ where
         &self,
         req: tonic::Request<ReadWindowAggregateRequest>,
     ) -> Result<tonic::Response<Self::ReadGroupStream>, Status> {
+        let _timer = self.metrics.time_rpc("read_window_aggregate");
         let (tx, rx) = mpsc::channel(4);
 
         let read_window_aggregate_request = req.into_inner();
@@ -403,6 +418,7 @@ where
         &self,
         req: tonic::Request<TagKeysRequest>,
     ) -> Result<tonic::Response<Self::TagKeysStream>, Status> {
+        let _timer = self.metrics.time_rpc("tag_keys");
         let (mut tx, rx) = mpsc::channel(4);
 
         let tag_keys_request = req.into_inner();
@@ -447,6 +463,7 @@ where
         &self,
         req: tonic::Request<TagValuesRequest>,
     ) -> Result<tonic::Response<Self::TagValuesStream>, Status> {
+        let _timer = self.metrics.time_rpc("tag_values");
         let (mut tx, rx) = mpsc::channel(4);
 
         let tag_values_request = req.into_inner();
@@ -538,6 +555,7 @@ where
         &self,
         _req: tonic::Request<()>,
     ) -> Result<tonic::Response<CapabilitiesResponse>, Status> {
+        let _timer = self.metrics.time_rpc("capabilities");
         // Full list of go capabilities in
         // idpe/storage/read/capabilities.go (aka window aggregate /
         // pushdown)
@@ -576,6 +594,7 @@ where
         &self,
         req: tonic::Request<MeasurementNamesRequest>,
     ) -> Result<tonic::Response<Self::MeasurementNamesStream>, Status> {
+        let _timer = self.metrics.time_rpc("measurement_names");
         let (mut tx, rx) = mpsc::channel(4);
 
         let measurement_names_request = req.into_inner();
@@ -623,6 +642,7 @@ where
         &self,
         req: tonic::Request<MeasurementTagKeysRequest>,
     ) -> Result<tonic::Response<Self::MeasurementTagKeysStream>, Status> {
+        let _timer = self.metrics.time_rpc("measurement_tag_keys");
         let (mut tx, rx) = mpsc::channel(4);
 
         let measurement_tag_keys_request = req.into_inner();
@@ -669,6 +689,7 @@ where
         &self,
         req: tonic::Request<MeasurementTagValuesRequest>,
     ) -> Result<tonic::Response<Self::MeasurementTagValuesStream>, Status> {
+        let _timer = self.metrics.time_rpc("measurement_tag_values");
         let (mut tx, rx) = mpsc::channel(4);
 
         let measurement_tag_values_request = req.into_inner();
@@ -715,6 +736,7 @@ where
         &self,
         req: tonic::Request<MeasurementFieldsRequest>,
     ) -> Result<tonic::Response<Self::MeasurementFieldsStream>, Status> {
+        let _timer = self.metrics.time_rpc("measurement_fields");
         let (mut tx, rx) = mpsc::channel(4);
 
         let measurement_fields_request = req.into_inner();
@@ -1142,17 +1164,46 @@ where
 
 /// Instantiate a server listening on the specified address
 /// implementing the IOx and Storage gRPC interfaces, the
-/// underlying hyper server instance. Resolves when the server has
-/// shutdown.
-pub async fn make_server<T>(socket: TcpListener, storage: Arc<T>) -> Result<()>
+/// underlying hyper server instance. If `server` is an IOx server, the
+/// replication, query and health interfaces are implemented too, so that
+/// other IOx servers can replicate writes to it and query it. Resolves when
+/// the server has shutdown.
+pub async fn make_server<T, M>(
+    socket: TcpListener,
+    storage: Arc<T>,
+    server: Option<Arc<AppServer<M>>>,
+) -> Result<()>
 where
     T: DatabaseStore + 'static,
+    M: ConnectionManager + Send + Sync + std::fmt::Debug + 'static,
 {
-    tonic::transport::Server::builder()
-        .add_service(IOxTestingServer::new(GrpcService::new(storage.clone())))
-        .add_service(StorageServer::new(GrpcService::new(storage.clone())))
-        .serve_with_incoming(socket)
-        .await
+    let metrics = server
+        .as_ref()
+        .map(|server| Arc::clone(server.metrics()))
+        .unwrap_or_default();
+
+    let builder = tonic::transport::Server::builder()
+        .add_service(IOxTestingServer::new(GrpcService::new(
+            storage.clone(),
+            Arc::clone(&metrics),
+        )))
+        .add_service(StorageServer::new(GrpcService::new(storage, metrics)));
+
+    let serve = match server {
+        Some(server) => {
+            builder
+                .add_service(IOxReplicationServer::new(ReplicationService::new(
+                    server.clone(),
+                )))
+                .add_service(IOxQueryServer::new(QueryService::new(server.clone())))
+                .add_service(HealthServer::new(HealthService::new(server)))
+                .serve_with_incoming(socket)
+                .await
+        }
+        None => builder.serve_with_incoming(socket).await,
+    };
+
+    serve
         .context(ServerError {})
         .log_if_error("Running Tonic Server")
 }
@@ -1174,6 +1225,7 @@ mod tests {
         test::TestDatabaseStore,
         test::{ColumnValuesRequest, QuerySeriesRequest},
     };
+    use server::server::ConnectionManagerImpl;
     use std::{
         convert::TryFrom,
         net::{IpAddr, Ipv4Addr, SocketAddr},
@@ -2578,7 +2630,11 @@ mod tests {
 
             println!("Starting InfluxDB IOx rpc test server on {:?}", bind_addr);
 
-            let server = make_server(socket, test_storage.clone());
+            let server = make_server(
+                socket,
+                test_storage.clone(),
+                None::<Arc<AppServer<ConnectionManagerImpl>>>,
+            );
             tokio::task::spawn(server);
 
             let iox_client = connect_to_server::<IOxTestingClient>(bind_addr)